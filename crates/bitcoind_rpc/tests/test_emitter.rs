@@ -5,7 +5,7 @@ use bdk_chain::{
     bitcoin::{Address, Amount, Txid},
     keychain::Balance,
     local_chain::{CheckPoint, LocalChain},
-    Append, BlockId, IndexedTxGraph, SpkTxOutIndex,
+    Append, BlockId, IndexedTxGraph, SpkTxOutIndex, COINBASE_MATURITY,
 };
 use bdk_testenv::{anyhow, TestEnv};
 use bitcoin::{hashes::Hash, Block, OutPoint, ScriptBuf, WScriptHash};
@@ -403,6 +403,125 @@ fn tx_can_become_unconfirmed_after_reorg() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Ensure that every tracked output is counted in the right [`Balance`] category.
+///
+/// 1. Mine a coinbase output to a tracked spk, it is `immature`.
+/// 2. Confirm payments to tracked spks, they are `confirmed`.
+/// 3. Have unconfirmed payments to tracked spks, they are `trusted_pending` or `untrusted_pending`
+///    depending on the trust predicate.
+/// 4. Mine until the coinbase output matures, everything is `confirmed`.
+#[test]
+fn balance_categories() -> anyhow::Result<()> {
+    const PREMINE_COUNT: usize = 101;
+    const SEND_AMOUNT: Amount = Amount::from_sat(10_000);
+
+    let env = TestEnv::new()?;
+    let mut emitter = Emitter::new(
+        env.rpc_client(),
+        CheckPoint::new(BlockId {
+            height: 0,
+            hash: env.rpc_client().get_block_hash(0)?,
+        }),
+        0,
+    );
+
+    // setup addresses, only outputs to the "change" spk are trusted
+    let change_spk = ScriptBuf::new_p2wsh(&WScriptHash::from_byte_array([1; 32]));
+    let receive_spk = ScriptBuf::new_p2wsh(&WScriptHash::from_byte_array([2; 32]));
+    let coinbase_spk = ScriptBuf::new_p2wsh(&WScriptHash::from_byte_array([3; 32]));
+    let change_addr = Address::from_script(&change_spk, bitcoin::Network::Regtest)?;
+    let receive_addr = Address::from_script(&receive_spk, bitcoin::Network::Regtest)?;
+    let coinbase_addr = Address::from_script(&coinbase_spk, bitcoin::Network::Regtest)?;
+
+    // setup receiver
+    let (mut recv_chain, _) = LocalChain::from_genesis_hash(env.rpc_client().get_block_hash(0)?);
+    let mut recv_graph = IndexedTxGraph::<BlockId, _>::new({
+        let mut recv_index = SpkTxOutIndex::default();
+        recv_index.insert_spk("change", change_spk.clone());
+        recv_index.insert_spk("receive", receive_spk.clone());
+        recv_index.insert_spk("coinbase", coinbase_spk.clone());
+        recv_index
+    });
+    let get_balance =
+        |recv_chain: &LocalChain, recv_graph: &IndexedTxGraph<BlockId, SpkTxOutIndex<&str>>| {
+            recv_graph.graph().balance(
+                recv_chain,
+                recv_chain.tip().block_id(),
+                recv_graph.index.outpoints().clone(),
+                |label, _| *label == "change",
+            )
+        };
+    let mut sync = |recv_chain: &mut LocalChain,
+                    recv_graph: &mut IndexedTxGraph<BlockId, SpkTxOutIndex<&str>>|
+     -> anyhow::Result<()> {
+        while let Some(emission) = emitter.next_block()? {
+            let height = emission.block_height();
+            recv_chain.apply_update(CheckPoint::from_header(&emission.block.header, height))?;
+            let _ = recv_graph.apply_block(emission.block, height);
+        }
+        let _ = recv_graph.batch_insert_unconfirmed(emitter.mempool()?);
+        Ok(())
+    };
+
+    env.mine_blocks(PREMINE_COUNT, None)?;
+    let coinbase_hash = env.mine_blocks(1, Some(coinbase_addr))?[0];
+    let coinbase_value = env.rpc_client().get_block(&coinbase_hash)?.txdata[0]
+        .output
+        .iter()
+        .filter(|txo| txo.script_pubkey == coinbase_spk)
+        .map(|txo| txo.value)
+        .sum::<Amount>();
+    env.send(&change_addr, SEND_AMOUNT)?;
+    env.send(&receive_addr, SEND_AMOUNT)?;
+    env.mine_blocks(1, None)?;
+    sync(&mut recv_chain, &mut recv_graph)?;
+    assert_eq!(
+        get_balance(&recv_chain, &recv_graph),
+        Balance {
+            immature: coinbase_value,
+            confirmed: SEND_AMOUNT * 2,
+            ..Balance::default()
+        },
+    );
+
+    env.send(&change_addr, SEND_AMOUNT * 2)?;
+    env.send(&receive_addr, SEND_AMOUNT * 3)?;
+    sync(&mut recv_chain, &mut recv_graph)?;
+    assert_eq!(
+        get_balance(&recv_chain, &recv_graph),
+        Balance {
+            immature: coinbase_value,
+            trusted_pending: SEND_AMOUNT * 2,
+            untrusted_pending: SEND_AMOUNT * 3,
+            confirmed: SEND_AMOUNT * 2,
+        },
+    );
+
+    // the coinbase output is one block short of maturity
+    env.mine_blocks(COINBASE_MATURITY as usize - 3, None)?;
+    sync(&mut recv_chain, &mut recv_graph)?;
+    assert_eq!(
+        get_balance(&recv_chain, &recv_graph),
+        Balance {
+            immature: coinbase_value,
+            confirmed: SEND_AMOUNT * 7,
+            ..Balance::default()
+        },
+    );
+
+    env.mine_blocks(1, None)?;
+    sync(&mut recv_chain, &mut recv_graph)?;
+    assert_eq!(
+        get_balance(&recv_chain, &recv_graph),
+        Balance {
+            confirmed: coinbase_value + SEND_AMOUNT * 7,
+            ..Balance::default()
+        },
+    );
+
+    Ok(())
+}
+
 /// Ensure avoid-re-emission-logic is sound when [`Emitter`] is synced to tip.
 ///
 /// The receiver (bdk_chain structures) is synced to the chain tip, and there is txs in the mempool.
@@ -0,0 +1,81 @@
+use bdk_chain::{
+    bitcoin::{
+        absolute, hashes::Hash, transaction, Amount, BlockHash, OutPoint, ScriptBuf, Transaction,
+        TxIn, TxOut, Txid,
+    },
+    tx_graph::{ChangeSet, TxGraph},
+    Append, ConfirmationHeightAnchor,
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Number of transactions of the wallet whose anchors are inserted.
+const TX_COUNT: u32 = 10_000;
+
+/// A graph of `TX_COUNT` transactions and an anchor for each of them, as a sync of a large wallet
+/// would return.
+fn setup() -> (
+    TxGraph<ConfirmationHeightAnchor>,
+    Vec<(Txid, ConfirmationHeightAnchor)>,
+) {
+    let mut graph = TxGraph::default();
+    let mut anchors = Vec::with_capacity(TX_COUNT as usize);
+    for i in 0..TX_COUNT {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&i.to_le_bytes()), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        let _ = graph.insert_tx(tx);
+        let height = 100 + i / 10;
+        anchors.push((
+            txid,
+            ConfirmationHeightAnchor {
+                anchor_block: bdk_chain::BlockId {
+                    height,
+                    hash: BlockHash::hash(&height.to_le_bytes()),
+                },
+                confirmation_height: height,
+            },
+        ));
+    }
+    (graph, anchors)
+}
+
+fn insert_anchors_one_at_a_time(c: &mut Criterion) {
+    let (graph, anchors) = setup();
+    c.bench_function("insert_anchor for 10k transactions", move |b| {
+        b.iter_batched(
+            || (graph.clone(), anchors.clone()),
+            |(mut graph, anchors)| {
+                let mut changeset = ChangeSet::default();
+                for (txid, anchor) in anchors {
+                    changeset.append(graph.insert_anchor(txid, anchor));
+                }
+                black_box(changeset)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn batch_insert_anchors(c: &mut Criterion) {
+    let (graph, anchors) = setup();
+    c.bench_function("batch_insert_anchors for 10k transactions", move |b| {
+        b.iter_batched(
+            || (graph.clone(), anchors.clone()),
+            |(mut graph, anchors)| black_box(graph.batch_insert_anchors(anchors)),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, insert_anchors_one_at_a_time, batch_insert_anchors);
+criterion_main!(benches);
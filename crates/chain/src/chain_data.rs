@@ -72,6 +72,14 @@ impl ConfirmationTime {
     pub fn is_confirmed(&self) -> bool {
         matches!(self, Self::Confirmed { .. })
     }
+
+    /// Returns the confirmation height, or `None` if unconfirmed.
+    pub fn confirmation_height(&self) -> Option<u32> {
+        match self {
+            Self::Confirmed { height, .. } => Some(*height),
+            Self::Unconfirmed { .. } => None,
+        }
+    }
 }
 
 impl From<ChainPosition<ConfirmationTimeHeightAnchor>> for ConfirmationTime {
@@ -0,0 +1,31 @@
+use crate::collections::BTreeMap;
+use bitcoin::FeeRate;
+
+/// Represents a source of fee rate estimates, such as a chain source or a fee oracle.
+///
+/// This lets code picking fee rates work with any source. `BTreeMap<u16, FeeRate>` implements it
+/// for estimates fetched ahead of time, e.g. with the batch fee methods of chain sources.
+pub trait FeeEstimator {
+    /// Estimate the fee rate needed for a transaction to confirm within `target` blocks.
+    ///
+    /// Returns `None` if no estimate is available for `target`.
+    fn estimate(&self, target: u16) -> Option<FeeRate>;
+}
+
+/// Estimates keyed by confirmation target.
+///
+/// The estimate of the largest target not above the requested one is used, as a fee rate that
+/// confirms within fewer blocks also confirms within `target` blocks.
+impl FeeEstimator for BTreeMap<u16, FeeRate> {
+    fn estimate(&self, target: u16) -> Option<FeeRate> {
+        self.range(..=target)
+            .next_back()
+            .map(|(_, &fee_rate)| fee_rate)
+    }
+}
+
+impl<E: FeeEstimator + ?Sized> FeeEstimator for &E {
+    fn estimate(&self, target: u16) -> Option<FeeRate> {
+        (**self).estimate(target)
+    }
+}
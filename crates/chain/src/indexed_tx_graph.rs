@@ -111,6 +111,16 @@ where
         self.graph.insert_anchor(txid, anchor).into()
     }
 
+    /// Batch insert `anchors` for their given transactions.
+    ///
+    /// See [`TxGraph::batch_insert_anchors`].
+    pub fn batch_insert_anchors(
+        &mut self,
+        anchors: impl IntoIterator<Item = (Txid, A)>,
+    ) -> ChangeSet<A, I::ChangeSet> {
+        self.graph.batch_insert_anchors(anchors).into()
+    }
+
     /// Insert a unix timestamp of when a transaction is seen in the mempool.
     ///
     /// This is used for transaction conflict resolution in [`TxGraph`] where the transaction with
@@ -145,9 +155,10 @@ where
             if self.index.is_tx_relevant(tx) {
                 let txid = tx.compute_txid();
                 graph.append(self.graph.insert_tx(tx.clone()));
-                for anchor in anchors {
-                    graph.append(self.graph.insert_anchor(txid, anchor));
-                }
+                graph.append(
+                    self.graph
+                        .batch_insert_anchors(anchors.into_iter().map(|anchor| (txid, anchor))),
+                );
             }
         }
 
@@ -310,6 +321,40 @@ impl<A: Anchor, IA: Append> Append for ChangeSet<A, IA> {
     }
 }
 
+/// The layout of [`ChangeSet`] in bdk_chain 0.15.0, whose [`graph`] had no `last_evicted` field.
+///
+/// See [`tx_graph::ChangeSetV0`].
+///
+/// [`graph`]: ChangeSet::graph
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(
+        crate = "serde_crate",
+        bound(
+            deserialize = "A: Ord + serde::Deserialize<'de>, IA: serde::Deserialize<'de>",
+            serialize = "A: Ord + serde::Serialize, IA: serde::Serialize"
+        )
+    )
+)]
+#[must_use]
+pub struct ChangeSetV0<A, IA> {
+    /// [`TxGraph`] changeset.
+    pub graph: tx_graph::ChangeSetV0<A>,
+    /// [`Indexer`] changeset.
+    pub indexer: IA,
+}
+
+impl<A, IA> From<ChangeSetV0<A, IA>> for ChangeSet<A, IA> {
+    fn from(changeset: ChangeSetV0<A, IA>) -> Self {
+        Self {
+            graph: changeset.graph.into(),
+            indexer: changeset.indexer,
+        }
+    }
+}
+
 impl<A, IA: Default> From<tx_graph::ChangeSet<A>> for ChangeSet<A, IA> {
     fn from(graph: tx_graph::ChangeSet<A>) -> Self {
         Self {
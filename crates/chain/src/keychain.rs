@@ -27,10 +27,15 @@ pub struct Balance {
     /// All coinbase outputs not yet matured
     pub immature: Amount,
     /// Unconfirmed UTXOs generated by a wallet tx
+    ///
+    /// Which UTXOs are trusted is decided by the caller, e.g. a wallet trusts the outputs to its
+    /// own change addresses.
     pub trusted_pending: Amount,
     /// Unconfirmed UTXOs received from an external wallet
     pub untrusted_pending: Amount,
     /// Confirmed and immediately spendable balance
+    ///
+    /// This includes coinbase outputs once they have matured.
     pub confirmed: Amount,
 }
 
@@ -259,7 +259,8 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
 
     /// Returns the keychain and keychain index associated with the spk.
     ///
-    /// This calls [`SpkTxOutIndex::index_of_spk`] internally.
+    /// The revealed and lookahead spks of every keychain are found. This calls
+    /// [`SpkTxOutIndex::index_of_spk`] internally, so it doesn't scan the derived spks.
     pub fn index_of_spk(&self, script: &Script) -> Option<&(K, u32)> {
         self.inner.index_of_spk(script)
     }
@@ -563,6 +564,26 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
             .map(|((_, i), spk)| (*i, spk))
     }
 
+    /// Iterate over the spks of all keychains that have at least one txout indexed, in ascending
+    /// order of keychain and derivation index.
+    ///
+    /// Unlike [`is_used`], this ignores spks that were only marked as used with [`mark_used`], and
+    /// includes spks that received a txout but haven't been revealed yet (within the lookahead).
+    ///
+    /// [`is_used`]: Self::is_used
+    /// [`mark_used`]: Self::mark_used
+    pub fn used_spks(&self) -> impl Iterator<Item = KeychainIndexed<K, &Script>> {
+        let mut last_index = Option::<&(K, u32)>::None;
+        self.inner.outpoints().iter().filter_map(move |(index, _)| {
+            if last_index == Some(index) {
+                return None;
+            }
+            last_index = Some(index);
+            let spk = self.inner.spk_at_index(index).expect("must exist");
+            Some((index.clone(), spk))
+        })
+    }
+
     /// Get the next derivation index for `keychain`. The next index is the index after the last revealed
     /// derivation index.
     ///
@@ -0,0 +1,171 @@
+//! Module for storing user-defined labels of transactions, outputs and script pubkeys.
+//!
+//! [`Labels`] is a simple key-value store. Like the other structures of this crate, every mutation
+//! returns a [`ChangeSet`] which can be persisted and applied to recover the store, for example as
+//! part of a [`CombinedChangeSet`].
+//!
+//! [`CombinedChangeSet`]: crate::persist::CombinedChangeSet
+
+use crate::collections::BTreeMap;
+use crate::Append;
+use alloc::string::String;
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+
+/// The entity a label is attached to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LabelKey {
+    /// A transaction.
+    Tx(Txid),
+    /// A transaction output.
+    Output(OutPoint),
+    /// A script pubkey, typically the one of an address.
+    Spk(ScriptBuf),
+}
+
+impl From<Txid> for LabelKey {
+    fn from(txid: Txid) -> Self {
+        Self::Tx(txid)
+    }
+}
+
+impl From<OutPoint> for LabelKey {
+    fn from(outpoint: OutPoint) -> Self {
+        Self::Output(outpoint)
+    }
+}
+
+impl From<ScriptBuf> for LabelKey {
+    fn from(spk: ScriptBuf) -> Self {
+        Self::Spk(spk)
+    }
+}
+
+/// The [`ChangeSet`] represents changes to [`Labels`].
+///
+/// A `None` value means the label was removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde_crate")
+)]
+#[must_use]
+pub struct ChangeSet {
+    /// Changed transaction labels.
+    pub txs: BTreeMap<Txid, Option<String>>,
+    /// Changed output labels.
+    pub outputs: BTreeMap<OutPoint, Option<String>>,
+    /// Changed script pubkey labels.
+    pub spks: BTreeMap<ScriptBuf, Option<String>>,
+}
+
+impl Append for ChangeSet {
+    fn append(&mut self, other: Self) {
+        // Labels are not monotone, later changes replace earlier ones.
+        self.txs.extend(other.txs);
+        self.outputs.extend(other.outputs);
+        self.spks.extend(other.spks);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.txs.is_empty() && self.outputs.is_empty() && self.spks.is_empty()
+    }
+}
+
+impl ChangeSet {
+    fn set(&mut self, key: LabelKey, label: Option<String>) {
+        match key {
+            LabelKey::Tx(txid) => self.txs.insert(txid, label),
+            LabelKey::Output(outpoint) => self.outputs.insert(outpoint, label),
+            LabelKey::Spk(spk) => self.spks.insert(spk, label),
+        };
+    }
+}
+
+/// A store of labels attached to transactions, outputs and script pubkeys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    labels: BTreeMap<LabelKey, String>,
+}
+
+impl Labels {
+    /// Construct [`Labels`] from a [`ChangeSet`].
+    pub fn from_changeset(changeset: ChangeSet) -> Self {
+        let mut labels = Self::default();
+        labels.apply_changeset(changeset);
+        labels
+    }
+
+    /// Apply a [`ChangeSet`] to the store.
+    pub fn apply_changeset(&mut self, changeset: ChangeSet) {
+        let ChangeSet { txs, outputs, spks } = changeset;
+        let changes = txs
+            .into_iter()
+            .map(|(txid, label)| (LabelKey::Tx(txid), label))
+            .chain(
+                outputs
+                    .into_iter()
+                    .map(|(outpoint, label)| (LabelKey::Output(outpoint), label)),
+            )
+            .chain(
+                spks.into_iter()
+                    .map(|(spk, label)| (LabelKey::Spk(spk), label)),
+            );
+        for (key, label) in changes {
+            match label {
+                Some(label) => self.labels.insert(key, label),
+                None => self.labels.remove(&key),
+            };
+        }
+    }
+
+    /// Get a [`ChangeSet`] that recreates the whole store.
+    pub fn initial_changeset(&self) -> ChangeSet {
+        let mut changeset = ChangeSet::default();
+        for (key, label) in &self.labels {
+            changeset.set(key.clone(), Some(label.clone()));
+        }
+        changeset
+    }
+
+    /// Set the label of `key`, replacing any previous label.
+    ///
+    /// The returned [`ChangeSet`] is empty if `key` already had this label.
+    pub fn insert(&mut self, key: impl Into<LabelKey>, label: impl Into<String>) -> ChangeSet {
+        let key = key.into();
+        let label = label.into();
+        let mut changeset = ChangeSet::default();
+        if self.labels.get(&key) != Some(&label) {
+            self.labels.insert(key.clone(), label.clone());
+            changeset.set(key, Some(label));
+        }
+        changeset
+    }
+
+    /// Remove the label of `key`.
+    ///
+    /// The returned [`ChangeSet`] is empty if `key` had no label.
+    pub fn remove(&mut self, key: impl Into<LabelKey>) -> ChangeSet {
+        let key = key.into();
+        let mut changeset = ChangeSet::default();
+        if self.labels.remove(&key).is_some() {
+            changeset.set(key, None);
+        }
+        changeset
+    }
+
+    /// Get the label of `key`.
+    pub fn get(&self, key: &LabelKey) -> Option<&str> {
+        self.labels.get(key).map(String::as_str)
+    }
+
+    /// Iterate over all labels, ordered by [`LabelKey`].
+    pub fn iter(&self) -> impl Iterator<Item = (&LabelKey, &str)> {
+        self.labels.iter().map(|(key, label)| (key, label.as_str()))
+    }
+
+    /// Whether the store has no labels.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
@@ -36,6 +36,8 @@ pub use tx_data_traits::*;
 pub use tx_graph::TxGraph;
 mod chain_oracle;
 pub use chain_oracle::*;
+mod fee_estimator;
+pub use fee_estimator::*;
 
 #[doc(hidden)]
 pub mod example_utils;
@@ -50,6 +52,8 @@ pub use descriptor_ext::{DescriptorExt, DescriptorId};
 mod spk_iter;
 #[cfg(feature = "miniscript")]
 pub use spk_iter::*;
+#[cfg(feature = "labels")]
+pub mod label;
 pub mod persist;
 pub mod spk_client;
 
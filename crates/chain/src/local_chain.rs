@@ -185,6 +185,9 @@ impl CheckPoint {
     /// it. If the height already existed and has a conflicting block hash then it will be purged
     /// along with all block followin it. The returned chain will have a tip of the `block_id`
     /// passed in. Of course, if the `block_id` was already present then this just returns `self`.
+    ///
+    /// The checkpoints don't have to be contiguous or start at genesis. If `block_id` is below
+    /// the lowest checkpoint, it becomes the new lowest one.
     #[must_use]
     pub fn insert(self, block_id: BlockId) -> Self {
         assert_ne!(block_id.height, 0, "cannot insert the genesis block");
@@ -199,19 +202,26 @@ impl CheckPoint {
                 // if we have a conflict we just return the inserted block because the tail is by
                 // implication invalid.
                 tail = vec![];
-                break cp.prev().expect("can't be called on genesis block");
+                break cp.prev();
             }
 
             if cp.height() < block_id.height {
-                break cp;
+                break Some(cp);
             }
 
             tail.push(cp.block_id());
-            cp = cp.prev().expect("will break before genesis block");
+            match cp.prev() {
+                Some(prev) => cp = prev,
+                None => break None,
+            }
         };
 
-        base.extend(core::iter::once(block_id).chain(tail.into_iter().rev()))
-            .expect("tail is in order")
+        let blocks = core::iter::once(block_id).chain(tail.into_iter().rev());
+        let new_cp = match base {
+            Some(base) => base.extend(blocks).ok(),
+            None => CheckPoint::from_block_ids(blocks).ok(),
+        };
+        new_cp.expect("tail is in order")
     }
 
     /// Apply `changeset` to the checkpoint.
@@ -566,6 +576,81 @@ impl LocalChain {
         Ok(changeset)
     }
 
+    /// Removes the checkpoints below `height`, except for the genesis checkpoint.
+    ///
+    /// The tip is never removed either, even if it is below `height`. The returned [`ChangeSet`]
+    /// records the removals so that they can be persisted.
+    ///
+    /// Reorgs at most `tip height - height` blocks deep still find their point of agreement among
+    /// the remaining checkpoints. A deeper reorg is still detected by [`apply_update`] (the genesis
+    /// checkpoint always remains as the point of agreement), but every block above genesis is then
+    /// disconnected and has to be fetched again. See [`prune`] to keep a sparse set of older
+    /// checkpoints instead.
+    ///
+    /// Transactions anchored to a removed checkpoint are no longer confirmed in the eyes of the
+    /// [`ChainOracle`] implementation of [`LocalChain`]. Re-insert the anchor blocks that are still
+    /// needed with [`insert_block`].
+    ///
+    /// [`apply_update`]: Self::apply_update
+    /// [`prune`]: Self::prune
+    /// [`insert_block`]: Self::insert_block
+    pub fn prune_below(&mut self, height: u32) -> ChangeSet {
+        self.prune_where(|cp_height| cp_height >= height)
+    }
+
+    /// Thins out old checkpoints to bound the size of the chain.
+    ///
+    /// Every checkpoint of the `keep_recent` most recent heights is kept. Below that, going
+    /// downwards, the gaps between kept checkpoints are at least 1, 2, 4, 8, ... blocks. They are
+    /// exactly that when the chain has a checkpoint at every height, in which case a chain of `n`
+    /// blocks keeps about `keep_recent + log2(n)` checkpoints. The genesis checkpoint and the tip
+    /// are always kept. The returned [`ChangeSet`] records the removals so that they can be
+    /// persisted.
+    ///
+    /// Reorgs at most `keep_recent` blocks deep are handled exactly as before pruning. Deeper reorgs
+    /// are still detected by [`apply_update`], but the point of agreement becomes the closest
+    /// checkpoint kept below the fork, so the blocks between the two are disconnected and have to
+    /// be fetched again. That distance is roughly how far the fork is below the `keep_recent` most
+    /// recent heights.
+    ///
+    /// The same caveat as for [`prune_below`] applies to transactions anchored to removed
+    /// checkpoints.
+    ///
+    /// [`apply_update`]: Self::apply_update
+    /// [`prune_below`]: Self::prune_below
+    pub fn prune(&mut self, keep_recent: u32) -> ChangeSet {
+        let dense_from = self.tip.height().saturating_sub(keep_recent);
+        let mut next_keep = dense_from;
+        let mut step = 1_u32;
+        self.prune_where(|cp_height| {
+            if cp_height > dense_from {
+                return true;
+            }
+            if cp_height > next_keep {
+                return false;
+            }
+            next_keep = cp_height.saturating_sub(step);
+            step = step.saturating_mul(2);
+            true
+        })
+    }
+
+    /// Removes every checkpoint for which `keep` returns `false`, except for the genesis checkpoint
+    /// and the tip. `keep` is called with the checkpoint heights in descending order.
+    fn prune_where(&mut self, mut keep: impl FnMut(u32) -> bool) -> ChangeSet {
+        let tip_height = self.tip.height();
+        let changeset = self
+            .tip
+            .iter()
+            .map(|cp| cp.height())
+            .filter(|&height| height != 0 && height != tip_height && !keep(height))
+            .map(|height| (height, None))
+            .collect::<ChangeSet>();
+        self.apply_changeset(&changeset)
+            .expect("genesis checkpoint is never removed");
+        changeset
+    }
+
     /// Derives an initial [`ChangeSet`], meaning that it can be applied to an empty chain to
     /// recover the current chain.
     pub fn initial_changeset(&self) -> ChangeSet {
@@ -614,6 +699,23 @@ impl LocalChain {
         self.tip.get(height)
     }
 
+    /// Whether `block_id` is part of this chain.
+    ///
+    /// Returns `Some(true)` if the chain has a checkpoint of the same height and hash,
+    /// `Some(false)` if the checkpoint at that height has a different hash (i.e. the block was
+    /// reorganized out), and `None` if the chain has no checkpoint at that height.
+    ///
+    /// This is the same as calling [`ChainOracle::is_block_in_chain`] with the chain's own [`tip`],
+    /// without the `Result` that never fails for a [`LocalChain`]. The oracle method checks
+    /// `block_id` against the ancestors of any `chain_tip` instead, and returns `None` when that
+    /// `chain_tip` is not part of this chain, even if `block_id` is.
+    ///
+    /// [`tip`]: LocalChain::tip
+    pub fn contains_block(&self, block_id: BlockId) -> Option<bool> {
+        self.get(block_id.height)
+            .map(|cp| cp.hash() == block_id.hash)
+    }
+
     /// Iterate checkpoints over a height range.
     ///
     /// Note that we always iterate checkpoints in reverse height order (iteration starts at tip
@@ -34,6 +34,17 @@ pub struct CombinedChangeSet<K, A> {
     pub indexed_tx_graph: crate::indexed_tx_graph::ChangeSet<A, crate::keychain::ChangeSet<K>>,
     /// Stores the network type of the transaction data.
     pub network: Option<bitcoin::Network>,
+    /// Changes to [`Labels`](crate::label::Labels).
+    ///
+    /// The field only exists with the `labels` feature, so enabling it changes the layout of the
+    /// changeset for formats that don't describe the encoded fields, like the bincode encoding of
+    /// `bdk_file_store`. Files written without the feature must be rewritten with
+    /// `bdk_file_store::Store::migrate`, reading them as [`CombinedChangeSetWithoutLabels`] (or
+    /// [`CombinedChangeSetV0`] if they were written by bdk_chain 0.15.0). Files written with the
+    /// feature can't be read without it.
+    #[cfg(feature = "labels")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub labels: crate::label::ChangeSet,
 }
 
 #[cfg(feature = "miniscript")]
@@ -43,6 +54,8 @@ impl<K, A> core::default::Default for CombinedChangeSet<K, A> {
             chain: core::default::Default::default(),
             indexed_tx_graph: core::default::Default::default(),
             network: None,
+            #[cfg(feature = "labels")]
+            labels: core::default::Default::default(),
         }
     }
 }
@@ -59,9 +72,15 @@ impl<K: Ord, A: crate::Anchor> crate::Append for CombinedChangeSet<K, A> {
             );
             self.network = other.network;
         }
+        #[cfg(feature = "labels")]
+        crate::Append::append(&mut self.labels, other.labels);
     }
 
     fn is_empty(&self) -> bool {
+        #[cfg(feature = "labels")]
+        if !self.labels.is_empty() {
+            return false;
+        }
         self.chain.is_empty() && self.indexed_tx_graph.is_empty() && self.network.is_none()
     }
 }
@@ -103,6 +122,88 @@ impl<K, A> From<crate::keychain::ChangeSet<K>> for CombinedChangeSet<K, A> {
     }
 }
 
+/// The layout of [`CombinedChangeSet`] in bdk_chain 0.15.0, before the `labels` field and the
+/// [`last_evicted`] field of its transaction graph changes were added.
+///
+/// Formats that don't describe the encoded fields, like the bincode encoding of
+/// `bdk_file_store`, can't decode changesets persisted with that layout as a [`CombinedChangeSet`].
+/// Decode them as this type instead and convert them with [`From`], for example with
+/// `bdk_file_store::Store::migrate`.
+///
+/// [`last_evicted`]: crate::tx_graph::ChangeSet::last_evicted
+#[derive(Debug, Clone, PartialEq)]
+#[cfg(feature = "miniscript")]
+#[cfg_attr(
+    feature = "serde",
+    derive(crate::serde::Deserialize, crate::serde::Serialize),
+    serde(
+        crate = "crate::serde",
+        bound(
+            deserialize = "A: Ord + crate::serde::Deserialize<'de>, K: Ord + crate::serde::Deserialize<'de>",
+            serialize = "A: Ord + crate::serde::Serialize, K: Ord + crate::serde::Serialize",
+        ),
+    )
+)]
+pub struct CombinedChangeSetV0<K, A> {
+    /// Changes to the [`LocalChain`](crate::local_chain::LocalChain).
+    pub chain: crate::local_chain::ChangeSet,
+    /// Changes to [`IndexedTxGraph`](crate::indexed_tx_graph::IndexedTxGraph).
+    pub indexed_tx_graph: crate::indexed_tx_graph::ChangeSetV0<A, crate::keychain::ChangeSet<K>>,
+    /// Stores the network type of the transaction data.
+    pub network: Option<bitcoin::Network>,
+}
+
+#[cfg(feature = "miniscript")]
+impl<K, A> From<CombinedChangeSetV0<K, A>> for CombinedChangeSet<K, A> {
+    fn from(changeset: CombinedChangeSetV0<K, A>) -> Self {
+        Self {
+            chain: changeset.chain,
+            indexed_tx_graph: changeset.indexed_tx_graph.into(),
+            network: changeset.network,
+            #[cfg(feature = "labels")]
+            labels: core::default::Default::default(),
+        }
+    }
+}
+
+/// The layout of [`CombinedChangeSet`] without the `labels` feature.
+///
+/// Decode changesets persisted before the `labels` feature was enabled as this type and convert
+/// them with [`From`], see [`CombinedChangeSet::labels`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg(all(feature = "miniscript", feature = "labels"))]
+#[cfg_attr(
+    feature = "serde",
+    derive(crate::serde::Deserialize, crate::serde::Serialize),
+    serde(
+        crate = "crate::serde",
+        bound(
+            deserialize = "A: Ord + crate::serde::Deserialize<'de>, K: Ord + crate::serde::Deserialize<'de>",
+            serialize = "A: Ord + crate::serde::Serialize, K: Ord + crate::serde::Serialize",
+        ),
+    )
+)]
+pub struct CombinedChangeSetWithoutLabels<K, A> {
+    /// Changes to the [`LocalChain`](crate::local_chain::LocalChain).
+    pub chain: crate::local_chain::ChangeSet,
+    /// Changes to [`IndexedTxGraph`](crate::indexed_tx_graph::IndexedTxGraph).
+    pub indexed_tx_graph: crate::indexed_tx_graph::ChangeSet<A, crate::keychain::ChangeSet<K>>,
+    /// Stores the network type of the transaction data.
+    pub network: Option<bitcoin::Network>,
+}
+
+#[cfg(all(feature = "miniscript", feature = "labels"))]
+impl<K, A> From<CombinedChangeSetWithoutLabels<K, A>> for CombinedChangeSet<K, A> {
+    fn from(changeset: CombinedChangeSetWithoutLabels<K, A>) -> Self {
+        Self {
+            chain: changeset.chain,
+            indexed_tx_graph: changeset.indexed_tx_graph,
+            network: changeset.network,
+            labels: core::default::Default::default(),
+        }
+    }
+}
+
 /// A persistence backend for writing and loading changesets.
 ///
 /// `C` represents the changeset; a datatype that records changes made to in-memory data structures
@@ -37,6 +37,17 @@ impl SyncRequest {
         }
     }
 
+    /// Estimate the number of requests needed to process this [`SyncRequest`].
+    ///
+    /// Each script pubkey and txid that is left to be synced against counts as one request, and
+    /// each outpoint as two: one for the transaction it resides in and one to look up its spend.
+    /// Fetching the spending transaction of an outpoint takes further requests, which aren't
+    /// counted, so the actual count may be higher. This can be used as the denominator when
+    /// reporting sync progress.
+    pub fn estimated_request_count(&self) -> usize {
+        self.spks.len() + self.txids.len() + 2 * self.outpoints.len()
+    }
+
     /// Set the [`Script`]s that will be synced against.
     ///
     /// This consumes the [`SyncRequest`] and returns the updated one.
@@ -198,6 +209,8 @@ pub struct FullScanRequest<K> {
     pub chain_tip: CheckPoint,
     /// Iterators of script pubkeys indexed by the keychain index.
     pub spks_by_keychain: BTreeMap<K, Box<dyn Iterator<Item = Indexed<ScriptBuf>> + Send>>,
+    /// The last revealed index of each keychain, used to estimate the size of the scan.
+    last_revealed: BTreeMap<K, u32>,
 }
 
 impl<K: Ord + Clone> FullScanRequest<K> {
@@ -207,6 +220,7 @@ impl<K: Ord + Clone> FullScanRequest<K> {
         Self {
             chain_tip,
             spks_by_keychain: BTreeMap::new(),
+            last_revealed: BTreeMap::new(),
         }
     }
 
@@ -230,9 +244,35 @@ impl<K: Ord + Clone> FullScanRequest<K> {
         for (keychain, spks) in index.all_unbounded_spk_iters() {
             req = req.set_spks_for_keychain(keychain, spks);
         }
+        req.last_revealed = index.last_revealed_indices();
         req
     }
 
+    /// Estimate the number of script pubkey requests needed to process this [`FullScanRequest`]
+    /// with `stop_gap`, which should be the `stop_gap` passed to the chain source.
+    ///
+    /// For each keychain, this assumes that every revealed script pubkey is checked and that the
+    /// scan then ends after `stop_gap` consecutive unused script pubkeys. The actual count is
+    /// larger if script pubkeys past the last revealed index turn out to be used. Last revealed
+    /// indices are only known for requests constructed with [`from_keychain_txout_index`].
+    ///
+    /// A `stop_gap` of 0 is treated as a `stop_gap` of 1, as the chain sources do.
+    ///
+    /// [`from_keychain_txout_index`]: Self::from_keychain_txout_index
+    pub fn estimated_request_count(&self, stop_gap: usize) -> usize {
+        let stop_gap = stop_gap.max(1);
+        self.spks_by_keychain
+            .keys()
+            .map(|keychain| {
+                let revealed = self
+                    .last_revealed
+                    .get(keychain)
+                    .map_or(0, |&index| index as usize + 1);
+                revealed + stop_gap
+            })
+            .sum()
+    }
+
     /// Set the [`Script`]s for a given `keychain`.
     ///
     /// This consumes the [`FullScanRequest`] and returns the updated one.
@@ -313,6 +353,59 @@ impl<K: Ord + Clone> FullScanRequest<K> {
         }
         self
     }
+
+    /// Add a closure that will be called with the [`ScanProgress`] every time a [`Script`] of this
+    /// request is about to be queried by the chain source.
+    ///
+    /// This works the same with blocking and async chain sources, as both pull the scripts from
+    /// this request. Together with [`estimated_request_count`], it can drive a progress bar. The
+    /// same closure is called for every keychain, so any state it captures is shared among them.
+    ///
+    /// This consumes the [`FullScanRequest`] and returns the updated one.
+    ///
+    /// [`estimated_request_count`]: Self::estimated_request_count
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn inspect_scan_progress(
+        mut self,
+        inspect: impl FnMut(ScanProgress<K>) + Send + 'static,
+    ) -> Self
+    where
+        K: Send + 'static,
+    {
+        use std::sync::{Arc, Mutex};
+
+        // the closure and the number of scripts inspected so far
+        let shared = Arc::new(Mutex::new((inspect, 0_usize)));
+        for (keychain, spks) in core::mem::take(&mut self.spks_by_keychain) {
+            let shared = shared.clone();
+            self.spks_by_keychain.insert(
+                keychain.clone(),
+                Box::new(spks.inspect(move |(index, _)| {
+                    let mut shared = shared.lock().expect("must not be poisoned");
+                    let (inspect, spks_inspected) = &mut *shared;
+                    *spks_inspected += 1;
+                    inspect(ScanProgress {
+                        keychain: keychain.clone(),
+                        index: *index,
+                        spks_inspected: *spks_inspected,
+                    })
+                })),
+            );
+        }
+        self
+    }
+}
+
+/// The progress of a full scan, reported by [`FullScanRequest::inspect_scan_progress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanProgress<K> {
+    /// The keychain being scanned.
+    pub keychain: K,
+    /// The index of the script pubkey being queried.
+    pub index: u32,
+    /// The number of script pubkeys queried so far for all keychains, including this one.
+    pub spks_inspected: usize,
 }
 
 /// Data returned from a spk-based blockchain client full scan.
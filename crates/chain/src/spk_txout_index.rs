@@ -266,6 +266,11 @@ impl<I: Clone + Ord + core::fmt::Debug> SpkTxOutIndex<I> {
     }
 
     /// Returns the index associated with the script pubkey.
+    ///
+    /// This is a lookup in the reverse map from script pubkey to index that is kept up to date by
+    /// [`insert_spk`], so its cost doesn't grow linearly with the number of stored script pubkeys.
+    ///
+    /// [`insert_spk`]: Self::insert_spk
     pub fn index_of_spk(&self, script: &Script) -> Option<&I> {
         self.spk_indices.get(script)
     }
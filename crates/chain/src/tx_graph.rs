@@ -93,9 +93,10 @@ use crate::{
     FullTxOut,
 };
 use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use bitcoin::{Amount, OutPoint, Script, SignedAmount, Transaction, TxOut, Txid};
+use bitcoin::{Amount, FeeRate, OutPoint, Script, SignedAmount, Transaction, TxIn, TxOut, Txid};
 use core::fmt::{self, Formatter};
 use core::{
     convert::Infallible,
@@ -113,6 +114,13 @@ pub struct TxGraph<A = ()> {
     txs: HashMap<Txid, (TxNodeInternal, BTreeSet<A>, u64)>,
     spends: BTreeMap<OutPoint, HashSet<Txid>>,
     anchors: BTreeSet<(A, Txid)>,
+    // last time each unconfirmed transaction was found to be evicted from the mempool
+    last_evicted: HashMap<Txid, u64>,
+    // first time each transaction was seen unconfirmed, used to order unconfirmed transactions
+    first_seen: HashMap<Txid, u64>,
+    // sources that provided each transaction, only populated by `insert_tx_from_source`. This is
+    // transient: it is not part of the changesets, so it is lost when the graph is persisted
+    provenance: BTreeMap<Txid, BTreeSet<Source>>,
 
     // This atrocity exists so that `TxGraph::outspends()` can return a reference.
     // FIXME: This can be removed once `HashSet::new` is a const fn.
@@ -125,11 +133,32 @@ impl<A> Default for TxGraph<A> {
             txs: Default::default(),
             spends: Default::default(),
             anchors: Default::default(),
+            last_evicted: Default::default(),
+            first_seen: Default::default(),
+            provenance: Default::default(),
             empty_outspends: Default::default(),
         }
     }
 }
 
+/// Identifies the chain source that provided data to a [`TxGraph`], e.g. `"electrum"` or `"rpc"`.
+///
+/// See [`TxGraph::insert_tx_from_source`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Source(pub String);
+
+impl From<&str> for Source {
+    fn from(name: &str) -> Self {
+        Self(name.into())
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// A transaction node in the [`TxGraph`].
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TxNode<'a, T, A> {
@@ -176,6 +205,41 @@ pub struct CanonicalTx<'a, T, A> {
     pub tx_node: TxNode<'a, T, A>,
 }
 
+/// The reason why a transaction is not canonical, see [`TxGraph::try_canonicalization_reason`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonCanonicalReason {
+    /// The transaction is not in the graph.
+    NotFound,
+    /// Only some outputs of the transaction are known and it isn't anchored in the chain.
+    Partial,
+    /// The transaction is a coinbase that isn't anchored in the chain.
+    CoinbaseNotInChain,
+    /// The transaction, or one of its unconfirmed ancestors, isn't anchored in the chain and was
+    /// evicted from the mempool after it was last seen, see [`TxGraph::insert_evicted_at`].
+    Evicted,
+    /// `spender`, the transaction itself or one of its unconfirmed ancestors, conflicts with
+    /// `conflict`, which is confirmed in the chain.
+    ///
+    /// `conflict` double spends an input of `spender`, or descends from a transaction that does.
+    ConflictConfirmed {
+        /// The transaction or unconfirmed ancestor that conflicts with `conflict`.
+        spender: Txid,
+        /// The conflicting transaction that is confirmed in the chain.
+        conflict: Txid,
+    },
+    /// `spender`, the transaction itself or one of its unconfirmed ancestors, conflicts with the
+    /// unconfirmed `conflict`, which takes precedence because it was seen later (or was seen at the
+    /// same time and has a greater txid).
+    ///
+    /// `conflict` double spends an input of `spender`, or descends from a transaction that does.
+    ConflictUnconfirmed {
+        /// The transaction or unconfirmed ancestor that conflicts with `conflict`.
+        spender: Txid,
+        /// The conflicting transaction that replaces `spender` in the mempool.
+        conflict: Txid,
+    },
+}
+
 /// Errors returned by `TxGraph::calculate_fee`.
 #[derive(Debug, PartialEq, Eq)]
 pub enum CalculateFeeError {
@@ -205,6 +269,30 @@ impl fmt::Display for CalculateFeeError {
 #[cfg(feature = "std")]
 impl std::error::Error for CalculateFeeError {}
 
+/// Error returned by [`TxGraph::try_insert_tx`] when the spent outputs of a transaction are all
+/// known and sum to less than its outputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegativeFeeError {
+    /// The offending transaction.
+    pub txid: Txid,
+    /// The (negative) fee computed from the known prevouts.
+    pub fee: SignedAmount,
+}
+
+impl fmt::Display for NegativeFeeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction {} spends less than it creates and has negative fee: {}",
+            self.txid,
+            self.fee.display_dynamic()
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NegativeFeeError {}
+
 impl<A> TxGraph<A> {
     /// Iterate over all tx outputs known by [`TxGraph`].
     ///
@@ -280,6 +368,22 @@ impl<A> TxGraph<A> {
         }
     }
 
+    /// Whether the graph knows anything about `txid`.
+    ///
+    /// This returns `true` if the graph contains the full transaction, some of its outputs, an
+    /// anchor or a last-seen timestamp for it. Use [`contains_full_tx`] to only check for full
+    /// transactions.
+    ///
+    /// [`contains_full_tx`]: Self::contains_full_tx
+    pub fn contains_tx(&self, txid: Txid) -> bool {
+        self.txs.contains_key(&txid)
+    }
+
+    /// Whether the graph contains the full transaction of `txid`.
+    pub fn contains_full_tx(&self, txid: Txid) -> bool {
+        matches!(self.txs.get(&txid), Some((TxNodeInternal::Whole(_), _, _)))
+    }
+
     /// Obtains a single tx output (if any) at the specified outpoint.
     pub fn get_txout(&self, outpoint: OutPoint) -> Option<&TxOut> {
         match &self.txs.get(&outpoint.txid)?.0 {
@@ -351,6 +455,30 @@ impl<A> TxGraph<A> {
             .map_err(|_| CalculateFeeError::NegativeFee(fee))
     }
 
+    /// Calculates the fee rate of a given transaction, that is its fee (see [`calculate_fee`])
+    /// divided by its weight.
+    ///
+    /// The weight is taken from `tx` as it is, including its witness data, so this is only the
+    /// final fee rate if `tx` is fully signed.
+    ///
+    /// [`calculate_fee`]: Self::calculate_fee
+    pub fn calculate_fee_rate(&self, tx: &Transaction) -> Result<FeeRate, CalculateFeeError> {
+        self.calculate_fee(tx).map(|fee| fee / tx.weight())
+    }
+
+    /// Iterate over full transactions whose prevouts are all known but sum to less than their
+    /// outputs, along with the (negative) fee.
+    ///
+    /// Such transactions can only come from corrupt or malformed data. Transactions with unknown
+    /// prevouts are skipped since their fee can't be determined.
+    pub fn negative_fee_txs(&self) -> impl Iterator<Item = (Txid, SignedAmount)> + '_ {
+        self.full_txs()
+            .filter_map(|tx_node| match self.calculate_fee(tx_node.tx.as_ref()) {
+                Err(CalculateFeeError::NegativeFee(fee)) => Some((tx_node.txid, fee)),
+                _ => None,
+            })
+    }
+
     /// The transactions spending from this output.
     ///
     /// [`TxGraph`] allows conflicting transactions within the graph. Obviously the transactions in
@@ -375,6 +503,24 @@ impl<A> TxGraph<A> {
             .range(start..=end)
             .map(|(outpoint, spends)| (outpoint.vout, spends))
     }
+
+    /// Iterates over all the transactions spending `outpoint`, alongside the index of the input
+    /// that spends it.
+    ///
+    /// This includes conflicting transactions, which cannot all be in the best chain. To only get
+    /// the spend that is in the best chain, use [`spend_of`].
+    ///
+    /// [`spend_of`]: Self::spend_of
+    pub fn all_spends_of(&self, outpoint: OutPoint) -> impl Iterator<Item = (Txid, u32)> + '_ {
+        self.outspends(outpoint).iter().filter_map(move |&txid| {
+            let tx = self.get_tx(txid)?;
+            let vin = tx
+                .input
+                .iter()
+                .position(|txin| txin.previous_output == outpoint)?;
+            Some((txid, vin as u32))
+        })
+    }
 }
 
 impl<A: Clone + Ord> TxGraph<A> {
@@ -463,6 +609,70 @@ impl<A> TxGraph<A> {
     pub fn is_empty(&self) -> bool {
         self.txs.is_empty()
     }
+
+    /// Returns a rough estimate of the heap memory (in bytes) used by the graph.
+    ///
+    /// This sums the approximate cost of stored transactions, floating txouts, anchors and the
+    /// spends index. The result is not byte-perfect (allocator overhead and collection slack are
+    /// ignored) but it scales with the amount of data held, which makes it useful for capacity
+    /// planning and for deciding when to prune.
+    pub fn estimated_memory_usage(&self) -> usize {
+        use core::mem::size_of;
+
+        let txs = self
+            .txs
+            .iter()
+            .map(|(_, (tx_node, anchors, _))| {
+                let node = match tx_node {
+                    TxNodeInternal::Whole(tx) => {
+                        // `Arc` allocates two reference counters alongside the transaction.
+                        2 * size_of::<usize>() + size_of::<Transaction>() + tx_heap_size(tx)
+                    }
+                    TxNodeInternal::Partial(txouts) => txouts
+                        .values()
+                        .map(|txout| {
+                            size_of::<u32>() + size_of::<TxOut>() + txout.script_pubkey.len()
+                        })
+                        .sum(),
+                };
+                size_of::<Txid>()
+                    + size_of::<(TxNodeInternal, BTreeSet<A>, u64)>()
+                    + node
+                    + anchors.len() * size_of::<A>()
+            })
+            .sum::<usize>();
+
+        let spends = self
+            .spends
+            .values()
+            .map(|txids| size_of::<OutPoint>() + txids.len() * size_of::<Txid>())
+            .sum::<usize>();
+
+        let anchors = self.anchors.len() * size_of::<(A, Txid)>();
+
+        txs + spends + anchors
+    }
+}
+
+/// Approximate heap size of the inputs and outputs of `tx`.
+fn tx_heap_size(tx: &Transaction) -> usize {
+    use core::mem::size_of;
+
+    let inputs = tx
+        .input
+        .iter()
+        .map(|txin| {
+            size_of::<TxIn>()
+                + txin.script_sig.len()
+                + txin.witness.iter().map(|w| w.len() + 1).sum::<usize>()
+        })
+        .sum::<usize>();
+    let outputs = tx
+        .output
+        .iter()
+        .map(|txout| size_of::<TxOut>() + txout.script_pubkey.len())
+        .sum::<usize>();
+    inputs + outputs
 }
 
 impl<A: Clone + Ord> TxGraph<A> {
@@ -476,6 +686,7 @@ impl<A: Clone + Ord> TxGraph<A> {
     {
         let mut new_graph = TxGraph::<A2>::default();
         new_graph.apply_changeset(self.initial_changeset().map_anchors(f));
+        new_graph.provenance = self.provenance;
         new_graph
     }
 
@@ -523,6 +734,69 @@ impl<A: Clone + Ord> TxGraph<A> {
         self.apply_update(update)
     }
 
+    /// Inserts the given transaction into [`TxGraph`] if its fee is not negative.
+    ///
+    /// This behaves like [`insert_tx`] but first checks the transaction against the graph. If all
+    /// of its prevouts are known and their values sum to less than its outputs, the transaction is
+    /// not inserted and a [`NegativeFeeError`] is returned. If any prevout is unknown the fee is
+    /// indeterminate and the transaction is inserted.
+    ///
+    /// Prevouts inserted later are not checked retroactively, use [`negative_fee_txs`] for that.
+    ///
+    /// [`insert_tx`]: Self::insert_tx
+    /// [`negative_fee_txs`]: Self::negative_fee_txs
+    pub fn try_insert_tx<T: Into<Arc<Transaction>>>(
+        &mut self,
+        tx: T,
+    ) -> Result<ChangeSet<A>, NegativeFeeError> {
+        let tx = tx.into();
+        if let Err(CalculateFeeError::NegativeFee(fee)) = self.calculate_fee(&tx) {
+            return Err(NegativeFeeError {
+                txid: tx.compute_txid(),
+                fee,
+            });
+        }
+        Ok(self.insert_tx(tx))
+    }
+
+    /// Inserts the given transaction into [`TxGraph`] and records that it was provided by `source`.
+    ///
+    /// The transaction is inserted exactly as with [`insert_tx`]. Additionally, `source` is added to
+    /// the sources returned by [`tx_sources`] for this transaction. This is useful to diagnose
+    /// discrepancies when a graph is combined from multiple chain sources.
+    ///
+    /// Provenance is transient: it is not part of the returned [`ChangeSet`] nor of
+    /// [`initial_changeset`], so it isn't persisted and a graph rebuilt from its changesets has no
+    /// sources. It is still part of the graph, so two graphs that only differ by the sources of
+    /// their transactions are not equal.
+    ///
+    /// [`initial_changeset`]: Self::initial_changeset
+    ///
+    /// [`insert_tx`]: Self::insert_tx
+    /// [`tx_sources`]: Self::tx_sources
+    pub fn insert_tx_from_source<T: Into<Arc<Transaction>>>(
+        &mut self,
+        tx: T,
+        source: Source,
+    ) -> ChangeSet<A> {
+        let tx = tx.into();
+        let txid = tx.compute_txid();
+        let changeset = self.insert_tx(tx);
+        self.provenance.entry(txid).or_default().insert(source);
+        changeset
+    }
+
+    /// Iterate over the sources that provided the transaction of `txid`.
+    ///
+    /// Only sources recorded with [`insert_tx_from_source`] (including those of updates applied
+    /// with [`apply_update`]) are returned.
+    ///
+    /// [`insert_tx_from_source`]: Self::insert_tx_from_source
+    /// [`apply_update`]: Self::apply_update
+    pub fn tx_sources(&self, txid: Txid) -> impl Iterator<Item = &Source> {
+        self.provenance.get(&txid).into_iter().flatten()
+    }
+
     /// Batch insert unconfirmed transactions.
     ///
     /// Items of `txs` are tuples containing the transaction and a *last seen* timestamp. The
@@ -550,9 +824,31 @@ impl<A: Clone + Ord> TxGraph<A> {
         self.apply_update(update)
     }
 
+    /// Batch insert the given `anchors` into [`TxGraph`].
+    ///
+    /// This inserts the anchors directly, without going through an update graph for each of them
+    /// like [`insert_anchor`] does. The returned [`ChangeSet`] is the same as the one obtained by
+    /// appending the changesets of the individual insertions.
+    ///
+    /// [`insert_anchor`]: Self::insert_anchor
+    pub fn batch_insert_anchors(
+        &mut self,
+        anchors: impl IntoIterator<Item = (Txid, A)>,
+    ) -> ChangeSet<A> {
+        let mut changeset = ChangeSet::default();
+        for (txid, anchor) in anchors {
+            if self.anchors.insert((anchor.clone(), txid)) {
+                let (_, tx_anchors, _) = self.txs.entry(txid).or_default();
+                tx_anchors.insert(anchor.clone());
+                changeset.anchors.insert((anchor, txid));
+            }
+        }
+        changeset
+    }
+
     /// Inserts the given `seen_at` for `txid` into [`TxGraph`].
     ///
-    /// Note that [`TxGraph`] only keeps track of the latest `seen_at`. To batch
+    /// Note that [`TxGraph`] only keeps track of the earliest and the latest `seen_at`. To batch
     /// update all unconfirmed transactions with the latest `seen_at`, see
     /// [`update_last_seen_unconfirmed`].
     ///
@@ -561,6 +857,7 @@ impl<A: Clone + Ord> TxGraph<A> {
         let mut update = Self::default();
         let (_, _, update_last_seen) = update.txs.entry(txid).or_default();
         *update_last_seen = seen_at;
+        update.first_seen.insert(txid, seen_at);
         self.apply_update(update)
     }
 
@@ -568,7 +865,8 @@ impl<A: Clone + Ord> TxGraph<A> {
     ///
     /// This method updates the last seen unconfirmed time for this [`TxGraph`] by inserting
     /// the given `seen_at` for every transaction not yet anchored to a confirmed block,
-    /// and returns the [`ChangeSet`] after applying all updates to `self`.
+    /// and returns the [`ChangeSet`] after applying all updates to `self`. Transactions evicted
+    /// from the mempool (see [`insert_evicted_at`]) are skipped.
     ///
     /// This is useful for keeping track of the latest time a transaction was seen
     /// unconfirmed, which is important for evaluating transaction conflicts in the same
@@ -600,21 +898,20 @@ impl<A: Clone + Ord> TxGraph<A> {
     /// To insert a last seen time for a single txid, see [`insert_seen_at`].
     ///
     /// [`insert_seen_at`]: Self::insert_seen_at
+    /// [`insert_evicted_at`]: Self::insert_evicted_at
     /// [`try_get_chain_position`]: Self::try_get_chain_position
     pub fn update_last_seen_unconfirmed(&mut self, seen_at: u64) -> ChangeSet<A> {
         let mut changeset = ChangeSet::default();
         let unanchored_txs: Vec<Txid> = self
             .txs
             .iter()
-            .filter_map(
-                |(&txid, (_, anchors, _))| {
-                    if anchors.is_empty() {
-                        Some(txid)
-                    } else {
-                        None
-                    }
-                },
-            )
+            .filter_map(|(&txid, (_, anchors, _))| {
+                if anchors.is_empty() && !self.is_evicted(txid) {
+                    Some(txid)
+                } else {
+                    None
+                }
+            })
             .collect();
 
         for txid in unanchored_txs {
@@ -623,14 +920,84 @@ impl<A: Clone + Ord> TxGraph<A> {
         changeset
     }
 
+    /// Inserts the given `evicted_at` for `txid` into [`TxGraph`].
+    ///
+    /// The transaction is considered evicted from the mempool, and so isn't canonical, as long as
+    /// it isn't anchored in the best chain and hasn't been seen after `evicted_at`. Note that
+    /// [`TxGraph`] only keeps track of the latest `evicted_at`.
+    pub fn insert_evicted_at(&mut self, txid: Txid, evicted_at: u64) -> ChangeSet<A> {
+        let mut update = Self::default();
+        update.last_evicted.insert(txid, evicted_at);
+        self.apply_update(update)
+    }
+
+    /// Evict the unconfirmed transactions last seen before `evicted_at`, along with their
+    /// unconfirmed descendants.
+    ///
+    /// This is useful to drop transactions that are no longer in the mempool, for example the ones
+    /// not seen for a few days. Transactions with an anchor are never evicted, and neither are the
+    /// ones with a descendant seen at or after `evicted_at`, since the descendant can only be in
+    /// the mempool along with them. See [`insert_evicted_at`] for how evicted transactions are
+    /// treated.
+    ///
+    /// [`insert_evicted_at`]: Self::insert_evicted_at
+    pub fn evict_unconfirmed_before(&mut self, evicted_at: u64) -> ChangeSet<A> {
+        let is_unanchored = |txid: Txid| match self.txs.get(&txid) {
+            Some((TxNodeInternal::Whole(_), anchors, _)) => anchors.is_empty(),
+            _ => false,
+        };
+        let last_seen = |txid: &Txid| self.txs.get(txid).map_or(0, |(_, _, ls)| *ls);
+
+        let mut to_evict = HashSet::<Txid>::new();
+        for &txid in self.txs.keys() {
+            if !is_unanchored(txid) || last_seen(&txid) >= evicted_at || self.is_evicted(txid) {
+                continue;
+            }
+            let descendants = self
+                .walk_descendants(txid, move |_, txid| {
+                    Some(txid).filter(|&txid| is_unanchored(txid))
+                })
+                .collect::<Vec<_>>();
+            if descendants.iter().all(|txid| last_seen(txid) < evicted_at) {
+                to_evict.insert(txid);
+                to_evict.extend(descendants);
+            }
+        }
+
+        let mut changeset = ChangeSet::default();
+        for txid in to_evict {
+            changeset.append(self.insert_evicted_at(txid, evicted_at));
+        }
+        changeset
+    }
+
+    // Whether the transaction was evicted after it, or any of its descendants, was last seen
+    fn is_evicted(&self, txid: Txid) -> bool {
+        let last_seen = TxDescendants::new_include_root(self, txid, |_, txid| {
+            self.txs.get(&txid).map(|(_, _, last_seen)| *last_seen)
+        })
+        .max()
+        .unwrap_or(0);
+        self.is_evicted_after(txid, last_seen)
+    }
+
+    // Whether the transaction was evicted after `last_seen`
+    fn is_evicted_after(&self, txid: Txid, last_seen: u64) -> bool {
+        matches!(self.last_evicted.get(&txid), Some(&evicted_at) if evicted_at > last_seen)
+    }
+
     /// Extends this graph with another so that `self` becomes the union of the two sets of
     /// transactions.
     ///
     /// The returned [`ChangeSet`] is the set difference between `update` and `self` (transactions that
     /// exist in `update` but not in `self`).
-    pub fn apply_update(&mut self, update: TxGraph<A>) -> ChangeSet<A> {
+    pub fn apply_update(&mut self, mut update: TxGraph<A>) -> ChangeSet<A> {
+        let provenance = core::mem::take(&mut update.provenance);
         let changeset = self.determine_changeset(update);
         self.apply_changeset(changeset.clone());
+        for (txid, sources) in provenance {
+            self.provenance.entry(txid).or_default().extend(sources);
+        }
         changeset
     }
 
@@ -700,6 +1067,20 @@ impl<A: Clone + Ord> TxGraph<A> {
                 *last_seen = new_last_seen;
             }
         }
+
+        for (txid, new_last_evicted) in changeset.last_evicted {
+            let last_evicted = self.last_evicted.entry(txid).or_default();
+            if new_last_evicted > *last_evicted {
+                *last_evicted = new_last_evicted;
+            }
+        }
+
+        for (txid, new_first_seen) in changeset.first_seen {
+            let first_seen = self.first_seen.entry(txid).or_insert(new_first_seen);
+            if new_first_seen < *first_seen {
+                *first_seen = new_first_seen;
+            }
+        }
     }
 
     /// Previews the resultant [`ChangeSet`] when [`Self`] is updated against the `update` graph.
@@ -752,6 +1133,22 @@ impl<A: Clone + Ord> TxGraph<A> {
 
         changeset.anchors = update.anchors.difference(&self.anchors).cloned().collect();
 
+        for (&txid, &update_last_evicted) in &update.last_evicted {
+            if self.last_evicted.get(&txid) < Some(&update_last_evicted) {
+                changeset.last_evicted.insert(txid, update_last_evicted);
+            }
+        }
+
+        for (&txid, &update_first_seen) in &update.first_seen {
+            if self
+                .first_seen
+                .get(&txid)
+                .map_or(true, |&first_seen| update_first_seen < first_seen)
+            {
+                changeset.first_seen.insert(txid, update_first_seen);
+            }
+        }
+
         changeset
     }
 }
@@ -786,14 +1183,64 @@ impl<A: Anchor> TxGraph<A> {
         chain_tip: BlockId,
         txid: Txid,
     ) -> Result<Option<ChainPosition<&A>>, C::Error> {
+        Ok(self
+            .try_chain_position_or_reason(chain, chain_tip, txid)?
+            .ok())
+    }
+
+    /// Get the reason why the transaction is not canonical in `chain` with tip `chain_tip`.
+    ///
+    /// This returns `Ok(None)` if the transaction is canonical, i.e. if [`try_get_chain_position`]
+    /// returns a position for it. Otherwise the returned [`NonCanonicalReason`] explains which of
+    /// the rules described in [`try_get_chain_position`] excluded the transaction.
+    ///
+    /// # Error
+    ///
+    /// An error will occur if the [`ChainOracle`] implementation (`chain`) fails. If the
+    /// [`ChainOracle`] is infallible, [`canonicalization_reason`] can be used instead.
+    ///
+    /// [`try_get_chain_position`]: Self::try_get_chain_position
+    /// [`canonicalization_reason`]: Self::canonicalization_reason
+    pub fn try_canonicalization_reason<C: ChainOracle>(
+        &self,
+        chain: &C,
+        chain_tip: BlockId,
+        txid: Txid,
+    ) -> Result<Option<NonCanonicalReason>, C::Error> {
+        Ok(self
+            .try_chain_position_or_reason(chain, chain_tip, txid)?
+            .err())
+    }
+
+    /// Get the reason why the transaction is not canonical in `chain` with tip `chain_tip`.
+    ///
+    /// This is the infallible version of [`try_canonicalization_reason`].
+    ///
+    /// [`try_canonicalization_reason`]: Self::try_canonicalization_reason
+    pub fn canonicalization_reason<C: ChainOracle<Error = Infallible>>(
+        &self,
+        chain: &C,
+        chain_tip: BlockId,
+        txid: Txid,
+    ) -> Option<NonCanonicalReason> {
+        self.try_canonicalization_reason(chain, chain_tip, txid)
+            .expect("error is infallible")
+    }
+
+    fn try_chain_position_or_reason<C: ChainOracle>(
+        &self,
+        chain: &C,
+        chain_tip: BlockId,
+        txid: Txid,
+    ) -> Result<Result<ChainPosition<&A>, NonCanonicalReason>, C::Error> {
         let (tx_node, anchors, last_seen) = match self.txs.get(&txid) {
             Some(v) => v,
-            None => return Ok(None),
+            None => return Ok(Err(NonCanonicalReason::NotFound)),
         };
 
         for anchor in anchors {
             match chain.is_block_in_chain(anchor.anchor_block(), chain_tip)? {
-                Some(true) => return Ok(Some(ChainPosition::Confirmed(anchor))),
+                Some(true) => return Ok(Ok(ChainPosition::Confirmed(anchor))),
                 _ => continue,
             }
         }
@@ -806,13 +1253,13 @@ impl<A: Anchor> TxGraph<A> {
                 // A coinbase tx that is not anchored in the best chain cannot be unconfirmed and
                 // should always be filtered out.
                 if tx.is_coinbase() {
-                    return Ok(None);
+                    return Ok(Err(NonCanonicalReason::CoinbaseNotInChain));
                 }
                 tx.clone()
             }
             TxNodeInternal::Partial(_) => {
                 // Partial transactions (outputs only) cannot have conflicts.
-                return Ok(None);
+                return Ok(Err(NonCanonicalReason::Partial));
             }
         };
 
@@ -867,6 +1314,16 @@ impl<A: Anchor> TxGraph<A> {
             .map(|tx| tx.last_seen_unconfirmed)
             .expect("descendants always includes at least one transaction (the root tx");
 
+        // A transaction evicted after it, or any of its descendants, was last seen is no longer in
+        // the mempool, and neither are its descendants. Our tx can only have been seen along with
+        // its unconfirmed ancestors, so an ancestor counts as seen at our `tx_last_seen` too.
+        for tx_node in &unconfirmed_ancestor_txs {
+            let last_seen = tx_node.last_seen_unconfirmed.max(tx_last_seen);
+            if self.is_evicted_after(tx_node.txid, last_seen) {
+                return Ok(Err(NonCanonicalReason::Evicted));
+            }
+        }
+
         // Now we traverse our ancestors and consider all their conflicts
         for tx_node in unconfirmed_ancestor_txs {
             // We retrieve all the transactions conflicting with this specific ancestor
@@ -878,22 +1335,34 @@ impl<A: Anchor> TxGraph<A> {
             for conflicting_tx in conflicting_txs {
                 for block in conflicting_tx.anchors {
                     if chain.is_block_in_chain(block.anchor_block(), chain_tip)? == Some(true) {
-                        return Ok(None);
+                        return Ok(Err(NonCanonicalReason::ConflictConfirmed {
+                            spender: tx_node.txid,
+                            conflict: conflicting_tx.txid,
+                        }));
                     }
                 }
+                if self.is_evicted(conflicting_tx.txid) {
+                    continue;
+                }
                 if conflicting_tx.last_seen_unconfirmed > tx_last_seen {
-                    return Ok(None);
+                    return Ok(Err(NonCanonicalReason::ConflictUnconfirmed {
+                        spender: tx_node.txid,
+                        conflict: conflicting_tx.txid,
+                    }));
                 }
                 if conflicting_tx.last_seen_unconfirmed == *last_seen
                     && conflicting_tx.as_ref().compute_txid() > tx.as_ref().compute_txid()
                 {
                     // Conflicting tx has priority if txid of conflicting tx > txid of original tx
-                    return Ok(None);
+                    return Ok(Err(NonCanonicalReason::ConflictUnconfirmed {
+                        spender: tx_node.txid,
+                        conflict: conflicting_tx.txid,
+                    }));
                 }
             }
         }
 
-        Ok(Some(ChainPosition::Unconfirmed(*last_seen)))
+        Ok(Ok(ChainPosition::Unconfirmed(*last_seen)))
     }
 
     /// Get the position of the transaction in `chain` with tip `chain_tip`.
@@ -961,6 +1430,51 @@ impl<A: Anchor> TxGraph<A> {
             .expect("error is infallible")
     }
 
+    /// Get the transaction in the `chain` of `chain_tip` that spends `outpoint`, alongside the index
+    /// of the input that spends it.
+    ///
+    /// If no in-chain transaction spends `outpoint`, `None` will be returned. To get all the
+    /// (possibly conflicting) spends of `outpoint` instead, use [`all_spends_of`].
+    ///
+    /// # Error
+    ///
+    /// An error will occur only if the [`ChainOracle`] implementation (`chain`) fails.
+    ///
+    /// If the [`ChainOracle`] is infallible, [`spend_of`] can be used instead.
+    ///
+    /// [`all_spends_of`]: Self::all_spends_of
+    /// [`spend_of`]: Self::spend_of
+    pub fn try_spend_of<C: ChainOracle>(
+        &self,
+        chain: &C,
+        chain_tip: BlockId,
+        outpoint: OutPoint,
+    ) -> Result<Option<(Txid, u32)>, C::Error> {
+        let txid = match self.try_get_chain_spend(chain, chain_tip, outpoint)? {
+            Some((_, txid)) => txid,
+            None => return Ok(None),
+        };
+        Ok(self
+            .all_spends_of(outpoint)
+            .find(|&(spend_txid, _)| spend_txid == txid))
+    }
+
+    /// Get the transaction in the `chain` of `chain_tip` that spends `outpoint`, alongside the index
+    /// of the input that spends it.
+    ///
+    /// This is the infallible version of [`try_spend_of`].
+    ///
+    /// [`try_spend_of`]: Self::try_spend_of
+    pub fn spend_of<C: ChainOracle<Error = Infallible>>(
+        &self,
+        chain: &C,
+        chain_tip: BlockId,
+        outpoint: OutPoint,
+    ) -> Option<(Txid, u32)> {
+        self.try_spend_of(chain, chain_tip, outpoint)
+            .expect("oracle is infallible")
+    }
+
     /// List graph transactions that are in `chain` with `chain_tip`.
     ///
     /// Each transaction is represented as a [`CanonicalTx`] that contains where the transaction is
@@ -1005,6 +1519,58 @@ impl<A: Anchor> TxGraph<A> {
             .map(|r| r.expect("oracle is infallible"))
     }
 
+    /// List graph transactions that are in `chain` with `chain_tip`, in a stable order.
+    ///
+    /// Confirmed transactions come first, ordered by confirmation height, followed by unconfirmed
+    /// transactions ordered by the time they were first seen in the mempool (see
+    /// [`insert_seen_at`]). Transactions at the same position are ordered by txid, since the
+    /// position within a block is not known.
+    ///
+    /// Unlike [`try_list_chain_txs`], this collects the transactions, so the first error of the
+    /// `chain` oracle is returned.
+    ///
+    /// [`insert_seen_at`]: Self::insert_seen_at
+    /// [`try_list_chain_txs`]: Self::try_list_chain_txs
+    pub fn try_canonical_txs_ordered<'a, C: ChainOracle + 'a>(
+        &'a self,
+        chain: &'a C,
+        chain_tip: BlockId,
+    ) -> Result<Vec<CanonicalTx<'a, Arc<Transaction>, A>>, C::Error> {
+        let mut txs = self
+            .try_list_chain_txs(chain, chain_tip)
+            .collect::<Result<Vec<_>, _>>()?;
+        txs.sort_by_key(|tx| {
+            let position = match tx.chain_position {
+                ChainPosition::Confirmed(anchor) => {
+                    (0, anchor.confirmation_height_upper_bound() as u64)
+                }
+                ChainPosition::Unconfirmed(last_seen) => (
+                    1,
+                    self.first_seen
+                        .get(&tx.tx_node.txid)
+                        .copied()
+                        .unwrap_or(last_seen),
+                ),
+            };
+            (position, tx.tx_node.txid)
+        });
+        Ok(txs)
+    }
+
+    /// List graph transactions that are in `chain` with `chain_tip`, in a stable order.
+    ///
+    /// This is the infallible version of [`try_canonical_txs_ordered`].
+    ///
+    /// [`try_canonical_txs_ordered`]: Self::try_canonical_txs_ordered
+    pub fn canonical_txs_ordered<'a, C: ChainOracle<Error = Infallible> + 'a>(
+        &'a self,
+        chain: &'a C,
+        chain_tip: BlockId,
+    ) -> Vec<CanonicalTx<'a, Arc<Transaction>, A>> {
+        self.try_canonical_txs_ordered(chain, chain_tip)
+            .expect("oracle is infallible")
+    }
+
     /// Get a filtered list of outputs from the given `outpoints` that are in `chain` with
     /// `chain_tip`.
     ///
@@ -1138,6 +1704,13 @@ impl<A: Anchor> TxGraph<A> {
     ///
     /// The output of `trust_predicate` should return `true` for scripts that we trust.
     ///
+    /// Every unspent output is counted in exactly one category of the returned [`Balance`]:
+    ///
+    /// - confirmed coinbase outputs that haven't reached [`COINBASE_MATURITY`] are `immature`
+    /// - all other confirmed outputs are `confirmed`
+    /// - unconfirmed outputs are `trusted_pending` if `trust_predicate` returns `true` for them,
+    ///   and `untrusted_pending` otherwise
+    ///
     /// `outpoints` is a list of outpoints we are interested in, coupled with an outpoint identifier
     /// (`OI`) for convenience. If `OI` is not necessary, the caller can use `()`, or
     /// [`Iterator::enumerate`] over a list of [`OutPoint`]s.
@@ -1146,6 +1719,7 @@ impl<A: Anchor> TxGraph<A> {
     /// used instead.
     ///
     /// [`balance`]: Self::balance
+    /// [`COINBASE_MATURITY`]: crate::COINBASE_MATURITY
     pub fn try_balance<C: ChainOracle, OI: Clone>(
         &self,
         chain: &C,
@@ -1163,9 +1737,9 @@ impl<A: Anchor> TxGraph<A> {
 
             match &txout.chain_position {
                 ChainPosition::Confirmed(_) => {
-                    if txout.is_confirmed_and_spendable(chain_tip.height) {
+                    if txout.is_mature(chain_tip.height) {
                         confirmed += txout.txout.value;
-                    } else if !txout.is_mature(chain_tip.height) {
+                    } else {
                         immature += txout.txout.value;
                     }
                 }
@@ -1234,6 +1808,22 @@ pub struct ChangeSet<A = ()> {
     pub anchors: BTreeSet<(A, Txid)>,
     /// Added last-seen unix timestamps of transactions.
     pub last_seen: BTreeMap<Txid, u64>,
+    /// Added last-evicted unix timestamps of transactions.
+    ///
+    /// This field was added after bdk_chain 0.15.0 and changes the bincode layout of the
+    /// changeset: files written by `bdk_file_store` with the previous layout must be rewritten
+    /// with `bdk_file_store::Store::migrate`, decoding them as [`ChangeSetV0`] (or the
+    /// changesets embedding it), before they can be opened.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_evicted: BTreeMap<Txid, u64>,
+    /// Added first-seen unix timestamps of transactions.
+    ///
+    /// Like [`last_evicted`], this field was added after bdk_chain 0.15.0 and is part of the same
+    /// layout change.
+    ///
+    /// [`last_evicted`]: Self::last_evicted
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub first_seen: BTreeMap<Txid, u64>,
 }
 
 impl<A> Default for ChangeSet<A> {
@@ -1243,6 +1833,8 @@ impl<A> Default for ChangeSet<A> {
             txouts: Default::default(),
             anchors: Default::default(),
             last_seen: Default::default(),
+            last_evicted: Default::default(),
+            first_seen: Default::default(),
         }
     }
 }
@@ -1297,6 +1889,26 @@ impl<A: Ord> Append for ChangeSet<A> {
                 .filter(|(txid, update_ls)| self.last_seen.get(txid) < Some(update_ls))
                 .collect::<Vec<_>>(),
         );
+        // last_evicted timestamps should only increase too
+        self.last_evicted.extend(
+            other
+                .last_evicted
+                .into_iter()
+                .filter(|(txid, update_le)| self.last_evicted.get(txid) < Some(update_le))
+                .collect::<Vec<_>>(),
+        );
+        // first_seen timestamps should only decrease
+        self.first_seen.extend(
+            other
+                .first_seen
+                .into_iter()
+                .filter(|(txid, update_fs)| {
+                    self.first_seen
+                        .get(txid)
+                        .map_or(true, |first_seen| update_fs < first_seen)
+                })
+                .collect::<Vec<_>>(),
+        );
     }
 
     fn is_empty(&self) -> bool {
@@ -1304,6 +1916,8 @@ impl<A: Ord> Append for ChangeSet<A> {
             && self.txouts.is_empty()
             && self.anchors.is_empty()
             && self.last_seen.is_empty()
+            && self.last_evicted.is_empty()
+            && self.first_seen.is_empty()
     }
 }
 
@@ -1323,6 +1937,53 @@ impl<A: Ord> ChangeSet<A> {
                 self.anchors.into_iter().map(|(a, txid)| (f(a), txid)),
             ),
             last_seen: self.last_seen,
+            last_evicted: self.last_evicted,
+            first_seen: self.first_seen,
+        }
+    }
+}
+
+/// The layout of [`ChangeSet`] in bdk_chain 0.15.0, before the [`last_evicted`] and
+/// [`first_seen`] fields were added.
+///
+/// Formats that don't describe the encoded fields, like bincode, can't decode changesets persisted
+/// with that layout as a [`ChangeSet`]. Decode them as this type and convert them with [`From`].
+///
+/// [`last_evicted`]: ChangeSet::last_evicted
+/// [`first_seen`]: ChangeSet::first_seen
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(
+        crate = "serde_crate",
+        bound(
+            deserialize = "A: Ord + serde::Deserialize<'de>",
+            serialize = "A: Ord + serde::Serialize",
+        )
+    )
+)]
+#[must_use]
+pub struct ChangeSetV0<A = ()> {
+    /// Added transactions.
+    pub txs: BTreeSet<Arc<Transaction>>,
+    /// Added txouts.
+    pub txouts: BTreeMap<OutPoint, TxOut>,
+    /// Added anchors.
+    pub anchors: BTreeSet<(A, Txid)>,
+    /// Added last-seen unix timestamps of transactions.
+    pub last_seen: BTreeMap<Txid, u64>,
+}
+
+impl<A> From<ChangeSetV0<A>> for ChangeSet<A> {
+    fn from(changeset: ChangeSetV0<A>) -> Self {
+        Self {
+            txs: changeset.txs,
+            txouts: changeset.txouts,
+            anchors: changeset.anchors,
+            last_seen: changeset.last_seen,
+            last_evicted: Default::default(),
+            first_seen: Default::default(),
         }
     }
 }
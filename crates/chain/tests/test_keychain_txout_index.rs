@@ -328,6 +328,41 @@ fn test_lookahead() {
     }
 }
 
+#[test]
+fn test_index_of_spk() {
+    let external_descriptor = parse_descriptor(DESCRIPTORS[0]);
+    let internal_descriptor = parse_descriptor(DESCRIPTORS[1]);
+    let mut txout_index =
+        init_txout_index(external_descriptor.clone(), internal_descriptor.clone(), 10);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 999);
+
+    // revealed spks
+    for index in [0, 500, 999] {
+        assert_eq!(
+            txout_index.index_of_spk(&spk_at_index(&external_descriptor, index)),
+            Some(&(TestKeychain::External, index))
+        );
+    }
+    // lookahead spks
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&external_descriptor, 1_009)),
+        Some(&(TestKeychain::External, 1_009))
+    );
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&internal_descriptor, 9)),
+        Some(&(TestKeychain::Internal, 9))
+    );
+    // spks past the lookahead are not derived yet
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&external_descriptor, 1_010)),
+        None
+    );
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&internal_descriptor, 10)),
+        None
+    );
+}
+
 // when:
 // - scanning txouts with spks above last stored index
 // expect:
@@ -761,3 +796,148 @@ fn when_querying_over_a_range_of_keychains_the_utxos_should_show_up() {
         (10_000 * (6 - 3 - /*the skipped one*/ 1)) as i64
     );
 }
+
+#[test]
+fn used_spks_only_include_spks_with_txouts() {
+    let external_descriptor = parse_descriptor(DESCRIPTORS[0]);
+    let internal_descriptor = parse_descriptor(DESCRIPTORS[1]);
+    let mut txout_index =
+        init_txout_index(external_descriptor.clone(), internal_descriptor.clone(), 10);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 5);
+    // marking as used doesn't make a spk show up
+    assert!(txout_index.mark_used(TestKeychain::External, 0));
+
+    let mut tx = common::new_tx(0);
+    // spk 2 receives twice, spk 7 is only within the lookahead
+    for (descriptor, index) in [
+        (&external_descriptor, 2),
+        (&external_descriptor, 2),
+        (&external_descriptor, 7),
+        (&internal_descriptor, 1),
+    ] {
+        tx.output.push(TxOut {
+            script_pubkey: spk_at_index(descriptor, index),
+            value: Amount::from_sat(10_000),
+        });
+    }
+    let _ = txout_index.index_tx(&tx);
+
+    assert_eq!(
+        txout_index
+            .used_spks()
+            .map(|(index, spk)| (index, spk.to_owned()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                (TestKeychain::External, 2),
+                spk_at_index(&external_descriptor, 2)
+            ),
+            (
+                (TestKeychain::External, 7),
+                spk_at_index(&external_descriptor, 7)
+            ),
+            (
+                (TestKeychain::Internal, 1),
+                spk_at_index(&internal_descriptor, 1)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn full_scan_request_estimates_request_count_from_revealed_spks() {
+    use bdk_chain::{
+        local_chain::CheckPoint,
+        spk_client::{FullScanRequest, SyncRequest},
+        BlockId,
+    };
+
+    let external_descriptor = parse_descriptor(DESCRIPTORS[0]);
+    let internal_descriptor = parse_descriptor(DESCRIPTORS[1]);
+    let mut txout_index = init_txout_index(external_descriptor, internal_descriptor, 0);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 9);
+
+    let chain_tip = CheckPoint::new(BlockId {
+        height: 0,
+        hash: bitcoin::hashes::Hash::all_zeros(),
+    });
+    let request = FullScanRequest::from_keychain_txout_index(chain_tip.clone(), &txout_index);
+    // 10 revealed external spks, no revealed internal spks, plus the stop gap for each keychain
+    assert_eq!(request.estimated_request_count(20), 10 + 20 + 20);
+    assert_eq!(request.estimated_request_count(5), 10 + 5 + 5);
+    // a stop gap of 0 is treated as 1
+    assert_eq!(request.estimated_request_count(0), 10 + 1 + 1);
+
+    // without an index, only the stop gap of each keychain is known
+    let request = FullScanRequest::from_chain_tip(chain_tip.clone()).set_spks_for_keychain(
+        TestKeychain::External,
+        txout_index
+            .unbounded_spk_iter(&TestKeychain::External)
+            .unwrap(),
+    );
+    assert_eq!(request.estimated_request_count(20), 20);
+
+    // a sync checks exactly the revealed spks
+    let request =
+        SyncRequest::from_chain_tip(chain_tip).populate_with_revealed_spks(&txout_index, ..);
+    assert_eq!(request.estimated_request_count(), 10);
+    // each outpoint takes at least two requests
+    let request = request.chain_outpoints([OutPoint::null()]);
+    assert_eq!(request.estimated_request_count(), 10 + 2);
+}
+
+#[test]
+fn full_scan_request_reports_progress() {
+    use bdk_chain::{
+        local_chain::CheckPoint,
+        spk_client::{FullScanRequest, ScanProgress},
+        BlockId,
+    };
+    use std::sync::{Arc, Mutex};
+
+    let external_descriptor = parse_descriptor(DESCRIPTORS[0]);
+    let internal_descriptor = parse_descriptor(DESCRIPTORS[1]);
+    let txout_index = init_txout_index(external_descriptor, internal_descriptor, 0);
+    let chain_tip = CheckPoint::new(BlockId {
+        height: 0,
+        hash: bitcoin::hashes::Hash::all_zeros(),
+    });
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let mut request = FullScanRequest::from_keychain_txout_index(chain_tip, &txout_index)
+        .inspect_scan_progress({
+            let progress = progress.clone();
+            // the closure is shared by all keychains rather than cloned for each of them
+            let mut calls = 0;
+            move |p| {
+                calls += 1;
+                assert_eq!(p.spks_inspected, calls);
+                progress.lock().unwrap().push(p)
+            }
+        });
+
+    // chain sources query the scripts of each keychain in turn
+    for (keychain, count) in [(TestKeychain::External, 3), (TestKeychain::Internal, 2)] {
+        let spks = request.spks_by_keychain.get_mut(&keychain).unwrap();
+        assert_eq!(spks.take(count).count(), count);
+    }
+
+    let progress = progress.lock().unwrap();
+    assert_eq!(
+        *progress,
+        [
+            (TestKeychain::External, 0, 1),
+            (TestKeychain::External, 1, 2),
+            (TestKeychain::External, 2, 3),
+            (TestKeychain::Internal, 0, 4),
+            (TestKeychain::Internal, 1, 5),
+        ]
+        .into_iter()
+        .map(|(keychain, index, spks_inspected)| ScanProgress {
+            keychain,
+            index,
+            spks_inspected,
+        })
+        .collect::<Vec<_>>()
+    );
+}
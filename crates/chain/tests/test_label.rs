@@ -0,0 +1,43 @@
+#![cfg(feature = "labels")]
+
+#[macro_use]
+mod common;
+
+use bdk_chain::{
+    label::{ChangeSet, LabelKey, Labels},
+    Append,
+};
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+
+#[test]
+fn insert_and_remove_labels() {
+    let txid: Txid = h!("tx");
+    let outpoint = OutPoint::new(h!("tx"), 1);
+    let spk = ScriptBuf::from_bytes(vec![0x51]);
+
+    let mut labels = Labels::default();
+    let mut changeset = ChangeSet::default();
+    changeset.append(labels.insert(txid, "rent"));
+    changeset.append(labels.insert(outpoint, "change"));
+    changeset.append(labels.insert(spk.clone(), "donations"));
+    assert_eq!(labels.get(&LabelKey::Tx(txid)), Some("rent"));
+    assert_eq!(labels.get(&LabelKey::Output(outpoint)), Some("change"));
+    assert_eq!(labels.get(&LabelKey::Spk(spk.clone())), Some("donations"));
+    assert_eq!(labels.iter().count(), 3);
+
+    // setting the same label again is not a change
+    assert!(labels.insert(txid, "rent").is_empty());
+
+    // the changeset recovers the store
+    assert_eq!(Labels::from_changeset(changeset.clone()), labels);
+    assert_eq!(labels.initial_changeset(), changeset);
+
+    // later changes replace earlier ones
+    changeset.append(labels.insert(txid, "rent for may"));
+    changeset.append(labels.remove(spk.clone()));
+    assert!(labels.remove(spk.clone()).is_empty());
+    assert_eq!(labels.get(&LabelKey::Tx(txid)), Some("rent for may"));
+    assert_eq!(labels.get(&LabelKey::Spk(spk.clone())), None);
+    assert_eq!(changeset.spks.get(&spk), Some(&None));
+    assert_eq!(Labels::from_changeset(changeset), labels);
+}
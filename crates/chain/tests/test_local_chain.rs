@@ -7,7 +7,7 @@ use bdk_chain::{
         AlterCheckPointError, ApplyHeaderError, CannotConnectError, ChangeSet, CheckPoint,
         LocalChain, MissingGenesisError,
     },
-    BlockId,
+    BlockId, ChainOracle,
 };
 use bitcoin::{block::Header, hashes::Hash, BlockHash};
 use proptest::prelude::*;
@@ -454,6 +454,144 @@ fn local_chain_disconnect_from() {
     }
 }
 
+#[test]
+fn local_chain_contains_block() {
+    let mut chain = local_chain![(0, h!("_")), (2, h!("B")), (3, h!("C"))];
+    let block_b = BlockId {
+        height: 2,
+        hash: h!("B"),
+    };
+    let block_c = BlockId {
+        height: 3,
+        hash: h!("C"),
+    };
+
+    assert_eq!(chain.contains_block(block_b), Some(true));
+    assert_eq!(chain.contains_block(block_c), Some(true));
+    assert_eq!(
+        chain.contains_block(BlockId {
+            height: 2,
+            hash: h!("not_B")
+        }),
+        Some(false)
+    );
+    assert_eq!(
+        chain.contains_block(BlockId {
+            height: 1,
+            hash: h!("A")
+        }),
+        None
+    );
+
+    // reorg out `C`
+    let update = chain_update![(0, h!("_")), (2, h!("B")), (3, h!("C'"))];
+    chain.apply_update(update).unwrap();
+    assert_eq!(chain.contains_block(block_b), Some(true));
+    assert_eq!(chain.contains_block(block_c), Some(false));
+
+    // the same as checking against the chain's own tip with the oracle, which can't tell once the
+    // given tip is reorged out
+    let tip = chain.tip().block_id();
+    assert_eq!(
+        ChainOracle::is_block_in_chain(&chain, block_c, tip),
+        Ok(Some(false))
+    );
+    assert_eq!(
+        ChainOracle::is_block_in_chain(&chain, block_b, block_c),
+        Ok(None)
+    );
+}
+
+#[test]
+fn local_chain_prune_below() {
+    let block = |height: u32| BlockId {
+        height,
+        hash: BlockHash::hash(&height.to_be_bytes()),
+    };
+    let mut chain = LocalChain::from_blocks((0..=1000).map(|h| (h, block(h).hash)).collect())
+        .expect("must have genesis");
+    let init_changeset = chain.initial_changeset();
+
+    let changeset = chain.prune_below(900);
+    assert_eq!(
+        changeset,
+        (1..900).map(|h| (h, None)).collect::<ChangeSet>()
+    );
+    assert_eq!(
+        chain
+            .iter_checkpoints()
+            .map(|cp| cp.height())
+            .collect::<Vec<_>>(),
+        (900..=1000).rev().chain([0]).collect::<Vec<_>>()
+    );
+
+    // the pruned chain can be recovered from the persisted changesets
+    let mut persisted = init_changeset;
+    persisted.extend(changeset);
+    assert_eq!(LocalChain::from_changeset(persisted), Ok(chain.clone()));
+
+    // the tip and genesis are never pruned
+    let mut short_chain = local_chain![(0, h!("_")), (5, h!("E"))];
+    assert!(short_chain.prune_below(10).is_empty());
+    assert_eq!(short_chain, local_chain![(0, h!("_")), (5, h!("E"))]);
+
+    // a reorg within the kept checkpoints can still be applied
+    let update = CheckPoint::from_block_ids([
+        block(949),
+        (950, h!("reorged_950")).into(),
+        (951, h!("reorged_951")).into(),
+    ])
+    .expect("blocks must be in order");
+    let changeset = chain.apply_update(update).expect("update must connect");
+    assert_eq!(changeset.get(&950), Some(&Some(h!("reorged_950"))));
+    assert_eq!(changeset.get(&1000), Some(&None));
+    assert_eq!(chain.tip().block_id(), (951, h!("reorged_951")).into());
+}
+
+#[test]
+fn local_chain_prune() {
+    let block = |height: u32| BlockId {
+        height,
+        hash: BlockHash::hash(&height.to_be_bytes()),
+    };
+    let mut chain = LocalChain::from_blocks((0..=10_000).map(|h| (h, block(h).hash)).collect())
+        .expect("must have genesis");
+
+    let changeset = chain.prune(144);
+    assert!(changeset.values().all(Option::is_none));
+    let heights = chain
+        .iter_checkpoints()
+        .map(|cp| cp.height())
+        .collect::<Vec<_>>();
+    assert_eq!(heights.len() + changeset.len(), 10_001);
+    // every recent block is kept
+    assert!(heights.starts_with(&(9_856..=10_000).rev().collect::<Vec<_>>()));
+    assert_eq!(heights.last(), Some(&0));
+    // older checkpoints get exponentially sparser
+    assert!(heights.len() < 144 + 20);
+    let gaps = heights[144..]
+        .windows(2)
+        .map(|w| w[0] - w[1])
+        .collect::<Vec<_>>();
+    assert!(gaps[..gaps.len() - 1].windows(2).all(|w| w[1] == 2 * w[0]));
+
+    // pruning again doesn't remove anything else
+    assert!(chain.prune(144).is_empty());
+
+    // a reorg below the recent checkpoints connects at the closest kept checkpoint
+    let fork_index = heights.iter().position(|&h| h < 9_000).unwrap();
+    let (fork_height, kept_below_fork) = (heights[fork_index - 1], heights[fork_index]);
+    let update =
+        CheckPoint::from_block_ids([block(kept_below_fork), (fork_height, h!("reorged")).into()])
+            .expect("blocks must be in order");
+    chain.apply_update(update).expect("update must connect");
+    assert_eq!(chain.tip().block_id(), (fork_height, h!("reorged")).into());
+    assert_eq!(
+        chain.get(kept_below_fork).map(|cp| cp.block_id()),
+        Some(block(kept_below_fork))
+    );
+}
+
 #[test]
 fn checkpoint_from_block_ids() {
     struct TestCase<'a> {
@@ -625,6 +763,42 @@ fn checkpoint_insert() {
         },
     ];
 
+    for (i, t) in test_cases.iter().enumerate() {
+        println!("Running [{}] '{}' without genesis", i, t.name);
+
+        // the checkpoints don't have to reach down to genesis
+        let chain = CheckPoint::from_block_ids(t.chain.iter().copied().map(BlockId::from))
+            .expect("test formed incorrectly, must construct checkpoint chain");
+        let exp_final_chain =
+            CheckPoint::from_block_ids(t.exp_final_chain.iter().copied().map(BlockId::from))
+                .expect("test formed incorrectly, must construct checkpoint chain");
+
+        assert_eq!(
+            chain.insert(t.to_insert.into()),
+            exp_final_chain,
+            "unexpected final chain"
+        );
+    }
+
+    let sparse_chain =
+        CheckPoint::from_block_ids([(5, h!("e")), (10, h!("j"))].into_iter().map(BlockId::from))
+            .unwrap();
+    assert_eq!(
+        sparse_chain.clone().insert((2, h!("b")).into()),
+        CheckPoint::from_block_ids(
+            [(2, h!("b")), (5, h!("e")), (10, h!("j"))]
+                .into_iter()
+                .map(BlockId::from)
+        )
+        .unwrap(),
+        "inserting below the lowest checkpoint must not panic"
+    );
+    assert_eq!(
+        sparse_chain.insert((5, h!("E")).into()),
+        CheckPoint::new((5, h!("E")).into()),
+        "replacing the lowest checkpoint must not panic"
+    );
+
     fn genesis_block() -> impl Iterator<Item = BlockId> {
         core::iter::once((0, h!("_"))).map(BlockId::from)
     }
@@ -650,6 +824,50 @@ fn checkpoint_insert() {
     }
 }
 
+#[test]
+fn local_chain_with_gaps() {
+    let chain = local_chain![(0, h!("_")), (3, h!("C")), (7, h!("G"))];
+    let tip = chain.tip().block_id();
+
+    // heights in a gap are unknown rather than not in chain
+    assert_eq!(chain.get(5).map(|cp| cp.height()), None);
+    assert_eq!(chain.contains_block((5, h!("E")).into()), None);
+    assert_eq!(
+        ChainOracle::is_block_in_chain(&chain, (5, h!("E")).into(), tip),
+        Ok(None)
+    );
+    assert_eq!(chain.contains_block((3, h!("C")).into()), Some(true));
+    assert_eq!(
+        ChainOracle::is_block_in_chain(&chain, (3, h!("C'")).into(), tip),
+        Ok(Some(false))
+    );
+    // a chain tip in a gap can't be identified
+    assert_eq!(
+        ChainOracle::is_block_in_chain(&chain, (3, h!("C")).into(), (5, h!("E")).into()),
+        Ok(None)
+    );
+
+    // a reorg is detected through the checkpoints the chain and update share, even when both
+    // have gaps at different heights
+    let mut chain = chain;
+    let changeset = chain
+        .apply_update(chain_update![
+            (0, h!("_")),
+            (3, h!("C")),
+            (6, h!("F'")),
+            (7, h!("G'"))
+        ])
+        .unwrap();
+    assert_eq!(changeset, [(6, Some(h!("F'"))), (7, Some(h!("G'")))].into());
+    assert_eq!(
+        chain
+            .iter_checkpoints()
+            .map(|cp| cp.height())
+            .collect::<Vec<_>>(),
+        vec![7, 6, 3, 0]
+    );
+}
+
 #[test]
 fn local_chain_apply_header_connected_to() {
     fn header_from_prev_blockhash(prev_blockhash: BlockHash) -> Header {
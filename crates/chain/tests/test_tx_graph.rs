@@ -2,11 +2,11 @@
 
 #[macro_use]
 mod common;
-use bdk_chain::tx_graph::CalculateFeeError;
+use bdk_chain::tx_graph::{CalculateFeeError, NegativeFeeError, NonCanonicalReason};
 use bdk_chain::{
     collections::*,
     local_chain::LocalChain,
-    tx_graph::{ChangeSet, TxGraph},
+    tx_graph::{ChangeSet, Source, TxGraph},
     Anchor, Append, BlockId, ChainOracle, ChainPosition, ConfirmationHeightAnchor,
 };
 use bitcoin::{
@@ -105,7 +105,9 @@ fn insert_txouts() {
                     txs: [].into(),
                     txouts: [].into(),
                     anchors: [(unconf_anchor, outpoint.txid)].into(),
-                    last_seen: [].into()
+                    last_seen: [].into(),
+                    last_evicted: [].into(),
+                    first_seen: [].into(),
                 }
             );
             // Mark them last seen at.
@@ -115,7 +117,9 @@ fn insert_txouts() {
                     txs: [].into(),
                     txouts: [].into(),
                     anchors: [].into(),
-                    last_seen: [(outpoint.txid, 1000000)].into()
+                    last_seen: [(outpoint.txid, 1000000)].into(),
+                    last_evicted: [].into(),
+                    first_seen: [(outpoint.txid, 1000000)].into(),
                 }
             );
         }
@@ -135,7 +139,9 @@ fn insert_txouts() {
                 txs: [].into(),
                 txouts: [].into(),
                 anchors: [(conf_anchor, update_txs.compute_txid())].into(),
-                last_seen: [].into()
+                last_seen: [].into(),
+                last_evicted: [].into(),
+                first_seen: [].into(),
             }
         );
         graph
@@ -154,7 +160,9 @@ fn insert_txouts() {
                 (unconf_anchor, h!("tx2"))
             ]
             .into(),
-            last_seen: [(h!("tx2"), 1000000)].into()
+            last_seen: [(h!("tx2"), 1000000)].into(),
+            last_evicted: [].into(),
+            first_seen: [(h!("tx2"), 1000000)].into(),
         }
     );
 
@@ -211,7 +219,9 @@ fn insert_txouts() {
                 (unconf_anchor, h!("tx2"))
             ]
             .into(),
-            last_seen: [(h!("tx2"), 1000000)].into()
+            last_seen: [(h!("tx2"), 1000000)].into(),
+            last_evicted: [].into(),
+            first_seen: [(h!("tx2"), 1000000)].into(),
         }
     );
 }
@@ -503,6 +513,107 @@ fn test_calculate_fee() {
     );
 }
 
+#[test]
+fn test_calculate_fee_rate() {
+    let mut graph = TxGraph::<()>::default();
+    let prev_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    };
+    let _ = graph.insert_tx(prev_tx.clone());
+
+    let mut tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(prev_tx.compute_txid(), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(9_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    };
+    assert_eq!(
+        graph.calculate_fee_rate(&tx),
+        Ok(Amount::from_sat(1_000) / tx.weight())
+    );
+
+    // the witness is accounted for, lowering the fee rate
+    let unsigned_fee_rate = graph.calculate_fee_rate(&tx).unwrap();
+    tx.input[0].witness.push([0x00; 72]);
+    let signed_fee_rate = graph.calculate_fee_rate(&tx).unwrap();
+    assert_eq!(signed_fee_rate, Amount::from_sat(1_000) / tx.weight());
+    assert!(signed_fee_rate < unsigned_fee_rate);
+
+    // errors are the same as `calculate_fee`
+    let outpoint = OutPoint::new(h!("unknown_txid"), 0);
+    tx.input.push(TxIn {
+        previous_output: outpoint,
+        ..Default::default()
+    });
+    assert_eq!(
+        graph.calculate_fee_rate(&tx),
+        Err(CalculateFeeError::MissingTxOut(vec![outpoint]))
+    );
+}
+
+#[test]
+fn test_negative_fee_detection() {
+    let mut graph = TxGraph::<()>::default();
+    // a coinbase, so that it has no fee itself
+    let prev_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![TxOut {
+            value: Amount::from_sat(100),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    };
+    let tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(prev_tx.compute_txid(), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(150),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    };
+
+    // with an unknown prevout the fee is indeterminate so the tx is inserted
+    let mut other_graph = TxGraph::<()>::default();
+    assert!(other_graph.try_insert_tx(tx.clone()).is_ok());
+    assert!(other_graph.contains_full_tx(tx.compute_txid()));
+    assert_eq!(other_graph.negative_fee_txs().count(), 0);
+
+    // once the prevout is known the tx is detected as invalid
+    let _ = other_graph.insert_tx(prev_tx.clone());
+    assert_eq!(
+        other_graph.negative_fee_txs().collect::<Vec<_>>(),
+        vec![(tx.compute_txid(), SignedAmount::from_sat(-50))]
+    );
+
+    // with the prevout already known the tx is rejected
+    let _ = graph.insert_tx(prev_tx);
+    assert_eq!(
+        graph.try_insert_tx(tx.clone()),
+        Err(NegativeFeeError {
+            txid: tx.compute_txid(),
+            fee: SignedAmount::from_sat(-50),
+        })
+    );
+    assert!(!graph.contains_tx(tx.compute_txid()));
+}
+
 #[test]
 fn test_calculate_fee_on_coinbase() {
     let tx = Transaction {
@@ -1059,6 +1170,138 @@ fn test_chain_spends() {
         .is_none());
 }
 
+#[test]
+fn test_canonicalization_reason() {
+    let local_chain = LocalChain::from_blocks(
+        (0..=100)
+            .map(|ht| (ht, BlockHash::hash(format!("Block Hash {}", ht).as_bytes())))
+            .collect(),
+    )
+    .expect("must have genesis hash");
+    let tip = local_chain.tip();
+    let spend = |outpoint: OutPoint, value: u64| Transaction {
+        input: vec![TxIn {
+            previous_output: outpoint,
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        }],
+        ..common::new_tx(0)
+    };
+
+    let tx_0 = Transaction {
+        input: vec![],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            },
+            TxOut {
+                value: Amount::from_sat(20_000),
+                script_pubkey: ScriptBuf::new(),
+            },
+        ],
+        ..common::new_tx(0)
+    };
+    // `tx_1` is confirmed and conflicts with the unconfirmed `tx_1_conflict`
+    let tx_1 = spend(OutPoint::new(tx_0.compute_txid(), 0), 9_000);
+    let tx_1_conflict = spend(OutPoint::new(tx_0.compute_txid(), 0), 8_000);
+    // `tx_2_conflict` is seen after `tx_2`, and `tx_3` spends from `tx_2`
+    let tx_2 = spend(OutPoint::new(tx_0.compute_txid(), 1), 19_000);
+    let tx_2_conflict = spend(OutPoint::new(tx_0.compute_txid(), 1), 18_000);
+    let tx_3 = spend(OutPoint::new(tx_2.compute_txid(), 0), 17_000);
+    let coinbase = spend(OutPoint::null(), 50_000);
+
+    let mut graph = TxGraph::<ConfirmationHeightAnchor>::default();
+    for tx in [
+        &tx_0,
+        &tx_1,
+        &tx_1_conflict,
+        &tx_2,
+        &tx_2_conflict,
+        &tx_3,
+        &coinbase,
+    ] {
+        let _ = graph.insert_tx(tx.clone());
+    }
+    for (ht, tx) in [(95, &tx_0), (98, &tx_1)] {
+        let _ = graph.insert_anchor(
+            tx.compute_txid(),
+            ConfirmationHeightAnchor {
+                anchor_block: tip.block_id(),
+                confirmation_height: ht,
+            },
+        );
+    }
+    let _ = graph.insert_seen_at(tx_2.compute_txid(), 1);
+    let _ = graph.insert_seen_at(tx_2_conflict.compute_txid(), 2);
+    let partial_outpoint = OutPoint::new(h!("partial"), 0);
+    let _ = graph.insert_txout(
+        partial_outpoint,
+        TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::new(),
+        },
+    );
+
+    let reason = |txid: Txid| graph.canonicalization_reason(&local_chain, tip.block_id(), txid);
+    assert_eq!(reason(tx_0.compute_txid()), None);
+    assert_eq!(reason(tx_1.compute_txid()), None);
+    assert_eq!(reason(tx_2_conflict.compute_txid()), None);
+    assert_eq!(
+        reason(tx_1_conflict.compute_txid()),
+        Some(NonCanonicalReason::ConflictConfirmed {
+            spender: tx_1_conflict.compute_txid(),
+            conflict: tx_1.compute_txid(),
+        })
+    );
+    assert_eq!(
+        reason(tx_2.compute_txid()),
+        Some(NonCanonicalReason::ConflictUnconfirmed {
+            spender: tx_2.compute_txid(),
+            conflict: tx_2_conflict.compute_txid(),
+        })
+    );
+    // `tx_3` is excluded because its parent is
+    assert_eq!(
+        reason(tx_3.compute_txid()),
+        Some(NonCanonicalReason::ConflictUnconfirmed {
+            spender: tx_2.compute_txid(),
+            conflict: tx_2_conflict.compute_txid(),
+        })
+    );
+    assert_eq!(
+        reason(coinbase.compute_txid()),
+        Some(NonCanonicalReason::CoinbaseNotInChain)
+    );
+    assert_eq!(
+        reason(partial_outpoint.txid),
+        Some(NonCanonicalReason::Partial)
+    );
+    assert_eq!(reason(h!("unknown")), Some(NonCanonicalReason::NotFound));
+
+    // the reason is consistent with the chain position
+    for tx in [
+        &tx_0,
+        &tx_1,
+        &tx_1_conflict,
+        &tx_2,
+        &tx_2_conflict,
+        &tx_3,
+        &coinbase,
+    ] {
+        let txid = tx.compute_txid();
+        assert_eq!(
+            reason(txid).is_none(),
+            graph
+                .get_chain_position(&local_chain, tip.block_id(), txid)
+                .is_some()
+        );
+    }
+}
+
 /// Ensure that `last_seen` values only increase during [`Append::append`].
 #[test]
 fn test_changeset_last_seen_append() {
@@ -1202,3 +1445,450 @@ fn call_map_anchors_with_non_deterministic_anchor() {
         ]
     );
 }
+
+#[test]
+fn test_estimated_memory_usage_scales_with_txs() {
+    let mut graph = TxGraph::<BlockId>::default();
+    assert_eq!(graph.estimated_memory_usage(), 0);
+
+    let mut last_usage = 0;
+    for i in 0..10_u32 {
+        let tx = Transaction {
+            input: vec![TxIn {
+                previous_output: OutPoint::new(h!("parent"), i),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::from_bytes(vec![0; 34]),
+            }],
+            ..new_tx(i)
+        };
+        let txid = tx.compute_txid();
+        let _ = graph.insert_tx(tx);
+        let usage = graph.estimated_memory_usage();
+        assert!(usage > last_usage);
+        last_usage = usage;
+
+        let _ = graph.insert_anchor(txid, block_id!(i, "A"));
+        let usage = graph.estimated_memory_usage();
+        assert!(usage > last_usage);
+        last_usage = usage;
+    }
+
+    // inserting a floating txout also increases the estimate
+    let _ = graph.insert_txout(
+        OutPoint::new(h!("floating"), 0),
+        TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::new(),
+        },
+    );
+    assert!(graph.estimated_memory_usage() > last_usage);
+}
+
+#[test]
+fn test_batch_insert_anchors_matches_individual_inserts() {
+    let mut batched = TxGraph::<BlockId>::default();
+    let mut individual = TxGraph::<BlockId>::default();
+
+    let mut anchors = (0..10_000_u32)
+        .map(|i| {
+            let tx = new_tx(i);
+            let _ = batched.insert_tx(tx.clone());
+            let _ = individual.insert_tx(tx.clone());
+            (tx.compute_txid(), block_id!(i % 100, "A"))
+        })
+        .collect::<Vec<_>>();
+    // include an anchor that is already known, which must not show up in the changeset
+    let _ = batched.insert_anchor(anchors[0].0, anchors[0].1);
+    let _ = individual.insert_anchor(anchors[0].0, anchors[0].1);
+    anchors.push(anchors[0]);
+
+    let batched_changeset = batched.batch_insert_anchors(anchors.clone());
+    let mut individual_changeset = ChangeSet::<BlockId>::default();
+    for (txid, anchor) in anchors {
+        individual_changeset.append(individual.insert_anchor(txid, anchor));
+    }
+
+    assert_eq!(batched_changeset.anchors.len(), 9_999);
+    assert_eq!(batched_changeset, individual_changeset);
+    assert_eq!(batched.all_anchors(), individual.all_anchors());
+    assert_eq!(batched, individual);
+}
+
+#[test]
+fn test_spend_of() {
+    let local_chain = local_chain!((0, h!("A")), (1, h!("B")));
+    let tip = local_chain.tip().block_id();
+
+    let tx_0 = Transaction {
+        output: vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+        ..new_tx(0)
+    };
+    let outpoint = OutPoint::new(tx_0.compute_txid(), 0);
+
+    // `tx_1` spends `outpoint` in its second input and is replaced by `tx_2`, which spends it in
+    // its first input.
+    let tx_1 = Transaction {
+        input: vec![
+            TxIn {
+                previous_output: OutPoint::new(h!("other"), 0),
+                ..TxIn::default()
+            },
+            TxIn {
+                previous_output: outpoint,
+                ..TxIn::default()
+            },
+        ],
+        ..new_tx(1)
+    };
+    let tx_2 = Transaction {
+        input: vec![TxIn {
+            previous_output: outpoint,
+            ..TxIn::default()
+        }],
+        ..new_tx(2)
+    };
+
+    let mut graph = TxGraph::<BlockId>::default();
+    let _ = graph.insert_tx(tx_0.clone());
+    let _ = graph.insert_anchor(tx_0.compute_txid(), tip);
+    assert_eq!(graph.all_spends_of(outpoint).count(), 0);
+    assert_eq!(graph.spend_of(&local_chain, tip, outpoint), None);
+
+    let _ = graph.insert_tx(tx_1.clone());
+    let _ = graph.insert_seen_at(tx_1.compute_txid(), 10);
+    assert_eq!(
+        graph.spend_of(&local_chain, tip, outpoint),
+        Some((tx_1.compute_txid(), 1))
+    );
+
+    let _ = graph.insert_tx(tx_2.clone());
+    let _ = graph.insert_seen_at(tx_2.compute_txid(), 20);
+    assert_eq!(
+        graph.spend_of(&local_chain, tip, outpoint),
+        Some((tx_2.compute_txid(), 0))
+    );
+    assert_eq!(
+        graph.all_spends_of(outpoint).collect::<BTreeSet<_>>(),
+        [(tx_1.compute_txid(), 1), (tx_2.compute_txid(), 0)].into()
+    );
+}
+
+#[test]
+fn test_tx_sources() {
+    let tx_a = new_tx(0);
+    let tx_b = new_tx(1);
+    let txid_a = tx_a.compute_txid();
+    let txid_b = tx_b.compute_txid();
+
+    let mut graph = TxGraph::<BlockId>::default();
+    let changeset = graph.insert_tx_from_source(tx_a.clone(), Source::from("electrum"));
+    assert_eq!(
+        changeset,
+        TxGraph::<BlockId>::default().insert_tx(tx_a.clone())
+    );
+    // inserting the same tx from another source records both sources
+    assert!(graph
+        .insert_tx_from_source(tx_a.clone(), Source::from("rpc"))
+        .is_empty());
+    assert_eq!(
+        graph.tx_sources(txid_a).collect::<Vec<_>>(),
+        vec![&Source::from("electrum"), &Source::from("rpc")]
+    );
+
+    // provenance is transient: it isn't part of the changesets, but it is part of the graph
+    let mut other_graph = TxGraph::<BlockId>::default();
+    other_graph.apply_changeset(graph.initial_changeset());
+    assert_eq!(other_graph.tx_sources(txid_a).count(), 0);
+    assert_ne!(graph, other_graph);
+    assert_eq!(
+        graph
+            .clone()
+            .map_anchors(|anchor| anchor)
+            .tx_sources(txid_a)
+            .count(),
+        2
+    );
+
+    // transactions inserted without a source have no provenance
+    let _ = graph.insert_tx(tx_b.clone());
+    assert_eq!(graph.tx_sources(txid_b).count(), 0);
+
+    // provenance of an update is merged into the graph
+    let mut update = TxGraph::<BlockId>::default();
+    let _ = update.insert_tx_from_source(tx_b, Source::from("esplora"));
+    let _ = graph.apply_update(update);
+    assert_eq!(
+        graph.tx_sources(txid_b).collect::<Vec<_>>(),
+        vec![&Source::from("esplora")]
+    );
+}
+
+#[test]
+fn test_contains_tx() {
+    let tx = Transaction {
+        output: vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+        ..new_tx(0)
+    };
+    let txid = tx.compute_txid();
+    let mut graph = TxGraph::<BlockId>::default();
+    assert!(!graph.contains_tx(txid));
+    assert!(!graph.contains_full_tx(txid));
+
+    // only metadata is known
+    let _ = graph.insert_seen_at(txid, 100);
+    assert!(graph.contains_tx(txid));
+    assert!(!graph.contains_full_tx(txid));
+
+    // a floating txout is not a full transaction either
+    let _ = graph.insert_txout(OutPoint::new(txid, 0), tx.output[0].clone());
+    assert!(!graph.contains_full_tx(txid));
+
+    let _ = graph.insert_tx(tx);
+    assert!(graph.contains_tx(txid));
+    assert!(graph.contains_full_tx(txid));
+}
+
+#[test]
+fn test_canonical_txs_ordered() {
+    let local_chain = LocalChain::from_blocks(
+        (0..=100)
+            .map(|ht| (ht, BlockHash::hash(format!("Block Hash {}", ht).as_bytes())))
+            .collect(),
+    )
+    .expect("must have genesis hash");
+    let tip = local_chain.tip();
+
+    let txs = (0..6).map(common::new_tx).collect::<Vec<_>>();
+    let mut graph = TxGraph::<ConfirmationHeightAnchor>::default();
+    for tx in &txs {
+        let _ = graph.insert_tx(tx.clone());
+    }
+    for (i, ht) in [(0, 90), (1, 50), (2, 70), (3, 70)] {
+        let _ = graph.insert_anchor(
+            txs[i].compute_txid(),
+            ConfirmationHeightAnchor {
+                anchor_block: tip.block_id(),
+                confirmation_height: ht,
+            },
+        );
+    }
+    // `txs[5]` is seen again after `txs[4]`, but it was first seen before it
+    for (i, seen_at) in [(4, 20), (5, 10), (5, 30)] {
+        let _ = graph.insert_seen_at(txs[i].compute_txid(), seen_at);
+    }
+    assert_eq!(
+        graph
+            .get_tx_node(txs[5].compute_txid())
+            .unwrap()
+            .last_seen_unconfirmed,
+        30
+    );
+
+    // txs confirmed in the same block are ordered by txid
+    let mut same_block = [txs[2].compute_txid(), txs[3].compute_txid()];
+    same_block.sort();
+    let expected = [
+        txs[1].compute_txid(),
+        same_block[0],
+        same_block[1],
+        txs[0].compute_txid(),
+        txs[5].compute_txid(),
+        txs[4].compute_txid(),
+    ];
+
+    let ordered = graph
+        .canonical_txs_ordered(&local_chain, tip.block_id())
+        .into_iter()
+        .map(|tx| tx.tx_node.txid)
+        .collect::<Vec<_>>();
+    assert_eq!(ordered, expected);
+}
+
+#[test]
+fn test_evict_unconfirmed_before() {
+    let local_chain = LocalChain::from_blocks(
+        (0..=100)
+            .map(|ht| (ht, BlockHash::hash(format!("Block Hash {}", ht).as_bytes())))
+            .collect(),
+    )
+    .expect("must have genesis hash");
+    let tip = local_chain.tip();
+    let spend = |outpoint: OutPoint, value: u64| Transaction {
+        input: vec![TxIn {
+            previous_output: outpoint,
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        }],
+        ..common::new_tx(0)
+    };
+
+    let tx_0 = Transaction {
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            };
+            3
+        ],
+        ..common::new_tx(0)
+    };
+    // `tx_a` and its child `tx_b` are stale, `tx_c` is not, and `tx_e` has a child `tx_f` which
+    // is not stale either
+    let tx_a = spend(OutPoint::new(tx_0.compute_txid(), 0), 9_000);
+    let tx_b = spend(OutPoint::new(tx_a.compute_txid(), 0), 8_000);
+    let tx_c = spend(OutPoint::new(tx_0.compute_txid(), 1), 9_000);
+    let tx_e = spend(OutPoint::new(tx_0.compute_txid(), 2), 9_000);
+    let tx_f = spend(OutPoint::new(tx_e.compute_txid(), 0), 8_000);
+
+    let mut graph = TxGraph::<ConfirmationHeightAnchor>::default();
+    for tx in [&tx_0, &tx_a, &tx_b, &tx_c, &tx_e, &tx_f] {
+        let _ = graph.insert_tx(tx.clone());
+    }
+    let _ = graph.insert_anchor(
+        tx_0.compute_txid(),
+        ConfirmationHeightAnchor {
+            anchor_block: tip.block_id(),
+            confirmation_height: 90,
+        },
+    );
+    for (tx, seen_at) in [
+        (&tx_a, 100),
+        (&tx_b, 100),
+        (&tx_c, 300),
+        (&tx_e, 100),
+        (&tx_f, 300),
+    ] {
+        let _ = graph.insert_seen_at(tx.compute_txid(), seen_at);
+    }
+
+    let changeset = graph.evict_unconfirmed_before(200);
+    assert_eq!(
+        changeset.last_evicted,
+        [(tx_a.compute_txid(), 200), (tx_b.compute_txid(), 200)].into()
+    );
+    assert!(graph.evict_unconfirmed_before(200).is_empty());
+
+    let canonical_txids = |graph: &TxGraph<ConfirmationHeightAnchor>| {
+        graph
+            .list_chain_txs(&local_chain, tip.block_id())
+            .map(|tx| tx.tx_node.txid)
+            .collect::<BTreeSet<_>>()
+    };
+    assert_eq!(
+        canonical_txids(&graph),
+        [&tx_0, &tx_c, &tx_e, &tx_f]
+            .iter()
+            .map(|tx| tx.compute_txid())
+            .collect()
+    );
+    assert_eq!(
+        graph.canonicalization_reason(&local_chain, tip.block_id(), tx_a.compute_txid()),
+        Some(NonCanonicalReason::Evicted)
+    );
+
+    // evictions are persisted
+    let mut recovered = TxGraph::default();
+    recovered.apply_changeset(graph.initial_changeset());
+    assert_eq!(recovered, graph);
+
+    // evicted transactions don't get a new last seen with the other unconfirmed ones
+    let changeset = graph.update_last_seen_unconfirmed(400);
+    assert!(!changeset.last_seen.contains_key(&tx_a.compute_txid()));
+    assert!(!changeset.last_seen.contains_key(&tx_b.compute_txid()));
+
+    // a transaction seen again after its eviction is canonical again
+    let _ = graph.insert_seen_at(tx_a.compute_txid(), 500);
+    assert!(canonical_txids(&graph).contains(&tx_a.compute_txid()));
+    assert!(!canonical_txids(&graph).contains(&tx_b.compute_txid()));
+}
+
+#[test]
+fn test_eviction_applies_to_unconfirmed_ancestors_and_conflicts() {
+    let local_chain = LocalChain::from_blocks(
+        (0..=100)
+            .map(|ht| (ht, BlockHash::hash(format!("Block Hash {}", ht).as_bytes())))
+            .collect(),
+    )
+    .expect("must have genesis hash");
+    let tip = local_chain.tip();
+    let spend = |outpoint: OutPoint, value: u64| Transaction {
+        input: vec![TxIn {
+            previous_output: outpoint,
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        }],
+        ..common::new_tx(0)
+    };
+
+    let tx_0 = Transaction {
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            };
+            2
+        ],
+        ..common::new_tx(0)
+    };
+    // only `tx_a` is evicted, its child `tx_b` was last seen before the eviction too
+    let tx_a = spend(OutPoint::new(tx_0.compute_txid(), 0), 9_000);
+    let tx_b = spend(OutPoint::new(tx_a.compute_txid(), 0), 8_000);
+    // `tx_c` is evicted, but its child `tx_d` was seen after the eviction, so both are still in
+    // the mempool and take precedence over `tx_e`, which double spends `tx_c`
+    let tx_c = spend(OutPoint::new(tx_0.compute_txid(), 1), 9_000);
+    let tx_d = spend(OutPoint::new(tx_c.compute_txid(), 0), 8_000);
+    let tx_e = spend(OutPoint::new(tx_0.compute_txid(), 1), 8_500);
+
+    let mut graph = TxGraph::<ConfirmationHeightAnchor>::default();
+    for tx in [&tx_0, &tx_a, &tx_b, &tx_c, &tx_d, &tx_e] {
+        let _ = graph.insert_tx(tx.clone());
+    }
+    let _ = graph.insert_anchor(
+        tx_0.compute_txid(),
+        ConfirmationHeightAnchor {
+            anchor_block: tip.block_id(),
+            confirmation_height: 90,
+        },
+    );
+    for (tx, seen_at) in [
+        (&tx_a, 100),
+        (&tx_b, 100),
+        (&tx_c, 100),
+        (&tx_d, 300),
+        (&tx_e, 250),
+    ] {
+        let _ = graph.insert_seen_at(tx.compute_txid(), seen_at);
+    }
+    let _ = graph.insert_evicted_at(tx_a.compute_txid(), 200);
+    let _ = graph.insert_evicted_at(tx_c.compute_txid(), 200);
+
+    let reason = |tx: &Transaction| {
+        graph.canonicalization_reason(&local_chain, tip.block_id(), tx.compute_txid())
+    };
+    assert_eq!(reason(&tx_a), Some(NonCanonicalReason::Evicted));
+    assert_eq!(reason(&tx_b), Some(NonCanonicalReason::Evicted));
+    assert_eq!(reason(&tx_c), None);
+    assert_eq!(reason(&tx_d), None);
+    assert_eq!(
+        reason(&tx_e),
+        Some(NonCanonicalReason::ConflictUnconfirmed {
+            spender: tx_e.compute_txid(),
+            conflict: tx_d.compute_txid(),
+        })
+    );
+}
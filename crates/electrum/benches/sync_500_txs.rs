@@ -0,0 +1,79 @@
+use bdk_chain::{
+    bitcoin::{hashes::Hash, Address, Amount, Network, ScriptBuf, WScriptHash},
+    local_chain::CheckPoint,
+    spk_client::SyncRequest,
+};
+use bdk_electrum::BdkElectrumClient;
+use bdk_testenv::{anyhow, bitcoincore_rpc::RpcApi, TestEnv};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Number of transactions paying to the synced script pubkey.
+const TX_COUNT: usize = 500;
+
+/// Starts a test environment in which `TX_COUNT` confirmed transactions pay to the returned script
+/// pubkey, as the history of a busy wallet address would.
+fn setup() -> anyhow::Result<(TestEnv, ScriptBuf, CheckPoint)> {
+    let env = TestEnv::new()?;
+    let addr_to_mine = env
+        .bitcoind
+        .client
+        .get_new_address(None, None)?
+        .assume_checked();
+    env.mine_blocks(101, Some(addr_to_mine))?;
+
+    let spk = ScriptBuf::new_p2wsh(&WScriptHash::all_zeros());
+    let addr = Address::from_script(&spk, Network::Regtest)?;
+    let genesis = CheckPoint::new(bdk_chain::BlockId {
+        height: 0,
+        hash: env.bitcoind.client.get_block_hash(0)?,
+    });
+    for i in 0..TX_COUNT {
+        env.send(&addr, Amount::from_sat(10_000))?;
+        // stay below the mempool's limit of unconfirmed ancestors
+        if i % 20 == 19 {
+            env.mine_blocks(1, None)?;
+        }
+    }
+    env.mine_blocks(1, None)?;
+    env.wait_until_electrum_sees_block()?;
+    Ok((env, spk, genesis))
+}
+
+fn sync_with_batch_size(c: &mut Criterion, name: &str, batch_size: usize) {
+    let (env, spk, genesis) = setup().expect("test environment must start");
+    c.bench_function(name, move |b| {
+        b.iter_batched(
+            // a new client has an empty transaction cache, so every transaction is fetched
+            || {
+                let client = electrum_client::Client::new(env.electrsd.electrum_url.as_str())
+                    .expect("must connect to electrs");
+                BdkElectrumClient::new(client)
+            },
+            |client| {
+                let request =
+                    SyncRequest::from_chain_tip(genesis.clone()).chain_spks([spk.clone()]);
+                black_box(
+                    client
+                        .sync(request, batch_size, false)
+                        .expect("sync must succeed"),
+                )
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+fn sync_one_tx_per_request(c: &mut Criterion) {
+    sync_with_batch_size(c, "sync 500 txs with batch_size 1", 1);
+}
+
+fn sync_batched(c: &mut Criterion) {
+    sync_with_batch_size(c, "sync 500 txs with batch_size 100", 100);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = sync_one_tx_per_request, sync_batched
+}
+criterion_main!(benches);
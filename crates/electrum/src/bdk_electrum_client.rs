@@ -1,10 +1,10 @@
 use bdk_chain::{
-    bitcoin::{OutPoint, ScriptBuf, Transaction, Txid},
+    bitcoin::{FeeRate, OutPoint, ScriptBuf, Transaction, Txid},
     collections::{BTreeMap, HashMap, HashSet},
     local_chain::CheckPoint,
     spk_client::{FullScanRequest, FullScanResult, SyncRequest, SyncResult},
     tx_graph::TxGraph,
-    BlockId, ConfirmationHeightAnchor, ConfirmationTimeHeightAnchor,
+    BlockId, ConfirmationHeightAnchor, ConfirmationTimeHeightAnchor, FeeEstimator,
 };
 use core::str::FromStr;
 use electrum_client::{ElectrumApi, Error, HeaderNotification};
@@ -13,6 +13,14 @@ use std::sync::{Arc, Mutex};
 /// We include a chain suffix of a certain length for the purpose of robustness.
 const CHAIN_SUFFIX_LENGTH: u32 = 8;
 
+/// Confirmation targets (in blocks) queried by [`BdkElectrumClient::recommended_fee_estimates`].
+pub const RECOMMENDED_FEE_TARGETS: [u16; 4] = [1, 3, 6, 144];
+
+/// Connection timeout (in seconds) used by [`BdkElectrumClient::with_socks5_proxy`].
+///
+/// This is higher than what a direct connection needs to account for the latency of Tor circuits.
+pub const SOCKS5_TIMEOUT_SECS: u8 = 60;
+
 /// Wrapper around an [`electrum_client::ElectrumApi`] which includes an internal in-memory
 /// transaction cache to avoid re-fetching already downloaded transactions.
 #[derive(Debug)]
@@ -23,6 +31,25 @@ pub struct BdkElectrumClient<E> {
     tx_cache: Mutex<HashMap<Txid, Arc<Transaction>>>,
 }
 
+impl BdkElectrumClient<electrum_client::Client> {
+    /// Connects to the Electrum server at `url` through the SOCKS5 proxy at `proxy_addr`.
+    ///
+    /// All traffic to the server is routed through the proxy. To use Tor, point `proxy_addr` to
+    /// the SOCKS port of a local Tor daemon (usually `127.0.0.1:9050`). Onion services can then be
+    /// used as `url`, e.g. `tcp://<address>.onion:50001`, as the proxy resolves the host name.
+    ///
+    /// The connection timeout is set to [`SOCKS5_TIMEOUT_SECS`]. Use
+    /// [`electrum_client::ConfigBuilder`] and [`BdkElectrumClient::new`] for more control.
+    pub fn with_socks5_proxy(url: &str, proxy_addr: &str) -> Result<Self, Error> {
+        let config = electrum_client::ConfigBuilder::new()
+            .socks5(Some(electrum_client::Socks5Config::new(proxy_addr)))
+            .timeout(Some(SOCKS5_TIMEOUT_SECS))
+            .build();
+        let client = electrum_client::Client::from_config(url, config)?;
+        Ok(Self::new(client))
+    }
+}
+
 impl<E: ElectrumApi> BdkElectrumClient<E> {
     /// Creates a new bdk client from a [`electrum_client::ElectrumApi`]
     pub fn new(client: E) -> Self {
@@ -65,6 +92,60 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
         Ok(tx)
     }
 
+    /// Fetch the transactions of `txids` that are not cached yet and insert them into the cache.
+    ///
+    /// Transactions are requested in batches of up to `batch_size`, so this makes one request per
+    /// `batch_size` transactions instead of one per transaction. Each returned transaction is
+    /// cached under the txid it hashes to, and only if that txid was requested, so a server
+    /// returning the transactions out of order or other transactions can't corrupt the cache.
+    ///
+    /// If the server answers a batch request with an error ([`Error::Protocol`]), e.g. because it
+    /// doesn't support batching or one of the transactions doesn't exist, the transactions of that
+    /// batch are fetched one at a time instead. Any other error, like a failure of the connection,
+    /// is returned as is. Transactions the server doesn't know are skipped, and requesting them
+    /// with [`fetch_tx`] returns the error.
+    ///
+    /// [`fetch_tx`]: Self::fetch_tx
+    pub fn fetch_txs(
+        &self,
+        txids: impl IntoIterator<Item = Txid>,
+        batch_size: usize,
+    ) -> Result<(), Error> {
+        let missing = {
+            let tx_cache = self.tx_cache.lock().unwrap();
+            txids
+                .into_iter()
+                .filter(|txid| !tx_cache.contains_key(txid))
+                .collect::<HashSet<Txid>>()
+                .into_iter()
+                .collect::<Vec<Txid>>()
+        };
+
+        for txids in missing.chunks(batch_size.max(1)) {
+            match self.inner.batch_transaction_get(txids) {
+                Ok(txs) => {
+                    let mut tx_cache = self.tx_cache.lock().unwrap();
+                    for tx in txs {
+                        let txid = tx.compute_txid();
+                        if txids.contains(&txid) {
+                            tx_cache.insert(txid, Arc::new(tx));
+                        }
+                    }
+                }
+                Err(Error::Protocol(_)) => {
+                    for &txid in txids {
+                        match self.fetch_tx(txid) {
+                            Ok(_) | Err(Error::Protocol(_)) => {}
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
     /// Broadcasts a transaction to the network.
     ///
     /// This is a re-export of [`ElectrumApi::transaction_broadcast`].
@@ -72,6 +153,33 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
         self.inner.transaction_broadcast(tx)
     }
 
+    /// Estimate the fee rate needed for a transaction to confirm within `target` blocks.
+    ///
+    /// This wraps the Electrum `blockchain.estimatefee` call and converts the returned BTC/kvB
+    /// value into a [`FeeRate`]. If the server has no estimate for `target`, an
+    /// [`EstimateFeeError::Unavailable`] error is returned.
+    pub fn estimate_fee(&self, target: u16) -> Result<FeeRate, EstimateFeeError> {
+        let btc_per_kvb = self.inner.estimate_fee(target as usize)?;
+        fee_rate_from_btc_per_kvb(btc_per_kvb).ok_or(EstimateFeeError::Unavailable { target })
+    }
+
+    /// Estimate fee rates for each of the [`RECOMMENDED_FEE_TARGETS`] in a single batch request.
+    ///
+    /// The returned map is keyed by confirmation target. Targets for which the server has no
+    /// estimate are left out of the map.
+    pub fn recommended_fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error> {
+        let estimates = self
+            .inner
+            .batch_estimate_fee(RECOMMENDED_FEE_TARGETS.iter().map(|&t| t as usize))?;
+        Ok(RECOMMENDED_FEE_TARGETS
+            .iter()
+            .zip(estimates)
+            .filter_map(|(&target, btc_per_kvb)| {
+                Some((target, fee_rate_from_btc_per_kvb(btc_per_kvb)?))
+            })
+            .collect())
+    }
+
     /// Full scan the keychain scripts specified with the blockchain (via an Electrum client) and
     /// returns updates for [`bdk_chain`] data structures.
     ///
@@ -79,8 +187,10 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
     ///              see [`FullScanRequest`]
     /// - `stop_gap`: the full scan for each keychain stops after a gap of script pubkeys with no
     ///              associated transactions
-    /// - `batch_size`: specifies the max number of script pubkeys to request for in a single batch
-    ///              request
+    /// - `batch_size`: specifies the max number of script pubkeys or transactions to request for
+    ///              in a single batch request, see [`fetch_txs`](Self::fetch_txs). Syncing a
+    ///              script pubkey with 500 transactions takes 5 transaction requests with a
+    ///              `batch_size` of 100 instead of 500, see the `sync_500_txs` benchmark
     /// - `fetch_prev_txouts`: specifies whether or not we want previous `TxOut`s for fee
     pub fn full_scan<K: Ord + Clone>(
         &self,
@@ -145,7 +255,7 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
 
             // Fetch previous `TxOut`s for fee calculation if flag is enabled.
             if fetch_prev_txouts {
-                self.fetch_prev_txout(&mut graph_update)?;
+                self.fetch_prev_txout(&mut graph_update, batch_size)?;
             }
 
             let chain_update = tip;
@@ -176,8 +286,10 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
     ///
     /// - `request`: struct with data required to perform a spk-based blockchain client sync,
     ///              see [`SyncRequest`]
-    /// - `batch_size`: specifies the max number of script pubkeys to request for in a single batch
-    ///              request
+    /// - `batch_size`: specifies the max number of script pubkeys or transactions to request for
+    ///              in a single batch request, see [`fetch_txs`](Self::fetch_txs). Syncing a
+    ///              script pubkey with 500 transactions takes 5 transaction requests with a
+    ///              `batch_size` of 100 instead of 500, see the `sync_500_txs` benchmark
     /// - `fetch_prev_txouts`: specifies whether or not we want previous `TxOut`s for fee
     ///              calculation
     ///
@@ -204,12 +316,17 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
             .map(|cp| (cp.height(), cp))
             .collect::<BTreeMap<u32, CheckPoint>>();
 
-        self.populate_with_txids(&cps, &mut full_scan_res.graph_update, request.txids)?;
+        self.populate_with_txids(
+            &cps,
+            &mut full_scan_res.graph_update,
+            request.txids,
+            batch_size,
+        )?;
         self.populate_with_outpoints(&cps, &mut full_scan_res.graph_update, request.outpoints)?;
 
         // Fetch previous `TxOut`s for fee calculation if flag is enabled.
         if fetch_prev_txouts {
-            self.fetch_prev_txout(&mut full_scan_res.graph_update)?;
+            self.fetch_prev_txout(&mut full_scan_res.graph_update, batch_size)?;
         }
 
         Ok(ElectrumSyncResult(SyncResult {
@@ -248,6 +365,11 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
                 .inner
                 .batch_script_get_history(spks.iter().map(|(_, s)| s.as_script()))?;
 
+            self.fetch_txs(
+                spk_histories.iter().flatten().map(|tx_res| tx_res.tx_hash),
+                batch_size,
+            )?;
+
             for ((spk_index, spk), spk_history) in spks.into_iter().zip(spk_histories) {
                 if spk_history.is_empty() {
                     scanned_spks.insert(spk_index, (spk, false));
@@ -276,9 +398,16 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
     fn fetch_prev_txout(
         &self,
         graph_update: &mut TxGraph<ConfirmationHeightAnchor>,
+        batch_size: usize,
     ) -> Result<(), Error> {
         let full_txs: Vec<Arc<Transaction>> =
             graph_update.full_txs().map(|tx_node| tx_node.tx).collect();
+        self.fetch_txs(
+            full_txs
+                .iter()
+                .flat_map(|tx| tx.input.iter().map(|txin| txin.previous_output.txid)),
+            batch_size,
+        )?;
         for tx in full_txs {
             for vin in &tx.input {
                 let outpoint = vin.previous_output;
@@ -355,7 +484,10 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
         cps: &BTreeMap<u32, CheckPoint>,
         graph_update: &mut TxGraph<ConfirmationHeightAnchor>,
         txids: impl IntoIterator<Item = Txid>,
+        batch_size: usize,
     ) -> Result<(), Error> {
+        let txids = txids.into_iter().collect::<Vec<_>>();
+        self.fetch_txs(txids.iter().copied(), batch_size)?;
         for txid in txids {
             let tx = match self.fetch_tx(txid) {
                 Ok(tx) => tx,
@@ -390,6 +522,15 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
     }
 }
 
+/// Estimates fee rates with [`BdkElectrumClient::estimate_fee`], making a request per call.
+///
+/// Errors, including the server having no estimate for the target, result in `None`.
+impl<E: ElectrumApi> FeeEstimator for BdkElectrumClient<E> {
+    fn estimate(&self, target: u16) -> Option<FeeRate> {
+        self.estimate_fee(target).ok()
+    }
+}
+
 /// The result of [`BdkElectrumClient::full_scan`].
 ///
 /// This can be transformed into a [`FullScanResult`] with either [`ConfirmationHeightAnchor`] or
@@ -542,6 +683,56 @@ fn construct_update_tip(
     Ok((new_tip, agreement_height))
 }
 
+/// Converts a fee estimate in BTC/kvB, as returned by Electrum, into a [`FeeRate`].
+///
+/// Electrum returns `-1` when it has no estimate, in which case [`None`] is returned. The fee rate
+/// is rounded up, like the estimates of `bdk_esplora`, so that it is never below the estimate.
+fn fee_rate_from_btc_per_kvb(btc_per_kvb: f64) -> Option<FeeRate> {
+    if !btc_per_kvb.is_finite() || btc_per_kvb < 0.0 {
+        return None;
+    }
+    // the estimate has a precision of 1 sat/kvB, round it first so that the float error of the
+    // conversion doesn't round it up to the next sat/kwu
+    let sat_per_kvb = (btc_per_kvb * 100_000_000.0).round() as u64;
+    // 1 sat/kvB = 1/4 sat/kwu
+    Some(FeeRate::from_sat_per_kwu((sat_per_kvb + 3) / 4))
+}
+
+/// An error returned by [`BdkElectrumClient::estimate_fee`].
+#[derive(Debug)]
+pub enum EstimateFeeError {
+    /// The Electrum request failed.
+    Electrum(Error),
+    /// The server has no fee estimate for the requested confirmation target.
+    Unavailable {
+        /// The requested confirmation target (in blocks).
+        target: u16,
+    },
+}
+
+impl core::fmt::Display for EstimateFeeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EstimateFeeError::Electrum(err) => write!(f, "electrum error: {}", err),
+            EstimateFeeError::Unavailable { target } => {
+                write!(
+                    f,
+                    "no fee estimate available for a target of {} blocks",
+                    target
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EstimateFeeError {}
+
+impl From<Error> for EstimateFeeError {
+    fn from(err: Error) -> Self {
+        EstimateFeeError::Electrum(err)
+    }
+}
+
 /// A [tx status] comprises of a concatenation of `tx_hash:height:`s. We transform a single one of
 /// these concatenations into a [`ConfirmationHeightAnchor`] if possible.
 ///
@@ -587,3 +778,34 @@ fn determine_tx_anchor(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::fee_rate_from_btc_per_kvb;
+    use bdk_chain::bitcoin::FeeRate;
+
+    #[test]
+    fn fee_rate_from_electrum_estimate() {
+        // 0.0002 BTC/kvB = 20 sat/vB
+        assert_eq!(
+            fee_rate_from_btc_per_kvb(0.0002),
+            Some(FeeRate::from_sat_per_kwu(5_000))
+        );
+        assert_eq!(
+            fee_rate_from_btc_per_kvb(0.0),
+            Some(FeeRate::from_sat_per_kwu(0))
+        );
+        // rounded up to the next sat/kwu
+        assert_eq!(
+            fee_rate_from_btc_per_kvb(0.00012345),
+            Some(FeeRate::from_sat_per_kwu(3_087))
+        );
+        assert_eq!(
+            fee_rate_from_btc_per_kvb(0.00001),
+            Some(FeeRate::from_sat_per_kwu(250))
+        );
+        // electrum returns -1 when it has no estimate
+        assert_eq!(fee_rate_from_btc_per_kvb(-1.0), None);
+        assert_eq!(fee_rate_from_btc_per_kvb(f64::NAN), None);
+    }
+}
@@ -1,11 +1,13 @@
 use bdk_chain::{
-    bitcoin::{hashes::Hash, Address, Amount, ScriptBuf, WScriptHash},
+    bitcoin::{hashes::Hash, Address, Amount, FeeRate, ScriptBuf, Txid, WScriptHash},
     keychain::Balance,
     local_chain::LocalChain,
     spk_client::SyncRequest,
     ConfirmationTimeHeightAnchor, IndexedTxGraph, SpkTxOutIndex,
 };
-use bdk_electrum::BdkElectrumClient;
+use bdk_electrum::{
+    electrum_client::ElectrumApi, BdkElectrumClient, EstimateFeeError, RECOMMENDED_FEE_TARGETS,
+};
 use bdk_testenv::{anyhow, bitcoincore_rpc::RpcApi, TestEnv};
 
 fn get_balance(
@@ -218,3 +220,60 @@ fn tx_can_become_unconfirmed_after_reorg() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Ensure that fee estimates are either converted or reported as unavailable.
+#[test]
+fn estimate_fee_returns_rate_or_unavailable() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let electrum_client = electrum_client::Client::new(env.electrsd.electrum_url.as_str())?;
+    let client = BdkElectrumClient::new(electrum_client);
+
+    env.mine_blocks(101, None)?;
+    env.wait_until_electrum_sees_block()?;
+
+    match client.estimate_fee(6) {
+        Ok(fee_rate) => assert!(fee_rate > FeeRate::ZERO),
+        Err(EstimateFeeError::Unavailable { target }) => assert_eq!(target, 6),
+        Err(err) => return Err(err.into()),
+    }
+
+    let estimates = client.recommended_fee_estimates()?;
+    assert!(estimates
+        .keys()
+        .all(|target| RECOMMENDED_FEE_TARGETS.contains(target)));
+
+    Ok(())
+}
+
+#[test]
+fn fetch_txs_in_batches_and_skips_unknown_txids() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let electrum_client = electrum_client::Client::new(env.electrsd.electrum_url.as_str())?;
+    let client = BdkElectrumClient::new(electrum_client);
+
+    env.mine_blocks(5, None)?;
+    env.wait_until_electrum_sees_block()?;
+
+    let coinbase_txids = (1..=5)
+        .map(|height| Ok(env.get_block_at_height(height)?.txdata[0].compute_txid()))
+        .collect::<anyhow::Result<Vec<Txid>>>()?;
+
+    // the unknown txid makes its batch fall back to fetching one transaction at a time
+    client.fetch_txs(
+        coinbase_txids
+            .iter()
+            .copied()
+            .chain(core::iter::once(Txid::all_zeros())),
+        2,
+    )?;
+
+    // every known transaction was cached, so getting them again makes no call to the server
+    let calls_made = client.inner.calls_made()?;
+    for txid in coinbase_txids {
+        assert_eq!(client.fetch_tx(txid)?.compute_txid(), txid);
+    }
+    assert_eq!(client.inner.calls_made()?, calls_made);
+    assert!(client.fetch_tx(Txid::all_zeros()).is_err());
+
+    Ok(())
+}
@@ -1,21 +1,23 @@
 use std::collections::BTreeSet;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use bdk_chain::spk_client::{FullScanRequest, FullScanResult, SyncRequest, SyncResult};
 use bdk_chain::{
-    bitcoin::{BlockHash, OutPoint, ScriptBuf, TxOut, Txid},
+    bitcoin::{BlockHash, FeeRate, Network, OutPoint, ScriptBuf, TxOut, Txid},
     collections::BTreeMap,
     local_chain::CheckPoint,
     BlockId, ConfirmationTimeHeightAnchor, TxGraph,
 };
 use bdk_chain::{Anchor, Indexed};
+use core::future::Future;
 use esplora_client::{Amount, TxStatus};
 use futures::{stream::FuturesOrdered, TryStreamExt};
 
-use crate::anchor_from_status;
-
-/// [`esplora_client::Error`]
-type Error = Box<esplora_client::Error>;
+use crate::{
+    anchor_from_status, capability_probe_paths, fee_rate_from_sat_per_vb, merkle_proof_is_valid,
+    probe_status, remove_txs, Error, RetryPolicy, RetryingClient, ServerCapabilities,
+};
 
 /// Trait to extend the functionality of [`esplora_client::AsyncClient`].
 ///
@@ -47,6 +49,12 @@ pub trait EsploraAsyncExt {
     /// and [Sparrow](https://www.sparrowwallet.com/docs/faq.html#ive-restored-my-wallet-but-some-of-my-funds-are-missing).
     ///
     /// A `stop_gap` of 0 will be treated as a `stop_gap` of 1.
+    ///
+    /// ## Consistency
+    ///
+    /// If the chain tip fetched at the start of the scan is reorged out before the update is
+    /// complete, [`Error::TipChangedDuringSync`] is returned instead of an update that may mix data
+    /// from both sides of the reorg. The caller should then scan again.
     async fn full_scan<K: Ord + Clone + Send>(
         &self,
         request: FullScanRequest<K>,
@@ -63,17 +71,134 @@ pub trait EsploraAsyncExt {
     /// If the scripts to sync are unknown, such as when restoring or importing a keychain that
     /// may include scripts that have been used, use [`full_scan`] with the keychain.
     ///
+    /// The same [consistency] guarantee as for [`full_scan`] applies.
+    ///
     /// [`full_scan`]: EsploraAsyncExt::full_scan
+    /// [consistency]: EsploraAsyncExt::full_scan#consistency
     async fn sync(
         &self,
         request: SyncRequest,
         parallel_requests: usize,
     ) -> Result<SyncResult, Error>;
+
+    /// Same as [`sync`], but also fetch the previous outputs spent by the transactions of the
+    /// update that are missing from it.
+    ///
+    /// Transactions found through script pubkeys come with their previous outputs, but the ones
+    /// found through txids and outpoints don't, so [`TxGraph::calculate_fee`] fails for them. This
+    /// fetches the previous transactions of up to `max_prevout_fetches` such txids and adds the
+    /// spent outputs to the update as floating txouts, trading extra requests for complete fee data.
+    ///
+    /// [`sync`]: EsploraAsyncExt::sync
+    async fn sync_with_prevouts(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_prevout_fetches: usize,
+    ) -> Result<SyncResult, Error>;
+
+    /// Same as [`sync`], but also fetch the unconfirmed ancestors of the unconfirmed transactions
+    /// of the update that are missing from it.
+    ///
+    /// This gives the complete package of a received 0-conf coin, e.g. to evaluate its fee rate
+    /// before bumping it with CPFP. The missing parents are fetched, the unconfirmed ones are
+    /// added to the update without anchor and their own parents are fetched in turn, up to
+    /// `max_depth` generations. Confirmed parents end the walk, only the outputs spent from them
+    /// are added as floating txouts. A `max_depth` of 0 is the same as [`sync`]. As the mempool
+    /// limits unconfirmed chains to 25 ancestors, a larger `max_depth` only costs requests when
+    /// the server is misbehaving.
+    ///
+    /// [`sync`]: EsploraAsyncExt::sync
+    async fn sync_with_unconfirmed_ancestors(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_depth: usize,
+    ) -> Result<SyncResult, Error>;
+
+    /// Probe the server for the optional endpoints of [`ServerCapabilities`].
+    ///
+    /// Each endpoint is requested once and is considered available if the server responds with a
+    /// success status. A transient [`Error`], like a `429` response or failing to reach the
+    /// server, is retried like any other request and returned if it persists, while any other
+    /// HTTP status (e.g. the 404 of a plain Esplora server) marks the endpoint as unavailable.
+    /// `network` is the network the server is on.
+    ///
+    /// The capabilities of a server don't change, so they are cached by server URL and network
+    /// and only the first successful call probes the server.
+    async fn detect_capabilities(&self, network: Network) -> Result<ServerCapabilities, Error>;
+
+    /// Fetch the server's fee rate estimates, keyed by confirmation target in blocks.
+    ///
+    /// The returned map implements [`FeeEstimator`], so it can be passed wherever a fee estimator
+    /// is expected. Fetch it again to refresh the estimates.
+    ///
+    /// [`FeeEstimator`]: bdk_chain::FeeEstimator
+    async fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error>;
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl EsploraAsyncExt for esplora_client::AsyncClient {
+    async fn full_scan<K: Ord + Clone + Send>(
+        &self,
+        request: FullScanRequest<K>,
+        stop_gap: usize,
+        parallel_requests: usize,
+    ) -> Result<FullScanResult<K>, Error> {
+        retrying(self)
+            .full_scan(request, stop_gap, parallel_requests)
+            .await
+    }
+
+    async fn sync(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+    ) -> Result<SyncResult, Error> {
+        retrying(self).sync(request, parallel_requests).await
+    }
+
+    async fn sync_with_prevouts(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_prevout_fetches: usize,
+    ) -> Result<SyncResult, Error> {
+        retrying(self)
+            .sync_with_prevouts(request, parallel_requests, max_prevout_fetches)
+            .await
+    }
+
+    async fn sync_with_unconfirmed_ancestors(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_depth: usize,
+    ) -> Result<SyncResult, Error> {
+        retrying(self)
+            .sync_with_unconfirmed_ancestors(request, parallel_requests, max_depth)
+            .await
+    }
+
+    async fn detect_capabilities(&self, network: Network) -> Result<ServerCapabilities, Error> {
+        retrying(self).detect_capabilities(network).await
+    }
+
+    async fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error> {
+        retrying(self).fee_estimates().await
+    }
+}
+
+/// Make the requests of `client` through a [`RetryingClient`], which attaches the URL of the
+/// server to their errors.
+fn retrying(client: &esplora_client::AsyncClient) -> RetryingClient<esplora_client::AsyncClient> {
+    RetryingClient::new(client.clone(), RetryPolicy::NONE)
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl EsploraAsyncExt for RetryingClient<esplora_client::AsyncClient> {
     async fn full_scan<K: Ord + Clone + Send>(
         &self,
         request: FullScanRequest<K>,
@@ -81,7 +206,7 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
         parallel_requests: usize,
     ) -> Result<FullScanResult<K>, Error> {
         let latest_blocks = fetch_latest_blocks(self).await?;
-        let (graph_update, last_active_indices) = full_scan_for_index_and_graph(
+        let (mut graph_update, last_active_indices) = full_scan_for_index_and_graph(
             self,
             request.spks_by_keychain,
             stop_gap,
@@ -95,6 +220,10 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
             graph_update.all_anchors(),
         )
         .await?;
+        if self.verify_merkle_proofs {
+            graph_update = drop_unproven_txs(self, graph_update, &chain_update).await?;
+        }
+        check_tip_unchanged(self, &latest_blocks).await?;
         Ok(FullScanResult {
             chain_update,
             graph_update,
@@ -108,7 +237,7 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
         parallel_requests: usize,
     ) -> Result<SyncResult, Error> {
         let latest_blocks = fetch_latest_blocks(self).await?;
-        let graph_update = sync_for_index_and_graph(
+        let mut graph_update = sync_for_index_and_graph(
             self,
             request.spks,
             request.txids,
@@ -123,11 +252,119 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
             graph_update.all_anchors(),
         )
         .await?;
+        if self.verify_merkle_proofs {
+            graph_update = drop_unproven_txs(self, graph_update, &chain_update).await?;
+        }
+        check_tip_unchanged(self, &latest_blocks).await?;
         Ok(SyncResult {
             chain_update,
             graph_update,
         })
     }
+
+    async fn sync_with_prevouts(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_prevout_fetches: usize,
+    ) -> Result<SyncResult, Error> {
+        let mut result = self.sync(request, parallel_requests).await?;
+        fetch_prev_txouts(
+            self,
+            &mut result.graph_update,
+            parallel_requests,
+            max_prevout_fetches,
+        )
+        .await?;
+        Ok(result)
+    }
+
+    async fn sync_with_unconfirmed_ancestors(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_depth: usize,
+    ) -> Result<SyncResult, Error> {
+        let mut result = self.sync(request, parallel_requests).await?;
+        fetch_unconfirmed_ancestors(self, &mut result.graph_update, parallel_requests, max_depth)
+            .await?;
+        Ok(result)
+    }
+
+    async fn detect_capabilities(&self, network: Network) -> Result<ServerCapabilities, Error> {
+        if let Some(capabilities) = self.cached_capabilities(network) {
+            return Ok(capabilities);
+        }
+        let mut supported = [false; 3];
+        let paths = capability_probe_paths(network);
+        for (path, supported) in paths.iter().zip(supported.iter_mut()) {
+            *supported = self
+                .request(|c| async move {
+                    let resp = c
+                        .client()
+                        .get(format!("{}{}", c.url(), path))
+                        .send()
+                        .await
+                        .map_err(esplora_client::Error::Reqwest)?;
+                    probe_status(resp.status().as_u16())
+                })
+                .await?;
+        }
+        let [cpfp, rbf, v1_fees] = supported;
+        let capabilities = ServerCapabilities { cpfp, rbf, v1_fees };
+        self.cache_capabilities(network, capabilities);
+        Ok(capabilities)
+    }
+
+    async fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error> {
+        Ok(self
+            .request(|c| c.get_fee_estimates())
+            .await?
+            .into_iter()
+            .filter_map(|(target, sat_per_vb)| {
+                Some((target, fee_rate_from_sat_per_vb(sat_per_vb)?))
+            })
+            .collect())
+    }
+}
+
+impl RetryingClient<esplora_client::AsyncClient> {
+    /// Wrap `client` so that the requests of the extension trait failing with a transient
+    /// [`Error`] are retried according to `retry_policy`.
+    pub fn new(client: esplora_client::AsyncClient, retry_policy: crate::RetryPolicy) -> Self {
+        let base_url = client.url().to_string();
+        Self::with_url(client, &base_url, retry_policy)
+    }
+
+    /// Make a request to the server with `f`, retrying it according to the [`RetryPolicy`] and
+    /// returning its error along with the server's URL.
+    ///
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    async fn request<'a, T, Fut>(
+        &'a self,
+        f: impl Fn(&'a esplora_client::AsyncClient) -> Fut,
+    ) -> Result<T, Error>
+    where
+        Fut: Future<Output = Result<T, esplora_client::Error>>,
+    {
+        let mut retries = 0;
+        loop {
+            match f(&self.inner).await {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let err = self.request_error(err);
+                    match self.retry_policy.retry_delay(&err, retries) {
+                        Some(delay) => {
+                            log_event!(debug, ?err, ?delay, "retrying esplora request");
+                            futures_timer::Delay::new(delay).await;
+                            retries += 1;
+                        }
+                        None => return Err(err),
+                    }
+                }
+            }
+        }
+    }
 }
 
 /// Fetch latest blocks from Esplora in an atomic call.
@@ -138,10 +375,10 @@ impl EsploraAsyncExt for esplora_client::AsyncClient {
 /// an earlier tip rather than a later tip) otherwise the caller may accidentally skip blocks when
 /// alternating between chain-sources.
 async fn fetch_latest_blocks(
-    client: &esplora_client::AsyncClient,
+    client: &RetryingClient<esplora_client::AsyncClient>,
 ) -> Result<BTreeMap<u32, BlockHash>, Error> {
     Ok(client
-        .get_blocks(None)
+        .request(|c| c.get_blocks(None))
         .await?
         .into_iter()
         .map(|b| (b.time.height, b.id))
@@ -152,7 +389,7 @@ async fn fetch_latest_blocks(
 ///
 /// This first checks the previously fetched `latest_blocks` before fetching from Esplora again.
 async fn fetch_block(
-    client: &esplora_client::AsyncClient,
+    client: &RetryingClient<esplora_client::AsyncClient>,
     latest_blocks: &BTreeMap<u32, BlockHash>,
     height: u32,
 ) -> Result<Option<BlockHash>, Error> {
@@ -170,7 +407,28 @@ async fn fetch_block(
         return Ok(None);
     }
 
-    Ok(Some(client.get_block_hash(height).await?))
+    Ok(Some(client.request(|c| c.get_block_hash(height)).await?))
+}
+
+/// Check that the tip of `latest_blocks` is still in the best chain.
+///
+/// This is called once the update is complete. If the tip was reorged out in the meantime, the
+/// update may contain data from both sides of the reorg so we return
+/// [`Error::TipChangedDuringSync`].
+async fn check_tip_unchanged(
+    client: &RetryingClient<esplora_client::AsyncClient>,
+    latest_blocks: &BTreeMap<u32, BlockHash>,
+) -> Result<(), Error> {
+    let (&height, &hash) = latest_blocks
+        .iter()
+        .last()
+        .expect("must have atleast one entry");
+    if client.request(|c| c.get_block_hash(height)).await? != hash {
+        return Err(Error::TipChangedDuringSync {
+            tip: BlockId { height, hash },
+        });
+    }
+    Ok(())
 }
 
 /// Create the [`local_chain::Update`].
@@ -178,7 +436,7 @@ async fn fetch_block(
 /// We want to have a corresponding checkpoint per anchor height. However, checkpoints fetched
 /// should not surpass `latest_blocks`.
 async fn chain_update<A: Anchor>(
-    client: &esplora_client::AsyncClient,
+    client: &RetryingClient<esplora_client::AsyncClient>,
     latest_blocks: &BTreeMap<u32, BlockHash>,
     local_tip: &CheckPoint,
     anchors: &BTreeSet<(A, Txid)>,
@@ -233,7 +491,7 @@ async fn chain_update<A: Anchor>(
 /// This performs a full scan to get an update for the [`TxGraph`] and
 /// [`KeychainTxOutIndex`](bdk_chain::keychain::KeychainTxOutIndex).
 async fn full_scan_for_index_and_graph<K: Ord + Clone + Send>(
-    client: &esplora_client::AsyncClient,
+    client: &RetryingClient<esplora_client::AsyncClient>,
     keychain_spks: BTreeMap<
         K,
         impl IntoIterator<IntoIter = impl Iterator<Item = Indexed<ScriptBuf>> + Send> + Send,
@@ -261,7 +519,9 @@ async fn full_scan_for_index_and_graph<K: Ord + Clone + Send>(
                         let mut last_seen = None;
                         let mut spk_txs = Vec::new();
                         loop {
-                            let txs = client.scripthash_txs(&spk, last_seen).await?;
+                            let txs = client
+                                .request(|c| c.scripthash_txs(&spk, last_seen))
+                                .await?;
                             let tx_count = txs.len();
                             last_seen = txs.last().map(|tx| tx.txid);
                             spk_txs.extend(txs);
@@ -328,7 +588,7 @@ async fn full_scan_for_index_and_graph<K: Ord + Clone + Send>(
 }
 
 async fn sync_for_index_and_graph(
-    client: &esplora_client::AsyncClient,
+    client: &RetryingClient<esplora_client::AsyncClient>,
     misc_spks: impl IntoIterator<IntoIter = impl Iterator<Item = ScriptBuf> + Send> + Send,
     txids: impl IntoIterator<IntoIter = impl Iterator<Item = Txid> + Send> + Send,
     outpoints: impl IntoIterator<IntoIter = impl Iterator<Item = OutPoint> + Send> + Send,
@@ -355,10 +615,15 @@ async fn sync_for_index_and_graph(
         let handles = txids
             .by_ref()
             .take(parallel_requests)
-            .filter(|&txid| graph.get_tx(txid).is_none())
+            .filter(|&txid| !graph.contains_full_tx(txid))
             .map(|txid| {
                 let client = client.clone();
-                async move { client.get_tx_status(&txid).await.map(|s| (txid, s)) }
+                async move {
+                    client
+                        .request(|c| c.get_tx_status(&txid))
+                        .await
+                        .map(|s| (txid, s))
+                }
             })
             .collect::<FuturesOrdered<_>>();
 
@@ -374,23 +639,26 @@ async fn sync_for_index_and_graph(
     }
 
     for op in outpoints.into_iter() {
-        if graph.get_tx(op.txid).is_none() {
-            if let Some(tx) = client.get_tx(&op.txid).await? {
+        if !graph.contains_full_tx(op.txid) {
+            if let Some(tx) = client.request(|c| c.get_tx(&op.txid)).await? {
                 let _ = graph.insert_tx(tx);
             }
-            let status = client.get_tx_status(&op.txid).await?;
+            let status = client.request(|c| c.get_tx_status(&op.txid)).await?;
             if let Some(anchor) = anchor_from_status(&status) {
                 let _ = graph.insert_anchor(op.txid, anchor);
             }
         }
 
-        if let Some(op_status) = client.get_output_status(&op.txid, op.vout as _).await? {
+        if let Some(op_status) = client
+            .request(|c| c.get_output_status(&op.txid, op.vout as _))
+            .await?
+        {
             if let Some(txid) = op_status.txid {
-                if graph.get_tx(txid).is_none() {
-                    if let Some(tx) = client.get_tx(&txid).await? {
+                if !graph.contains_full_tx(txid) {
+                    if let Some(tx) = client.request(|c| c.get_tx(&txid)).await? {
                         let _ = graph.insert_tx(tx);
                     }
-                    let status = client.get_tx_status(&txid).await?;
+                    let status = client.request(|c| c.get_tx_status(&txid)).await?;
                     if let Some(anchor) = anchor_from_status(&status) {
                         let _ = graph.insert_anchor(txid, anchor);
                     }
@@ -402,19 +670,211 @@ async fn sync_for_index_and_graph(
     Ok(graph)
 }
 
+/// Drop the confirmed transactions of `graph` whose merkle proof doesn't match the header of the
+/// block they are anchored to.
+///
+/// The header is only trusted if the anchor block is part of `chain`, the chain update, and the
+/// header hashes to it, otherwise every transaction anchored to that block is dropped.
+async fn drop_unproven_txs(
+    client: &RetryingClient<esplora_client::AsyncClient>,
+    graph: TxGraph<ConfirmationTimeHeightAnchor>,
+    chain: &CheckPoint,
+) -> Result<TxGraph<ConfirmationTimeHeightAnchor>, Error> {
+    let mut merkle_roots = BTreeMap::new();
+    let mut unproven = BTreeSet::new();
+    for (anchor, txid) in graph.all_anchors() {
+        let block = anchor.anchor_block;
+        let merkle_root = match merkle_roots.get(&block.hash) {
+            Some(&merkle_root) => merkle_root,
+            None => {
+                let merkle_root = if chain.get(block.height).map(|cp| cp.hash()) == Some(block.hash)
+                {
+                    let header = client
+                        .request(|c| c.get_header_by_hash(&block.hash))
+                        .await?;
+                    if header.block_hash() == block.hash {
+                        Some(header.merkle_root)
+                    } else {
+                        log_event!(warn, hash = %block.hash, "header does not match the anchor block");
+                        None
+                    }
+                } else {
+                    log_event!(warn, hash = %block.hash, "anchor block is not in the chain update");
+                    None
+                };
+                merkle_roots.insert(block.hash, merkle_root);
+                merkle_root
+            }
+        };
+        let proven = match (
+            merkle_root,
+            client.request(|c| c.get_merkle_proof(txid)).await?,
+        ) {
+            (Some(merkle_root), Some(proof)) => {
+                proof.block_height == block.height
+                    && merkle_proof_is_valid(*txid, &proof, merkle_root)
+            }
+            _ => false,
+        };
+        if !proven {
+            log_event!(warn, %txid, "dropping transaction with an invalid merkle proof");
+            unproven.insert(*txid);
+        }
+    }
+    Ok(remove_txs(graph, &unproven))
+}
+
+/// Fetch the previous outputs spent by the full transactions of `tx_graph` that are missing from
+/// it, and insert them as floating txouts.
+///
+/// At most `max_fetches` previous transactions are fetched.
+async fn fetch_prev_txouts(
+    client: &RetryingClient<esplora_client::AsyncClient>,
+    tx_graph: &mut TxGraph<ConfirmationTimeHeightAnchor>,
+    parallel_requests: usize,
+    max_fetches: usize,
+) -> Result<(), Error> {
+    let parallel_requests = Ord::max(parallel_requests, 1);
+    let mut missing = BTreeMap::<Txid, Vec<u32>>::new();
+    for tx_node in tx_graph.full_txs() {
+        if tx_node.tx.is_coinbase() {
+            continue;
+        }
+        for txin in &tx_node.tx.input {
+            let prev_op = txin.previous_output;
+            if tx_graph.get_txout(prev_op).is_none() {
+                missing.entry(prev_op.txid).or_default().push(prev_op.vout);
+            }
+        }
+    }
+
+    let mut prev_txids = missing.into_iter().take(max_fetches);
+    loop {
+        let handles = prev_txids
+            .by_ref()
+            .take(parallel_requests)
+            .map(|(txid, vouts)| {
+                let client = client.clone();
+                async move {
+                    client
+                        .request(|c| c.get_tx(&txid))
+                        .await
+                        .map(|tx| (tx, vouts))
+                }
+            })
+            .collect::<FuturesOrdered<_>>();
+
+        if handles.is_empty() {
+            break;
+        }
+
+        for (tx, vouts) in handles.try_collect::<Vec<_>>().await? {
+            let tx = match tx {
+                Some(tx) => tx,
+                None => continue,
+            };
+            let txid = tx.compute_txid();
+            for vout in vouts {
+                if let Some(txout) = tx.output.get(vout as usize) {
+                    let _ = tx_graph.insert_txout(OutPoint { txid, vout }, txout.clone());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch the unconfirmed ancestors of the unconfirmed transactions of `tx_graph` that are missing
+/// from it, up to `max_depth` generations.
+///
+/// Unconfirmed ancestors are inserted without anchor. For confirmed parents only the spent outputs
+/// are inserted, as floating txouts.
+async fn fetch_unconfirmed_ancestors(
+    client: &RetryingClient<esplora_client::AsyncClient>,
+    tx_graph: &mut TxGraph<ConfirmationTimeHeightAnchor>,
+    parallel_requests: usize,
+    max_depth: usize,
+) -> Result<(), Error> {
+    let parallel_requests = Ord::max(parallel_requests, 1);
+    let mut children = tx_graph
+        .full_txs()
+        .filter(|tx_node| tx_node.anchors.is_empty())
+        .map(|tx_node| tx_node.tx)
+        .collect::<Vec<_>>();
+
+    for _ in 0..max_depth {
+        let mut missing = BTreeMap::<Txid, Vec<u32>>::new();
+        for tx in children.iter().filter(|tx| !tx.is_coinbase()) {
+            for txin in &tx.input {
+                let prev_op = txin.previous_output;
+                if !tx_graph.contains_full_tx(prev_op.txid) {
+                    missing.entry(prev_op.txid).or_default().push(prev_op.vout);
+                }
+            }
+        }
+
+        children = Vec::new();
+        let mut parent_txids = missing.into_iter();
+        loop {
+            let handles = parent_txids
+                .by_ref()
+                .take(parallel_requests)
+                .map(|(txid, vouts)| {
+                    let client = client.clone();
+                    async move {
+                        let tx = client.request(|c| c.get_tx(&txid)).await?;
+                        let status = client.request(|c| c.get_tx_status(&txid)).await?;
+                        Ok::<_, Error>((tx, status, vouts))
+                    }
+                })
+                .collect::<FuturesOrdered<_>>();
+
+            if handles.is_empty() {
+                break;
+            }
+
+            for (tx, status, vouts) in handles.try_collect::<Vec<_>>().await? {
+                let tx = match tx {
+                    Some(tx) => tx,
+                    None => continue,
+                };
+                if status.confirmed {
+                    let txid = tx.compute_txid();
+                    for vout in vouts {
+                        if let Some(txout) = tx.output.get(vout as usize) {
+                            let _ = tx_graph.insert_txout(OutPoint { txid, vout }, txout.clone());
+                        }
+                    }
+                } else {
+                    let tx = Arc::new(tx);
+                    let _ = tx_graph.insert_tx(tx.clone());
+                    children.push(tx);
+                }
+            }
+        }
+
+        if children.is_empty() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use std::{collections::BTreeSet, time::Duration};
+    use std::{collections::BTreeSet, sync::atomic::Ordering, time::Duration};
 
+    use crate::{mock_server, EsploraAsyncExt, RetryPolicy, RetryingClient};
     use bdk_chain::{
-        bitcoin::{hashes::Hash, Txid},
+        bitcoin::{hashes::Hash, Network, Txid},
         local_chain::LocalChain,
         BlockId,
     };
     use bdk_testenv::{anyhow, bitcoincore_rpc::RpcApi, TestEnv};
-    use esplora_client::Builder;
 
-    use crate::async_ext::{chain_update, fetch_latest_blocks};
+    use crate::async_ext::{chain_update, fetch_latest_blocks, retrying};
 
     macro_rules! h {
         ($index:literal) => {{
@@ -467,7 +927,7 @@ mod test {
 
             let env = TestEnv::new()?;
             let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
-            let client = Builder::new(base_url.as_str()).build_async()?;
+            let client = retrying(&esplora_client::Builder::new(base_url.as_str()).build_async()?);
 
             // set env to `initial_env_height`
             if let Some(to_mine) = t
@@ -587,4 +1047,75 @@ mod test {
 
         Ok(())
     }
+
+    /// Ensure that the capability probes are retried like any other request and that the
+    /// detected capabilities are cached.
+    #[tokio::test]
+    async fn detect_capabilities_is_retried_and_cached() -> anyhow::Result<()> {
+        let (url, requests) = mock_server(vec![(429, ""), (200, "[]"), (404, ""), (200, "{}")]);
+        let policy = RetryPolicy {
+            max_retries: 1,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let client = RetryingClient::new(esplora_client::Builder::new(&url).build_async()?, policy);
+        let capabilities = client.detect_capabilities(Network::Regtest).await?;
+        assert!(capabilities.cpfp);
+        assert!(!capabilities.rbf);
+        assert!(capabilities.v1_fees);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        // other clients of the same server use the cache
+        let client = esplora_client::Builder::new(&url).build_async()?;
+        assert_eq!(
+            client.detect_capabilities(Network::Regtest).await?,
+            capabilities
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+
+    /// Ensure that only the requests failing with a transient error are retried, and only if
+    /// retrying is enabled.
+    #[tokio::test]
+    async fn retry_transient_errors() -> anyhow::Result<()> {
+        let policy = |max_retries| RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+
+        let (url, requests) = mock_server(vec![(429, ""), (503, ""), (200, r#"{"1":10.0}"#)]);
+        let client =
+            RetryingClient::new(esplora_client::Builder::new(&url).build_async()?, policy(2));
+        let estimates = client.fee_estimates().await?;
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        let (url, requests) = mock_server(vec![(429, ""), (429, "")]);
+        let client =
+            RetryingClient::new(esplora_client::Builder::new(&url).build_async()?, policy(1));
+        let err = client.fee_estimates().await.expect_err("must fail");
+        assert_eq!(err.http_status(), Some(429));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        for status in [400, 404] {
+            let (url, requests) = mock_server(vec![(status, ""), (200, r#"{"1":10.0}"#)]);
+            let client =
+                RetryingClient::new(esplora_client::Builder::new(&url).build_async()?, policy(3));
+            let err = client.fee_estimates().await.expect_err("must fail");
+            assert_eq!(err.http_status(), Some(status));
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+        }
+
+        // the bare client doesn't retry
+        let (url, requests) = mock_server(vec![(429, ""), (200, r#"{"1":10.0}"#)]);
+        let client = esplora_client::Builder::new(&url).build_async()?;
+        let err = client.fee_estimates().await.expect_err("must fail");
+        assert_eq!(err.http_status(), Some(429));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
 }
@@ -1,21 +1,22 @@
 use std::collections::BTreeSet;
+use std::sync::Arc;
 use std::thread::JoinHandle;
 use std::usize;
 
 use bdk_chain::collections::BTreeMap;
 use bdk_chain::spk_client::{FullScanRequest, FullScanResult, SyncRequest, SyncResult};
 use bdk_chain::{
-    bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, TxOut, Txid},
+    bitcoin::{Amount, BlockHash, FeeRate, Network, OutPoint, ScriptBuf, Transaction, TxOut, Txid},
     local_chain::CheckPoint,
     BlockId, ConfirmationTimeHeightAnchor, TxGraph,
 };
 use bdk_chain::{Anchor, Indexed};
 use esplora_client::TxStatus;
 
-use crate::anchor_from_status;
-
-/// [`esplora_client::Error`]
-pub type Error = Box<esplora_client::Error>;
+use crate::{
+    anchor_from_status, capability_probe_paths, fee_rate_from_sat_per_vb, merkle_proof_is_valid,
+    probe_status, remove_txs, Error, RetryPolicy, RetryingClient, ServerCapabilities,
+};
 
 /// Trait to extend the functionality of [`esplora_client::BlockingClient`].
 ///
@@ -45,6 +46,12 @@ pub trait EsploraExt {
     /// and [Sparrow](https://www.sparrowwallet.com/docs/faq.html#ive-restored-my-wallet-but-some-of-my-funds-are-missing).
     ///
     /// A `stop_gap` of 0 will be treated as a `stop_gap` of 1.
+    ///
+    /// ## Consistency
+    ///
+    /// If the chain tip fetched at the start of the scan is reorged out before the update is
+    /// complete, [`Error::TipChangedDuringSync`] is returned instead of an update that may mix data
+    /// from both sides of the reorg. The caller should then scan again.
     fn full_scan<K: Ord + Clone>(
         &self,
         request: FullScanRequest<K>,
@@ -61,11 +68,118 @@ pub trait EsploraExt {
     /// If the scripts to sync are unknown, such as when restoring or importing a keychain that
     /// may include scripts that have been used, use [`full_scan`] with the keychain.
     ///
+    /// The same [consistency] guarantee as for [`full_scan`] applies.
+    ///
     /// [`full_scan`]: EsploraExt::full_scan
+    /// [consistency]: EsploraExt::full_scan#consistency
     fn sync(&self, request: SyncRequest, parallel_requests: usize) -> Result<SyncResult, Error>;
+
+    /// Same as [`sync`], but also fetch the previous outputs spent by the transactions of the
+    /// update that are missing from it.
+    ///
+    /// Transactions found through script pubkeys come with their previous outputs, but the ones
+    /// found through txids and outpoints don't, so [`TxGraph::calculate_fee`] fails for them. This
+    /// fetches the previous transactions of up to `max_prevout_fetches` such txids and adds the
+    /// spent outputs to the update as floating txouts, trading extra requests for complete fee data.
+    ///
+    /// [`sync`]: EsploraExt::sync
+    fn sync_with_prevouts(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_prevout_fetches: usize,
+    ) -> Result<SyncResult, Error>;
+
+    /// Same as [`sync`], but also fetch the unconfirmed ancestors of the unconfirmed transactions
+    /// of the update that are missing from it.
+    ///
+    /// This gives the complete package of a received 0-conf coin, e.g. to evaluate its fee rate
+    /// before bumping it with CPFP. The missing parents are fetched, the unconfirmed ones are
+    /// added to the update without anchor and their own parents are fetched in turn, up to
+    /// `max_depth` generations. Confirmed parents end the walk, only the outputs spent from them
+    /// are added as floating txouts. A `max_depth` of 0 is the same as [`sync`]. As the mempool
+    /// limits unconfirmed chains to 25 ancestors, a larger `max_depth` only costs requests when
+    /// the server is misbehaving.
+    ///
+    /// [`sync`]: EsploraExt::sync
+    fn sync_with_unconfirmed_ancestors(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_depth: usize,
+    ) -> Result<SyncResult, Error>;
+
+    /// Probe the server for the optional endpoints of [`ServerCapabilities`].
+    ///
+    /// Each endpoint is requested once and is considered available if the server responds with a
+    /// success status. A transient [`Error`], like a `429` response or failing to reach the
+    /// server, is retried like any other request and returned if it persists, while any other
+    /// HTTP status (e.g. the 404 of a plain Esplora server) marks the endpoint as unavailable.
+    /// `network` is the network the server is on.
+    ///
+    /// The capabilities of a server don't change, so they are cached by server URL and network
+    /// and only the first successful call probes the server.
+    fn detect_capabilities(&self, network: Network) -> Result<ServerCapabilities, Error>;
+
+    /// Fetch the server's fee rate estimates, keyed by confirmation target in blocks.
+    ///
+    /// The returned map implements [`FeeEstimator`], so it can be passed wherever a fee estimator
+    /// is expected. Fetch it again to refresh the estimates.
+    ///
+    /// [`FeeEstimator`]: bdk_chain::FeeEstimator
+    fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error>;
 }
 
 impl EsploraExt for esplora_client::BlockingClient {
+    fn full_scan<K: Ord + Clone>(
+        &self,
+        request: FullScanRequest<K>,
+        stop_gap: usize,
+        parallel_requests: usize,
+    ) -> Result<FullScanResult<K>, Error> {
+        retrying(self).full_scan(request, stop_gap, parallel_requests)
+    }
+
+    fn sync(&self, request: SyncRequest, parallel_requests: usize) -> Result<SyncResult, Error> {
+        retrying(self).sync(request, parallel_requests)
+    }
+
+    fn sync_with_prevouts(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_prevout_fetches: usize,
+    ) -> Result<SyncResult, Error> {
+        retrying(self).sync_with_prevouts(request, parallel_requests, max_prevout_fetches)
+    }
+
+    fn sync_with_unconfirmed_ancestors(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_depth: usize,
+    ) -> Result<SyncResult, Error> {
+        retrying(self).sync_with_unconfirmed_ancestors(request, parallel_requests, max_depth)
+    }
+
+    fn detect_capabilities(&self, network: Network) -> Result<ServerCapabilities, Error> {
+        retrying(self).detect_capabilities(network)
+    }
+
+    fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error> {
+        retrying(self).fee_estimates()
+    }
+}
+
+/// Make the requests of `client` through a [`RetryingClient`], which attaches the URL of the
+/// server to their errors.
+fn retrying(
+    client: &esplora_client::BlockingClient,
+) -> RetryingClient<esplora_client::BlockingClient> {
+    RetryingClient::new(client.clone(), RetryPolicy::NONE)
+}
+
+impl EsploraExt for RetryingClient<esplora_client::BlockingClient> {
     fn full_scan<K: Ord + Clone>(
         &self,
         request: FullScanRequest<K>,
@@ -73,7 +187,7 @@ impl EsploraExt for esplora_client::BlockingClient {
         parallel_requests: usize,
     ) -> Result<FullScanResult<K>, Error> {
         let latest_blocks = fetch_latest_blocks(self)?;
-        let (graph_update, last_active_indices) = full_scan_for_index_and_graph_blocking(
+        let (mut graph_update, last_active_indices) = full_scan_for_index_and_graph_blocking(
             self,
             request.spks_by_keychain,
             stop_gap,
@@ -85,6 +199,10 @@ impl EsploraExt for esplora_client::BlockingClient {
             &request.chain_tip,
             graph_update.all_anchors(),
         )?;
+        if self.verify_merkle_proofs {
+            graph_update = drop_unproven_txs(self, graph_update, &chain_update)?;
+        }
+        check_tip_unchanged(self, &latest_blocks)?;
         Ok(FullScanResult {
             chain_update,
             graph_update,
@@ -94,7 +212,7 @@ impl EsploraExt for esplora_client::BlockingClient {
 
     fn sync(&self, request: SyncRequest, parallel_requests: usize) -> Result<SyncResult, Error> {
         let latest_blocks = fetch_latest_blocks(self)?;
-        let graph_update = sync_for_index_and_graph_blocking(
+        let mut graph_update = sync_for_index_and_graph_blocking(
             self,
             request.spks,
             request.txids,
@@ -107,11 +225,109 @@ impl EsploraExt for esplora_client::BlockingClient {
             &request.chain_tip,
             graph_update.all_anchors(),
         )?;
+        if self.verify_merkle_proofs {
+            graph_update = drop_unproven_txs(self, graph_update, &chain_update)?;
+        }
+        check_tip_unchanged(self, &latest_blocks)?;
         Ok(SyncResult {
             chain_update,
             graph_update,
         })
     }
+
+    fn sync_with_prevouts(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_prevout_fetches: usize,
+    ) -> Result<SyncResult, Error> {
+        let mut result = self.sync(request, parallel_requests)?;
+        fetch_prev_txouts(
+            self,
+            &mut result.graph_update,
+            parallel_requests,
+            max_prevout_fetches,
+        )?;
+        Ok(result)
+    }
+
+    fn sync_with_unconfirmed_ancestors(
+        &self,
+        request: SyncRequest,
+        parallel_requests: usize,
+        max_depth: usize,
+    ) -> Result<SyncResult, Error> {
+        let mut result = self.sync(request, parallel_requests)?;
+        fetch_unconfirmed_ancestors(self, &mut result.graph_update, parallel_requests, max_depth)?;
+        Ok(result)
+    }
+
+    fn detect_capabilities(&self, network: Network) -> Result<ServerCapabilities, Error> {
+        if let Some(capabilities) = self.cached_capabilities(network) {
+            return Ok(capabilities);
+        }
+        let mut supported = [false; 3];
+        let paths = capability_probe_paths(network);
+        for (path, supported) in paths.iter().zip(supported.iter_mut()) {
+            *supported = self.request(|c| {
+                let resp = c
+                    .get_request(path)?
+                    .send()
+                    .map_err(esplora_client::Error::Minreq)?;
+                probe_status(resp.status_code as u16)
+            })?;
+        }
+        let [cpfp, rbf, v1_fees] = supported;
+        let capabilities = ServerCapabilities { cpfp, rbf, v1_fees };
+        self.cache_capabilities(network, capabilities);
+        Ok(capabilities)
+    }
+
+    fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error> {
+        Ok(self
+            .request(|c| c.get_fee_estimates())?
+            .into_iter()
+            .filter_map(|(target, sat_per_vb)| {
+                Some((target, fee_rate_from_sat_per_vb(sat_per_vb)?))
+            })
+            .collect())
+    }
+}
+
+impl RetryingClient<esplora_client::BlockingClient> {
+    /// Wrap `client` so that the requests of the extension trait failing with a transient
+    /// [`Error`] are retried according to `retry_policy`.
+    pub fn new(client: esplora_client::BlockingClient, retry_policy: crate::RetryPolicy) -> Self {
+        let base_url = client.url().to_string();
+        Self::with_url(client, &base_url, retry_policy)
+    }
+
+    /// Make a request to the server with `f`, retrying it according to the [`RetryPolicy`] and
+    /// returning its error along with the server's URL.
+    ///
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    fn request<T>(
+        &self,
+        f: impl Fn(&esplora_client::BlockingClient) -> Result<T, esplora_client::Error>,
+    ) -> Result<T, Error> {
+        let mut retries = 0;
+        loop {
+            match f(&self.inner) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let err = self.request_error(err);
+                    match self.retry_policy.retry_delay(&err, retries) {
+                        Some(delay) => {
+                            log_event!(debug, ?err, ?delay, "retrying esplora request");
+                            std::thread::sleep(delay);
+                            retries += 1;
+                        }
+                        None => return Err(err),
+                    }
+                }
+            }
+        }
+    }
 }
 
 /// Fetch latest blocks from Esplora in an atomic call.
@@ -122,10 +338,10 @@ impl EsploraExt for esplora_client::BlockingClient {
 /// an earlier tip rather than a later tip) otherwise the caller may accidentally skip blocks when
 /// alternating between chain-sources.
 fn fetch_latest_blocks(
-    client: &esplora_client::BlockingClient,
+    client: &RetryingClient<esplora_client::BlockingClient>,
 ) -> Result<BTreeMap<u32, BlockHash>, Error> {
     Ok(client
-        .get_blocks(None)?
+        .request(|c| c.get_blocks(None))?
         .into_iter()
         .map(|b| (b.time.height, b.id))
         .collect())
@@ -135,7 +351,7 @@ fn fetch_latest_blocks(
 ///
 /// This first checks the previously fetched `latest_blocks` before fetching from Esplora again.
 fn fetch_block(
-    client: &esplora_client::BlockingClient,
+    client: &RetryingClient<esplora_client::BlockingClient>,
     latest_blocks: &BTreeMap<u32, BlockHash>,
     height: u32,
 ) -> Result<Option<BlockHash>, Error> {
@@ -153,7 +369,28 @@ fn fetch_block(
         return Ok(None);
     }
 
-    Ok(Some(client.get_block_hash(height)?))
+    Ok(Some(client.request(|c| c.get_block_hash(height))?))
+}
+
+/// Check that the tip of `latest_blocks` is still in the best chain.
+///
+/// This is called once the update is complete. If the tip was reorged out in the meantime, the
+/// update may contain data from both sides of the reorg so we return
+/// [`Error::TipChangedDuringSync`].
+fn check_tip_unchanged(
+    client: &RetryingClient<esplora_client::BlockingClient>,
+    latest_blocks: &BTreeMap<u32, BlockHash>,
+) -> Result<(), Error> {
+    let (&height, &hash) = latest_blocks
+        .iter()
+        .last()
+        .expect("must have atleast one entry");
+    if client.request(|c| c.get_block_hash(height))? != hash {
+        return Err(Error::TipChangedDuringSync {
+            tip: BlockId { height, hash },
+        });
+    }
+    Ok(())
 }
 
 /// Create the [`local_chain::Update`].
@@ -161,7 +398,7 @@ fn fetch_block(
 /// We want to have a corresponding checkpoint per anchor height. However, checkpoints fetched
 /// should not surpass `latest_blocks`.
 fn chain_update<A: Anchor>(
-    client: &esplora_client::BlockingClient,
+    client: &RetryingClient<esplora_client::BlockingClient>,
     latest_blocks: &BTreeMap<u32, BlockHash>,
     local_tip: &CheckPoint,
     anchors: &BTreeSet<(A, Txid)>,
@@ -216,7 +453,7 @@ fn chain_update<A: Anchor>(
 /// This performs a full scan to get an update for the [`TxGraph`] and
 /// [`KeychainTxOutIndex`](bdk_chain::keychain::KeychainTxOutIndex).
 fn full_scan_for_index_and_graph_blocking<K: Ord + Clone>(
-    client: &esplora_client::BlockingClient,
+    client: &RetryingClient<esplora_client::BlockingClient>,
     keychain_spks: BTreeMap<K, impl IntoIterator<Item = Indexed<ScriptBuf>>>,
     stop_gap: usize,
     parallel_requests: usize,
@@ -242,7 +479,7 @@ fn full_scan_for_index_and_graph_blocking<K: Ord + Clone>(
                             let mut last_seen = None;
                             let mut spk_txs = Vec::new();
                             loop {
-                                let txs = client.scripthash_txs(&spk, last_seen)?;
+                                let txs = client.request(|c| c.scripthash_txs(&spk, last_seen))?;
                                 let tx_count = txs.len();
                                 last_seen = txs.last().map(|tx| tx.txid);
                                 spk_txs.extend(txs);
@@ -311,7 +548,7 @@ fn full_scan_for_index_and_graph_blocking<K: Ord + Clone>(
 }
 
 fn sync_for_index_and_graph_blocking(
-    client: &esplora_client::BlockingClient,
+    client: &RetryingClient<esplora_client::BlockingClient>,
     misc_spks: impl IntoIterator<Item = ScriptBuf>,
     txids: impl IntoIterator<Item = Txid>,
     outpoints: impl IntoIterator<Item = OutPoint>,
@@ -339,14 +576,13 @@ fn sync_for_index_and_graph_blocking(
         let handles = txids
             .by_ref()
             .take(parallel_requests)
-            .filter(|&txid| tx_graph.get_tx(txid).is_none())
+            .filter(|&txid| !tx_graph.contains_full_tx(txid))
             .map(|txid| {
                 std::thread::spawn({
                     let client = client.clone();
                     move || {
                         client
-                            .get_tx_status(&txid)
-                            .map_err(Box::new)
+                            .request(|c| c.get_tx_status(&txid))
                             .map(|s| (txid, s))
                     }
                 })
@@ -366,23 +602,23 @@ fn sync_for_index_and_graph_blocking(
     }
 
     for op in outpoints {
-        if tx_graph.get_tx(op.txid).is_none() {
-            if let Some(tx) = client.get_tx(&op.txid)? {
+        if !tx_graph.contains_full_tx(op.txid) {
+            if let Some(tx) = client.request(|c| c.get_tx(&op.txid))? {
                 let _ = tx_graph.insert_tx(tx);
             }
-            let status = client.get_tx_status(&op.txid)?;
+            let status = client.request(|c| c.get_tx_status(&op.txid))?;
             if let Some(anchor) = anchor_from_status(&status) {
                 let _ = tx_graph.insert_anchor(op.txid, anchor);
             }
         }
 
-        if let Some(op_status) = client.get_output_status(&op.txid, op.vout as _)? {
+        if let Some(op_status) = client.request(|c| c.get_output_status(&op.txid, op.vout as _))? {
             if let Some(txid) = op_status.txid {
-                if tx_graph.get_tx(txid).is_none() {
-                    if let Some(tx) = client.get_tx(&txid)? {
+                if !tx_graph.contains_full_tx(txid) {
+                    if let Some(tx) = client.request(|c| c.get_tx(&txid))? {
                         let _ = tx_graph.insert_tx(tx);
                     }
-                    let status = client.get_tx_status(&txid)?;
+                    let status = client.request(|c| c.get_tx_status(&txid))?;
                     if let Some(anchor) = anchor_from_status(&status) {
                         let _ = tx_graph.insert_anchor(txid, anchor);
                     }
@@ -394,16 +630,208 @@ fn sync_for_index_and_graph_blocking(
     Ok(tx_graph)
 }
 
+/// Drop the confirmed transactions of `graph` whose merkle proof doesn't match the header of the
+/// block they are anchored to.
+///
+/// The header is only trusted if the anchor block is part of `chain`, the chain update, and the
+/// header hashes to it, otherwise every transaction anchored to that block is dropped.
+fn drop_unproven_txs(
+    client: &RetryingClient<esplora_client::BlockingClient>,
+    graph: TxGraph<ConfirmationTimeHeightAnchor>,
+    chain: &CheckPoint,
+) -> Result<TxGraph<ConfirmationTimeHeightAnchor>, Error> {
+    let mut merkle_roots = BTreeMap::new();
+    let mut unproven = BTreeSet::new();
+    for (anchor, txid) in graph.all_anchors() {
+        let block = anchor.anchor_block;
+        let merkle_root = match merkle_roots.get(&block.hash) {
+            Some(&merkle_root) => merkle_root,
+            None => {
+                let merkle_root = if chain.get(block.height).map(|cp| cp.hash()) == Some(block.hash)
+                {
+                    let header = client.request(|c| c.get_header_by_hash(&block.hash))?;
+                    if header.block_hash() == block.hash {
+                        Some(header.merkle_root)
+                    } else {
+                        log_event!(warn, hash = %block.hash, "header does not match the anchor block");
+                        None
+                    }
+                } else {
+                    log_event!(warn, hash = %block.hash, "anchor block is not in the chain update");
+                    None
+                };
+                merkle_roots.insert(block.hash, merkle_root);
+                merkle_root
+            }
+        };
+        let proven = match (merkle_root, client.request(|c| c.get_merkle_proof(txid))?) {
+            (Some(merkle_root), Some(proof)) => {
+                proof.block_height == block.height
+                    && merkle_proof_is_valid(*txid, &proof, merkle_root)
+            }
+            _ => false,
+        };
+        if !proven {
+            log_event!(warn, %txid, "dropping transaction with an invalid merkle proof");
+            unproven.insert(*txid);
+        }
+    }
+    Ok(remove_txs(graph, &unproven))
+}
+
+/// Fetch the previous outputs spent by the full transactions of `tx_graph` that are missing from
+/// it, and insert them as floating txouts.
+///
+/// At most `max_fetches` previous transactions are fetched.
+fn fetch_prev_txouts(
+    client: &RetryingClient<esplora_client::BlockingClient>,
+    tx_graph: &mut TxGraph<ConfirmationTimeHeightAnchor>,
+    parallel_requests: usize,
+    max_fetches: usize,
+) -> Result<(), Error> {
+    let parallel_requests = Ord::max(parallel_requests, 1);
+    let mut missing = BTreeMap::<Txid, Vec<u32>>::new();
+    for tx_node in tx_graph.full_txs() {
+        if tx_node.tx.is_coinbase() {
+            continue;
+        }
+        for txin in &tx_node.tx.input {
+            let prev_op = txin.previous_output;
+            if tx_graph.get_txout(prev_op).is_none() {
+                missing.entry(prev_op.txid).or_default().push(prev_op.vout);
+            }
+        }
+    }
+
+    let mut prev_txids = missing.into_iter().take(max_fetches);
+    loop {
+        let handles = prev_txids
+            .by_ref()
+            .take(parallel_requests)
+            .map(|(txid, vouts)| {
+                std::thread::spawn({
+                    let client = client.clone();
+                    move || client.request(|c| c.get_tx(&txid)).map(|tx| (tx, vouts))
+                })
+            })
+            .collect::<Vec<JoinHandle<Result<(Option<Transaction>, Vec<u32>), Error>>>>();
+
+        if handles.is_empty() {
+            break;
+        }
+
+        for handle in handles {
+            let (tx, vouts) = handle.join().expect("thread must not panic")?;
+            let tx = match tx {
+                Some(tx) => tx,
+                None => continue,
+            };
+            let txid = tx.compute_txid();
+            for vout in vouts {
+                if let Some(txout) = tx.output.get(vout as usize) {
+                    let _ = tx_graph.insert_txout(OutPoint { txid, vout }, txout.clone());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch the unconfirmed ancestors of the unconfirmed transactions of `tx_graph` that are missing
+/// from it, up to `max_depth` generations.
+///
+/// Unconfirmed ancestors are inserted without anchor. For confirmed parents only the spent outputs
+/// are inserted, as floating txouts.
+fn fetch_unconfirmed_ancestors(
+    client: &RetryingClient<esplora_client::BlockingClient>,
+    tx_graph: &mut TxGraph<ConfirmationTimeHeightAnchor>,
+    parallel_requests: usize,
+    max_depth: usize,
+) -> Result<(), Error> {
+    let parallel_requests = Ord::max(parallel_requests, 1);
+    let mut children = tx_graph
+        .full_txs()
+        .filter(|tx_node| tx_node.anchors.is_empty())
+        .map(|tx_node| tx_node.tx)
+        .collect::<Vec<_>>();
+
+    for _ in 0..max_depth {
+        let mut missing = BTreeMap::<Txid, Vec<u32>>::new();
+        for tx in children.iter().filter(|tx| !tx.is_coinbase()) {
+            for txin in &tx.input {
+                let prev_op = txin.previous_output;
+                if !tx_graph.contains_full_tx(prev_op.txid) {
+                    missing.entry(prev_op.txid).or_default().push(prev_op.vout);
+                }
+            }
+        }
+
+        children = Vec::new();
+        let mut parent_txids = missing.into_iter();
+        loop {
+            let handles = parent_txids
+                .by_ref()
+                .take(parallel_requests)
+                .map(|(txid, vouts)| {
+                    std::thread::spawn({
+                        let client = client.clone();
+                        move || -> Result<_, Error> {
+                            let tx = client.request(|c| c.get_tx(&txid))?;
+                            let status = client.request(|c| c.get_tx_status(&txid))?;
+                            Ok((tx, status, vouts))
+                        }
+                    })
+                })
+                .collect::<Vec<JoinHandle<Result<(Option<Transaction>, TxStatus, Vec<u32>), Error>>>>();
+
+            if handles.is_empty() {
+                break;
+            }
+
+            for handle in handles {
+                let (tx, status, vouts) = handle.join().expect("thread must not panic")?;
+                let tx = match tx {
+                    Some(tx) => tx,
+                    None => continue,
+                };
+                if status.confirmed {
+                    let txid = tx.compute_txid();
+                    for vout in vouts {
+                        if let Some(txout) = tx.output.get(vout as usize) {
+                            let _ = tx_graph.insert_txout(OutPoint { txid, vout }, txout.clone());
+                        }
+                    }
+                } else {
+                    let tx = Arc::new(tx);
+                    let _ = tx_graph.insert_tx(tx.clone());
+                    children.push(tx);
+                }
+            }
+        }
+
+        if children.is_empty() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use crate::blocking_ext::{chain_update, fetch_latest_blocks};
+    use crate::blocking_ext::{chain_update, check_tip_unchanged, fetch_latest_blocks, retrying};
+    use crate::Error;
+    use crate::{mock_server, EsploraExt, RetryPolicy, RetryingClient};
     use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::Network;
     use bdk_chain::bitcoin::Txid;
     use bdk_chain::local_chain::LocalChain;
     use bdk_chain::BlockId;
     use bdk_testenv::{anyhow, bitcoincore_rpc::RpcApi, TestEnv};
-    use esplora_client::{BlockHash, Builder};
+    use esplora_client::BlockHash;
     use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::atomic::Ordering;
     use std::time::Duration;
 
     macro_rules! h {
@@ -465,7 +893,8 @@ mod test {
 
             let env = TestEnv::new()?;
             let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
-            let client = Builder::new(base_url.as_str()).build_blocking();
+            let client =
+                retrying(&esplora_client::Builder::new(base_url.as_str()).build_blocking());
 
             // set env to `initial_env_height`
             if let Some(to_mine) = t
@@ -603,7 +1032,7 @@ mod test {
         // so new blocks can be seen by Electrs
         let env = env.reset_electrsd()?;
         let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
-        let client = Builder::new(base_url.as_str()).build_blocking();
+        let client = retrying(&esplora_client::Builder::new(base_url.as_str()).build_blocking());
 
         struct TestCase {
             name: &'static str,
@@ -783,4 +1212,109 @@ mod test {
 
         Ok(())
     }
+
+    /// Ensure that a reorg of the tip fetched at the start of a sync is detected.
+    #[test]
+    fn detect_tip_reorged_out() -> anyhow::Result<()> {
+        let env = TestEnv::new()?;
+        let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
+        let client = retrying(&esplora_client::Builder::new(base_url.as_str()).build_blocking());
+
+        env.mine_blocks(10, None)?;
+        let tip_height = env.make_checkpoint_tip().height();
+        while client.get_height()? < tip_height {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let latest_blocks = fetch_latest_blocks(&client)?;
+        let (&height, &hash) = latest_blocks.iter().last().expect("must have blocks");
+        check_tip_unchanged(&client, &latest_blocks)?;
+
+        env.reorg(1)?;
+        while client.get_block_hash(height)? == hash {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        match check_tip_unchanged(&client, &latest_blocks) {
+            Err(Error::TipChangedDuringSync { tip }) => assert_eq!(tip, BlockId { height, hash }),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        Ok(())
+    }
+
+    /// Ensure that the capability probes are retried like any other request and that the
+    /// detected capabilities are cached.
+    #[test]
+    fn detect_capabilities_is_retried_and_cached() -> anyhow::Result<()> {
+        let (url, requests) = mock_server(vec![(429, ""), (200, "[]"), (404, ""), (200, "{}")]);
+        let policy = RetryPolicy {
+            max_retries: 1,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let client =
+            RetryingClient::new(esplora_client::Builder::new(&url).build_blocking(), policy);
+        let capabilities = client.detect_capabilities(Network::Regtest)?;
+        assert!(capabilities.cpfp);
+        assert!(!capabilities.rbf);
+        assert!(capabilities.v1_fees);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        // other clients of the same server use the cache
+        let client = esplora_client::Builder::new(&url).build_blocking();
+        assert_eq!(client.detect_capabilities(Network::Regtest)?, capabilities);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+
+    /// Ensure that only the requests failing with a transient error are retried, and only if
+    /// retrying is enabled.
+    #[test]
+    fn retry_transient_errors() -> anyhow::Result<()> {
+        let policy = |max_retries| RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+
+        let (url, requests) = mock_server(vec![(429, ""), (503, ""), (200, r#"{"1":10.0}"#)]);
+        let client = RetryingClient::new(
+            esplora_client::Builder::new(&url).build_blocking(),
+            policy(2),
+        );
+        let estimates = client.fee_estimates()?;
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        let (url, requests) = mock_server(vec![(429, ""), (429, "")]);
+        let client = RetryingClient::new(
+            esplora_client::Builder::new(&url).build_blocking(),
+            policy(1),
+        );
+        let err = client.fee_estimates().expect_err("must fail");
+        assert_eq!(err.http_status(), Some(429));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        for status in [400, 404] {
+            let (url, requests) = mock_server(vec![(status, ""), (200, r#"{"1":10.0}"#)]);
+            let client = RetryingClient::new(
+                esplora_client::Builder::new(&url).build_blocking(),
+                policy(3),
+            );
+            let err = client.fee_estimates().expect_err("must fail");
+            assert_eq!(err.http_status(), Some(status));
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+        }
+
+        // the bare client doesn't retry
+        let (url, requests) = mock_server(vec![(429, ""), (200, r#"{"1":10.0}"#)]);
+        let client = esplora_client::Builder::new(&url).build_blocking();
+        let err = client.fee_estimates().expect_err("must fail");
+        assert_eq!(err.http_status(), Some(429));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
 }
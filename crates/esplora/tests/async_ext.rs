@@ -6,7 +6,7 @@ use std::str::FromStr;
 use std::thread::sleep;
 use std::time::Duration;
 
-use bdk_chain::bitcoin::{Address, Amount, Txid};
+use bdk_chain::bitcoin::{Address, Amount, Network, Txid};
 use bdk_testenv::{anyhow, bitcoincore_rpc::RpcApi, TestEnv};
 
 #[tokio::test]
@@ -229,3 +229,17 @@ pub async fn test_async_update_tx_graph_stop_gap() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+pub async fn detect_capabilities_of_plain_esplora() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
+    let client = Builder::new(base_url.as_str()).build_async()?;
+
+    // electrs only serves the standard Esplora API
+    let capabilities = client.detect_capabilities(Network::Regtest).await?;
+    assert_eq!(capabilities, bdk_esplora::ServerCapabilities::default());
+    assert!(!capabilities.has_extensions());
+
+    Ok(())
+}
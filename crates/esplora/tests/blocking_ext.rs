@@ -1,12 +1,12 @@
 use bdk_chain::spk_client::{FullScanRequest, SyncRequest};
-use bdk_esplora::EsploraExt;
+use bdk_esplora::{EsploraExt, RetryPolicy, RetryingClient};
 use esplora_client::{self, Builder};
 use std::collections::{BTreeSet, HashSet};
 use std::str::FromStr;
 use std::thread::sleep;
 use std::time::Duration;
 
-use bdk_chain::bitcoin::{Address, Amount, Txid};
+use bdk_chain::bitcoin::{Address, Amount, Network, OutPoint, Txid};
 use bdk_testenv::{anyhow, bitcoincore_rpc::RpcApi, TestEnv};
 
 #[test]
@@ -230,3 +230,188 @@ pub fn test_update_tx_graph_stop_gap() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+pub fn test_sync_with_prevouts() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
+    let client = Builder::new(base_url.as_str()).build_blocking();
+
+    let receive_address =
+        Address::from_str("bcrt1qc6fweuf4xjvz4x3gx3t9e0fh4hvqyu2qw4wvxm")?.assume_checked();
+
+    let _block_hashes = env.mine_blocks(101, None)?;
+    let txid = env.bitcoind.client.send_to_address(
+        &receive_address,
+        Amount::from_sat(10000),
+        None,
+        None,
+        None,
+        None,
+        Some(1),
+        None,
+    )?;
+    let _block_hashes = env.mine_blocks(1, None)?;
+    while client.get_height().unwrap() < 102 {
+        sleep(Duration::from_millis(10))
+    }
+    let cp_tip = env.make_checkpoint_tip();
+    let request =
+        || SyncRequest::from_chain_tip(cp_tip.clone()).set_outpoints(vec![OutPoint::new(txid, 0)]);
+
+    // transactions found through outpoints don't come with their previous outputs
+    let graph_update = client.sync(request(), 1)?.graph_update;
+    let tx = graph_update.get_tx(txid).expect("tx must exist");
+    assert!(graph_update.calculate_fee(&tx).is_err());
+
+    let graph_update = client.sync_with_prevouts(request(), 1, 0)?.graph_update;
+    assert!(graph_update.calculate_fee(&tx).is_err());
+
+    let graph_update = client.sync_with_prevouts(request(), 1, 10)?.graph_update;
+    let fee = graph_update.calculate_fee(&tx).expect("fee must exist");
+    let tx_fee = env
+        .bitcoind
+        .client
+        .get_transaction(&txid, None)?
+        .fee
+        .expect("fee must exist")
+        .abs()
+        .to_unsigned()
+        .expect("valid `Amount`");
+    assert_eq!(fee, tx_fee);
+
+    Ok(())
+}
+
+#[test]
+pub fn test_sync_verify_merkle_proofs() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
+    let client = Builder::new(base_url.as_str()).build_blocking();
+
+    let receive_address =
+        Address::from_str("bcrt1qc6fweuf4xjvz4x3gx3t9e0fh4hvqyu2qw4wvxm")?.assume_checked();
+
+    let _block_hashes = env.mine_blocks(101, None)?;
+    let txid = env.send(&receive_address, Amount::from_sat(10_000))?;
+    let _block_hashes = env.mine_blocks(1, None)?;
+    while client.get_height().unwrap() < 102 {
+        sleep(Duration::from_millis(10))
+    }
+
+    // the proofs served by a honest server are valid, so nothing is dropped
+    let client = RetryingClient::new(client, RetryPolicy::NONE).verify_merkle_proofs(true);
+    let request = SyncRequest::from_chain_tip(env.make_checkpoint_tip())
+        .set_spks(vec![receive_address.script_pubkey()]);
+    let graph_update = client.sync(request, 1)?.graph_update;
+    assert!(graph_update.get_tx(txid).is_some());
+    assert!(graph_update
+        .all_anchors()
+        .iter()
+        .any(|(anchor, anchor_txid)| *anchor_txid == txid && anchor.confirmation_height == 102));
+
+    // full scans are verified too
+    let request = FullScanRequest::from_chain_tip(env.make_checkpoint_tip())
+        .set_spks_for_keychain(0, [(0, receive_address.script_pubkey())]);
+    let graph_update = client.full_scan(request, 1, 1)?.graph_update;
+    assert!(graph_update.get_tx(txid).is_some());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_sync_with_unconfirmed_ancestors() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
+    let client = Builder::new(base_url.as_str()).build_blocking();
+
+    let receive_address =
+        Address::from_str("bcrt1qc6fweuf4xjvz4x3gx3t9e0fh4hvqyu2qw4wvxm")?.assume_checked();
+
+    let _block_hashes = env.mine_blocks(101, None)?;
+    let parent_address = env
+        .bitcoind
+        .client
+        .get_new_address(None, None)?
+        .assume_checked();
+    let parent_txid = env.bitcoind.client.send_to_address(
+        &parent_address,
+        Amount::from_sat(100_000),
+        None,
+        None,
+        None,
+        None,
+        Some(1),
+        None,
+    )?;
+    // lock the confirmed coins so that the payment spends the unconfirmed parent
+    let confirmed = env
+        .bitcoind
+        .client
+        .list_unspent(Some(1), None, None, None, None)?
+        .into_iter()
+        .map(|utxo| OutPoint::new(utxo.txid, utxo.vout))
+        .collect::<Vec<_>>();
+    env.bitcoind.client.lock_unspent(&confirmed)?;
+    let txid = env.bitcoind.client.send_to_address(
+        &receive_address,
+        Amount::from_sat(10_000),
+        None,
+        None,
+        None,
+        None,
+        Some(1),
+        None,
+    )?;
+    let tx = env.bitcoind.client.get_raw_transaction(&txid, None)?;
+    assert!(tx
+        .input
+        .iter()
+        .all(|txin| txin.previous_output.txid == parent_txid));
+    while client.get_tx(&txid)?.is_none() {
+        sleep(Duration::from_millis(10))
+    }
+    let cp_tip = env.make_checkpoint_tip();
+    let request = || {
+        SyncRequest::from_chain_tip(cp_tip.clone()).set_spks(vec![receive_address.script_pubkey()])
+    };
+
+    let graph_update = client.sync(request(), 1)?.graph_update;
+    assert!(graph_update.get_tx(txid).is_some());
+    assert!(graph_update.get_tx(parent_txid).is_none());
+
+    let graph_update = client
+        .sync_with_unconfirmed_ancestors(request(), 1, 0)?
+        .graph_update;
+    assert!(graph_update.get_tx(parent_txid).is_none());
+
+    // the parent is unconfirmed, its own parents are confirmed so the walk stops there
+    let graph_update = client
+        .sync_with_unconfirmed_ancestors(request(), 1, 10)?
+        .graph_update;
+    assert!(graph_update.get_tx(parent_txid).is_some());
+    assert_eq!(
+        graph_update
+            .full_txs()
+            .map(|tx| tx.txid)
+            .collect::<BTreeSet<_>>(),
+        [txid, parent_txid].into()
+    );
+    assert!(graph_update.all_anchors().is_empty());
+
+    Ok(())
+}
+
+#[test]
+pub fn detect_capabilities_of_plain_esplora() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let base_url = format!("http://{}", &env.electrsd.esplora_url.clone().unwrap());
+    let client = Builder::new(base_url.as_str()).build_blocking();
+
+    // electrs only serves the standard Esplora API
+    let capabilities = client.detect_capabilities(Network::Regtest)?;
+    assert_eq!(capabilities, bdk_esplora::ServerCapabilities::default());
+    assert!(!capabilities.has_extensions());
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use bdk_chain::Append;
 use bincode::Options;
 use std::{
     fmt::{self, Debug},
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::{self, Read, Seek, Write},
     marker::PhantomData,
     path::Path,
@@ -131,6 +131,54 @@ where
         }
     }
 
+    /// Open an existing [`Store`] file whose changesets were written as `L`, and rewrite them as
+    /// `C`.
+    ///
+    /// Changesets are encoded with bincode, which doesn't describe the encoded fields, so changesets
+    /// written with a previous layout of `C` can't be read as `C`. This reads them as `L` instead,
+    /// converts them to `C` and writes their aggregate to a new file prefixed with `magic`, which
+    /// then replaces the file at `file_path`. The file is left untouched if reading it fails.
+    ///
+    /// `old_magic` and `magic` may be the same. Using new magic bytes for the new layout makes
+    /// [`open`] fail with [`FileError::InvalidMagicBytes`] for files that still need to be migrated.
+    ///
+    /// [`open`]: Store::open
+    pub fn migrate<L, P>(old_magic: &[u8], magic: &[u8], file_path: P) -> Result<Self, MigrateError>
+    where
+        L: Into<C> + serde::de::DeserializeOwned,
+        P: AsRef<Path>,
+    {
+        let file_path = file_path.as_ref();
+        let mut old_file = Self::open(old_magic, file_path)?.db_file;
+        let mut changeset = Option::<C>::None;
+        for next_changeset in EntryIter::<L>::new(old_magic.len() as u64, &mut old_file) {
+            let next_changeset = next_changeset?.into();
+            match &mut changeset {
+                Some(changeset) => changeset.append(next_changeset),
+                changeset => *changeset = Some(next_changeset),
+            }
+        }
+        drop(old_file);
+
+        let mut migrated_path = file_path.as_os_str().to_owned();
+        migrated_path.push(".migrated");
+        // a previous migration may have failed before replacing the file
+        if Path::new(&migrated_path).exists() {
+            fs::remove_file(&migrated_path)?;
+        }
+        let mut store = Self::create_new(magic, &migrated_path)?;
+        if let Some(changeset) = changeset {
+            store.append_changeset(&changeset)?;
+        }
+        store.db_file.sync_all()?;
+        drop(store);
+        fs::rename(&migrated_path, file_path)?;
+
+        let mut store = Self::open(magic, file_path)?;
+        store.db_file.seek(io::SeekFrom::End(0))?;
+        Ok(store)
+    }
+
     /// Iterates over the stored changeset from first to last, changing the seek position at each
     /// iteration.
     ///
@@ -222,6 +270,44 @@ impl<C> std::fmt::Display for AggregateChangesetsError<C> {
 
 impl<C: fmt::Debug> std::error::Error for AggregateChangesetsError<C> {}
 
+/// Error type for [`Store::migrate`].
+#[derive(Debug)]
+pub enum MigrateError {
+    /// Failure to open or write a store file.
+    File(FileError),
+    /// Failure to read a changeset written with the previous layout.
+    Iter(IterError),
+}
+
+impl std::fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(e) => write!(f, "failed to migrate the store file: {}", e),
+            Self::Iter(e) => write!(f, "failed to read the store file to migrate: {}", e),
+        }
+    }
+}
+
+impl From<FileError> for MigrateError {
+    fn from(value: FileError) -> Self {
+        Self::File(value)
+    }
+}
+
+impl From<IterError> for MigrateError {
+    fn from(value: IterError) -> Self {
+        Self::Iter(value)
+    }
+}
+
+impl From<io::Error> for MigrateError {
+    fn from(value: io::Error) -> Self {
+        Self::File(FileError::Io(value))
+    }
+}
+
+impl std::error::Error for MigrateError {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -462,4 +548,164 @@ mod test {
             assert_eq!(aggregation, exp_aggregation);
         }
     }
+
+    #[test]
+    fn migrate_combined_changeset_v0() {
+        use bdk_chain::bitcoin::{
+            absolute, constants::genesis_block, transaction, Amount, Network, ScriptBuf,
+            Transaction, TxOut,
+        };
+        #[cfg(feature = "labels")]
+        use bdk_chain::label;
+        use bdk_chain::persist::{CombinedChangeSet, CombinedChangeSetV0};
+        use bdk_chain::{indexed_tx_graph, local_chain, tx_graph, BlockId};
+        use std::sync::Arc;
+
+        type ChangeSet = CombinedChangeSet<u32, BlockId>;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("db_file");
+        let genesis_hash = genesis_block(Network::Signet).block_hash();
+        let tx = Arc::new(Transaction {
+            version: transaction::Version::ONE,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        });
+        let txid = tx.compute_txid();
+        let old_changesets = [
+            CombinedChangeSetV0::<u32, BlockId> {
+                chain: local_chain::ChangeSet::from([(0, Some(genesis_hash))]),
+                indexed_tx_graph: indexed_tx_graph::ChangeSetV0 {
+                    graph: tx_graph::ChangeSetV0 {
+                        txs: [tx].into(),
+                        txouts: Default::default(),
+                        anchors: Default::default(),
+                        last_seen: [(txid, 100)].into(),
+                    },
+                    indexer: Default::default(),
+                },
+                network: Some(Network::Signet),
+            },
+            CombinedChangeSetV0 {
+                chain: local_chain::ChangeSet::from([(1, Some(genesis_hash))]),
+                indexed_tx_graph: indexed_tx_graph::ChangeSetV0 {
+                    graph: tx_graph::ChangeSetV0 {
+                        txs: Default::default(),
+                        txouts: Default::default(),
+                        anchors: [(
+                            BlockId {
+                                height: 1,
+                                hash: genesis_hash,
+                            },
+                            txid,
+                        )]
+                        .into(),
+                        last_seen: [(txid, 200)].into(),
+                    },
+                    indexer: Default::default(),
+                },
+                network: None,
+            },
+        ];
+
+        // a store written before `labels` and `last_evicted` were added to the changesets
+        {
+            let mut file = File::create(&file_path).unwrap();
+            file.write_all(&TEST_MAGIC_BYTES).unwrap();
+            for changeset in &old_changesets {
+                bincode_options()
+                    .serialize_into(&mut file, changeset)
+                    .unwrap();
+            }
+        }
+
+        let mut exp_aggregation = old_changesets.iter().cloned().map(ChangeSet::from).fold(
+            ChangeSet::default(),
+            |mut acc, v| {
+                Append::append(&mut acc, v);
+                acc
+            },
+        );
+        let mut db = Store::<ChangeSet>::migrate::<CombinedChangeSetV0<u32, BlockId>, _>(
+            &TEST_MAGIC_BYTES,
+            &TEST_MAGIC_BYTES,
+            &file_path,
+        )
+        .expect("must migrate");
+        assert_eq!(
+            db.aggregate_changesets().expect("must read migrated file"),
+            Some(exp_aggregation.clone())
+        );
+
+        // changesets with labels and evictions are written after the migrated ones
+        let new_changeset = ChangeSet {
+            indexed_tx_graph: tx_graph::ChangeSet {
+                last_evicted: [(txid, 300)].into(),
+                ..Default::default()
+            }
+            .into(),
+            #[cfg(feature = "labels")]
+            labels: label::ChangeSet {
+                txs: [(txid, Some("rent".to_string()))].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        db.append_changeset(&new_changeset).unwrap();
+        drop(db);
+        Append::append(&mut exp_aggregation, new_changeset);
+
+        let aggregation = Store::<ChangeSet>::open(&TEST_MAGIC_BYTES, &file_path)
+            .unwrap()
+            .aggregate_changesets()
+            .expect("must aggregate changesets");
+        assert_eq!(aggregation, Some(exp_aggregation));
+        assert!(!temp_dir.path().join("db_file.migrated").exists());
+    }
+
+    #[test]
+    #[cfg(feature = "labels")]
+    fn migrate_combined_changeset_without_labels() {
+        use bdk_chain::bitcoin::{constants::genesis_block, Network};
+        use bdk_chain::persist::{CombinedChangeSet, CombinedChangeSetWithoutLabels};
+        use bdk_chain::{local_chain, BlockId};
+
+        type ChangeSet = CombinedChangeSet<u32, BlockId>;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("db_file");
+        let old_changeset = CombinedChangeSetWithoutLabels::<u32, BlockId> {
+            chain: local_chain::ChangeSet::from([(
+                0,
+                Some(genesis_block(Network::Signet).block_hash()),
+            )]),
+            indexed_tx_graph: Default::default(),
+            network: Some(Network::Signet),
+        };
+
+        // a store written without the `labels` feature
+        {
+            let mut file = File::create(&file_path).unwrap();
+            file.write_all(&TEST_MAGIC_BYTES).unwrap();
+            bincode_options()
+                .serialize_into(&mut file, &old_changeset)
+                .unwrap();
+        }
+
+        let mut db =
+            Store::<ChangeSet>::migrate::<CombinedChangeSetWithoutLabels<u32, BlockId>, _>(
+                &TEST_MAGIC_BYTES,
+                &TEST_MAGIC_BYTES,
+                &file_path,
+            )
+            .expect("must migrate");
+        assert_eq!(
+            db.aggregate_changesets().expect("must read migrated file"),
+            Some(ChangeSet::from(old_changeset))
+        );
+    }
 }
@@ -0,0 +1,26 @@
+-- label of a transaction
+-- txid is transaction hash hex string (reversed)
+CREATE TABLE tx_label
+(
+    txid  TEXT PRIMARY KEY NOT NULL,
+    label TEXT             NOT NULL
+) STRICT;
+
+-- label of a transaction output
+-- Outpoint txid hash hex string (reversed)
+-- Outpoint vout
+CREATE TABLE txout_label
+(
+    txid  TEXT    NOT NULL,
+    vout  INTEGER NOT NULL,
+    label TEXT    NOT NULL,
+    PRIMARY KEY (txid, vout)
+) STRICT;
+
+-- label of a script pubkey
+-- script is the consensus encoded script pubkey
+CREATE TABLE spk_label
+(
+    script BLOB PRIMARY KEY NOT NULL,
+    label  TEXT             NOT NULL
+) STRICT;
@@ -0,0 +1,2 @@
+-- last evicted is a u64 unix epoch seconds
+ALTER TABLE tx ADD COLUMN last_evicted INTEGER;
@@ -0,0 +1,2 @@
+-- first seen is a u64 unix epoch seconds
+ALTER TABLE tx ADD COLUMN first_seen INTEGER;
@@ -12,6 +12,8 @@ use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
 use crate::Error;
+#[cfg(feature = "labels")]
+use bdk_chain::label;
 use bdk_chain::persist::{CombinedChangeSet, PersistBackend};
 use bdk_chain::{
     indexed_tx_graph, keychain, local_chain, tx_graph, Anchor, Append, DescriptorExt, DescriptorId,
@@ -410,6 +412,52 @@ impl<K, A> Store<K, A> {
             .collect()
     }
 
+    /// Select all transactions with last_evicted values.
+    fn select_last_evicted(
+        db_transaction: &rusqlite::Transaction,
+    ) -> Result<BTreeMap<Txid, u64>, Error> {
+        // load tx last_evicted
+        let mut select_last_evicted_stmt = db_transaction
+            .prepare_cached("SELECT txid, last_evicted FROM tx WHERE last_evicted IS NOT NULL")
+            .expect("select tx last evicted statement");
+
+        let last_evicted = select_last_evicted_stmt
+            .query_map([], |row| {
+                let txid = row.get_unwrap::<usize, String>(0);
+                let txid = Txid::from_str(&txid).expect("txid");
+                let last_evicted = row.get_unwrap::<usize, u64>(1);
+                Ok((txid, last_evicted))
+            })
+            .map_err(Error::Sqlite)?;
+        last_evicted
+            .into_iter()
+            .map(|row| row.map_err(Error::Sqlite))
+            .collect()
+    }
+
+    /// Select all transactions with first_seen values.
+    fn select_first_seen(
+        db_transaction: &rusqlite::Transaction,
+    ) -> Result<BTreeMap<Txid, u64>, Error> {
+        // load tx first_seen
+        let mut select_first_seen_stmt = db_transaction
+            .prepare_cached("SELECT txid, first_seen FROM tx WHERE first_seen IS NOT NULL")
+            .expect("select tx first seen statement");
+
+        let first_seen = select_first_seen_stmt
+            .query_map([], |row| {
+                let txid = row.get_unwrap::<usize, String>(0);
+                let txid = Txid::from_str(&txid).expect("txid");
+                let first_seen = row.get_unwrap::<usize, u64>(1);
+                Ok((txid, first_seen))
+            })
+            .map_err(Error::Sqlite)?;
+        first_seen
+            .into_iter()
+            .map(|row| row.map_err(Error::Sqlite))
+            .collect()
+    }
+
     /// Update transaction last seen times.
     fn update_last_seen(
         db_transaction: &rusqlite::Transaction,
@@ -427,6 +475,163 @@ impl<K, A> Store<K, A> {
         }
         Ok(())
     }
+
+    /// Update transaction first seen times.
+    fn update_first_seen(
+        db_transaction: &rusqlite::Transaction,
+        tx_graph_changeset: &indexed_tx_graph::ChangeSet<A, keychain::ChangeSet<K>>,
+    ) -> Result<(), Error> {
+        for (txid, first_seen) in tx_graph_changeset.graph.first_seen.iter() {
+            let insert_or_update_tx_stmt = &mut db_transaction
+                .prepare_cached("INSERT INTO tx (txid, first_seen) VALUES (:txid, :first_seen) ON CONFLICT (txid) DO UPDATE SET first_seen = :first_seen WHERE txid = :txid")
+                .expect("insert or update tx first_seen statement");
+            insert_or_update_tx_stmt
+                .execute(named_params! {":txid": txid.to_string(), ":first_seen": first_seen })
+                .map_err(Error::Sqlite)?;
+        }
+        Ok(())
+    }
+
+    /// Update transaction last evicted times.
+    fn update_last_evicted(
+        db_transaction: &rusqlite::Transaction,
+        tx_graph_changeset: &indexed_tx_graph::ChangeSet<A, keychain::ChangeSet<K>>,
+    ) -> Result<(), Error> {
+        for (txid, last_evicted) in tx_graph_changeset.graph.last_evicted.iter() {
+            let insert_or_update_tx_stmt = &mut db_transaction
+                .prepare_cached("INSERT INTO tx (txid, last_evicted) VALUES (:txid, :last_evicted) ON CONFLICT (txid) DO UPDATE SET last_evicted = :last_evicted WHERE txid = :txid")
+                .expect("insert or update tx last_evicted statement");
+            insert_or_update_tx_stmt
+                .execute(named_params! {":txid": txid.to_string(), ":last_evicted": last_evicted })
+                .map_err(Error::Sqlite)?;
+        }
+        Ok(())
+    }
+}
+
+/// Label table related functions.
+#[cfg(feature = "labels")]
+impl<K, A> Store<K, A> {
+    /// Insert, update or delete labels.
+    fn insert_or_delete_labels(
+        db_transaction: &rusqlite::Transaction,
+        label_changeset: &label::ChangeSet,
+    ) -> Result<(), Error> {
+        for (txid, label) in label_changeset.txs.iter() {
+            let txid = txid.to_string();
+            match label {
+                Some(label) => {
+                    let insert_stmt = &mut db_transaction
+                        .prepare_cached("INSERT INTO tx_label (txid, label) VALUES (:txid, :label) ON CONFLICT (txid) DO UPDATE SET label = :label WHERE txid = :txid")
+                        .expect("insert or update tx label statement");
+                    insert_stmt
+                        .execute(named_params! {":txid": txid, ":label": label })
+                        .map_err(Error::Sqlite)?;
+                }
+                None => {
+                    let delete_stmt = &mut db_transaction
+                        .prepare_cached("DELETE FROM tx_label WHERE txid = :txid")
+                        .expect("delete tx label statement");
+                    delete_stmt
+                        .execute(named_params! {":txid": txid })
+                        .map_err(Error::Sqlite)?;
+                }
+            }
+        }
+        for (outpoint, label) in label_changeset.outputs.iter() {
+            let txid = outpoint.txid.to_string();
+            let vout = outpoint.vout;
+            match label {
+                Some(label) => {
+                    let insert_stmt = &mut db_transaction
+                        .prepare_cached("INSERT INTO txout_label (txid, vout, label) VALUES (:txid, :vout, :label) ON CONFLICT (txid, vout) DO UPDATE SET label = :label WHERE txid = :txid AND vout = :vout")
+                        .expect("insert or update txout label statement");
+                    insert_stmt
+                        .execute(named_params! {":txid": txid, ":vout": vout, ":label": label })
+                        .map_err(Error::Sqlite)?;
+                }
+                None => {
+                    let delete_stmt = &mut db_transaction
+                        .prepare_cached(
+                            "DELETE FROM txout_label WHERE txid = :txid AND vout = :vout",
+                        )
+                        .expect("delete txout label statement");
+                    delete_stmt
+                        .execute(named_params! {":txid": txid, ":vout": vout })
+                        .map_err(Error::Sqlite)?;
+                }
+            }
+        }
+        for (spk, label) in label_changeset.spks.iter() {
+            let script = spk.as_bytes();
+            match label {
+                Some(label) => {
+                    let insert_stmt = &mut db_transaction
+                        .prepare_cached("INSERT INTO spk_label (script, label) VALUES (:script, :label) ON CONFLICT (script) DO UPDATE SET label = :label WHERE script = :script")
+                        .expect("insert or update spk label statement");
+                    insert_stmt
+                        .execute(named_params! {":script": script, ":label": label })
+                        .map_err(Error::Sqlite)?;
+                }
+                None => {
+                    let delete_stmt = &mut db_transaction
+                        .prepare_cached("DELETE FROM spk_label WHERE script = :script")
+                        .expect("delete spk label statement");
+                    delete_stmt
+                        .execute(named_params! {":script": script })
+                        .map_err(Error::Sqlite)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Select all labels.
+    fn select_labels(db_transaction: &rusqlite::Transaction) -> Result<label::ChangeSet, Error> {
+        let mut select_tx_label_stmt = db_transaction
+            .prepare_cached("SELECT txid, label FROM tx_label")
+            .expect("select tx label statement");
+        let txs = select_tx_label_stmt
+            .query_map([], |row| {
+                let txid = row.get_unwrap::<usize, String>(0);
+                let txid = Txid::from_str(&txid).expect("txid");
+                let label = row.get_unwrap::<usize, String>(1);
+                Ok((txid, Some(label)))
+            })
+            .map_err(Error::Sqlite)?
+            .map(|row| row.map_err(Error::Sqlite))
+            .collect::<Result<_, _>>()?;
+
+        let mut select_txout_label_stmt = db_transaction
+            .prepare_cached("SELECT txid, vout, label FROM txout_label")
+            .expect("select txout label statement");
+        let outputs = select_txout_label_stmt
+            .query_map([], |row| {
+                let txid = row.get_unwrap::<usize, String>(0);
+                let txid = Txid::from_str(&txid).expect("txid");
+                let vout = row.get_unwrap::<usize, u32>(1);
+                let label = row.get_unwrap::<usize, String>(2);
+                Ok((OutPoint::new(txid, vout), Some(label)))
+            })
+            .map_err(Error::Sqlite)?
+            .map(|row| row.map_err(Error::Sqlite))
+            .collect::<Result<_, _>>()?;
+
+        let mut select_spk_label_stmt = db_transaction
+            .prepare_cached("SELECT script, label FROM spk_label")
+            .expect("select spk label statement");
+        let spks = select_spk_label_stmt
+            .query_map([], |row| {
+                let script = row.get_unwrap::<usize, Vec<u8>>(0);
+                let label = row.get_unwrap::<usize, String>(1);
+                Ok((ScriptBuf::from_bytes(script), Some(label)))
+            })
+            .map_err(Error::Sqlite)?
+            .map(|row| row.map_err(Error::Sqlite))
+            .collect::<Result<_, _>>()?;
+
+        Ok(label::ChangeSet { txs, outputs, spks })
+    }
 }
 
 /// Anchor table related functions.
@@ -510,6 +715,11 @@ where
         Self::insert_txouts(&db_transaction, tx_graph_changeset)?;
         Self::insert_anchors(&db_transaction, tx_graph_changeset)?;
         Self::update_last_seen(&db_transaction, tx_graph_changeset)?;
+        Self::update_last_evicted(&db_transaction, tx_graph_changeset)?;
+        Self::update_first_seen(&db_transaction, tx_graph_changeset)?;
+
+        #[cfg(feature = "labels")]
+        Self::insert_or_delete_labels(&db_transaction, &changeset.labels)?;
         db_transaction.commit().map_err(Error::Sqlite)
     }
 
@@ -522,14 +732,20 @@ where
         let last_revealed = Self::select_last_revealed(&db_transaction)?;
         let txs = Self::select_txs(&db_transaction)?;
         let last_seen = Self::select_last_seen(&db_transaction)?;
+        let last_evicted = Self::select_last_evicted(&db_transaction)?;
+        let first_seen = Self::select_first_seen(&db_transaction)?;
         let txouts = Self::select_txouts(&db_transaction)?;
         let anchors = Self::select_anchors(&db_transaction)?;
+        #[cfg(feature = "labels")]
+        let labels = Self::select_labels(&db_transaction)?;
 
         let graph: tx_graph::ChangeSet<A> = tx_graph::ChangeSet {
             txs,
             txouts,
             anchors,
             last_seen,
+            last_evicted,
+            first_seen,
         };
 
         let indexer: keychain::ChangeSet<K> = keychain::ChangeSet {
@@ -540,14 +756,21 @@ where
         let indexed_tx_graph: indexed_tx_graph::ChangeSet<A, keychain::ChangeSet<K>> =
             indexed_tx_graph::ChangeSet { graph, indexer };
 
-        if network.is_none() && chain.is_empty() && indexed_tx_graph.is_empty() {
+        // `labels` may be a field even without the `labels` feature, if another crate enables it
+        // on `bdk_chain`
+        #[allow(clippy::needless_update)]
+        let changeset = CombinedChangeSet {
+            chain,
+            indexed_tx_graph,
+            network,
+            #[cfg(feature = "labels")]
+            labels,
+            ..Default::default()
+        };
+        if changeset.is_empty() {
             Ok(None)
         } else {
-            Ok(Some(CombinedChangeSet {
-                chain,
-                indexed_tx_graph,
-                network,
-            }))
+            Ok(Some(changeset))
         }
     }
 }
@@ -561,7 +784,9 @@ mod test {
     use bdk_chain::bitcoin::hashes::hex::FromHex;
     use bdk_chain::bitcoin::transaction::Transaction;
     use bdk_chain::bitcoin::Network::Testnet;
-    use bdk_chain::bitcoin::{secp256k1, BlockHash, OutPoint};
+    use bdk_chain::bitcoin::{secp256k1, BlockHash, OutPoint, Txid};
+    #[cfg(feature = "labels")]
+    use bdk_chain::label;
     use bdk_chain::miniscript::Descriptor;
     use bdk_chain::persist::{CombinedChangeSet, PersistBackend};
     use bdk_chain::{
@@ -637,6 +862,39 @@ mod test {
         assert_eq!(agg_changeset, Some(agg_test_changesets));
     }
 
+    #[test]
+    #[cfg(feature = "labels")]
+    fn insert_and_delete_labels() {
+        let conn = Connection::open_in_memory().expect("in memory connection");
+        let mut store = Store::<Keychain, BlockId>::new(conn).expect("create new memory db store");
+
+        let outpoint = OutPoint::new(Txid::all_zeros(), 1);
+        let changeset = CombinedChangeSet {
+            labels: label::ChangeSet {
+                outputs: [(outpoint, Some("payment".to_string()))].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        store.write_changes(&changeset).expect("write changeset");
+        assert_eq!(
+            store.load_changes().expect("load changeset"),
+            Some(changeset)
+        );
+
+        let changeset = CombinedChangeSet {
+            labels: label::ChangeSet {
+                outputs: [(outpoint, None)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        store.write_changes(&changeset).expect("write changeset");
+        assert_eq!(store.load_changes().expect("load changeset"), None);
+    }
+
+    // the changesets have a `labels` field whenever `bdk_chain` has the `labels` feature
+    #[allow(clippy::needless_update)]
     fn create_test_changesets<A: Anchor + Copy>(
         anchor_fn: &dyn Fn(u32, u64, BlockHash) -> A,
     ) -> (
@@ -700,6 +958,13 @@ mod test {
                 (tx2.compute_txid(), 1608919121),
             ]
             .into(),
+            last_evicted: [(tx2.compute_txid(), 1608919122)].into(),
+            first_seen: [
+                (tx0.compute_txid(), 1598918400),
+                (tx1.compute_txid(), 1598919121),
+                (tx2.compute_txid(), 1608919121),
+            ]
+            .into(),
         };
 
         let keychain_changeset = keychain::ChangeSet {
@@ -716,10 +981,28 @@ mod test {
         // test changesets to write to db
         let mut changesets = Vec::new();
 
+        #[cfg(feature = "labels")]
+        let label_changeset = label::ChangeSet {
+            txs: [(tx0.compute_txid(), Some("genesis".to_string()))].into(),
+            outputs: [
+                (outpoint0_0, Some("satoshi".to_string())),
+                (outpoint1_0, Some("coinbase".to_string())),
+            ]
+            .into(),
+            spks: [(
+                tx2.output[0].script_pubkey.clone(),
+                Some("p2pk".to_string()),
+            )]
+            .into(),
+        };
+
         changesets.push(CombinedChangeSet {
             chain: block_changeset,
             indexed_tx_graph: graph_changeset,
             network: network_changeset,
+            #[cfg(feature = "labels")]
+            labels: label_changeset,
+            ..Default::default()
         });
 
         // create changeset that sets the whole tx2 and updates it's lastseen where before there was only the txid and last_seen
@@ -728,6 +1011,8 @@ mod test {
             txouts: BTreeMap::default(),
             anchors: BTreeSet::default(),
             last_seen: [(tx2.compute_txid(), 1708919121)].into(),
+            last_evicted: BTreeMap::default(),
+            first_seen: BTreeMap::default(),
         };
 
         let graph_changeset2: indexed_tx_graph::ChangeSet<A, keychain::ChangeSet<Keychain>> =
@@ -740,6 +1025,7 @@ mod test {
             chain: local_chain::ChangeSet::default(),
             indexed_tx_graph: graph_changeset2,
             network: None,
+            ..Default::default()
         });
 
         // create changeset that adds a new anchor2 for tx0 and tx1
@@ -748,6 +1034,8 @@ mod test {
             txouts: BTreeMap::default(),
             anchors: [(anchor2, tx0.compute_txid()), (anchor2, tx1.compute_txid())].into(),
             last_seen: BTreeMap::default(),
+            last_evicted: BTreeMap::default(),
+            first_seen: BTreeMap::default(),
         };
 
         let graph_changeset3: indexed_tx_graph::ChangeSet<A, keychain::ChangeSet<Keychain>> =
@@ -760,6 +1048,13 @@ mod test {
             chain: local_chain::ChangeSet::default(),
             indexed_tx_graph: graph_changeset3,
             network: None,
+            // update an output label
+            #[cfg(feature = "labels")]
+            labels: label::ChangeSet {
+                outputs: [(outpoint0_0, Some("genesis coinbase".to_string()))].into(),
+                ..Default::default()
+            },
+            ..Default::default()
         });
 
         // aggregated test changesets
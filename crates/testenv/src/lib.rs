@@ -2,13 +2,13 @@ use bdk_chain::{
     bitcoin::{
         address::NetworkChecked, block::Header, hash_types::TxMerkleNode, hashes::Hash,
         secp256k1::rand::random, transaction, Address, Amount, Block, BlockHash, CompactTarget,
-        ScriptBuf, ScriptHash, Transaction, TxIn, TxOut, Txid,
+        OutPoint, ScriptBuf, ScriptHash, Transaction, TxIn, TxOut, Txid,
     },
     local_chain::CheckPoint,
-    BlockId,
+    BlockId, COINBASE_MATURITY,
 };
 use bitcoincore_rpc::{
-    bitcoincore_rpc_json::{GetBlockTemplateModes, GetBlockTemplateRules},
+    bitcoincore_rpc_json::{GetBlockTemplateModes, GetBlockTemplateRules, GetMempoolEntryResult},
     RpcApi,
 };
 pub use electrsd;
@@ -21,42 +21,157 @@ use std::time::Duration;
 
 /// Struct for running a regtest environment with a single `bitcoind` node with an `electrs`
 /// instance connected to it.
+///
+/// Additional `bitcoind` nodes, which `electrs` is not connected to, can be started with
+/// [`TestEnv::new_with_peer`].
 pub struct TestEnv {
     pub bitcoind: electrsd::bitcoind::BitcoinD,
     pub electrsd: electrsd::ElectrsD,
+    /// Additional `bitcoind` nodes, with node indexes starting at 1.
+    pub peers: Vec<electrsd::bitcoind::BitcoinD>,
 }
 
 impl TestEnv {
     /// Construct a new [`TestEnv`] instance with default configurations.
     pub fn new() -> anyhow::Result<Self> {
-        let bitcoind = match std::env::var_os("BITCOIND_EXE") {
-            Some(bitcoind_path) => electrsd::bitcoind::BitcoinD::new(bitcoind_path),
-            None => {
-                let bitcoind_exe = electrsd::bitcoind::downloaded_exe_path()
-                    .expect(
+        let bitcoind = Self::start_bitcoind(&electrsd::bitcoind::Conf::default())?;
+        let electrsd = Self::start_electrsd(&bitcoind)?;
+        Ok(Self {
+            bitcoind,
+            electrsd,
+            peers: Vec::new(),
+        })
+    }
+
+    /// Construct a new [`TestEnv`] instance with a second `bitcoind` node.
+    ///
+    /// Both nodes listen for P2P connections but are not connected to each other, so blocks can be
+    /// mined independently on each to create competing chains. Node `0` is [`TestEnv::bitcoind`],
+    /// which `electrs` is connected to, and node `1` is the peer. Use [`TestEnv::connect_nodes`]
+    /// to let the nodes reconcile to the chain with the most work.
+    pub fn new_with_peer() -> anyhow::Result<Self> {
+        let mut conf = electrsd::bitcoind::Conf::default();
+        conf.p2p = electrsd::bitcoind::P2P::Yes;
+        let bitcoind = Self::start_bitcoind(&conf)?;
+        let peer = Self::start_bitcoind(&conf)?;
+        let electrsd = Self::start_electrsd(&bitcoind)?;
+        Ok(Self {
+            bitcoind,
+            electrsd,
+            peers: vec![peer],
+        })
+    }
+
+    fn start_bitcoind(
+        conf: &electrsd::bitcoind::Conf,
+    ) -> anyhow::Result<electrsd::bitcoind::BitcoinD> {
+        let bitcoind_exe = match std::env::var_os("BITCOIND_EXE") {
+            Some(bitcoind_path) => bitcoind_path,
+            None => electrsd::bitcoind::downloaded_exe_path()
+                .expect(
                 "you need to provide an env var BITCOIND_EXE or specify a bitcoind version feature",
-                );
-                electrsd::bitcoind::BitcoinD::with_conf(
-                    bitcoind_exe,
-                    &electrsd::bitcoind::Conf::default(),
                 )
-            }
-        }?;
+                .into(),
+        };
+        let bitcoind = electrsd::bitcoind::BitcoinD::with_conf(bitcoind_exe, conf)?;
+        Ok(bitcoind)
+    }
 
+    fn start_electrsd(
+        bitcoind: &electrsd::bitcoind::BitcoinD,
+    ) -> anyhow::Result<electrsd::ElectrsD> {
         let mut electrsd_conf = electrsd::Conf::default();
         electrsd_conf.http_enabled = true;
         let electrsd = match std::env::var_os("ELECTRS_EXE") {
             Some(env_electrs_exe) => {
-                electrsd::ElectrsD::with_conf(env_electrs_exe, &bitcoind, &electrsd_conf)
+                electrsd::ElectrsD::with_conf(env_electrs_exe, bitcoind, &electrsd_conf)
             }
             None => {
                 let electrs_exe = electrsd::downloaded_exe_path()
                     .expect("electrs version feature must be enabled");
-                electrsd::ElectrsD::with_conf(electrs_exe, &bitcoind, &electrsd_conf)
+                electrsd::ElectrsD::with_conf(electrs_exe, bitcoind, &electrsd_conf)
             }
         }?;
+        Ok(electrsd)
+    }
+
+    /// Get the `bitcoind` node at `index`, `0` being [`TestEnv::bitcoind`] and the following ones
+    /// [`TestEnv::peers`].
+    ///
+    /// # Panics
+    ///
+    /// If there is no node at `index`.
+    pub fn node(&self, index: usize) -> &electrsd::bitcoind::BitcoinD {
+        match index {
+            0 => &self.bitcoind,
+            _ => &self.peers[index - 1],
+        }
+    }
+
+    /// Mine `count` blocks on the node at `index`, see [`TestEnv::mine_blocks`].
+    pub fn mine_blocks_on(
+        &self,
+        index: usize,
+        count: usize,
+        address: Option<Address>,
+    ) -> anyhow::Result<Vec<BlockHash>> {
+        let client = &self.node(index).client;
+        let coinbase_address = match address {
+            Some(address) => address,
+            None => client.get_new_address(None, None)?.assume_checked(),
+        };
+        let block_hashes = client.generate_to_address(count as _, &coinbase_address)?;
+        Ok(block_hashes)
+    }
+
+    /// Connect node `a` to node `b` and wait until the connection is established.
+    ///
+    /// Once connected, the nodes exchange their blocks and both switch to the chain with the most
+    /// work.
+    pub fn connect_nodes(&self, a: usize, b: usize) -> anyhow::Result<()> {
+        let b_socket = self.p2p_socket(b)?;
+        let client = &self.node(a).client;
+        client.onetry_node(&b_socket)?;
 
-        Ok(Self { bitcoind, electrsd })
+        let mut delay = Duration::from_millis(64);
+        for _ in 0..10 {
+            if client
+                .get_peer_info()?
+                .iter()
+                .any(|peer| peer.addr == b_socket)
+            {
+                return Ok(());
+            }
+            std::thread::sleep(delay);
+            delay = delay.mul_f32(2.0);
+        }
+        Err(anyhow::anyhow!(
+            "node {} could not connect to node {}",
+            a,
+            b
+        ))
+    }
+
+    /// Disconnect nodes `a` and `b`, whichever initiated the connection.
+    pub fn disconnect_nodes(&self, a: usize, b: usize) -> anyhow::Result<()> {
+        for (from, to) in [(a, b), (b, a)] {
+            let to_socket = self.p2p_socket(to)?;
+            let client = &self.node(from).client;
+            for peer in client.get_peer_info()? {
+                if peer.addr == to_socket {
+                    client.disconnect_node_by_id(peer.id as _)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn p2p_socket(&self, index: usize) -> anyhow::Result<String> {
+        self.node(index)
+            .params
+            .p2p_socket
+            .map(|socket| socket.to_string())
+            .ok_or_else(|| anyhow::anyhow!("node {} does not listen for P2P connections", index))
     }
 
     /// Exposes the [`ElectrumApi`] calls from the Electrum client.
@@ -71,19 +186,7 @@ impl TestEnv {
 
     // Reset `electrsd` so that new blocks can be seen.
     pub fn reset_electrsd(mut self) -> anyhow::Result<Self> {
-        let mut electrsd_conf = electrsd::Conf::default();
-        electrsd_conf.http_enabled = true;
-        let electrsd = match std::env::var_os("ELECTRS_EXE") {
-            Some(env_electrs_exe) => {
-                electrsd::ElectrsD::with_conf(env_electrs_exe, &self.bitcoind, &electrsd_conf)
-            }
-            None => {
-                let electrs_exe = electrsd::downloaded_exe_path()
-                    .expect("electrs version feature must be enabled");
-                electrsd::ElectrsD::with_conf(electrs_exe, &self.bitcoind, &electrsd_conf)
-            }
-        }?;
-        self.electrsd = electrsd;
+        self.electrsd = Self::start_electrsd(&self.bitcoind)?;
         Ok(self)
     }
 
@@ -94,28 +197,39 @@ impl TestEnv {
         count: usize,
         address: Option<Address>,
     ) -> anyhow::Result<Vec<BlockHash>> {
-        let coinbase_address = match address {
-            Some(address) => address,
-            None => self
-                .bitcoind
-                .client
-                .get_new_address(None, None)?
-                .assume_checked(),
-        };
-        let block_hashes = self
-            .bitcoind
-            .client
-            .generate_to_address(count as _, &coinbase_address)?;
-        Ok(block_hashes)
+        self.mine_blocks_on(0, count, address)
     }
 
     /// Mine a block that is guaranteed to be empty even with transactions in the mempool.
     pub fn mine_empty_block(&self) -> anyhow::Result<(usize, BlockHash)> {
+        self.mine_empty_block_with_time(None)
+    }
+
+    /// Mine a block that is guaranteed to be empty, with its timestamp set to `time`.
+    ///
+    /// This can be used to move the median-time-past forward in a controlled way. Returns an error
+    /// if `time` is below the minimum timestamp allowed by consensus for the next block.
+    pub fn mine_empty_block_at(&self, time: u32) -> anyhow::Result<(usize, BlockHash)> {
+        self.mine_empty_block_with_time(Some(time))
+    }
+
+    fn mine_empty_block_with_time(&self, time: Option<u32>) -> anyhow::Result<(usize, BlockHash)> {
         let bt = self.bitcoind.client.get_block_template(
             GetBlockTemplateModes::Template,
             &[GetBlockTemplateRules::SegWit],
             &[],
         )?;
+        let time = match time {
+            Some(time) if (time as u64) < bt.min_time => {
+                return Err(anyhow::anyhow!(
+                    "block time {} is below the minimum time {}",
+                    time,
+                    bt.min_time
+                ))
+            }
+            Some(time) => time,
+            None => Ord::max(bt.min_time, std::time::UNIX_EPOCH.elapsed()?.as_secs()) as u32,
+        };
 
         let txdata = vec![Transaction {
             version: transaction::Version::ONE,
@@ -147,7 +261,7 @@ impl TestEnv {
                 version: bdk_chain::bitcoin::block::Version::default(),
                 prev_blockhash: bt.previous_block_hash,
                 merkle_root: TxMerkleNode::all_zeros(),
-                time: Ord::max(bt.min_time, std::time::UNIX_EPOCH.elapsed()?.as_secs()) as u32,
+                time,
                 bits: CompactTarget::from_consensus(u32::from_be_bytes(bits)),
                 nonce: 0,
             },
@@ -244,6 +358,123 @@ impl TestEnv {
         Ok(txid)
     }
 
+    /// Create two transactions spending `outpoint`, one paying to `addr_a` and the other to
+    /// `addr_b`, and broadcast the first one.
+    ///
+    /// `outpoint` must be an unspent output of the node wallet. Both transactions are signed by
+    /// the node wallet and signal RBF. The second one pays a higher fee, so broadcasting it later
+    /// replaces the first one in the mempool.
+    pub fn create_double_spend(
+        &self,
+        outpoint: OutPoint,
+        addr_a: &Address<NetworkChecked>,
+        addr_b: &Address<NetworkChecked>,
+    ) -> anyhow::Result<(Transaction, Transaction)> {
+        const FEES: [Amount; 2] = [Amount::from_sat(1_000), Amount::from_sat(2_000)];
+
+        let utxo = self
+            .bitcoind
+            .client
+            .list_unspent(Some(0), None, None, Some(true), None)?
+            .into_iter()
+            .find(|utxo| utxo.txid == outpoint.txid && utxo.vout == outpoint.vout)
+            .ok_or_else(|| {
+                anyhow::anyhow!("{} is not an unspent output of the node wallet", outpoint)
+            })?;
+        if !utxo.spendable {
+            return Err(anyhow::anyhow!(
+                "{} can't be spent by the node wallet",
+                outpoint
+            ));
+        }
+        if utxo.amount <= FEES[1] {
+            return Err(anyhow::anyhow!(
+                "{} of {} is too small to pay the fees",
+                utxo.amount,
+                outpoint
+            ));
+        }
+
+        let mut txs = Vec::with_capacity(2);
+        for (address, fee) in [addr_a, addr_b].into_iter().zip(FEES) {
+            let tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: bdk_chain::bitcoin::absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: outpoint,
+                    sequence: bdk_chain::bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    ..Default::default()
+                }],
+                output: vec![TxOut {
+                    value: utxo.amount - fee,
+                    script_pubkey: address.script_pubkey(),
+                }],
+            };
+            let signed = self
+                .bitcoind
+                .client
+                .sign_raw_transaction_with_wallet(&tx, None, None)?;
+            if !signed.complete {
+                return Err(anyhow::anyhow!(
+                    "node wallet could not sign the spend of {}",
+                    outpoint
+                ));
+            }
+            txs.push(signed.transaction()?);
+        }
+        let tx_b = txs.pop().expect("must have two transactions");
+        let tx_a = txs.pop().expect("must have two transactions");
+
+        self.bitcoind.client.send_raw_transaction(&tx_a)?;
+        Ok((tx_a, tx_b))
+    }
+
+    /// Send `amount` from the node wallet to `address` and mine a block to confirm it.
+    ///
+    /// If the node wallet can't cover `amount`, enough blocks are mined to it first for a coinbase
+    /// output to mature. Returns the txid of the funding transaction and its confirmation height.
+    pub fn fund_address(
+        &self,
+        address: &Address<NetworkChecked>,
+        amount: Amount,
+    ) -> anyhow::Result<(Txid, u32)> {
+        if self.bitcoind.client.get_balance(None, None)? < amount {
+            let node_address = self
+                .bitcoind
+                .client
+                .get_new_address(None, None)?
+                .assume_checked();
+            self.mine_blocks(COINBASE_MATURITY as usize + 1, Some(node_address))?;
+        }
+        let balance = self.bitcoind.client.get_balance(None, None)?;
+        if balance < amount {
+            return Err(anyhow::anyhow!(
+                "node wallet has {} but {} is needed to fund {}",
+                balance,
+                amount,
+                address
+            ));
+        }
+
+        let txid = self.send(address, amount)?;
+        self.mine_blocks(1, None)?;
+        let height = self
+            .bitcoind
+            .client
+            .get_transaction(&txid, None)?
+            .info
+            .blockheight
+            .ok_or_else(|| anyhow::anyhow!("funding transaction {} was not mined", txid))?;
+        Ok((txid, height))
+    }
+
+    /// Get the mempool entry of the transaction `txid`, with its fees, size and relationship to
+    /// other mempool transactions.
+    pub fn get_mempool_entry(&self, txid: Txid) -> anyhow::Result<GetMempoolEntryResult> {
+        let entry = self.bitcoind.client.get_mempool_entry(&txid)?;
+        Ok(entry)
+    }
+
     /// Create a checkpoint linked list of all the blocks in the chain.
     pub fn make_checkpoint_tip(&self) -> CheckPoint {
         CheckPoint::from_block_ids((0_u32..).map_while(|height| {
@@ -256,6 +487,36 @@ impl TestEnv {
         .expect("must craft tip")
     }
 
+    /// Create a checkpoint linked list of the blocks of the chain at `heights` only.
+    ///
+    /// Unlike [`make_checkpoint_tip`](Self::make_checkpoint_tip), the checkpoints may have gaps,
+    /// like the ones of a light client. The genesis block is always included and heights above
+    /// the chain tip are skipped.
+    pub fn make_sparse_checkpoint_tip(
+        &self,
+        heights: impl IntoIterator<Item = u32>,
+    ) -> anyhow::Result<CheckPoint> {
+        let tip_height = self.bitcoind.client.get_block_count()? as u32;
+        let block_ids = core::iter::once(0)
+            .chain(heights)
+            .filter(|&height| height <= tip_height)
+            .collect::<std::collections::BTreeSet<u32>>()
+            .into_iter()
+            .map(|height| {
+                let hash = self.bitcoind.client.get_block_hash(height as u64)?;
+                Ok(BlockId { height, hash })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(CheckPoint::from_block_ids(block_ids).expect("heights are sorted and unique"))
+    }
+
+    /// Get the block at `height` of the best chain.
+    pub fn get_block_at_height(&self, height: u32) -> anyhow::Result<Block> {
+        let hash = self.bitcoind.client.get_block_hash(height as u64)?;
+        let block = self.bitcoind.client.get_block(&hash)?;
+        Ok(block)
+    }
+
     /// Get the genesis hash of the blockchain.
     pub fn genesis_hash(&self) -> anyhow::Result<BlockHash> {
         let hash = self.bitcoind.client.get_block_hash(0)?;
@@ -266,6 +527,7 @@ impl TestEnv {
 #[cfg(test)]
 mod test {
     use crate::TestEnv;
+    use bdk_chain::bitcoin::{Amount, OutPoint};
     use electrsd::bitcoind::{anyhow::Result, bitcoincore_rpc::RpcApi};
 
     /// This checks that reorgs initiated by `bitcoind` is detected by our `electrsd` instance.
@@ -301,4 +563,162 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_make_sparse_checkpoint_tip() -> Result<()> {
+        let env = TestEnv::new()?;
+        env.mine_blocks(10, None)?;
+
+        let tip = env.make_sparse_checkpoint_tip([3, 7, 10, 1_000])?;
+        let heights = tip.iter().map(|cp| cp.height()).collect::<Vec<_>>();
+        assert_eq!(heights, vec![10, 7, 3, 0]);
+        assert_eq!(tip.hash(), env.bitcoind.client.get_block_hash(10)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fund_address() -> Result<()> {
+        let env = TestEnv::new()?;
+        let address = env
+            .bitcoind
+            .client
+            .get_new_address(None, None)?
+            .assume_checked();
+
+        // the node wallet starts without funds, so coinbase outputs are matured first
+        let (txid, height) = env.fund_address(&address, Amount::from_btc(1.0)?)?;
+        assert_eq!(env.bitcoind.client.get_block_count()?, height as u64);
+        let tx = env.bitcoind.client.get_transaction(&txid, None)?;
+        assert_eq!(tx.info.confirmations, 1);
+
+        // the node wallet can't fund more than it has
+        assert!(env
+            .fund_address(&address, Amount::from_btc(1_000_000.0)?)
+            .is_err());
+
+        Ok(())
+    }
+
+    /// This checks that a longer chain mined on a disconnected peer replaces the chain of
+    /// `bitcoind` once the nodes are connected.
+    #[test]
+    fn test_reorg_with_peer() -> Result<()> {
+        let env = TestEnv::new_with_peer()?;
+        let wait_for_tip = |index: usize, tip| -> Result<()> {
+            for _ in 0..50 {
+                if env.node(index).client.get_best_block_hash()? == tip {
+                    return Ok(());
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            Err(electrsd::bitcoind::anyhow::anyhow!(
+                "node {} did not sync",
+                index
+            ))
+        };
+
+        // Both nodes share a common chain.
+        let common_tip = *env.mine_blocks_on(0, 10, None)?.last().unwrap();
+        env.connect_nodes(0, 1)?;
+        wait_for_tip(1, common_tip)?;
+        env.disconnect_nodes(0, 1)?;
+
+        // The nodes diverge and the peer mines the longer chain.
+        let stale_tip = *env.mine_blocks_on(0, 2, None)?.last().unwrap();
+        let peer_tip = *env.mine_blocks_on(1, 5, None)?.last().unwrap();
+        assert_eq!(env.bitcoind.client.get_best_block_hash()?, stale_tip);
+
+        env.connect_nodes(1, 0)?;
+        wait_for_tip(0, peer_tip)?;
+        assert_eq!(env.bitcoind.client.get_block_count()?, 15);
+        assert_eq!(env.bitcoind.client.get_block_hash(10)?, common_tip);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_mempool_entry() -> Result<()> {
+        let env = TestEnv::new()?;
+        let address = env
+            .bitcoind
+            .client
+            .get_new_address(None, None)?
+            .assume_checked();
+        env.fund_address(&address, Amount::from_btc(1.0)?)?;
+
+        let txid = env.send(&address, Amount::from_btc(0.5)?)?;
+        let entry = env.get_mempool_entry(txid)?;
+        assert!(entry.vsize > 0);
+        assert!(entry.fees.base > Amount::ZERO);
+        assert_eq!(entry.ancestor_count, 1);
+
+        // the entry of a transaction not in the mempool can't be fetched
+        env.mine_blocks(1, None)?;
+        assert!(env.get_mempool_entry(txid).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mine_empty_block_at() -> Result<()> {
+        let env = TestEnv::new()?;
+        env.mine_blocks(11, None)?;
+        let tip = env.bitcoind.client.get_best_block_hash()?;
+        let tip_time = env.bitcoind.client.get_block_header_info(&tip)?.time as u32;
+
+        let time = tip_time + 60 * 60;
+        let (_, hash) = env.mine_empty_block_at(time)?;
+        assert_eq!(env.bitcoind.client.get_block_header(&hash)?.time, time);
+
+        // the median time past can't go backwards
+        let mtp = env
+            .bitcoind
+            .client
+            .get_block_header_info(&hash)?
+            .median_time
+            .expect("must have median time past") as u32;
+        assert!(env.mine_empty_block_at(mtp - 1).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_double_spend() -> Result<()> {
+        let env = TestEnv::new()?;
+        let new_address = || -> Result<_> {
+            Ok(env
+                .bitcoind
+                .client
+                .get_new_address(None, None)?
+                .assume_checked())
+        };
+        let (addr_a, addr_b) = (new_address()?, new_address()?);
+        let (txid, _) = env.fund_address(&new_address()?, Amount::from_btc(1.0)?)?;
+        let vout = env
+            .bitcoind
+            .client
+            .get_raw_transaction(&txid, None)?
+            .output
+            .iter()
+            .position(|txout| txout.value == Amount::from_btc(1.0).unwrap())
+            .expect("must have funding output");
+        let outpoint = OutPoint::new(txid, vout as u32);
+
+        let (tx_a, tx_b) = env.create_double_spend(outpoint, &addr_a, &addr_b)?;
+        assert_eq!(tx_a.input[0].previous_output, outpoint);
+        assert_eq!(tx_b.input[0].previous_output, outpoint);
+        assert!(env.get_mempool_entry(tx_a.compute_txid()).is_ok());
+        assert!(env.get_mempool_entry(tx_b.compute_txid()).is_err());
+
+        // broadcasting the second transaction replaces the first one
+        env.bitcoind.client.send_raw_transaction(&tx_b)?;
+        assert!(env.get_mempool_entry(tx_a.compute_txid()).is_err());
+        assert!(env.get_mempool_entry(tx_b.compute_txid()).is_ok());
+
+        // the outpoint is not an unspent output of the node wallet anymore
+        assert!(env.create_double_spend(outpoint, &addr_a, &addr_b).is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,86 @@
+use bdk_chain::{BlockId, ConfirmationTime};
+use bdk_wallet::{KeychainKind, Wallet};
+use bitcoin::{
+    absolute, hashes::Hash, transaction, Amount, BlockHash, Network, OutPoint, Transaction, TxIn,
+    TxOut, Txid,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Number of unspent outputs owned by the wallet.
+const UTXO_COUNT: u32 = 5_000;
+
+const DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/0/*)";
+const CHANGE_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/1/*)";
+
+/// A wallet with `UTXO_COUNT` unspent outputs, each received in its own transaction. Most of them
+/// are confirmed and every tenth one is still unconfirmed, so that all of the balance categories
+/// have to be computed.
+fn setup() -> Wallet {
+    let mut wallet = Wallet::new(DESCRIPTOR, CHANGE_DESCRIPTOR, Network::Regtest).unwrap();
+    let tip = UTXO_COUNT / 10 + 1;
+    wallet
+        .insert_checkpoint(BlockId {
+            height: tip,
+            hash: BlockHash::all_zeros(),
+        })
+        .unwrap();
+    for i in 0..UTXO_COUNT {
+        let keychain = if i % 2 == 0 {
+            KeychainKind::External
+        } else {
+            KeychainKind::Internal
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&i.to_le_bytes()), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: wallet
+                    .reveal_next_address(keychain)
+                    .unwrap()
+                    .script_pubkey(),
+            }],
+        };
+        let position = if i % 10 == 0 {
+            ConfirmationTime::Unconfirmed {
+                last_seen: i as u64,
+            }
+        } else {
+            ConfirmationTime::Confirmed {
+                height: i / 10 + 1,
+                time: 0,
+            }
+        };
+        wallet.insert_tx(tx, position).unwrap();
+    }
+    wallet
+}
+
+fn balance_warm_cache(c: &mut Criterion) {
+    let wallet = setup();
+    // fill the cache, so that every iteration returns the cached balance
+    let _ = wallet.balance();
+    c.bench_function("balance of 5k utxos (warm cache)", move |b| {
+        b.iter(|| black_box(wallet.balance()))
+    });
+}
+
+fn balance_cold_cache(c: &mut Criterion) {
+    let mut wallet = setup();
+    let tip = wallet.latest_checkpoint().block_id();
+    c.bench_function("balance of 5k utxos (cold cache)", move |b| {
+        b.iter(|| {
+            // re-inserting the tip changes nothing, but drops the cached balance like any other
+            // mutation of the wallet, so `Wallet::balance` has to compute it again
+            wallet.insert_checkpoint(tip).unwrap();
+            black_box(wallet.balance())
+        })
+    });
+}
+
+criterion_group!(benches, balance_warm_cache, balance_cold_cache);
+criterion_main!(benches);
@@ -43,6 +43,13 @@ pub enum Error {
     Hex(bitcoin::hex::HexToBytesError),
     /// The provided wallet descriptors are identical
     ExternalAndInternalAreTheSame,
+    /// Error while expanding a wallet policy
+    WalletPolicy(crate::descriptor::wallet_policy::WalletPolicyError),
+    /// Templates only derive the [`External`] and [`Internal`] keychains
+    ///
+    /// [`External`]: crate::KeychainKind::External
+    /// [`Internal`]: crate::KeychainKind::Internal
+    CustomKeychain(u32),
 }
 
 impl From<crate::keys::KeyError> for Error {
@@ -83,6 +90,12 @@ impl fmt::Display for Error {
             Self::ExternalAndInternalAreTheSame => {
                 write!(f, "External and internal descriptors are the same")
             }
+            Self::WalletPolicy(err) => write!(f, "Wallet policy error: {}", err),
+            Self::CustomKeychain(id) => write!(
+                f,
+                "Templates can't derive the custom keychain {}, only the external and internal ones",
+                id
+            ),
         }
     }
 }
@@ -125,3 +138,9 @@ impl From<crate::descriptor::policy::PolicyError> for Error {
         Error::Policy(err)
     }
 }
+
+impl From<crate::descriptor::wallet_policy::WalletPolicyError> for Error {
+    fn from(err: crate::descriptor::wallet_policy::WalletPolicyError) -> Self {
+        Error::WalletPolicy(err)
+    }
+}
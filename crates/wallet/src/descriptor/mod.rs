@@ -40,12 +40,14 @@ pub mod dsl;
 pub mod error;
 pub mod policy;
 pub mod template;
+pub mod wallet_policy;
 
 pub use self::checksum::calc_checksum;
 use self::checksum::calc_checksum_bytes;
 pub use self::error::Error as DescriptorError;
 pub use self::policy::Policy;
 use self::template::DescriptorTemplateOut;
+pub use self::wallet_policy::WalletPolicy;
 use crate::keys::{IntoDescriptorKey, KeyError};
 use crate::wallet::signer::SignersContainer;
 use crate::wallet::utils::SecpCtx;
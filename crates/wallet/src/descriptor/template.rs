@@ -13,6 +13,26 @@
 //!
 //! This module contains the definition of various common script templates that are ready to be
 //! used. See the documentation of each template for an example.
+//!
+//! ## Custom account paths
+//!
+//! The `Bip*Custom` templates, e.g. [`Bip84Custom`], replace the account derivation path of the
+//! matching BIP template, `m/<purpose>'/<coin type>'/<account>'`, with any path, e.g. to restore
+//! a wallet that uses a non-standard account index or purpose. The keychain (`0` or `1`) and the
+//! address index are still appended to it.
+//!
+//! Neither the purpose of the path is checked against the script type of the template, nor its
+//! coin type against the network. Some wallets derive, for instance, their `wpkh` keys under
+//! `m/44'`, and restoring those is what these templates are for: a mismatching purpose gives a
+//! valid descriptor, only with different addresses than the standard path would. New wallets
+//! should use the standard templates, like [`Bip84`], instead.
+//!
+//! The private templates derive the key along the path, so a path with hardened steps requires a
+//! key with private data (generally a `xprv`/`tprv`), otherwise
+//! [`DescriptorError::HardenedDerivationXpub`] is returned. The `Bip*CustomPublic` templates take
+//! a key already derived along the path, which is then only used as the key origin, together
+//! with the parent fingerprint. A correct key origin is needed for the metadata of PSBTs, e.g.
+//! for hardware wallets to verify the change outputs.
 
 use bitcoin::bip32;
 use bitcoin::Network;
@@ -85,7 +105,7 @@ impl<T: DescriptorTemplate> IntoWalletDescriptor for T {
 ///
 /// assert_eq!(
 ///     wallet
-///         .next_unused_address(KeychainKind::External)
+///         .next_unused_address(KeychainKind::External)?
 ///         .to_string(),
 ///     "mwJ8hxFYW19JLuc65RCTaP4v1rzVU8cVMT"
 /// );
@@ -121,7 +141,7 @@ impl<K: IntoDescriptorKey<Legacy>> DescriptorTemplate for P2Pkh<K> {
 ///
 /// assert_eq!(
 ///     wallet
-///         .next_unused_address(KeychainKind::External)
+///         .next_unused_address(KeychainKind::External)?
 ///         .to_string(),
 ///     "2NB4ox5VDRw1ecUv6SnT3VQHPXveYztRqk5"
 /// );
@@ -154,7 +174,7 @@ impl<K: IntoDescriptorKey<Segwitv0>> DescriptorTemplate for P2Wpkh_P2Sh<K> {
 ///
 /// assert_eq!(
 ///     wallet
-///         .next_unused_address(KeychainKind::External)
+///         .next_unused_address(KeychainKind::External)?
 ///         .to_string(),
 ///     "tb1q4525hmgw265tl3drrl8jjta7ayffu6jf68ltjd"
 /// );
@@ -186,7 +206,7 @@ impl<K: IntoDescriptorKey<Segwitv0>> DescriptorTemplate for P2Wpkh<K> {
 ///
 /// assert_eq!(
 ///     wallet
-///         .next_unused_address(KeychainKind::External)
+///         .next_unused_address(KeychainKind::External)?
 ///         .to_string(),
 ///     "tb1pvjf9t34fznr53u5tqhejz4nr69luzkhlvsdsdfq9pglutrpve2xq7hps46"
 /// );
@@ -221,8 +241,8 @@ impl<K: IntoDescriptorKey<Tap>> DescriptorTemplate for P2TR<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "mmogjc7HJEZkrLqyQYqJmxUqFaC7i4uf89");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "pkh([c55b303f/44'/1'/0']tpubDCuorCpzvYS2LCD75BR46KHE8GdDeg1wsAgNZeNr6DaB5gQK1o14uErKwKLuFmeemkQ6N2m3rNgvctdJLyr7nwu2yia7413Hhg8WWE44cgT/0/*)#5wrnv0xt");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "mmogjc7HJEZkrLqyQYqJmxUqFaC7i4uf89");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "pkh([c55b303f/44'/1'/0']tpubDCuorCpzvYS2LCD75BR46KHE8GdDeg1wsAgNZeNr6DaB5gQK1o14uErKwKLuFmeemkQ6N2m3rNgvctdJLyr7nwu2yia7413Hhg8WWE44cgT/0/*)#5wrnv0xt");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip44<K: DerivableKey<Legacy>>(pub K, pub KeychainKind);
@@ -258,8 +278,8 @@ impl<K: DerivableKey<Legacy>> DescriptorTemplate for Bip44<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "miNG7dJTzJqNbFS19svRdTCisC65dsubtR");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "pkh([c55b303f/44'/1'/0']tpubDDDzQ31JkZB7VxUr9bjvBivDdqoFLrDPyLWtLapArAi51ftfmCb2DPxwLQzX65iNcXz1DGaVvyvo6JQ6rTU73r2gqdEo8uov9QKRb7nKCSU/0/*)#cfhumdqz");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "miNG7dJTzJqNbFS19svRdTCisC65dsubtR");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "pkh([c55b303f/44'/1'/0']tpubDDDzQ31JkZB7VxUr9bjvBivDdqoFLrDPyLWtLapArAi51ftfmCb2DPxwLQzX65iNcXz1DGaVvyvo6JQ6rTU73r2gqdEo8uov9QKRb7nKCSU/0/*)#cfhumdqz");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip44Public<K: DerivableKey<Legacy>>(pub K, pub bip32::Fingerprint, pub KeychainKind);
@@ -294,8 +314,8 @@ impl<K: DerivableKey<Legacy>> DescriptorTemplate for Bip44Public<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "2N4zkWAoGdUv4NXhSsU8DvS5MB36T8nKHEB");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "sh(wpkh([c55b303f/49'/1'/0']tpubDDYr4kdnZgjjShzYNjZUZXUUtpXaofdkMaipyS8ThEh45qFmhT4hKYways7UXmg6V7het1QiFo9kf4kYUXyDvV4rHEyvSpys9pjCB3pukxi/0/*))#s9vxlc8e");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "2N4zkWAoGdUv4NXhSsU8DvS5MB36T8nKHEB");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "sh(wpkh([c55b303f/49'/1'/0']tpubDDYr4kdnZgjjShzYNjZUZXUUtpXaofdkMaipyS8ThEh45qFmhT4hKYways7UXmg6V7het1QiFo9kf4kYUXyDvV4rHEyvSpys9pjCB3pukxi/0/*))#s9vxlc8e");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip49<K: DerivableKey<Segwitv0>>(pub K, pub KeychainKind);
@@ -331,8 +351,8 @@ impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip49<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "2N3K4xbVAHoiTQSwxkZjWDfKoNC27pLkYnt");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "sh(wpkh([c55b303f/49'/1'/0']tpubDC49r947KGK52X5rBWS4BLs5m9SRY3pYHnvRrm7HcybZ3BfdEsGFyzCMzayi1u58eT82ZeyFZwH7DD6Q83E3fM9CpfMtmnTygnLfP59jL9L/0/*))#3tka9g0q");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "2N3K4xbVAHoiTQSwxkZjWDfKoNC27pLkYnt");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "sh(wpkh([c55b303f/49'/1'/0']tpubDC49r947KGK52X5rBWS4BLs5m9SRY3pYHnvRrm7HcybZ3BfdEsGFyzCMzayi1u58eT82ZeyFZwH7DD6Q83E3fM9CpfMtmnTygnLfP59jL9L/0/*))#3tka9g0q");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip49Public<K: DerivableKey<Segwitv0>>(pub K, pub bip32::Fingerprint, pub KeychainKind);
@@ -367,8 +387,8 @@ impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip49Public<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "tb1qhl85z42h7r4su5u37rvvw0gk8j2t3n9y7zsg4n");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "wpkh([c55b303f/84'/1'/0']tpubDDc5mum24DekpNw92t6fHGp8Gr2JjF9J7i4TZBtN6Vp8xpAULG5CFaKsfugWa5imhrQQUZKXe261asP5koDHo5bs3qNTmf3U3o4v9SaB8gg/0/*)#6kfecsmr");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "tb1qhl85z42h7r4su5u37rvvw0gk8j2t3n9y7zsg4n");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "wpkh([c55b303f/84'/1'/0']tpubDDc5mum24DekpNw92t6fHGp8Gr2JjF9J7i4TZBtN6Vp8xpAULG5CFaKsfugWa5imhrQQUZKXe261asP5koDHo5bs3qNTmf3U3o4v9SaB8gg/0/*)#6kfecsmr");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip84<K: DerivableKey<Segwitv0>>(pub K, pub KeychainKind);
@@ -404,8 +424,8 @@ impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip84<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "tb1qedg9fdlf8cnnqfd5mks6uz5w4kgpk2pr6y4qc7");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "wpkh([c55b303f/84'/1'/0']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*)#dhu402yv");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "tb1qedg9fdlf8cnnqfd5mks6uz5w4kgpk2pr6y4qc7");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "wpkh([c55b303f/84'/1'/0']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*)#dhu402yv");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip84Public<K: DerivableKey<Segwitv0>>(pub K, pub bip32::Fingerprint, pub KeychainKind);
@@ -440,8 +460,8 @@ impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip84Public<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "tb1p5unlj09djx8xsjwe97269kqtxqpwpu2epeskgqjfk4lnf69v4tnqpp35qu");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "tr([c55b303f/86'/1'/0']tpubDCiHofpEs47kx358bPdJmTZHmCDqQ8qw32upCSxHrSEdeeBs2T5Mq6QMB2ukeMqhNBiyhosBvJErteVhfURPGXPv3qLJPw5MVpHUewsbP2m/0/*)#dkgvr5hm");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "tb1p5unlj09djx8xsjwe97269kqtxqpwpu2epeskgqjfk4lnf69v4tnqpp35qu");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "tr([c55b303f/86'/1'/0']tpubDCiHofpEs47kx358bPdJmTZHmCDqQ8qw32upCSxHrSEdeeBs2T5Mq6QMB2ukeMqhNBiyhosBvJErteVhfURPGXPv3qLJPw5MVpHUewsbP2m/0/*)#dkgvr5hm");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip86<K: DerivableKey<Tap>>(pub K, pub KeychainKind);
@@ -477,8 +497,8 @@ impl<K: DerivableKey<Tap>> DescriptorTemplate for Bip86<K> {
 ///     Network::Testnet,
 /// )?;
 ///
-/// assert_eq!(wallet.next_unused_address(KeychainKind::External).to_string(), "tb1pwjp9f2k5n0xq73ecuu0c5njvgqr3vkh7yaylmpqvsuuaafymh0msvcmh37");
-/// assert_eq!(wallet.public_descriptor(KeychainKind::External).to_string(), "tr([c55b303f/86'/1'/0']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*)#2p65srku");
+/// assert_eq!(wallet.next_unused_address(KeychainKind::External)?.to_string(), "tb1pwjp9f2k5n0xq73ecuu0c5njvgqr3vkh7yaylmpqvsuuaafymh0msvcmh37");
+/// assert_eq!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string(), "tr([c55b303f/86'/1'/0']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*)#2p65srku");
 /// # Ok::<_, Box<dyn std::error::Error>>(())
 /// ```
 pub struct Bip86Public<K: DerivableKey<Tap>>(pub K, pub bip32::Fingerprint, pub KeychainKind);
@@ -492,6 +512,339 @@ impl<K: DerivableKey<Tap>> DescriptorTemplate for Bip86Public<K> {
     }
 }
 
+/// BIP44 template with a custom account path. Expands to `pkh(key/<account_path>/{0,1}/*)`
+///
+/// This is the same as [`Bip44`] with the account derivation path `m/44'/{0,1}'/0'` replaced
+/// by `account_path`, see [custom account paths] for how it is used.
+///
+/// See [`Bip44CustomPublic`] for a template that can work with a `xpub`/`tpub`.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip44Custom;
+///
+/// let key = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/44'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip44Custom(key.clone(), account_path.clone(), KeychainKind::External),
+///     Bip44Custom(key, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("pkh([c55b303f/44'/1'/5']"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip44Custom<K: DerivableKey<Legacy>>(pub K, pub bip32::DerivationPath, pub KeychainKind);
+
+impl<K: DerivableKey<Legacy>> DescriptorTemplate for Bip44Custom<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2Pkh(legacy::make_custom_private(self.0, self.1, self.2)?).build(network)
+    }
+}
+
+/// BIP44 public template with a custom account path. Expands to `pkh(key/{0,1}/*)`
+///
+/// This is the same as [`Bip44Public`] for a key already derived with `account_path`, see
+/// [custom account paths] for how it is used.
+///
+/// See [`Bip44Custom`] for a template that does the full derivation, but requires private data
+/// for the key.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip44CustomPublic;
+///
+/// let key = bitcoin::bip32::Xpub::from_str("tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q")?;
+/// let fingerprint = bitcoin::bip32::Fingerprint::from_str("c55b303f")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/44'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip44CustomPublic(key.clone(), fingerprint, account_path.clone(), KeychainKind::External),
+///     Bip44CustomPublic(key, fingerprint, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("pkh([c55b303f/44'/1'/5']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip44CustomPublic<K: DerivableKey<Legacy>>(
+    pub K,
+    pub bip32::Fingerprint,
+    pub bip32::DerivationPath,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Legacy>> DescriptorTemplate for Bip44CustomPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2Pkh(legacy::make_custom_public(self.0, self.1, self.2, self.3)?).build(network)
+    }
+}
+
+/// BIP49 template with a custom account path. Expands to `sh(wpkh(key/<account_path>/{0,1}/*))`
+///
+/// This is the same as [`Bip49`] with the account derivation path `m/49'/{0,1}'/0'` replaced
+/// by `account_path`, see [custom account paths] for how it is used.
+///
+/// See [`Bip49CustomPublic`] for a template that can work with a `xpub`/`tpub`.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip49Custom;
+///
+/// let key = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/49'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip49Custom(key.clone(), account_path.clone(), KeychainKind::External),
+///     Bip49Custom(key, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("sh(wpkh([c55b303f/49'/1'/5']"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip49Custom<K: DerivableKey<Segwitv0>>(
+    pub K,
+    pub bip32::DerivationPath,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip49Custom<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2Wpkh_P2Sh(segwit_v0::make_custom_private(self.0, self.1, self.2)?).build(network)
+    }
+}
+
+/// BIP49 public template with a custom account path. Expands to `sh(wpkh(key/{0,1}/*))`
+///
+/// This is the same as [`Bip49Public`] for a key already derived with `account_path`, see
+/// [custom account paths] for how it is used.
+///
+/// See [`Bip49Custom`] for a template that does the full derivation, but requires private data
+/// for the key.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip49CustomPublic;
+///
+/// let key = bitcoin::bip32::Xpub::from_str("tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q")?;
+/// let fingerprint = bitcoin::bip32::Fingerprint::from_str("c55b303f")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/49'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip49CustomPublic(key.clone(), fingerprint, account_path.clone(), KeychainKind::External),
+///     Bip49CustomPublic(key, fingerprint, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("sh(wpkh([c55b303f/49'/1'/5']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip49CustomPublic<K: DerivableKey<Segwitv0>>(
+    pub K,
+    pub bip32::Fingerprint,
+    pub bip32::DerivationPath,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip49CustomPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2Wpkh_P2Sh(segwit_v0::make_custom_public(
+            self.0, self.1, self.2, self.3,
+        )?)
+        .build(network)
+    }
+}
+
+/// BIP84 template with a custom account path. Expands to `wpkh(key/<account_path>/{0,1}/*)`
+///
+/// This is the same as [`Bip84`] with the account derivation path `m/84'/{0,1}'/0'` replaced
+/// by `account_path`, see [custom account paths] for how it is used.
+///
+/// See [`Bip84CustomPublic`] for a template that can work with a `xpub`/`tpub`.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip84Custom;
+///
+/// let key = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/84'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip84Custom(key.clone(), account_path.clone(), KeychainKind::External),
+///     Bip84Custom(key, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("wpkh([c55b303f/84'/1'/5']"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip84Custom<K: DerivableKey<Segwitv0>>(
+    pub K,
+    pub bip32::DerivationPath,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip84Custom<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2Wpkh(segwit_v0::make_custom_private(self.0, self.1, self.2)?).build(network)
+    }
+}
+
+/// BIP84 public template with a custom account path. Expands to `wpkh(key/{0,1}/*)`
+///
+/// This is the same as [`Bip84Public`] for a key already derived with `account_path`, see
+/// [custom account paths] for how it is used.
+///
+/// See [`Bip84Custom`] for a template that does the full derivation, but requires private data
+/// for the key.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip84CustomPublic;
+///
+/// let key = bitcoin::bip32::Xpub::from_str("tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q")?;
+/// let fingerprint = bitcoin::bip32::Fingerprint::from_str("c55b303f")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/84'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip84CustomPublic(key.clone(), fingerprint, account_path.clone(), KeychainKind::External),
+///     Bip84CustomPublic(key, fingerprint, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("wpkh([c55b303f/84'/1'/5']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip84CustomPublic<K: DerivableKey<Segwitv0>>(
+    pub K,
+    pub bip32::Fingerprint,
+    pub bip32::DerivationPath,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Segwitv0>> DescriptorTemplate for Bip84CustomPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2Wpkh(segwit_v0::make_custom_public(
+            self.0, self.1, self.2, self.3,
+        )?)
+        .build(network)
+    }
+}
+
+/// BIP86 template with a custom account path. Expands to `tr(key/<account_path>/{0,1}/*)`
+///
+/// This is the same as [`Bip86`] with the account derivation path `m/86'/{0,1}'/0'` replaced
+/// by `account_path`, see [custom account paths] for how it is used.
+///
+/// See [`Bip86CustomPublic`] for a template that can work with a `xpub`/`tpub`.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip86Custom;
+///
+/// let key = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/86'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip86Custom(key.clone(), account_path.clone(), KeychainKind::External),
+///     Bip86Custom(key, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("tr([c55b303f/86'/1'/5']"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip86Custom<K: DerivableKey<Tap>>(pub K, pub bip32::DerivationPath, pub KeychainKind);
+
+impl<K: DerivableKey<Tap>> DescriptorTemplate for Bip86Custom<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2TR(segwit_v1::make_custom_private(self.0, self.1, self.2)?).build(network)
+    }
+}
+
+/// BIP86 public template with a custom account path. Expands to `tr(key/{0,1}/*)`
+///
+/// This is the same as [`Bip86Public`] for a key already derived with `account_path`, see
+/// [custom account paths] for how it is used.
+///
+/// See [`Bip86Custom`] for a template that does the full derivation, but requires private data
+/// for the key.
+///
+/// [custom account paths]: crate::descriptor::template#custom-account-paths
+///
+/// ## Example
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use bdk_wallet::bitcoin::{PrivateKey, Network};
+/// # use bdk_wallet::{Wallet,  KeychainKind};
+/// use bdk_wallet::template::Bip86CustomPublic;
+///
+/// let key = bitcoin::bip32::Xpub::from_str("tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q")?;
+/// let fingerprint = bitcoin::bip32::Fingerprint::from_str("c55b303f")?;
+/// let account_path = bitcoin::bip32::DerivationPath::from_str("m/86'/1'/5'")?;
+/// let mut wallet = Wallet::new(
+///     Bip86CustomPublic(key.clone(), fingerprint, account_path.clone(), KeychainKind::External),
+///     Bip86CustomPublic(key, fingerprint, account_path, KeychainKind::Internal),
+///     Network::Testnet,
+/// )?;
+///
+/// assert!(wallet.public_descriptor(KeychainKind::External).unwrap().to_string().starts_with("tr([c55b303f/86'/1'/5']tpubDC2Qwo2TFsaNC4ju8nrUJ9mqVT3eSgdmy1yPqhgkjwmke3PRXutNGRYAUo6RCHTcVQaDR3ohNU9we59brGHuEKPvH1ags2nevW5opEE9Z5Q/0/*"));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bip86CustomPublic<K: DerivableKey<Tap>>(
+    pub K,
+    pub bip32::Fingerprint,
+    pub bip32::DerivationPath,
+    pub KeychainKind,
+);
+
+impl<K: DerivableKey<Tap>> DescriptorTemplate for Bip86CustomPublic<K> {
+    fn build(self, network: Network) -> Result<DescriptorTemplateOut, DescriptorError> {
+        P2TR(segwit_v1::make_custom_public(
+            self.0, self.1, self.2, self.3,
+        )?)
+        .build(network)
+    }
+}
+
 macro_rules! expand_make_bipxx {
     ( $mod_name:ident, $ctx:ty ) => {
         mod $mod_name {
@@ -516,14 +869,7 @@ macro_rules! expand_make_bipxx {
                 }
                 derivation_path.push(bip32::ChildNumber::from_hardened_idx(0)?);
 
-                match keychain {
-                    KeychainKind::External => {
-                        derivation_path.push(bip32::ChildNumber::from_normal_idx(0)?)
-                    }
-                    KeychainKind::Internal => {
-                        derivation_path.push(bip32::ChildNumber::from_normal_idx(1)?)
-                    }
-                };
+                derivation_path.push(keychain_child_number(keychain)?);
 
                 let derivation_path: bip32::DerivationPath = derivation_path.into();
 
@@ -536,10 +882,8 @@ macro_rules! expand_make_bipxx {
                 keychain: KeychainKind,
                 network: Network,
             ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
-                let derivation_path: bip32::DerivationPath = match keychain {
-                    KeychainKind::External => vec![bip32::ChildNumber::from_normal_idx(0)?].into(),
-                    KeychainKind::Internal => vec![bip32::ChildNumber::from_normal_idx(1)?].into(),
-                };
+                let derivation_path: bip32::DerivationPath =
+                    vec![keychain_child_number(keychain)?].into();
 
                 let source_path = bip32::DerivationPath::from(vec![
                     bip32::ChildNumber::from_hardened_idx(bip)?,
@@ -552,10 +896,50 @@ macro_rules! expand_make_bipxx {
 
                 Ok((key, (parent_fingerprint, source_path), derivation_path))
             }
+
+            pub(super) fn make_custom_private<K: DerivableKey<$ctx>>(
+                key: K,
+                account_path: bip32::DerivationPath,
+                keychain: KeychainKind,
+            ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
+                let key = key.into_extended_key()?;
+                if !key.has_secret()
+                    && account_path
+                        .as_ref()
+                        .iter()
+                        .any(bip32::ChildNumber::is_hardened)
+                {
+                    return Err(DescriptorError::HardenedDerivationXpub);
+                }
+
+                let derivation_path = account_path.child(keychain_child_number(keychain)?);
+
+                Ok((key, derivation_path))
+            }
+
+            pub(super) fn make_custom_public<K: DerivableKey<$ctx>>(
+                key: K,
+                parent_fingerprint: bip32::Fingerprint,
+                account_path: bip32::DerivationPath,
+                keychain: KeychainKind,
+            ) -> Result<impl IntoDescriptorKey<$ctx>, DescriptorError> {
+                let derivation_path: bip32::DerivationPath =
+                    vec![keychain_child_number(keychain)?].into();
+
+                Ok((key, (parent_fingerprint, account_path), derivation_path))
+            }
         }
     };
 }
 
+fn keychain_child_number(keychain: KeychainKind) -> Result<bip32::ChildNumber, DescriptorError> {
+    Ok(match keychain {
+        KeychainKind::External => bip32::ChildNumber::from_normal_idx(0)?,
+        KeychainKind::Internal => bip32::ChildNumber::from_normal_idx(1)?,
+        KeychainKind::Custom(id) => return Err(DescriptorError::CustomKeychain(id)),
+    })
+}
+
 expand_make_bipxx!(legacy, Legacy);
 expand_make_bipxx!(segwit_v0, Segwitv0);
 expand_make_bipxx!(segwit_v1, Tap);
@@ -901,6 +1285,15 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_bip84_template_custom_keychain() {
+        let prvkey = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPcx5nBGsR63Pe8KnRUqmbJNENAfGftF3yuXoMMoVJJcYeUw5eVkm9WBPjWYt6HMWYJNesB5HaNVBaFc1M6dRjWSYnmewUMYy").unwrap();
+        assert_matches!(
+            Bip84(prvkey, KeychainKind::Custom(2)).build(Network::Bitcoin),
+            Err(DescriptorError::CustomKeychain(2))
+        );
+    }
+
     // BIP84 public `wpkh(key/{0,1}/*)`
     #[test]
     fn test_bip84_public_template() {
@@ -994,4 +1387,104 @@ mod test {
             ],
         );
     }
+
+    // a custom account path equal to the standard one must expand to the same descriptor
+    #[test]
+    fn test_bip_custom_templates_with_standard_path() {
+        let prvkey = bitcoin::bip32::Xpriv::from_str("xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu").unwrap();
+        let pubkey = bitcoin::bip32::Xpub::from_str("xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ").unwrap();
+        let fingerprint = bitcoin::bip32::Fingerprint::from_str("73c5da0a").unwrap();
+
+        for keychain in [KeychainKind::External, KeychainKind::Internal] {
+            let path = bip32::DerivationPath::from_str("m/84'/0'/0'").unwrap();
+            assert_eq!(
+                Bip84Custom(prvkey, path, keychain)
+                    .build(Network::Bitcoin)
+                    .unwrap()
+                    .0,
+                Bip84(prvkey, keychain).build(Network::Bitcoin).unwrap().0
+            );
+
+            let path = bip32::DerivationPath::from_str("m/86'/0'/0'").unwrap();
+            assert_eq!(
+                Bip86Custom(prvkey, path.clone(), keychain)
+                    .build(Network::Bitcoin)
+                    .unwrap()
+                    .0,
+                Bip86(prvkey, keychain).build(Network::Bitcoin).unwrap().0
+            );
+            assert_eq!(
+                Bip86CustomPublic(pubkey, fingerprint, path, keychain)
+                    .build(Network::Bitcoin)
+                    .unwrap()
+                    .0,
+                Bip86Public(pubkey, fingerprint, keychain)
+                    .build(Network::Bitcoin)
+                    .unwrap()
+                    .0
+            );
+        }
+    }
+
+    #[test]
+    fn test_bip_custom_template_key_origin() {
+        use bitcoin::bip32::DerivationPath;
+        use miniscript::ForEachKey;
+
+        let prvkey = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPcx5nBGsR63Pe8KnRUqmbJNENAfGftF3yuXoMMoVJJcYeUw5eVkm9WBPjWYt6HMWYJNesB5HaNVBaFc1M6dRjWSYnmewUMYy").unwrap();
+        let path = DerivationPath::from_str("m/49'/1'/7'").unwrap();
+        let (desc, _, _) = Bip49Custom(prvkey, path, KeychainKind::Internal)
+            .build(Network::Testnet)
+            .unwrap();
+        let mut full_path = None;
+        desc.for_each_key(|key| {
+            full_path = key.full_derivation_path();
+            true
+        });
+        assert_eq!(
+            full_path,
+            Some(DerivationPath::from_str("m/49'/1'/7'/1").unwrap())
+        );
+
+        // a public key can't do hardened derivation
+        let pubkey = bip32::Xpub::from_priv(&SecpCtx::new(), &prvkey);
+        let path = DerivationPath::from_str("m/44'/1'/0'").unwrap();
+        assert_matches!(
+            Bip44Custom(pubkey, path, KeychainKind::External).build(Network::Testnet),
+            Err(DescriptorError::HardenedDerivationXpub)
+        );
+        let path = DerivationPath::from_str("m/44/1/0").unwrap();
+        assert!(Bip44Custom(pubkey, path, KeychainKind::External)
+            .build(Network::Testnet)
+            .is_ok());
+    }
+
+    // the purpose of a custom account path is not checked against the script type
+    #[test]
+    fn test_bip_custom_template_any_purpose() {
+        use bitcoin::bip32::DerivationPath;
+        use miniscript::ForEachKey;
+
+        let prvkey = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPcx5nBGsR63Pe8KnRUqmbJNENAfGftF3yuXoMMoVJJcYeUw5eVkm9WBPjWYt6HMWYJNesB5HaNVBaFc1M6dRjWSYnmewUMYy").unwrap();
+        let path = DerivationPath::from_str("m/44'/1'/0'").unwrap();
+        let (desc, _, _) = Bip84Custom(prvkey, path.clone(), KeychainKind::External)
+            .build(Network::Testnet)
+            .unwrap();
+        assert!(matches!(desc, ExtendedDescriptor::Wpkh(_)));
+        let mut full_path = None;
+        desc.for_each_key(|key| {
+            full_path = key.full_derivation_path();
+            true
+        });
+        assert_eq!(full_path, Some(path.child(bip32::ChildNumber::from(0))));
+
+        // a different purpose gives different addresses than the standard path
+        assert_ne!(
+            desc,
+            Bip84(prvkey, KeychainKind::External)
+                .build(Network::Testnet)
+                .unwrap()
+                .0
+        );
+    }
 }
@@ -0,0 +1,363 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2024 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! BIP388 wallet policies
+//!
+//! A [wallet policy] is a compact form of a pair of receive/change descriptors, used by hardware
+//! signers to register wallets. It is made of a descriptor template, where each key is replaced
+//! by a placeholder like `@0/**`, and the vector of keys that the placeholders refer to.
+//!
+//! ```
+//! # use bdk_wallet::descriptor::WalletPolicy;
+//! # use bdk_wallet::{KeychainKind, Wallet};
+//! # use bitcoin::Network;
+//! let policy = WalletPolicy::new(
+//!     "wpkh(@0/**)",
+//!     vec!["[c55b303f/84'/1'/0']tpubDDDzQ31JkZB7VxUr9bjvBivDdqoFLrDPyLWtLapArAi51ftfmCb2DPxwLQzX65iNcXz1DGaVvyvo6JQ6rTU73r2gqdEo8uov9QKRb7nKCSU".to_string()],
+//! )?;
+//! let wallet = Wallet::new(
+//!     (&policy, KeychainKind::External),
+//!     (&policy, KeychainKind::Internal),
+//!     Network::Testnet,
+//! )?;
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+//!
+//! [wallet policy]: https://github.com/bitcoin/bips/blob/master/bip-0388.mediawiki
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use miniscript::descriptor::{DescriptorPublicKey, KeyMap, Wildcard};
+use miniscript::ForEachKey;
+
+use bitcoin::bip32::{ChildNumber, DerivationPath};
+use bitcoin::Network;
+
+use super::{DescriptorError, ExtendedDescriptor, IntoWalletDescriptor};
+use crate::wallet::utils::SecpCtx;
+use crate::KeychainKind;
+
+/// A [BIP388] wallet policy: a descriptor template plus the keys it refers to.
+///
+/// [BIP388]: https://github.com/bitcoin/bips/blob/master/bip-0388.mediawiki
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletPolicy {
+    descriptor_template: String,
+    keys: Vec<String>,
+}
+
+/// Errors related to [`WalletPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletPolicyError {
+    /// A key placeholder at the given position of the template is not of the form `@i/**` or
+    /// `@i/<M;N>/*`
+    InvalidPlaceholder(usize),
+    /// A placeholder refers to a key index that is not in the keys vector
+    KeyIndexOutOfRange(usize),
+    /// The key at the given index is never referred to by the template
+    UnusedKey(usize),
+    /// The key at the given index has a derivation path or wildcard after the extended key
+    InvalidKey(usize),
+    /// The key at the given index appears more than once in the keys vector
+    DuplicateKey(usize),
+    /// The descriptors cannot be expressed as a wallet policy
+    UnsupportedDescriptor,
+    /// Wallet policies only describe the [`KeychainKind::External`] and
+    /// [`KeychainKind::Internal`] keychains
+    CustomKeychain(u32),
+}
+
+impl fmt::Display for WalletPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPlaceholder(pos) => {
+                write!(f, "Invalid key placeholder at position {}", pos)
+            }
+            Self::KeyIndexOutOfRange(index) => {
+                write!(f, "Key placeholder @{} is out of range", index)
+            }
+            Self::UnusedKey(index) => write!(f, "Key @{} is not used by the template", index),
+            Self::InvalidKey(index) => write!(
+                f,
+                "Key @{} must be an extended key without derivation steps",
+                index
+            ),
+            Self::DuplicateKey(index) => write!(f, "Key @{} is duplicated", index),
+            Self::UnsupportedDescriptor => {
+                write!(f, "The descriptors cannot be expressed as a wallet policy")
+            }
+            Self::CustomKeychain(id) => write!(
+                f,
+                "Wallet policies don't describe the custom keychain {}",
+                id
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WalletPolicyError {}
+
+impl WalletPolicy {
+    /// Create a new [`WalletPolicy`] from a `descriptor_template` and the `keys` it refers to.
+    ///
+    /// Every key must be an extended key, optionally with its origin, and must be referred to by
+    /// at least one placeholder of the template.
+    pub fn new(
+        descriptor_template: impl Into<String>,
+        keys: Vec<String>,
+    ) -> Result<Self, WalletPolicyError> {
+        for (i, key) in keys.iter().enumerate() {
+            let key_without_origin = key.rsplit(']').next().unwrap_or(key);
+            if key_without_origin.contains(['/', '*']) {
+                return Err(WalletPolicyError::InvalidKey(i));
+            }
+            if keys[..i].contains(key) {
+                return Err(WalletPolicyError::DuplicateKey(i));
+            }
+        }
+        let policy = Self {
+            descriptor_template: descriptor_template.into(),
+            keys,
+        };
+        policy.descriptor(KeychainKind::External)?;
+        Ok(policy)
+    }
+
+    /// Build the [`WalletPolicy`] of a pair of `external` and `internal` descriptors.
+    ///
+    /// Every key of `external` must be an extended key derived with `/0/*`, and `internal` must
+    /// be the same descriptor with every key derived with `/1/*`.
+    pub fn from_descriptors(
+        external: &ExtendedDescriptor,
+        internal: &ExtendedDescriptor,
+    ) -> Result<Self, WalletPolicyError> {
+        let mut keys = Vec::new();
+        let mut descriptor_keys = Vec::new();
+        let mut supported = true;
+        external.for_each_key(|pk| {
+            let xkey = match pk {
+                DescriptorPublicKey::XPub(xkey)
+                    if xkey.wildcard == Wildcard::Unhardened
+                        && xkey.derivation_path.as_ref() == [ChildNumber::from(0)] =>
+                {
+                    xkey
+                }
+                _ => {
+                    supported = false;
+                    return false;
+                }
+            };
+            let mut policy_key = xkey.clone();
+            policy_key.derivation_path = DerivationPath::master();
+            policy_key.wildcard = Wildcard::None;
+            let policy_key = DescriptorPublicKey::XPub(policy_key).to_string();
+            if !keys.contains(&policy_key) {
+                keys.push(policy_key);
+                descriptor_keys.push(pk.to_string());
+            }
+            true
+        });
+        if !supported {
+            return Err(WalletPolicyError::UnsupportedDescriptor);
+        }
+
+        let external = external.to_string();
+        let mut descriptor_template = external
+            .split_once('#')
+            .map_or(external.as_str(), |(desc, _)| desc)
+            .to_string();
+        for (i, descriptor_key) in descriptor_keys.iter().enumerate() {
+            descriptor_template =
+                descriptor_template.replace(descriptor_key, &format!("@{}/**", i));
+        }
+
+        let policy = Self::new(descriptor_template, keys)?;
+        let expected_internal =
+            ExtendedDescriptor::from_str(&policy.descriptor(KeychainKind::Internal)?)
+                .map_err(|_| WalletPolicyError::UnsupportedDescriptor)?;
+        if &expected_internal != internal {
+            return Err(WalletPolicyError::UnsupportedDescriptor);
+        }
+        Ok(policy)
+    }
+
+    /// The descriptor template, with key placeholders.
+    pub fn descriptor_template(&self) -> &str {
+        &self.descriptor_template
+    }
+
+    /// The keys referred to by the placeholders of the descriptor template.
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Expand the wallet policy into the descriptor of the given `keychain`.
+    ///
+    /// Placeholders `@i/**` are replaced with `key/0/*` for [`KeychainKind::External`] and
+    /// `key/1/*` for [`KeychainKind::Internal`]. Placeholders `@i/<M;N>/*` use `M` and `N`
+    /// respectively. [`KeychainKind::Custom`] keychains are rejected.
+    pub fn descriptor(&self, keychain: KeychainKind) -> Result<String, WalletPolicyError> {
+        let template = self.descriptor_template.as_str();
+        let mut used = vec![false; self.keys.len()];
+        let mut descriptor = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(at) = rest.find('@') {
+            descriptor.push_str(&rest[..at]);
+            let pos = template.len() - rest.len() + at;
+            let after_at = &rest[at + 1..];
+            let digits = after_at
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after_at.len());
+            let index = after_at[..digits]
+                .parse::<usize>()
+                .map_err(|_| WalletPolicyError::InvalidPlaceholder(pos))?;
+            let key = self
+                .keys
+                .get(index)
+                .ok_or(WalletPolicyError::KeyIndexOutOfRange(index))?;
+            used[index] = true;
+
+            let suffix = &after_at[digits..];
+            let (step, remaining) = if let Some(remaining) = suffix.strip_prefix("/**") {
+                let step = match keychain {
+                    KeychainKind::External => "0",
+                    KeychainKind::Internal => "1",
+                    KeychainKind::Custom(id) => return Err(WalletPolicyError::CustomKeychain(id)),
+                };
+                (step, remaining)
+            } else {
+                let (steps, remaining) = suffix
+                    .strip_prefix("/<")
+                    .and_then(|s| s.split_once(">/*"))
+                    .ok_or(WalletPolicyError::InvalidPlaceholder(pos))?;
+                let (receive, change) = steps
+                    .split_once(';')
+                    .filter(|(m, n)| m.parse::<u32>().is_ok() && n.parse::<u32>().is_ok() && m != n)
+                    .ok_or(WalletPolicyError::InvalidPlaceholder(pos))?;
+                let step = match keychain {
+                    KeychainKind::External => receive,
+                    KeychainKind::Internal => change,
+                    KeychainKind::Custom(id) => return Err(WalletPolicyError::CustomKeychain(id)),
+                };
+                (step, remaining)
+            };
+
+            descriptor.push_str(key);
+            descriptor.push('/');
+            descriptor.push_str(step);
+            descriptor.push_str("/*");
+            rest = remaining;
+        }
+        descriptor.push_str(rest);
+
+        if let Some(unused) = used.iter().position(|used| !used) {
+            return Err(WalletPolicyError::UnusedKey(unused));
+        }
+        Ok(descriptor)
+    }
+}
+
+impl IntoWalletDescriptor for (&WalletPolicy, KeychainKind) {
+    fn into_wallet_descriptor(
+        self,
+        secp: &SecpCtx,
+        network: Network,
+    ) -> Result<(ExtendedDescriptor, KeyMap), DescriptorError> {
+        let (policy, keychain) = self;
+        policy
+            .descriptor(keychain)?
+            .as_str()
+            .into_wallet_descriptor(secp, network)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    const XPUB_A: &str = "[c55b303f/84'/1'/0']tpubDDDzQ31JkZB7VxUr9bjvBivDdqoFLrDPyLWtLapArAi51ftfmCb2DPxwLQzX65iNcXz1DGaVvyvo6JQ6rTU73r2gqdEo8uov9QKRb7nKCSU";
+    const XPUB_B: &str = "[c55b303f/49'/1'/0']tpubDC49r947KGK52X5rBWS4BLs5m9SRY3pYHnvRrm7HcybZ3BfdEsGFyzCMzayi1u58eT82ZeyFZwH7DD6Q83E3fM9CpfMtmnTygnLfP59jL9L";
+
+    #[test]
+    fn test_wallet_policy_expansion() {
+        let policy = WalletPolicy::new(
+            "wsh(sortedmulti(2,@0/**,@1/<2;3>/*))",
+            vec![XPUB_A.to_string(), XPUB_B.to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            policy.descriptor(KeychainKind::External).unwrap(),
+            format!("wsh(sortedmulti(2,{}/0/*,{}/2/*))", XPUB_A, XPUB_B)
+        );
+        assert_eq!(
+            policy.descriptor(KeychainKind::Internal).unwrap(),
+            format!("wsh(sortedmulti(2,{}/1/*,{}/3/*))", XPUB_A, XPUB_B)
+        );
+    }
+
+    #[test]
+    fn test_wallet_policy_invalid() {
+        let keys = vec![XPUB_A.to_string(), XPUB_B.to_string()];
+        assert_matches!(
+            WalletPolicy::new("wsh(multi(1,@0/**,@2/**))", keys.clone()),
+            Err(WalletPolicyError::KeyIndexOutOfRange(2))
+        );
+        assert_matches!(
+            WalletPolicy::new("wpkh(@0/**)", keys.clone()),
+            Err(WalletPolicyError::UnusedKey(1))
+        );
+        assert_matches!(
+            WalletPolicy::new("wsh(multi(1,@0/*,@1/**))", keys),
+            Err(WalletPolicyError::InvalidPlaceholder(12))
+        );
+        assert_matches!(
+            WalletPolicy::new("wpkh(@0/**)", vec![format!("{}/0", XPUB_A)]),
+            Err(WalletPolicyError::InvalidKey(0))
+        );
+        assert_matches!(
+            WalletPolicy::new(
+                "wsh(multi(1,@0/**,@1/**))",
+                vec![XPUB_A.to_string(), XPUB_A.to_string()]
+            ),
+            Err(WalletPolicyError::DuplicateKey(1))
+        );
+    }
+
+    #[test]
+    fn test_wallet_policy_round_trip() {
+        let policy = WalletPolicy::new(
+            "wsh(sortedmulti(2,@0/**,@1/**))",
+            vec![XPUB_A.to_string(), XPUB_B.to_string()],
+        )
+        .unwrap();
+        let external =
+            ExtendedDescriptor::from_str(&policy.descriptor(KeychainKind::External).unwrap())
+                .unwrap();
+        let internal =
+            ExtendedDescriptor::from_str(&policy.descriptor(KeychainKind::Internal).unwrap())
+                .unwrap();
+        assert_eq!(
+            WalletPolicy::from_descriptors(&external, &internal).unwrap(),
+            policy
+        );
+
+        // the internal descriptor must match the external one
+        assert_matches!(
+            WalletPolicy::from_descriptors(&external, &external),
+            Err(WalletPolicyError::UnsupportedDescriptor)
+        );
+    }
+}
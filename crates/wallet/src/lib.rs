@@ -24,6 +24,20 @@ extern crate serde_json;
 #[cfg(feature = "keys-bip39")]
 extern crate bip39;
 
+/// Emit a `tracing` event at the given level, e.g. `log_event!(debug, count = 1, "message")`.
+///
+/// The arguments are not evaluated when the `tracing` feature is disabled.
+#[cfg(feature = "tracing")]
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)+) => {
+        tracing::$level!($($arg)+)
+    };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)+) => {};
+}
+
 pub mod descriptor;
 pub mod keys;
 pub mod psbt;
@@ -10,6 +10,7 @@
 // licenses.
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::convert::AsRef;
 
 use bdk_chain::ConfirmationTime;
@@ -22,26 +23,54 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum KeychainKind {
     /// External keychain, used for deriving recipient addresses.
-    External = 0,
+    External,
     /// Internal keychain, used for deriving change addresses.
-    Internal = 1,
+    Internal,
+    /// Additional keychain added with [`Wallet::add_keychain`], identified by a number of the
+    /// caller's choosing.
+    ///
+    /// [`Wallet::add_keychain`]: crate::wallet::Wallet::add_keychain
+    Custom(u32),
 }
 
 impl KeychainKind {
     /// Return [`KeychainKind`] as a byte
+    ///
+    /// Every custom keychain is `b'c'`, use [`to_bytes`](Self::to_bytes) to tell them apart.
     pub fn as_byte(&self) -> u8 {
         match self {
             KeychainKind::External => b'e',
             KeychainKind::Internal => b'i',
+            KeychainKind::Custom(_) => b'c',
+        }
+    }
+
+    /// Return [`KeychainKind`] as bytes
+    ///
+    /// The external and internal keychains are encoded as `b"e"` and `b"i"`, a custom keychain as
+    /// `b'c'` followed by the big-endian bytes of its id, so that every keychain is encoded
+    /// differently.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            KeychainKind::External => b"e".to_vec(),
+            KeychainKind::Internal => b"i".to_vec(),
+            KeychainKind::Custom(id) => {
+                let mut bytes = vec![b'c'];
+                bytes.extend_from_slice(&id.to_be_bytes());
+                bytes
+            }
         }
     }
 }
 
+/// The bytes of [`KeychainKind::as_byte`], so every custom keychain is `b"c"`. Use
+/// [`KeychainKind::to_bytes`] to tell them apart.
 impl AsRef<[u8]> for KeychainKind {
     fn as_ref(&self) -> &[u8] {
         match self {
             KeychainKind::External => b"e",
             KeychainKind::Internal => b"i",
+            KeychainKind::Custom(_) => b"c",
         }
     }
 }
@@ -65,6 +94,17 @@ pub struct LocalOutput {
     pub confirmation_time: ConfirmationTime,
 }
 
+impl LocalOutput {
+    /// The height of the block the transaction creating this output was confirmed in, or `None`
+    /// if it is unconfirmed.
+    ///
+    /// This is read from [`confirmation_time`](Self::confirmation_time) rather than stored in a
+    /// field of its own, so the two can't disagree.
+    pub fn confirmation_height(&self) -> Option<u32> {
+        self.confirmation_time.confirmation_height()
+    }
+}
+
 /// A [`Utxo`] with its `satisfaction_weight`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WeightedUtxo {
@@ -0,0 +1,277 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2024 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! BIP21 payment URIs
+//!
+//! This module implements encoding and decoding of the `bitcoin:` URIs defined in
+//! [BIP21](https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki).
+//!
+//! ## Example
+//!
+//! ```
+//! # use bitcoin::{Amount, Network};
+//! # use bdk_wallet::wallet::bip21::Bip21;
+//! let uri = "bitcoin:tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7?amount=0.001&label=Coffee%20shop";
+//! let bip21 = Bip21::parse(uri, Network::Testnet)?;
+//! assert_eq!(bip21.amount, Some(Amount::from_sat(100_000)));
+//! assert_eq!(bip21.label.as_deref(), Some("Coffee shop"));
+//! assert_eq!(bip21.to_string(), uri);
+//! # Ok::<_, bdk_wallet::wallet::bip21::Bip21Error>(())
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{amount, Address, Amount, Denomination, Network};
+
+const SCHEME: &str = "bitcoin:";
+
+/// A BIP21 payment request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bip21 {
+    /// Address to pay to
+    pub address: Address,
+    /// Amount requested
+    pub amount: Option<Amount>,
+    /// Label of the address, e.g. the name of the receiver
+    pub label: Option<String>,
+    /// Message describing the payment
+    pub message: Option<String>,
+}
+
+impl Bip21 {
+    /// Parse a BIP21 URI, checking that its address is valid for `network`.
+    ///
+    /// The parameters other than `amount`, `label` and `message` are ignored, unless they start
+    /// with `req-` in which case the URI is rejected as required by BIP21.
+    pub fn parse(uri: &str, network: Network) -> Result<Self, Bip21Error> {
+        let rest = match uri.get(..SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(SCHEME) => &uri[SCHEME.len()..],
+            _ => return Err(Bip21Error::InvalidScheme),
+        };
+        let (address, query) = match rest.split_once('?') {
+            Some((address, query)) => (address, Some(query)),
+            None => (rest, None),
+        };
+
+        let address =
+            Address::<NetworkUnchecked>::from_str(address).map_err(Bip21Error::Address)?;
+        if !address.is_valid_for_network(network) {
+            return Err(Bip21Error::WrongNetwork(network));
+        }
+
+        let mut bip21 = Bip21 {
+            address: address.assume_checked(),
+            amount: None,
+            label: None,
+            message: None,
+        };
+        let mut seen: Vec<&str> = Vec::new();
+        for param in query.into_iter().flat_map(|q| q.split('&')) {
+            if param.is_empty() {
+                continue;
+            }
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            if seen.iter().any(|seen| seen.eq_ignore_ascii_case(key)) {
+                return Err(Bip21Error::DuplicateParameter(key.to_string()));
+            }
+            seen.push(key);
+
+            // keys are case-insensitive
+            match key {
+                _ if key.eq_ignore_ascii_case("amount") => {
+                    let amount = Amount::from_str_in(value, Denomination::Bitcoin)
+                        .map_err(Bip21Error::Amount)?;
+                    bip21.amount = Some(amount);
+                }
+                _ if key.eq_ignore_ascii_case("label") => {
+                    bip21.label = Some(percent_decode(value)?)
+                }
+                _ if key.eq_ignore_ascii_case("message") => {
+                    bip21.message = Some(percent_decode(value)?)
+                }
+                _ if matches!(key.get(..4), Some(prefix) if prefix.eq_ignore_ascii_case("req-")) => {
+                    return Err(Bip21Error::UnknownRequiredParameter(key.to_string()))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(bip21)
+    }
+}
+
+impl fmt::Display for Bip21 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", SCHEME, self.address)?;
+
+        let mut separator = '?';
+        if let Some(amount) = self.amount {
+            let sats = amount.to_sat();
+            let btc = sats / Amount::ONE_BTC.to_sat();
+            let fraction = sats % Amount::ONE_BTC.to_sat();
+            write!(f, "{}amount={}", separator, btc)?;
+            if fraction > 0 {
+                let fraction = format!("{:08}", fraction);
+                write!(f, ".{}", fraction.trim_end_matches('0'))?;
+            }
+            separator = '&';
+        }
+        for (key, value) in [("label", &self.label), ("message", &self.message)] {
+            if let Some(value) = value {
+                write!(f, "{}{}=", separator, key)?;
+                percent_encode(f, value)?;
+                separator = '&';
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors returned by [`Bip21::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bip21Error {
+    /// The URI doesn't start with `bitcoin:`
+    InvalidScheme,
+    /// The address couldn't be parsed
+    Address(bitcoin::address::ParseError),
+    /// The address isn't valid for the network
+    WrongNetwork(Network),
+    /// The amount couldn't be parsed
+    Amount(amount::ParseAmountError),
+    /// The label or message isn't validly percent-encoded UTF-8
+    InvalidEncoding,
+    /// A parameter is given more than once
+    DuplicateParameter(String),
+    /// A parameter prefixed with `req-` isn't supported
+    UnknownRequiredParameter(String),
+}
+
+impl fmt::Display for Bip21Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bip21Error::InvalidScheme => write!(f, "The URI doesn't start with `{}`", SCHEME),
+            Bip21Error::Address(e) => write!(f, "Invalid address: {}", e),
+            Bip21Error::WrongNetwork(network) => {
+                write!(f, "The address is not valid for network {}", network)
+            }
+            Bip21Error::Amount(e) => write!(f, "Invalid amount: {}", e),
+            Bip21Error::InvalidEncoding => write!(f, "Invalid percent-encoding"),
+            Bip21Error::DuplicateParameter(key) => write!(f, "Duplicate parameter `{}`", key),
+            Bip21Error::UnknownRequiredParameter(key) => {
+                write!(f, "Unsupported required parameter `{}`", key)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Bip21Error {}
+
+/// Write `value` percent-encoding everything but the unreserved characters of RFC 3986.
+fn percent_encode(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                write!(f, "{}", byte as char)?
+            }
+            _ => write!(f, "%{:02X}", byte)?,
+        }
+    }
+    Ok(())
+}
+
+fn percent_decode(value: &str) -> Result<String, Bip21Error> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [
+                iter.next().ok_or(Bip21Error::InvalidEncoding)?,
+                iter.next().ok_or(Bip21Error::InvalidEncoding)?,
+            ];
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return Err(Bip21Error::InvalidEncoding);
+            }
+            let hex = core::str::from_utf8(&hex).expect("ascii hex digits");
+            bytes.push(u8::from_str_radix(hex, 16).expect("ascii hex digits"));
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| Bip21Error::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percent_encoding_roundtrip() {
+        let address = Address::from_str("tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7")
+            .unwrap()
+            .assume_checked();
+        let bip21 = Bip21 {
+            address,
+            amount: Some(Amount::from_sat(1)),
+            label: Some("Alice & Bob".to_string()),
+            message: Some("50% off ☕ =?".to_string()),
+        };
+        let uri = bip21.to_string();
+        assert_eq!(
+            uri,
+            "bitcoin:tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7?amount=0.00000001\
+             &label=Alice%20%26%20Bob&message=50%25%20off%20%E2%98%95%20%3D%3F"
+        );
+        assert_eq!(Bip21::parse(&uri, Network::Testnet).unwrap(), bip21);
+    }
+
+    #[test]
+    fn test_parse_case_insensitive_keys() {
+        let bip21 = Bip21::parse(
+            "BITCOIN:tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7?AMOUNT=0.001&Label=Coffee",
+            Network::Testnet,
+        )
+        .unwrap();
+        assert_eq!(bip21.amount, Some(Amount::from_sat(100_000)));
+        assert_eq!(bip21.label.as_deref(), Some("Coffee"));
+
+        assert_eq!(
+            Bip21::parse(
+                "bitcoin:tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7?label=a&LABEL=b",
+                Network::Testnet,
+            ),
+            Err(Bip21Error::DuplicateParameter("LABEL".to_string()))
+        );
+        assert_eq!(
+            Bip21::parse(
+                "bitcoin:tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7?REQ-foo=bar",
+                Network::Testnet,
+            ),
+            Err(Bip21Error::UnknownRequiredParameter("REQ-foo".to_string()))
+        );
+        assert!(matches!(
+            Bip21::parse("bitcoin:notanaddress", Network::Testnet),
+            Err(Bip21Error::Address(_))
+        ));
+    }
+
+    #[test]
+    fn test_percent_decode_invalid() {
+        assert_eq!(percent_decode("%E2%98"), Err(Bip21Error::InvalidEncoding));
+        assert_eq!(percent_decode("%4"), Err(Bip21Error::InvalidEncoding));
+        assert_eq!(percent_decode("%zz"), Err(Bip21Error::InvalidEncoding));
+        assert_eq!(percent_decode("%+F"), Err(Bip21Error::InvalidEncoding));
+    }
+}
@@ -114,7 +114,9 @@ use bitcoin::{Script, Weight};
 
 use core::convert::TryInto;
 use core::fmt::{self, Formatter};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::{RngCore, SeedableRng};
 
 /// Default coin selection algorithm used by [`TxBuilder`](super::tx_builder::TxBuilder) if not
 /// overridden
@@ -178,6 +180,41 @@ pub enum Excess {
     },
 }
 
+impl Excess {
+    /// Decide again whether the excess can create a change output, with the dust limit of
+    /// `drain_script` computed using `dust_relay_fee` instead of the default dust relay fee.
+    pub(crate) fn with_dust_relay_fee(
+        &self,
+        drain_script: &Script,
+        dust_relay_fee: FeeRate,
+    ) -> Excess {
+        let (remaining_amount, change_fee) = match *self {
+            Excess::NoChange {
+                remaining_amount,
+                change_fee,
+                ..
+            } => (remaining_amount, change_fee),
+            Excess::Change { amount, fee } => (amount + fee, fee),
+        };
+        let drain_val = remaining_amount.saturating_sub(change_fee);
+
+        if drain_val.is_dust_with_relay_fee(drain_script, dust_relay_fee) {
+            Excess::NoChange {
+                dust_threshold: drain_script
+                    .minimal_non_dust_custom(dust_relay_fee)
+                    .to_sat(),
+                change_fee,
+                remaining_amount,
+            }
+        } else {
+            Excess::Change {
+                amount: drain_val,
+                fee: change_fee,
+            }
+        }
+    }
+}
+
 /// Result of a successful coin selection
 #[derive(Debug)]
 pub struct CoinSelectionResult {
@@ -235,6 +272,32 @@ pub trait CoinSelectionAlgorithm: core::fmt::Debug {
         target_amount: u64,
         drain_script: &Script,
     ) -> Result<CoinSelectionResult, Error>;
+
+    /// Perform the coin selection without making any random choice
+    ///
+    /// This is used instead of [`coin_select`] when the transaction is built with
+    /// [`TxBuilder::deterministic`], so the result must only depend on the arguments.
+    /// Implementations that select randomly must override this method, the default implementation
+    /// simply calls [`coin_select`].
+    ///
+    /// [`coin_select`]: Self::coin_select
+    /// [`TxBuilder::deterministic`]: crate::TxBuilder::deterministic
+    fn coin_select_deterministic(
+        &self,
+        required_utxos: Vec<WeightedUtxo>,
+        optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        target_amount: u64,
+        drain_script: &Script,
+    ) -> Result<CoinSelectionResult, Error> {
+        self.coin_select(
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script,
+        )
+    }
 }
 
 /// Simple and dumb coin selection
@@ -436,6 +499,46 @@ impl CoinSelectionAlgorithm for BranchAndBoundCoinSelection {
         fee_rate: FeeRate,
         target_amount: u64,
         drain_script: &Script,
+    ) -> Result<CoinSelectionResult, Error> {
+        self.select(
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    fn coin_select_deterministic(
+        &self,
+        required_utxos: Vec<WeightedUtxo>,
+        optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        target_amount: u64,
+        drain_script: &Script,
+    ) -> Result<CoinSelectionResult, Error> {
+        // the single random draw fallback uses a fixed seed
+        self.select(
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script,
+            &mut StdRng::seed_from_u64(0),
+        )
+    }
+}
+
+impl BranchAndBoundCoinSelection {
+    fn select(
+        &self,
+        required_utxos: Vec<WeightedUtxo>,
+        optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        target_amount: u64,
+        drain_script: &Script,
+        rng: &mut impl RngCore,
     ) -> Result<CoinSelectionResult, Error> {
         // Mapping every (UTXO, usize) to an output group
         let required_utxos: Vec<OutputGroup> = required_utxos
@@ -535,12 +638,11 @@ impl CoinSelectionAlgorithm for BranchAndBoundCoinSelection {
                     target_amount,
                     drain_script,
                     fee_rate,
+                    rng,
                 )
             }))
     }
-}
 
-impl BranchAndBoundCoinSelection {
     // TODO: make this more Rust-onic :)
     // (And perhaps refactor with less arguments?)
     #[allow(clippy::too_many_arguments)]
@@ -672,8 +774,9 @@ impl BranchAndBoundCoinSelection {
         target_amount: i64,
         drain_script: &Script,
         fee_rate: FeeRate,
+        rng: &mut impl RngCore,
     ) -> CoinSelectionResult {
-        optional_utxos.shuffle(&mut rand::thread_rng());
+        optional_utxos.shuffle(rng);
         let selected_utxos = optional_utxos.into_iter().fold(
             (curr_value, vec![]),
             |(mut amount, mut utxos), utxo| {
@@ -717,6 +820,200 @@ impl BranchAndBoundCoinSelection {
     }
 }
 
+/// Coin selection minimizing the waste metric
+///
+/// The waste of a selection is the cost of spending its inputs now rather than at
+/// `long_term_feerate`, plus either the cost of creating the change output and spending it later
+/// at `long_term_feerate`, or, for a changeless selection, the excess given to the fees. When the
+/// current fee rate is low, spending more inputs has a negative waste, and the other way around.
+///
+/// The candidates considered are the changeless selection found by [branch and bound], the
+/// selections adding the optional UTXOs largest first and smallest first, and every single
+/// optional UTXO covering the target on its own. The one with the lowest waste is picked, which
+/// avoids a change output barely above the dust limit when a changeless selection wastes less.
+///
+/// [branch and bound]: BranchAndBoundCoinSelection
+#[derive(Debug, Clone, Copy)]
+pub struct WasteMinimizingCoinSelection {
+    long_term_feerate: FeeRate,
+}
+
+impl WasteMinimizingCoinSelection {
+    /// Create new instance with the fee rate expected to spend UTXOs in the long term
+    pub fn new(long_term_feerate: FeeRate) -> Self {
+        Self { long_term_feerate }
+    }
+
+    // Cost of spending a change output later, assuming a P2WPKH one:
+    // TxIn base weight + witness of a signature and public key.
+    pub(crate) fn change_spend_weight() -> Weight {
+        Weight::from_wu(TxIn::default().segwit_weight().to_wu() + 1 + 72 + 1 + 33)
+    }
+
+    // `group.fee` is the cost of the input at the current fee rate
+    fn input_waste(&self, group: &OutputGroup) -> i64 {
+        let weight = Weight::from_wu(
+            TxIn::default().segwit_weight().to_wu()
+                + group.weighted_utxo.satisfaction_weight as u64,
+        );
+        group.fee as i64 - (self.long_term_feerate * weight).to_sat() as i64
+    }
+}
+
+impl CoinSelectionAlgorithm for WasteMinimizingCoinSelection {
+    fn coin_select(
+        &self,
+        required_utxos: Vec<WeightedUtxo>,
+        optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        target_amount: u64,
+        drain_script: &Script,
+    ) -> Result<CoinSelectionResult, Error> {
+        let required_utxos: Vec<OutputGroup> = required_utxos
+            .into_iter()
+            .map(|u| OutputGroup::new(u, fee_rate))
+            .collect();
+        let mut optional_utxos: Vec<OutputGroup> = optional_utxos
+            .into_iter()
+            .map(|u| OutputGroup::new(u, fee_rate))
+            .filter(|u| u.effective_value.is_positive())
+            .collect();
+        optional_utxos.sort_by_key(|u| core::cmp::Reverse(u.effective_value));
+
+        let required_value = required_utxos
+            .iter()
+            .map(|u| u.effective_value)
+            .sum::<i64>();
+        let required_waste = required_utxos
+            .iter()
+            .map(|u| self.input_waste(u))
+            .sum::<i64>();
+        let target = target_amount
+            .try_into()
+            .expect("Bitcoin amount to fit into i64");
+
+        // Candidates are lists of indexes in `optional_utxos`
+        let mut candidates: Vec<Vec<usize>> = Vec::new();
+        let prefix = |order: &mut dyn Iterator<Item = usize>| {
+            let mut value = required_value;
+            let mut selection = Vec::new();
+            for i in order {
+                if value >= target {
+                    break;
+                }
+                value += optional_utxos[i].effective_value;
+                selection.push(i);
+            }
+            selection
+        };
+        candidates.push(prefix(&mut (0..optional_utxos.len())));
+        candidates.push(prefix(&mut (0..optional_utxos.len()).rev()));
+        candidates.extend((0..optional_utxos.len()).map(|i| vec![i]));
+
+        // The changeless selection from BnB, with the change costed as this algorithm does
+        let drain_output_weight =
+            Weight::from_vb(serialize(drain_script).len() as u64 + 8).expect("overflow occurred");
+        let cost_of_change = (fee_rate * drain_output_weight).to_sat()
+            + (self.long_term_feerate * Self::change_spend_weight()).to_sat();
+        let optional_value = optional_utxos
+            .iter()
+            .map(|u| u.effective_value)
+            .sum::<i64>();
+        if required_value < target {
+            if let Ok(result) = BranchAndBoundCoinSelection::default().bnb(
+                required_utxos.clone(),
+                optional_utxos.clone(),
+                required_value,
+                optional_value,
+                target,
+                cost_of_change,
+                drain_script,
+                fee_rate,
+            ) {
+                let selected = result
+                    .selected
+                    .iter()
+                    .map(|u| u.outpoint())
+                    .collect::<HashSet<_>>();
+                candidates.push(
+                    (0..optional_utxos.len())
+                        .filter(|&i| {
+                            selected.contains(&optional_utxos[i].weighted_utxo.utxo.outpoint())
+                        })
+                        .collect(),
+                );
+            }
+        }
+
+        let mut best: Option<(i64, Vec<usize>, Excess)> = None;
+        for candidate in candidates {
+            let value = required_value
+                + candidate
+                    .iter()
+                    .map(|&i| optional_utxos[i].effective_value)
+                    .sum::<i64>();
+            if value < target {
+                continue;
+            }
+            let excess = decide_change((value - target) as u64, fee_rate, drain_script);
+            let excess_waste = match excess {
+                Excess::Change { fee, .. } => {
+                    fee + (self.long_term_feerate * Self::change_spend_weight()).to_sat()
+                }
+                Excess::NoChange {
+                    remaining_amount, ..
+                } => remaining_amount,
+            };
+            let waste = required_waste
+                + candidate
+                    .iter()
+                    .map(|&i| self.input_waste(&optional_utxos[i]))
+                    .sum::<i64>()
+                + excess_waste as i64;
+            let is_better = match &best {
+                Some((best_waste, best_candidate, _)) => {
+                    waste < *best_waste
+                        || (waste == *best_waste && candidate.len() < best_candidate.len())
+                }
+                None => true,
+            };
+            if is_better {
+                best = Some((waste, candidate, excess));
+            }
+        }
+
+        match best {
+            Some((_, candidate, excess)) => {
+                let mut optional_utxos = optional_utxos.into_iter().map(Some).collect::<Vec<_>>();
+                let selected = candidate
+                    .into_iter()
+                    .filter_map(|i| optional_utxos[i].take())
+                    .collect();
+                Ok(BranchAndBoundCoinSelection::calculate_cs_result(
+                    selected,
+                    required_utxos,
+                    excess,
+                ))
+            }
+            None => {
+                let (utxo_fees, utxo_value) = required_utxos
+                    .iter()
+                    .chain(optional_utxos.iter())
+                    .fold((0, 0), |(fees, value), utxo| {
+                        (
+                            fees + utxo.fee,
+                            value + utxo.weighted_utxo.utxo.txout().value.to_sat(),
+                        )
+                    });
+                Err(Error::InsufficientFunds {
+                    needed: target_amount + utxo_fees,
+                    available: utxo_value,
+                })
+            }
+        }
+    }
+}
+
 /// Remove duplicate UTXOs.
 ///
 /// If a UTXO appears in both `required` and `optional`, the appearance in `required` is kept.
@@ -1432,6 +1729,7 @@ mod test {
             target_amount as i64,
             &drain_script,
             fee_rate,
+            &mut rng,
         );
 
         assert!(result.selected_amount() > target_amount);
@@ -1508,6 +1806,146 @@ mod test {
         );
     }
 
+    fn selection_waste(
+        result: &CoinSelectionResult,
+        fee_rate: FeeRate,
+        long_term_feerate: FeeRate,
+    ) -> i64 {
+        let input_weight = Weight::from_wu(
+            TxIn::default().segwit_weight().to_wu() + P2WPKH_SATISFACTION_SIZE as u64,
+        );
+        let input_waste = (fee_rate * input_weight).to_sat() as i64
+            - (long_term_feerate * input_weight).to_sat() as i64;
+        let excess_waste = match result.excess {
+            Excess::Change { fee, .. } => fee + (long_term_feerate * input_weight).to_sat(),
+            Excess::NoChange {
+                remaining_amount, ..
+            } => remaining_amount,
+        };
+        result.selected.len() as i64 * input_waste + excess_waste as i64
+    }
+
+    #[test]
+    fn test_waste_minimizing_prefers_changeless() {
+        let utxos = vec![
+            utxo(100_000, 0, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+            utxo(60_000, 1, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+            utxo(40_100, 2, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+        ];
+        let drain_script = ScriptBuf::default();
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+        let target_amount = 40_000;
+
+        let result = WasteMinimizingCoinSelection::new(fee_rate)
+            .coin_select(
+                vec![],
+                utxos.clone(),
+                fee_rate,
+                target_amount,
+                &drain_script,
+            )
+            .unwrap();
+
+        assert_eq!(result.selected.len(), 1);
+        assert_eq!(result.selected_amount(), 40_100);
+        assert_matches!(result.excess, Excess::NoChange { .. });
+
+        // no exact match for BnB, which falls back to a selection with change
+        let bnb_result = BranchAndBoundCoinSelection::default()
+            .coin_select(vec![], utxos, fee_rate, target_amount, &drain_script)
+            .unwrap();
+        assert!(
+            selection_waste(&result, fee_rate, fee_rate)
+                <= selection_waste(&bnb_result, fee_rate, fee_rate)
+        );
+    }
+
+    #[test]
+    fn test_waste_minimizing_not_worse_than_bnb() {
+        let drain_script = ScriptBuf::default();
+        let long_term_feerate = FeeRate::from_sat_per_vb_unchecked(5);
+
+        for fee_rate in [1, 5, 20] {
+            let fee_rate = FeeRate::from_sat_per_vb_unchecked(fee_rate);
+            for target_amount in [40_000, 150_000, 250_000, 280_000] {
+                let utxos = get_test_utxos();
+                let result = WasteMinimizingCoinSelection::new(long_term_feerate)
+                    .coin_select(
+                        vec![],
+                        utxos.clone(),
+                        fee_rate,
+                        target_amount,
+                        &drain_script,
+                    )
+                    .unwrap();
+                let bnb_result = BranchAndBoundCoinSelection::default()
+                    .coin_select(vec![], utxos, fee_rate, target_amount, &drain_script)
+                    .unwrap();
+
+                assert!(result.selected_amount() >= target_amount + result.fee_amount);
+                assert!(
+                    selection_waste(&result, fee_rate, long_term_feerate)
+                        <= selection_waste(&bnb_result, fee_rate, long_term_feerate)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_waste_minimizing_consolidates_at_low_feerate() {
+        let utxos = vec![
+            utxo(200_000, 0, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+            utxo(60_000, 1, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+            utxo(50_000, 2, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+            utxo(45_000, 3, ConfirmationTime::Unconfirmed { last_seen: 0 }),
+        ];
+        let drain_script = ScriptBuf::default();
+        let target_amount = 150_000;
+
+        // spending inputs now is cheaper than later
+        let result = WasteMinimizingCoinSelection::new(FeeRate::from_sat_per_vb_unchecked(10))
+            .coin_select(
+                vec![],
+                utxos.clone(),
+                FeeRate::from_sat_per_vb_unchecked(1),
+                target_amount,
+                &drain_script,
+            )
+            .unwrap();
+        assert_eq!(result.selected.len(), 3);
+        assert_eq!(result.selected_amount(), 155_000);
+
+        // spending inputs now is more expensive than later
+        let result = WasteMinimizingCoinSelection::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .coin_select(
+                vec![],
+                utxos,
+                FeeRate::from_sat_per_vb_unchecked(10),
+                target_amount,
+                &drain_script,
+            )
+            .unwrap();
+        assert_eq!(result.selected.len(), 1);
+        assert_eq!(result.selected_amount(), 200_000);
+    }
+
+    #[test]
+    fn test_waste_minimizing_insufficient_funds() {
+        let utxos = get_test_utxos();
+        let drain_script = ScriptBuf::default();
+
+        let selection = WasteMinimizingCoinSelection::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .coin_select(
+                vec![],
+                utxos,
+                FeeRate::from_sat_per_vb_unchecked(1),
+                500_000,
+                &drain_script,
+            );
+
+        assert_matches!(selection, Err(Error::InsufficientFunds { .. }));
+    }
+
     #[test]
     fn test_filter_duplicates() {
         fn utxo(txid: &str, value: u64) -> WeightedUtxo {
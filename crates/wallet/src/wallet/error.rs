@@ -13,10 +13,11 @@
 
 use crate::descriptor::policy::PolicyError;
 use crate::descriptor::DescriptorError;
+use crate::signer::SignerError;
 use crate::wallet::coin_selection;
 use crate::{descriptor, KeychainKind};
-use alloc::string::String;
-use bitcoin::{absolute, psbt, Amount, OutPoint, Sequence, Txid};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use bitcoin::{absolute, psbt, Amount, OutPoint, ScriptBuf, Sequence, Txid};
 use core::fmt;
 
 /// Errors returned by miniscript when updating inconsistent PSBTs
@@ -84,6 +85,24 @@ pub enum CreateTxError {
         /// Required fee rate
         required: bitcoin::FeeRate,
     },
+    /// The fee rate is higher than the maximum set with [`TxBuilder::max_fee_rate`]
+    ///
+    /// [`TxBuilder::max_fee_rate`]: crate::wallet::tx_builder::TxBuilder::max_fee_rate
+    FeeRateTooHigh {
+        /// Maximum fee rate
+        max: bitcoin::FeeRate,
+        /// Requested fee rate, or the minimum fee rate required to replace a transaction
+        required: bitcoin::FeeRate,
+    },
+    /// The fee is higher than the maximum set with [`TxBuilder::max_fee`]
+    ///
+    /// [`TxBuilder::max_fee`]: crate::wallet::tx_builder::TxBuilder::max_fee
+    FeeTooHigh {
+        /// Maximum fee
+        max: Amount,
+        /// Fee of the transaction
+        fee: Amount,
+    },
     /// `manually_selected_only` option is selected but no utxo has been passed
     NoUtxosSelected,
     /// Output created is under the dust limit, 546 satoshis
@@ -106,6 +125,84 @@ pub enum CreateTxError {
     MissingNonWitnessUtxo(OutPoint),
     /// Miniscript PSBT error
     MiniscriptPsbt(MiniscriptPsbtError),
+    /// The change index requested with [`TxBuilder::change_to_index`] has already received funds
+    ///
+    /// [`TxBuilder::change_to_index`]: crate::wallet::tx_builder::TxBuilder::change_to_index
+    ChangeIndexUsed {
+        /// Keychain of the requested change index
+        keychain: KeychainKind,
+        /// Requested change index
+        index: u32,
+    },
+    /// The change index requested with [`TxBuilder::change_to_index`] cannot be derived from the
+    /// keychain's descriptor
+    ///
+    /// [`TxBuilder::change_to_index`]: crate::wallet::tx_builder::TxBuilder::change_to_index
+    ChangeIndexUnavailable {
+        /// Keychain of the requested change index
+        keychain: KeychainKind,
+        /// Requested change index
+        index: u32,
+    },
+    /// The change keychain is not part of the wallet
+    KeychainNotFound(KeychainKind),
+    /// The output reserved with [`TxBuilder::reserve_cpfp_output`] would not belong to the wallet
+    ///
+    /// [`TxBuilder::reserve_cpfp_output`]: crate::wallet::tx_builder::TxBuilder::reserve_cpfp_output
+    CpfpReserveNotMine,
+    /// The data of an `OP_RETURN` output is larger than the 80 bytes allowed by the standardness
+    /// rules
+    OpReturnTooLarge(usize),
+    /// The estimated weight of the transaction exceeds the standardness limit, so it wouldn't be
+    /// relayed
+    TransactionTooLarge {
+        /// The estimated weight of the signed transaction
+        weight: u64,
+    },
+    /// The UTXOs that must be spent are more than allowed by [`TxBuilder::max_inputs`]
+    ///
+    /// [`TxBuilder::max_inputs`]: crate::wallet::tx_builder::TxBuilder::max_inputs
+    TooManyInputs {
+        /// Number of UTXOs that must be spent
+        required: usize,
+        /// Maximum number of inputs allowed
+        max_inputs: usize,
+    },
+    /// The nSequence set with [`TxBuilder::set_sequence_for`] doesn't satisfy the required OP_CSV
+    ///
+    /// [`TxBuilder::set_sequence_for`]: crate::wallet::tx_builder::TxBuilder::set_sequence_for
+    SequenceCsv {
+        /// The input the nSequence was set for
+        outpoint: OutPoint,
+        /// Given `Sequence`
+        sequence: Sequence,
+        /// Required OP_CSV `Sequence`
+        csv: Sequence,
+    },
+    /// RBF was enabled but the nSequence of no input signals it
+    RbfNotSignaled,
+    /// The recipients of a fee bump built with [`Wallet::build_fee_bump_preserving_outputs`] were
+    /// modified
+    ///
+    /// [`Wallet::build_fee_bump_preserving_outputs`]: crate::wallet::Wallet::build_fee_bump_preserving_outputs
+    PreservedRecipientsModified,
+    /// An input spends an output that is already spent by a confirmed transaction, so the
+    /// transaction can never confirm
+    InputAlreadySpent {
+        /// The output spent by the input
+        outpoint: OutPoint,
+        /// The confirmed transaction spending `outpoint`
+        by_txid: Txid,
+    },
+    /// The sig hash set with [`TxBuilder::sighash_for`] isn't valid for the type of the input
+    ///
+    /// [`TxBuilder::sighash_for`]: crate::wallet::tx_builder::TxBuilder::sighash_for
+    InvalidSighash {
+        /// The input the sig hash was set for
+        outpoint: OutPoint,
+        /// Given sig hash
+        sighash: psbt::PsbtSighashType,
+    },
 }
 
 impl fmt::Display for CreateTxError {
@@ -153,6 +250,22 @@ impl fmt::Display for CreateTxError {
                     crate::floating_rate!(required)
                 )
             }
+            CreateTxError::FeeRateTooHigh { max, required } => {
+                write!(
+                    f,
+                    "Fee rate too high: {} sat/vb is required but the maximum is {} sat/vb",
+                    crate::floating_rate!(required),
+                    crate::floating_rate!(max)
+                )
+            }
+            CreateTxError::FeeTooHigh { max, fee } => {
+                write!(
+                    f,
+                    "Fee too high: {} but the maximum is {}",
+                    fee.display_dynamic(),
+                    max.display_dynamic()
+                )
+            }
             CreateTxError::NoUtxosSelected => {
                 write!(f, "No UTXO selected")
             }
@@ -176,6 +289,85 @@ impl fmt::Display for CreateTxError {
             CreateTxError::MiniscriptPsbt(err) => {
                 write!(f, "Miniscript PSBT error: {}", err)
             }
+            CreateTxError::ChangeIndexUsed { keychain, index } => {
+                write!(
+                    f,
+                    "Change index {} of keychain {:?} has already received funds",
+                    index, keychain
+                )
+            }
+            CreateTxError::ChangeIndexUnavailable { keychain, index } => {
+                write!(
+                    f,
+                    "Change index {} of keychain {:?} cannot be derived",
+                    index, keychain
+                )
+            }
+            CreateTxError::KeychainNotFound(keychain) => {
+                write!(f, "The keychain {:?} is not part of the wallet", keychain)
+            }
+            CreateTxError::CpfpReserveNotMine => {
+                write!(f, "The reserved CPFP output does not belong to the wallet")
+            }
+            CreateTxError::OpReturnTooLarge(size) => {
+                write!(
+                    f,
+                    "OP_RETURN data of {} bytes exceeds the 80 bytes limit",
+                    size
+                )
+            }
+            CreateTxError::TransactionTooLarge { weight } => {
+                write!(
+                    f,
+                    "Transaction weight {} exceeds the standard limit of {}",
+                    weight,
+                    bitcoin::policy::MAX_STANDARD_TX_WEIGHT
+                )
+            }
+            CreateTxError::TooManyInputs {
+                required,
+                max_inputs,
+            } => {
+                write!(
+                    f,
+                    "{} UTXOs must be spent but at most {} inputs are allowed",
+                    required, max_inputs
+                )
+            }
+            CreateTxError::SequenceCsv {
+                outpoint,
+                sequence,
+                csv,
+            } => {
+                write!(
+                    f,
+                    "nSequence `{:?}` of input {} doesn't satisfy the required OP_CSV of `{:?}`",
+                    sequence, outpoint, csv
+                )
+            }
+            CreateTxError::RbfNotSignaled => {
+                write!(f, "RBF is enabled but no input signals it")
+            }
+            CreateTxError::PreservedRecipientsModified => {
+                write!(
+                    f,
+                    "The recipients of the original transaction must be preserved by the fee bump"
+                )
+            }
+            CreateTxError::InputAlreadySpent { outpoint, by_txid } => {
+                write!(
+                    f,
+                    "Input {} is already spent by confirmed transaction {}",
+                    outpoint, by_txid
+                )
+            }
+            CreateTxError::InvalidSighash { outpoint, sighash } => {
+                write!(
+                    f,
+                    "Sighash `{:?}` is not valid for the type of input {}",
+                    sighash, outpoint
+                )
+            }
         }
     }
 }
@@ -210,6 +402,12 @@ impl From<coin_selection::Error> for CreateTxError {
     }
 }
 
+impl From<KeychainNotFound> for CreateTxError {
+    fn from(err: KeychainNotFound) -> Self {
+        CreateTxError::KeychainNotFound(err.0)
+    }
+}
+
 #[cfg(feature = "std")]
 impl std::error::Error for CreateTxError {}
 
@@ -258,3 +456,302 @@ impl fmt::Display for BuildFeeBumpError {
 
 #[cfg(feature = "std")]
 impl std::error::Error for BuildFeeBumpError {}
+
+/// Error explaining why a transaction passed to [`Wallet::build_fee_bumps`] could not be replaced
+///
+/// [`Wallet::build_fee_bumps`]: crate::wallet::Wallet::build_fee_bumps
+#[derive(Debug)]
+pub enum FeeBumpsError {
+    /// The fee bump could not be started, see [`BuildFeeBumpError`]
+    BuildFeeBump(BuildFeeBumpError),
+    /// The replacement transaction could not be created, see [`CreateTxError`]
+    CreateTx(CreateTxError),
+    /// The transaction spends an output of another of the replaced transactions
+    SpendsReplacedTransaction(Txid),
+}
+
+impl fmt::Display for FeeBumpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BuildFeeBump(e) => e.fmt(f),
+            Self::CreateTx(e) => e.fmt(f),
+            Self::SpendsReplacedTransaction(txid) => write!(
+                f,
+                "Transaction spends an output of the replaced transaction with txid: {}",
+                txid
+            ),
+        }
+    }
+}
+
+impl From<BuildFeeBumpError> for FeeBumpsError {
+    fn from(err: BuildFeeBumpError) -> Self {
+        FeeBumpsError::BuildFeeBump(err)
+    }
+}
+
+impl From<CreateTxError> for FeeBumpsError {
+    fn from(err: CreateTxError) -> Self {
+        FeeBumpsError::CreateTx(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FeeBumpsError {}
+
+/// A difference between a PSBT and the payment it's expected to make, see [`MismatchError`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentMismatch {
+    /// No output pays to the script pubkey of a recipient
+    MissingRecipient {
+        /// Script pubkey of the recipient
+        script_pubkey: ScriptBuf,
+        /// Expected amount
+        amount: Amount,
+    },
+    /// The output paying to a recipient has a different amount
+    WrongAmount {
+        /// Index of the output
+        vout: usize,
+        /// Expected amount
+        expected: Amount,
+        /// Amount of the output
+        actual: Amount,
+    },
+    /// An output pays to a script pubkey that is neither a recipient nor the wallet's
+    UnexpectedOutput {
+        /// Index of the output
+        vout: usize,
+        /// Script pubkey of the output
+        script_pubkey: ScriptBuf,
+        /// Amount of the output
+        amount: Amount,
+    },
+    /// The fee is higher than the maximum
+    FeeTooHigh {
+        /// Maximum fee
+        max: Amount,
+        /// Fee of the PSBT
+        actual: Amount,
+    },
+    /// The fee can't be computed because the previous output of an input is unknown to the wallet
+    UnknownFee,
+}
+
+impl fmt::Display for PaymentMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRecipient {
+                script_pubkey,
+                amount,
+            } => write!(f, "No output pays {} to {}", amount, script_pubkey),
+            Self::WrongAmount {
+                vout,
+                expected,
+                actual,
+            } => write!(f, "Output {} pays {} instead of {}", vout, actual, expected),
+            Self::UnexpectedOutput {
+                vout,
+                script_pubkey,
+                amount,
+            } => write!(
+                f,
+                "Unexpected output {} paying {} to {}",
+                vout, amount, script_pubkey
+            ),
+            Self::FeeTooHigh { max, actual } => {
+                write!(f, "Fee {} is higher than the maximum {}", actual, max)
+            }
+            Self::UnknownFee => write!(f, "The fee is unknown"),
+        }
+    }
+}
+
+/// Error returned from [`Wallet::assert_psbt_matches`]
+///
+/// [`Wallet::assert_psbt_matches`]: super::Wallet::assert_psbt_matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MismatchError {
+    /// Every difference between the PSBT and the expected payment
+    pub mismatches: Vec<PaymentMismatch>,
+}
+
+impl fmt::Display for MismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The PSBT doesn't match the expected payment")?;
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { ":" } else { ";" }, mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MismatchError {}
+
+#[derive(Debug)]
+/// Error returned from [`Wallet::try_extract_tx`]
+///
+/// [`Wallet::try_extract_tx`]: super::Wallet::try_extract_tx
+pub enum ExtractError {
+    /// The inputs at these indexes are not finalized
+    NotFinalized(Vec<usize>),
+    /// The finalized transaction couldn't be extracted from the PSBT
+    ExtractTx(Box<psbt::ExtractTxError>),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFinalized(inputs) => {
+                write!(f, "The inputs {:?} of the PSBT are not finalized", inputs)
+            }
+            Self::ExtractTx(e) => write!(f, "Failed to extract the transaction: {}", e),
+        }
+    }
+}
+
+impl From<psbt::ExtractTxError> for ExtractError {
+    fn from(err: psbt::ExtractTxError) -> Self {
+        ExtractError::ExtractTx(Box::new(err))
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ExtractError {}
+
+#[derive(Debug)]
+/// Error returned from [`Wallet::create_signed_tx`]
+///
+/// [`Wallet::create_signed_tx`]: super::Wallet::create_signed_tx
+pub enum CreateSignedTxError {
+    /// There was an error building the transaction
+    CreateTx(CreateTxError),
+    /// There was an error signing the transaction
+    Signer(SignerError),
+    /// The wallet couldn't sign all the inputs of the transaction
+    NotFinalized,
+    /// The finalized transaction couldn't be extracted from the PSBT
+    ExtractTx(Box<psbt::ExtractTxError>),
+}
+
+impl fmt::Display for CreateSignedTxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateTx(e) => write!(f, "Failed to build the transaction: {}", e),
+            Self::Signer(e) => write!(f, "Failed to sign the transaction: {}", e),
+            Self::NotFinalized => write!(f, "The transaction couldn't be fully signed"),
+            Self::ExtractTx(e) => write!(f, "Failed to extract the transaction: {}", e),
+        }
+    }
+}
+
+impl From<CreateTxError> for CreateSignedTxError {
+    fn from(err: CreateTxError) -> Self {
+        CreateSignedTxError::CreateTx(err)
+    }
+}
+
+impl From<SignerError> for CreateSignedTxError {
+    fn from(err: SignerError) -> Self {
+        CreateSignedTxError::Signer(err)
+    }
+}
+
+impl From<psbt::ExtractTxError> for CreateSignedTxError {
+    fn from(err: psbt::ExtractTxError) -> Self {
+        CreateSignedTxError::ExtractTx(Box::new(err))
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CreateSignedTxError {}
+
+#[derive(Debug)]
+/// Error returned from [`Wallet::add_private_keys`]
+///
+/// [`Wallet::add_private_keys`]: super::Wallet::add_private_keys
+pub enum AddPrivateKeysError {
+    /// There was a problem with the passed-in descriptor
+    Descriptor(DescriptorError),
+    /// The descriptor doesn't match any of the wallet's descriptors, so its keys don't derive the
+    /// wallet's scripts
+    DescriptorMismatch,
+}
+
+impl fmt::Display for AddPrivateKeysError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Descriptor(e) => e.fmt(f),
+            Self::DescriptorMismatch => write!(
+                f,
+                "The descriptor doesn't match any of the wallet's descriptors"
+            ),
+        }
+    }
+}
+
+impl From<DescriptorError> for AddPrivateKeysError {
+    fn from(err: DescriptorError) -> Self {
+        AddPrivateKeysError::Descriptor(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AddPrivateKeysError {}
+
+#[derive(Debug)]
+/// Error returned from [`Wallet::add_keychain`]
+///
+/// [`Wallet::add_keychain`]: super::Wallet::add_keychain
+pub enum AddKeychainError {
+    /// There was a problem with the passed-in descriptor
+    Descriptor(DescriptorError),
+    /// The keychain is already tracking a different descriptor
+    KeychainAlreadyAssigned(KeychainKind),
+    /// The descriptor is already tracked by the given keychain
+    DescriptorAlreadyAssigned(KeychainKind),
+}
+
+impl fmt::Display for AddKeychainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Descriptor(e) => e.fmt(f),
+            Self::KeychainAlreadyAssigned(keychain) => write!(
+                f,
+                "The keychain {:?} is already tracking a different descriptor",
+                keychain
+            ),
+            Self::DescriptorAlreadyAssigned(keychain) => write!(
+                f,
+                "The descriptor is already tracked by the keychain {:?}",
+                keychain
+            ),
+        }
+    }
+}
+
+impl From<DescriptorError> for AddKeychainError {
+    fn from(err: DescriptorError) -> Self {
+        AddKeychainError::Descriptor(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AddKeychainError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned when a keychain is not part of the wallet, e.g. a [`KeychainKind::Custom`]
+/// that wasn't added with [`Wallet::add_keychain`]
+///
+/// [`Wallet::add_keychain`]: super::Wallet::add_keychain
+pub struct KeychainNotFound(pub KeychainKind);
+
+impl fmt::Display for KeychainNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The keychain {:?} is not part of the wallet", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeychainNotFound {}
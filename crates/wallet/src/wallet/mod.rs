@@ -12,7 +12,7 @@
 //! Wallet
 //!
 //! This module defines the [`Wallet`].
-use crate::collections::{BTreeMap, HashMap};
+use crate::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use alloc::{
     boxed::Box,
     string::{String, ToString},
@@ -28,25 +28,32 @@ use bdk_chain::{
     },
     persist::{PersistBackend, StageExt},
     spk_client::{FullScanRequest, FullScanResult, SyncRequest, SyncResult},
-    tx_graph::{CanonicalTx, TxGraph},
-    Append, BlockId, ChainPosition, ConfirmationTime, ConfirmationTimeHeightAnchor, FullTxOut,
-    Indexed, IndexedTxGraph,
+    tx_graph::{CanonicalTx, NonCanonicalReason, TxGraph},
+    Append, BlockId, ChainPosition, ConfirmationTime, ConfirmationTimeHeightAnchor, DescriptorExt,
+    FullTxOut, Indexed, IndexedTxGraph,
 };
+use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::secp256k1::{All, Secp256k1};
-use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+use bitcoin::sighash::TapSighashType;
 use bitcoin::{
     absolute, psbt, Address, Block, FeeRate, Network, OutPoint, Script, ScriptBuf, Sequence,
-    Transaction, TxOut, Txid, Witness,
+    Transaction, TxOut, Txid, Weight, Witness,
 };
 use bitcoin::{consensus::encode::serialize, transaction, BlockHash, Psbt};
 use bitcoin::{constants::genesis_block, Amount};
 use core::fmt;
 use core::ops::Deref;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use core::time::Duration;
 use descriptor::error::Error as DescriptorError;
 use miniscript::psbt::{PsbtExt, PsbtInputExt, PsbtInputSatisfier};
 
+#[cfg(feature = "labels")]
+use bdk_chain::label::{LabelKey, Labels};
 use bdk_chain::tx_graph::CalculateFeeError;
 
+pub mod bip21;
 pub mod coin_selection;
 pub mod export;
 pub mod signer;
@@ -57,12 +64,16 @@ pub mod error;
 
 pub use utils::IsDust;
 
+use bip21::{Bip21, Bip21Error};
 use coin_selection::DefaultCoinSelectionAlgorithm;
 use signer::{SignOptions, SignerOrdering, SignersContainer, TransactionSigner};
-use tx_builder::{FeePolicy, TxBuilder, TxParams};
-use utils::{check_nsequence_rbf, After, Older, SecpCtx};
+use tx_builder::{
+    ChangeOutput, CoinSelectionDetails, FeeDetails, FeePolicy, TxBuilder, TxDetails, TxOrdering,
+    TxParams,
+};
+use utils::{check_nsequence_rbf, After, BalanceCache, Older, SecpCtx};
 
-use crate::descriptor::policy::BuildSatisfaction;
+use crate::descriptor::policy::{BuildSatisfaction, Condition};
 use crate::descriptor::{
     self, calc_checksum, into_wallet_descriptor_checked, DerivedDescriptor, DescriptorMeta,
     ExtendedDescriptor, ExtractPolicy, IntoWalletDescriptor, Policy, XKeyUtils,
@@ -71,12 +82,27 @@ use crate::psbt::PsbtUtils;
 use crate::signer::SignerError;
 use crate::types::*;
 use crate::wallet::coin_selection::Excess::{Change, NoChange};
-use crate::wallet::error::{BuildFeeBumpError, CreateTxError, MiniscriptPsbtError};
+use crate::wallet::error::{
+    AddKeychainError, AddPrivateKeysError, BuildFeeBumpError, CreateSignedTxError, CreateTxError,
+    ExtractError, FeeBumpsError, KeychainNotFound, MiniscriptPsbtError, MismatchError,
+    PaymentMismatch,
+};
 
 use self::coin_selection::Error;
 
 const COINBASE_MATURITY: u32 = 100;
 
+/// The default dust relay fee of Bitcoin Core, 3000 sat/kvB.
+///
+/// See [`Wallet::set_dust_relay_fee`].
+pub const DEFAULT_DUST_RELAY_FEE: FeeRate = FeeRate::from_sat_per_kwu(750);
+
+/// The default time the inputs of a created transaction stay locked, 10 minutes.
+///
+/// See [`Wallet::set_utxo_lock_ttl`].
+#[cfg(feature = "std")]
+pub const DEFAULT_UTXO_LOCK_TTL: Duration = Duration::from_secs(600);
+
 /// A Bitcoin wallet
 ///
 /// The `Wallet` acts as a way of coherently interfacing with output descriptors and related transactions.
@@ -89,17 +115,33 @@ const COINBASE_MATURITY: u32 = 100;
 /// [`PersistBackend`]. See individual functions and example for instructions on when [`Wallet`]
 /// state needs to be persisted.
 ///
+/// Besides its [`KeychainKind::External`] and [`KeychainKind::Internal`] keychains, a `Wallet` can
+/// track sub-accounts as [`KeychainKind::Custom`] keychains added with [`Wallet::add_keychain`].
+///
 /// [`PersistBackend`]: bdk_chain::persist::PersistBackend
 /// [`signer`]: crate::signer
 #[derive(Debug)]
 pub struct Wallet {
     signers: Arc<SignersContainer>,
     change_signers: Arc<SignersContainer>,
+    custom_signers: BTreeMap<u32, Arc<SignersContainer>>,
     chain: LocalChain,
     indexed_graph: IndexedTxGraph<ConfirmationTimeHeightAnchor, KeychainTxOutIndex<KeychainKind>>,
     stage: ChangeSet,
     network: Network,
     secp: SecpCtx,
+    balance_cache: BalanceCache,
+    #[cfg(feature = "labels")]
+    labels: Labels,
+    #[cfg(feature = "labels")]
+    pending_labels: BTreeMap<Txid, BTreeMap<u32, String>>,
+    dust_relay_fee: FeeRate,
+    change_keychain: KeychainKind,
+    #[cfg(feature = "std")]
+    last_commit: Option<std::time::Instant>,
+    locked_utxos: BTreeMap<OutPoint, u64>,
+    #[cfg(feature = "std")]
+    utxo_lock_ttl: Option<Duration>,
 }
 
 /// An update to [`Wallet`].
@@ -144,6 +186,11 @@ impl From<SyncResult> for Update {
 pub type ChangeSet =
     bdk_chain::persist::CombinedChangeSet<KeychainKind, ConfirmationTimeHeightAnchor>;
 
+/// The layout of the wallet's [`ChangeSet`] in bdk_wallet 1.0.0-alpha.12, to migrate the stores
+/// written with it, e.g. with `bdk_file_store::Store::migrate`.
+pub type ChangeSetV0 =
+    bdk_chain::persist::CombinedChangeSetV0<KeychainKind, ConfirmationTimeHeightAnchor>;
+
 /// A derived address and the index it was found at.
 /// For convenience this automatically derefs to `Address`
 #[derive(Debug, PartialEq, Eq)]
@@ -170,6 +217,129 @@ impl fmt::Display for AddressInfo {
     }
 }
 
+impl AddressInfo {
+    /// Build a [BIP21](https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki) URI
+    /// requesting a payment to this address, percent-encoding the `label` and `message`.
+    ///
+    /// See [`Wallet::parse_bip21`] for the paying side.
+    pub fn to_bip21(
+        &self,
+        amount: Option<Amount>,
+        label: Option<&str>,
+        message: Option<&str>,
+    ) -> String {
+        Bip21 {
+            address: self.address.clone(),
+            amount,
+            label: label.map(String::from),
+            message: message.map(String::from),
+        }
+        .to_string()
+    }
+}
+
+/// Ownership information about the inputs and outputs of a [`Psbt`].
+///
+/// Returned by [`Wallet::annotate_psbt`]. The entries of `inputs` and `outputs` follow the order
+/// of the inputs and outputs of the PSBT's unsigned transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsbtAnnotation {
+    /// Annotations for each input
+    pub inputs: Vec<InputAnnotation>,
+    /// Annotations for each output
+    pub outputs: Vec<OutputAnnotation>,
+}
+
+/// Ownership information about a [`Psbt`] input, see [`PsbtAnnotation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputAnnotation {
+    /// Whether the input spends an output of this wallet
+    pub is_mine: bool,
+}
+
+/// Ownership information about a [`Psbt`] output, see [`PsbtAnnotation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputAnnotation {
+    /// Whether the output pays to a script pubkey of this wallet
+    pub is_mine: bool,
+    /// Whether the output pays to a change keychain of this wallet, see
+    /// [`Wallet::set_change_descriptor`]
+    pub is_change: bool,
+    /// Keychain and derivation index of the output's script pubkey, if it belongs to this wallet
+    pub derivation: Option<(KeychainKind, u32)>,
+}
+
+/// The payment a [`Psbt`] is expected to make.
+///
+/// Checked by [`Wallet::assert_psbt_matches`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaymentIntent {
+    /// Script pubkeys to pay and the amount each one should receive
+    pub recipients: Vec<(ScriptBuf, Amount)>,
+    /// Maximum fee of the transaction, if any
+    pub max_fee: Option<Amount>,
+}
+
+/// The differences between the wallet's UTXO set and an expected one.
+///
+/// Returned by [`Wallet::reconcile_utxos`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// UTXOs of the wallet that are not in the expected set
+    pub unexpected: Vec<(OutPoint, Amount)>,
+    /// UTXOs of the expected set that the wallet doesn't have
+    pub missing: Vec<(OutPoint, Amount)>,
+    /// UTXOs present in both sets with a different amount, as `(outpoint, wallet, expected)`
+    pub mismatched: Vec<(OutPoint, Amount, Amount)>,
+}
+
+impl Reconciliation {
+    /// Whether the wallet's UTXO set matches the expected one.
+    pub fn is_consistent(&self) -> bool {
+        self.unexpected.is_empty() && self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// Address usage statistics of a keychain.
+///
+/// Returned by [`Wallet::keychain_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeychainStats {
+    /// Highest revealed derivation index, `None` if no address has been revealed
+    pub last_revealed: Option<u32>,
+    /// Highest derivation index used by a transaction output, `None` if no address was used
+    pub last_used: Option<u32>,
+    /// Derivation index of the address [`Wallet::next_unused_address`] would return
+    pub next_unused: u32,
+    /// How many more addresses can be revealed without being used before the gap after the last
+    /// used address exceeds the lookahead
+    ///
+    /// Addresses past that gap won't be found when restoring the wallet with the same lookahead.
+    pub gap_to_lookahead: u32,
+}
+
+/// The replacement transactions built by [`Wallet::build_fee_bumps`], one per replaced
+/// transaction.
+#[derive(Debug, Default)]
+pub struct FeeBumps {
+    /// Txids of the replaced transactions and the PSBTs replacing them
+    pub psbts: Vec<(Txid, Psbt)>,
+    /// Txids of the transactions that couldn't be replaced, and why
+    pub failed: Vec<(Txid, FeeBumpsError)>,
+}
+
+/// A stable identifier of a [`Wallet`], see [`Wallet::id`].
+///
+/// It is the SHA256 hash of the wallet's public descriptors and network, and is displayed as hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WalletId(pub sha256::Hash);
+
+impl fmt::Display for WalletId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// The error type when constructing a fresh [`Wallet`].
 ///
 /// Methods [`new`] and [`new_with_genesis_hash`] may return this error.
@@ -228,7 +398,8 @@ impl std::error::Error for LoadError {}
 
 /// Error type for when we try load a [`Wallet`] from persistence and creating it if non-existent.
 ///
-/// Methods [`new_or_load`] and [`new_or_load_with_genesis_hash`] may return this error.
+/// Methods [`new_or_load`], [`new_or_load_with_genesis_hash`] and [`LoadParams::new_or_load`]
+/// may return this error.
 ///
 /// [`new_or_load`]: Wallet::new_or_load
 /// [`new_or_load_with_genesis_hash`]: Wallet::new_or_load_with_genesis_hash
@@ -283,6 +454,224 @@ impl fmt::Display for NewOrLoadError {
 #[cfg(feature = "std")]
 impl std::error::Error for NewOrLoadError {}
 
+/// What to do when a descriptor loaded from persistence differs from the one provided.
+///
+/// Set with [`LoadParams::on_descriptor_mismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum DescriptorMismatchPolicy {
+    /// Fail with [`NewOrLoadError::LoadedDescriptorDoesNotMatch`].
+    #[default]
+    Error,
+    /// Keep the loaded descriptor and ignore the provided one.
+    KeepStored,
+    /// Replace the loaded descriptor with the provided one.
+    ///
+    /// The last revealed index of the loaded descriptor is carried over to the provided one and
+    /// the transactions are re-indexed against its script pubkeys, though a full scan may be
+    /// needed to find transactions of script pubkeys beyond that. As a persisted keychain can't
+    /// be reassigned by appending to it, the whole migrated wallet is staged and must be written
+    /// to a new, empty store.
+    Migrate,
+}
+
+/// A keychain whose loaded descriptor differs from the one provided, returned by
+/// [`LoadParams::new_or_load`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorMismatch {
+    /// The keychain of the mismatching descriptors.
+    pub keychain: KeychainKind,
+    /// The descriptor loaded from persistence, discarded with [`DescriptorMismatchPolicy::Migrate`].
+    pub stored: ExtendedDescriptor,
+    /// The descriptor provided, discarded with [`DescriptorMismatchPolicy::KeepStored`].
+    pub provided: ExtendedDescriptor,
+}
+
+/// Parameters for loading a [`Wallet`] from a [`ChangeSet`], or initializing it if one does not
+/// exist.
+///
+/// Start with [`Wallet::load`], set the parameters that differ from the defaults and finish with
+/// [`LoadParams::new_or_load`]:
+///
+/// ```rust,no_run
+/// # use bdk_wallet::wallet::{DescriptorMismatchPolicy, Wallet};
+/// # use bitcoin::Network::Testnet;
+/// # let changeset = None;
+/// # let external_descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/0'/0/*)";
+/// # let internal_descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/0'/1/*)";
+/// let (wallet, mismatches) = Wallet::load()
+///     .on_descriptor_mismatch(DescriptorMismatchPolicy::KeepStored)
+///     .new_or_load(external_descriptor, internal_descriptor, changeset, Testnet)?;
+/// for mismatch in mismatches {
+///     println!("ignored {} for {:?}", mismatch.provided, mismatch.keychain);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+#[must_use]
+pub struct LoadParams {
+    genesis_hash: Option<BlockHash>,
+    on_descriptor_mismatch: DescriptorMismatchPolicy,
+}
+
+impl LoadParams {
+    /// Use a custom genesis hash instead of the one of the network, which is useful for syncing
+    /// from alternative networks.
+    pub fn genesis_hash(mut self, genesis_hash: BlockHash) -> Self {
+        self.genesis_hash = Some(genesis_hash);
+        self
+    }
+
+    /// Decide what to do with a loaded descriptor that differs from the one provided,
+    /// [`DescriptorMismatchPolicy::Error`] by default.
+    ///
+    /// With [`DescriptorMismatchPolicy::KeepStored`] the descriptors loaded from the
+    /// [`ChangeSet`] are kept and the provided descriptor of a mismatching keychain is ignored,
+    /// including any private keys it contains. With [`DescriptorMismatchPolicy::Migrate`] the
+    /// provided descriptor replaces the loaded one. Mismatching network or genesis hash are always
+    /// an error.
+    pub fn on_descriptor_mismatch(mut self, policy: DescriptorMismatchPolicy) -> Self {
+        self.on_descriptor_mismatch = policy;
+        self
+    }
+
+    /// Either loads [`Wallet`] from a [`ChangeSet`] or initializes it if one does not exist,
+    /// using the provided descriptor, change descriptor and network.
+    ///
+    /// This is like [`Wallet::new_or_load`] with the parameters set on the builder. Along with the
+    /// wallet it returns the [`DescriptorMismatch`]es resolved by the
+    /// [`DescriptorMismatchPolicy`], so the discarded descriptors are not lost.
+    pub fn new_or_load<E: IntoWalletDescriptor>(
+        self,
+        descriptor: E,
+        change_descriptor: E,
+        changeset: Option<ChangeSet>,
+        network: Network,
+    ) -> Result<(Wallet, Vec<DescriptorMismatch>), NewOrLoadError> {
+        let genesis_hash = self
+            .genesis_hash
+            .unwrap_or_else(|| genesis_block(network).block_hash());
+        let on_descriptor_mismatch = self.on_descriptor_mismatch;
+        if let Some(changeset) = changeset {
+            let migrate_from = (on_descriptor_mismatch == DescriptorMismatchPolicy::Migrate)
+                .then(|| changeset.clone());
+            let mut wallet = Wallet::load_from_changeset(changeset).map_err(|e| match e {
+                LoadError::Descriptor(e) => NewOrLoadError::Descriptor(e),
+                LoadError::MissingNetwork => NewOrLoadError::LoadedNetworkDoesNotMatch {
+                    expected: network,
+                    got: None,
+                },
+                LoadError::MissingGenesis => NewOrLoadError::LoadedGenesisDoesNotMatch {
+                    expected: genesis_hash,
+                    got: None,
+                },
+                LoadError::MissingDescriptor(keychain) => {
+                    NewOrLoadError::LoadedDescriptorDoesNotMatch {
+                        got: None,
+                        keychain,
+                    }
+                }
+            })?;
+            if wallet.network != network {
+                return Err(NewOrLoadError::LoadedNetworkDoesNotMatch {
+                    expected: network,
+                    got: Some(wallet.network),
+                });
+            }
+            if wallet.chain.genesis_hash() != genesis_hash {
+                return Err(NewOrLoadError::LoadedGenesisDoesNotMatch {
+                    expected: genesis_hash,
+                    got: Some(wallet.chain.genesis_hash()),
+                });
+            }
+
+            let (expected_descriptor, expected_descriptor_keymap) = descriptor
+                .into_wallet_descriptor(&wallet.secp, network)
+                .map_err(NewOrLoadError::Descriptor)?;
+            let (expected_change_descriptor, expected_change_descriptor_keymap) = change_descriptor
+                .into_wallet_descriptor(&wallet.secp, network)
+                .map_err(NewOrLoadError::Descriptor)?;
+
+            let mut mismatches = Vec::new();
+            for (keychain, expected) in [
+                (KeychainKind::External, &expected_descriptor),
+                (KeychainKind::Internal, &expected_change_descriptor),
+            ] {
+                let stored = wallet.known_descriptor(keychain);
+                if stored != expected {
+                    if on_descriptor_mismatch == DescriptorMismatchPolicy::Error {
+                        return Err(NewOrLoadError::LoadedDescriptorDoesNotMatch {
+                            got: Some(stored.clone()),
+                            keychain,
+                        });
+                    }
+                    mismatches.push(DescriptorMismatch {
+                        keychain,
+                        stored: stored.clone(),
+                        provided: expected.clone(),
+                    });
+                }
+            }
+
+            if let Some(mut changeset) = migrate_from.filter(|_| !mismatches.is_empty()) {
+                let indexer = &mut changeset.indexed_tx_graph.indexer;
+                for mismatch in &mismatches {
+                    // carry the revealed index over so the replacement derives (and the tx graph
+                    // is re-indexed against) at least as many script pubkeys as before
+                    let last_revealed = indexer
+                        .last_revealed
+                        .remove(&mismatch.stored.descriptor_id());
+                    let descriptor_id = mismatch.provided.descriptor_id();
+                    if let Some(index) = last_revealed {
+                        indexer.last_revealed.insert(descriptor_id, index);
+                    }
+                    indexer
+                        .keychains_added
+                        .insert(mismatch.keychain, mismatch.provided.clone());
+                }
+                wallet = Wallet::load_from_changeset(changeset.clone()).map_err(|e| match e {
+                    LoadError::Descriptor(e) => NewOrLoadError::Descriptor(e),
+                    _ => unreachable!("network, genesis and descriptors were already loaded"),
+                })?;
+                // the stored keychains can't be reassigned by appending, so the whole migrated
+                // state is staged for writing to a new store
+                wallet.stage = changeset;
+            }
+
+            for (keychain, expected, keymap) in [
+                (
+                    KeychainKind::External,
+                    expected_descriptor,
+                    expected_descriptor_keymap,
+                ),
+                (
+                    KeychainKind::Internal,
+                    expected_change_descriptor,
+                    expected_change_descriptor_keymap,
+                ),
+            ] {
+                // if expected descriptor has private keys add them as new signers
+                if wallet.known_descriptor(keychain) != &expected || keymap.is_empty() {
+                    continue;
+                }
+                let signer_container = SignersContainer::build(keymap, &expected, &wallet.secp);
+                signer_container.signers().into_iter().for_each(|signer| {
+                    wallet.add_signer(keychain, SignerOrdering::default(), signer.clone())
+                });
+            }
+
+            Ok((wallet, mismatches))
+        } else {
+            let wallet =
+                Wallet::new_with_genesis_hash(descriptor, change_descriptor, network, genesis_hash)
+                    .map_err(|e| match e {
+                        NewError::Descriptor(e) => NewOrLoadError::Descriptor(e),
+                    })?;
+            Ok((wallet, Vec::new()))
+        }
+    }
+}
+
 /// An error that may occur when inserting a transaction into [`Wallet`].
 #[derive(Debug)]
 pub enum InsertTxError {
@@ -347,6 +736,16 @@ impl std::error::Error for ApplyBlockError {}
 
 impl Wallet {
     /// Initialize an empty [`Wallet`].
+    ///
+    /// `descriptor` is the [`KeychainKind::External`] keychain used for receive addresses and
+    /// `change_descriptor` the [`KeychainKind::Internal`] keychain used for change outputs. The
+    /// two descriptors don't need to have the same script type, e.g. taproot receive addresses
+    /// can be paired with cheaper to spend P2WPKH change. The change outputs are tracked and
+    /// spent like any other output of the wallet, and they are the outputs that
+    /// [`ChangeSpendPolicy`] refers to as change. Use [`Wallet::set_change_descriptor`] to derive
+    /// change from a descriptor of its own.
+    ///
+    /// [`ChangeSpendPolicy`]: tx_builder::ChangeSpendPolicy
     pub fn new<E: IntoWalletDescriptor>(
         descriptor: E,
         change_descriptor: E,
@@ -376,20 +775,36 @@ impl Wallet {
 
         let indexed_graph = IndexedTxGraph::new(index);
 
+        // the `labels` field only exists with the `labels` feature of `bdk_chain`
+        #[allow(clippy::needless_update)]
         let staged = ChangeSet {
             chain: chain_changeset,
             indexed_tx_graph: indexed_graph.initial_changeset(),
             network: Some(network),
+            ..Default::default()
         };
 
         Ok(Wallet {
             signers,
             change_signers,
+            custom_signers: BTreeMap::new(),
             network,
             chain,
             indexed_graph,
             stage: staged,
             secp,
+            balance_cache: BalanceCache::default(),
+            #[cfg(feature = "labels")]
+            labels: Labels::default(),
+            #[cfg(feature = "labels")]
+            pending_labels: BTreeMap::new(),
+            dust_relay_fee: DEFAULT_DUST_RELAY_FEE,
+            change_keychain: KeychainKind::Internal,
+            #[cfg(feature = "std")]
+            last_commit: None,
+            locked_utxos: BTreeMap::new(),
+            #[cfg(feature = "std")]
+            utxo_lock_ttl: Some(DEFAULT_UTXO_LOCK_TTL),
         })
     }
 
@@ -459,24 +874,45 @@ impl Wallet {
 
         let (signers, change_signers) =
             create_signers(&mut index, &secp, descriptor, change_descriptor, network)
-                .expect("Can't fail: we passed in valid descriptors, recovered from the changeset");
+                .map_err(LoadError::Descriptor)?;
 
         let mut indexed_graph = IndexedTxGraph::new(index);
         indexed_graph.apply_changeset(changeset.indexed_tx_graph);
+        #[cfg(feature = "labels")]
+        let labels = Labels::from_changeset(changeset.labels);
 
         let stage = ChangeSet::default();
 
         Ok(Wallet {
             signers,
             change_signers,
+            custom_signers: BTreeMap::new(),
             chain,
             indexed_graph,
             stage,
             network,
             secp,
+            balance_cache: BalanceCache::default(),
+            #[cfg(feature = "labels")]
+            labels,
+            #[cfg(feature = "labels")]
+            pending_labels: BTreeMap::new(),
+            dust_relay_fee: DEFAULT_DUST_RELAY_FEE,
+            change_keychain: KeychainKind::Internal,
+            #[cfg(feature = "std")]
+            last_commit: None,
+            locked_utxos: BTreeMap::new(),
+            #[cfg(feature = "std")]
+            utxo_lock_ttl: Some(DEFAULT_UTXO_LOCK_TTL),
         })
     }
 
+    /// Start building the [`LoadParams`] to load a [`Wallet`] from a [`ChangeSet`], or initialize
+    /// it if one does not exist, e.g. to set the [`DescriptorMismatchPolicy`].
+    pub fn load() -> LoadParams {
+        LoadParams::default()
+    }
+
     /// Either loads [`Wallet`] from the given [`ChangeSet`] or initializes it if one does not exist.
     ///
     /// This method will fail if the loaded [`ChangeSet`] has different parameters to those provided.
@@ -525,101 +961,74 @@ impl Wallet {
         network: Network,
         genesis_hash: BlockHash,
     ) -> Result<Self, NewOrLoadError> {
-        if let Some(changeset) = changeset {
-            let mut wallet = Self::load_from_changeset(changeset).map_err(|e| match e {
-                LoadError::Descriptor(e) => NewOrLoadError::Descriptor(e),
-                LoadError::MissingNetwork => NewOrLoadError::LoadedNetworkDoesNotMatch {
-                    expected: network,
-                    got: None,
-                },
-                LoadError::MissingGenesis => NewOrLoadError::LoadedGenesisDoesNotMatch {
-                    expected: genesis_hash,
-                    got: None,
-                },
-                LoadError::MissingDescriptor(keychain) => {
-                    NewOrLoadError::LoadedDescriptorDoesNotMatch {
-                        got: None,
-                        keychain,
-                    }
-                }
-            })?;
-            if wallet.network != network {
-                return Err(NewOrLoadError::LoadedNetworkDoesNotMatch {
-                    expected: network,
-                    got: Some(wallet.network),
-                });
-            }
-            if wallet.chain.genesis_hash() != genesis_hash {
-                return Err(NewOrLoadError::LoadedGenesisDoesNotMatch {
-                    expected: genesis_hash,
-                    got: Some(wallet.chain.genesis_hash()),
-                });
-            }
+        // the default policy fails on any mismatch, so there are none to return
+        let (wallet, _) = Self::load().genesis_hash(genesis_hash).new_or_load(
+            descriptor,
+            change_descriptor,
+            changeset,
+            network,
+        )?;
+        Ok(wallet)
+    }
 
-            let (expected_descriptor, expected_descriptor_keymap) = descriptor
-                .into_wallet_descriptor(&wallet.secp, network)
-                .map_err(NewOrLoadError::Descriptor)?;
-            let wallet_descriptor = wallet.public_descriptor(KeychainKind::External);
-            if wallet_descriptor != &expected_descriptor {
-                return Err(NewOrLoadError::LoadedDescriptorDoesNotMatch {
-                    got: Some(wallet_descriptor.clone()),
-                    keychain: KeychainKind::External,
-                });
-            }
-            // if expected descriptor has private keys add them as new signers
-            if !expected_descriptor_keymap.is_empty() {
-                let signer_container = SignersContainer::build(
-                    expected_descriptor_keymap,
-                    &expected_descriptor,
-                    &wallet.secp,
-                );
-                signer_container.signers().into_iter().for_each(|signer| {
-                    wallet.add_signer(
-                        KeychainKind::External,
-                        SignerOrdering::default(),
-                        signer.clone(),
-                    )
-                });
-            }
+    /// Get the Bitcoin network the wallet is using.
+    pub fn network(&self) -> Network {
+        self.network
+    }
 
-            let (expected_change_descriptor, expected_change_descriptor_keymap) = change_descriptor
-                .into_wallet_descriptor(&wallet.secp, network)
-                .map_err(NewOrLoadError::Descriptor)?;
-            let wallet_change_descriptor = wallet.public_descriptor(KeychainKind::Internal);
-            if wallet_change_descriptor != &expected_change_descriptor {
-                return Err(NewOrLoadError::LoadedDescriptorDoesNotMatch {
-                    got: Some(wallet_change_descriptor.clone()),
-                    keychain: KeychainKind::Internal,
-                });
-            }
-            // if expected change descriptor has private keys add them as new signers
-            if !expected_change_descriptor_keymap.is_empty() {
-                let signer_container = SignersContainer::build(
-                    expected_change_descriptor_keymap,
-                    &expected_change_descriptor,
-                    &wallet.secp,
-                );
-                signer_container.signers().into_iter().for_each(|signer| {
-                    wallet.add_signer(
-                        KeychainKind::Internal,
-                        SignerOrdering::default(),
-                        signer.clone(),
-                    )
-                });
-            }
+    /// Get the dust relay fee used to compute the dust limit of outputs.
+    ///
+    /// Defaults to [`DEFAULT_DUST_RELAY_FEE`].
+    pub fn dust_relay_fee(&self) -> FeeRate {
+        self.dust_relay_fee
+    }
 
-            Ok(wallet)
-        } else {
-            Self::new_with_genesis_hash(descriptor, change_descriptor, network, genesis_hash)
-                .map_err(|e| match e {
-                    NewError::Descriptor(e) => NewOrLoadError::Descriptor(e),
-                })
+    /// Set the dust relay fee used to compute the dust limit of outputs.
+    ///
+    /// The dust limit decides whether the change of a transaction is dropped to the fee and
+    /// whether recipient outputs are rejected with [`CreateTxError::OutputBelowDustLimit`]. Set
+    /// this to match the `-dustrelayfee` of the nodes the wallet's transactions are relayed
+    /// through if it isn't the default.
+    ///
+    /// The dust relay fee is not persisted and has to be set again after loading the wallet.
+    pub fn set_dust_relay_fee(&mut self, dust_relay_fee: FeeRate) {
+        self.dust_relay_fee = dust_relay_fee;
+    }
+
+    /// Parse a [BIP21](https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki) URI,
+    /// checking that its address is valid for the wallet's network.
+    ///
+    /// See [`Bip21::parse`].
+    pub fn parse_bip21(&self, uri: &str) -> Result<Bip21, Bip21Error> {
+        Bip21::parse(uri, self.network)
+    }
+
+    /// Get a stable identifier of the wallet.
+    ///
+    /// The [`WalletId`] commits to the public external and internal descriptors (in their
+    /// normalized string form, including the checksum) and to the wallet's [`Network`]. It doesn't
+    /// depend on how many addresses have been revealed, so it stays the same across reloads and can
+    /// be used to key a wallet in maps or databases.
+    pub fn id(&self) -> WalletId {
+        let mut engine = sha256::Hash::engine();
+        for keychain in [KeychainKind::External, KeychainKind::Internal] {
+            engine.input(self.known_descriptor(keychain).to_string().as_bytes());
+            engine.input(&[0]);
         }
+        engine.input(self.network.to_string().as_bytes());
+        WalletId(sha256::Hash::from_engine(engine))
     }
 
-    /// Get the Bitcoin network the wallet is using.
-    pub fn network(&self) -> Network {
-        self.network
+    /// Parse an address and check that it is valid for the wallet's [`Network`].
+    ///
+    /// A mainnet address is always rejected by a wallet on a test network and vice versa. Note
+    /// that testnet, testnet4 and signet addresses share the same prefixes (e.g. the `tb` bech32
+    /// HRP), so an address meant for any of these networks is accepted by a wallet on any other of
+    /// them: the intended test network cannot be recovered from the address alone. Regtest
+    /// addresses use the `bcrt` HRP for segwit outputs, but share base58 prefixes with the other
+    /// test networks.
+    pub fn parse_address(&self, address: &str) -> Result<Address, bitcoin::address::ParseError> {
+        Address::from_str(address)?.require_network(self.network)
     }
 
     /// Iterator over all keychains in this wallet
@@ -627,20 +1036,139 @@ impl Wallet {
         self.indexed_graph.index.keychains()
     }
 
+    /// Add a keychain tracking `descriptor`, identified as [`KeychainKind::Custom`] with `id`.
+    ///
+    /// The keychain is tracked like the external and internal ones: its revealed script pubkeys
+    /// are part of the [`start_sync_with_revealed_spks`] and [`start_full_scan`] requests, its
+    /// utxos are returned by [`list_unspent`] and counted in the [`balance`], and it's restored by
+    /// [`load_from_changeset`]. Its utxos are selected when building transactions, unless coin
+    /// selection is restricted to other keychains with [`TxBuilder::spend_from_keychains`]. Signers
+    /// are added for the private keys in `descriptor`, if any.
+    ///
+    /// Adding a keychain again with the same descriptor does nothing.
+    ///
+    /// **WARNING**: You must persist the changes resulting from this method, otherwise the keychain
+    /// is missing once the wallet is loaded again. See [`Wallet::reveal_next_address`].
+    ///
+    /// [`start_sync_with_revealed_spks`]: Self::start_sync_with_revealed_spks
+    /// [`start_full_scan`]: Self::start_full_scan
+    /// [`list_unspent`]: Self::list_unspent
+    /// [`balance`]: Self::balance
+    /// [`load_from_changeset`]: Self::load_from_changeset
+    pub fn add_keychain<E: IntoWalletDescriptor>(
+        &mut self,
+        id: u32,
+        descriptor: E,
+    ) -> Result<(), AddKeychainError> {
+        use bdk_chain::indexed_tx_graph::Indexer;
+        use bdk_chain::keychain::InsertDescriptorError;
+
+        let keychain = KeychainKind::Custom(id);
+        let (descriptor, keymap) =
+            into_wallet_descriptor_checked(descriptor, &self.secp, self.network)?;
+        let index_changeset = self
+            .indexed_graph
+            .index
+            .insert_descriptor(keychain, descriptor.clone())
+            .map_err(|e| match e {
+                InsertDescriptorError::DescriptorAlreadyAssigned {
+                    existing_assignment,
+                    ..
+                } => AddKeychainError::DescriptorAlreadyAssigned(existing_assignment),
+                InsertDescriptorError::KeychainAlreadyAssigned { keychain, .. } => {
+                    AddKeychainError::KeychainAlreadyAssigned(keychain)
+                }
+            })?;
+        let mut changeset = indexed_tx_graph::ChangeSet::from(index_changeset);
+
+        // the wallet may already know transactions paying to the new keychain
+        let txs = self
+            .indexed_graph
+            .graph()
+            .full_txs()
+            .map(|tx| tx.tx.clone())
+            .collect::<Vec<_>>();
+        for tx in txs {
+            changeset
+                .indexer
+                .append(self.indexed_graph.index.index_tx(&tx));
+        }
+
+        let signer_container = SignersContainer::build(keymap, &descriptor, &self.secp);
+        for signer in signer_container.signers() {
+            self.add_signer(keychain, SignerOrdering::default(), signer.clone());
+        }
+
+        self.stage.append(changeset.into());
+        self.balance_cache.invalidate();
+        Ok(())
+    }
+
+    /// Derive change outputs from `descriptor` instead of the [`KeychainKind::Internal`] keychain.
+    ///
+    /// This allows change to use a different script type than the internal descriptor, e.g.
+    /// cheaper to spend P2WPKH change for a taproot wallet. `descriptor` is added as the
+    /// [`KeychainKind::Custom`] keychain `id` with [`Wallet::add_keychain`], so it is tracked,
+    /// synced and persisted like any other keychain. Calling this again with another `id` moves
+    /// new change to that keychain, and the outputs of the previous change keychain are still
+    /// spent as usual.
+    ///
+    /// The outputs of the change keychain are change for the [`ChangeSpendPolicy`], together with
+    /// the outputs of the [`KeychainKind::Internal`] keychain, which may hold change created
+    /// before the change descriptor was set. Both are also trusted when unconfirmed in the
+    /// [`balance`]. [`TxBuilder::change_to_index`] derives change from the keychain it is given
+    /// instead.
+    ///
+    /// Which keychain is used for change is not persisted and has to be set again after loading
+    /// the wallet. The keychain itself is restored from the persisted changes, and setting it again
+    /// with the same `descriptor` doesn't change it.
+    ///
+    /// [`ChangeSpendPolicy`]: tx_builder::ChangeSpendPolicy
+    /// [`balance`]: Self::balance
+    /// [`TxBuilder::change_to_index`]: tx_builder::TxBuilder::change_to_index
+    pub fn set_change_descriptor<E: IntoWalletDescriptor>(
+        &mut self,
+        id: u32,
+        descriptor: E,
+    ) -> Result<(), AddKeychainError> {
+        self.add_keychain(id, descriptor)?;
+        self.change_keychain = KeychainKind::Custom(id);
+        self.balance_cache.invalidate();
+        Ok(())
+    }
+
+    /// The keychain that change outputs are derived from.
+    ///
+    /// This is [`KeychainKind::Internal`] unless it was changed with
+    /// [`Wallet::set_change_descriptor`].
+    pub fn change_keychain(&self) -> KeychainKind {
+        self.change_keychain
+    }
+
+    /// Whether the outputs of `keychain` are change, see [`Wallet::set_change_descriptor`].
+    fn is_change_keychain(&self, keychain: KeychainKind) -> bool {
+        keychain == KeychainKind::Internal || keychain == self.change_keychain
+    }
+
     /// Peek an address of the given `keychain` at `index` without revealing it.
     ///
     /// For non-wildcard descriptors this returns the same address at every provided index.
+    /// Returns [`KeychainNotFound`] if `keychain` is not part of the wallet.
     ///
     /// # Panics
     ///
     /// This panics when the caller requests for an address of derivation index greater than the
     /// [BIP32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki) max index.
-    pub fn peek_address(&self, keychain: KeychainKind, mut index: u32) -> AddressInfo {
+    pub fn peek_address(
+        &self,
+        keychain: KeychainKind,
+        mut index: u32,
+    ) -> Result<AddressInfo, KeychainNotFound> {
         let mut spk_iter = self
             .indexed_graph
             .index
             .unbounded_spk_iter(&keychain)
-            .expect("keychain must exist");
+            .ok_or(KeychainNotFound(keychain))?;
         if !spk_iter.descriptor().has_wildcard() {
             index = 0;
         }
@@ -648,11 +1176,11 @@ impl Wallet {
             .nth(index as usize)
             .expect("derivation index is out of bounds");
 
-        AddressInfo {
+        Ok(AddressInfo {
             index,
             address: Address::from_script(&spk, self.network).expect("must have address form"),
             keychain,
-        }
+        })
     }
 
     /// Attempt to reveal the next address of the given `keychain`.
@@ -660,7 +1188,8 @@ impl Wallet {
     /// This will increment the keychain's derivation index. If the keychain's descriptor doesn't
     /// contain a wildcard or every address is already revealed up to the maximum derivation
     /// index defined in [BIP32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki),
-    /// then the last revealed address will be returned.
+    /// then the last revealed address will be returned. Returns [`KeychainNotFound`] if `keychain`
+    /// is not part of the wallet.
     ///
     /// **WARNING**: To avoid address reuse you must persist the changes resulting from one or more
     /// calls to this method before closing the wallet. For example:
@@ -674,29 +1203,32 @@ impl Wallet {
     /// # let mut db = Store::new(conn).expect("must create store");
     /// # let changeset = ChangeSet::default();
     /// # let mut wallet = Wallet::load_from_changeset(changeset).expect("load wallet");
-    /// let next_address = wallet.reveal_next_address(KeychainKind::External);
+    /// let next_address = wallet.reveal_next_address(KeychainKind::External)?;
     /// wallet.commit_to(&mut db)?;
     ///
     /// // Now it's safe to show the user their next address!
     /// println!("Next address: {}", next_address.address);
     /// # Ok::<(), anyhow::Error>(())
     /// ```
-    pub fn reveal_next_address(&mut self, keychain: KeychainKind) -> AddressInfo {
+    pub fn reveal_next_address(
+        &mut self,
+        keychain: KeychainKind,
+    ) -> Result<AddressInfo, KeychainNotFound> {
         let index = &mut self.indexed_graph.index;
         let stage = &mut self.stage;
 
         let ((index, spk), index_changeset) = index
             .reveal_next_spk(&keychain)
-            .expect("keychain must exist");
+            .ok_or(KeychainNotFound(keychain))?;
 
         stage.append(indexed_tx_graph::ChangeSet::from(index_changeset).into());
 
-        AddressInfo {
+        Ok(AddressInfo {
             index,
             address: Address::from_script(spk.as_script(), self.network)
                 .expect("must have address form"),
             keychain,
-        }
+        })
     }
 
     /// Reveal addresses up to and including the target `index` and return an iterator
@@ -704,7 +1236,8 @@ impl Wallet {
     ///
     /// If the target `index` is unreachable, we make a best effort to reveal up to the last
     /// possible index. If all addresses up to the given `index` are already revealed, then
-    /// no new addresses are returned.
+    /// no new addresses are returned. Returns [`KeychainNotFound`] if `keychain` is not part of the
+    /// wallet.
     ///
     /// **WARNING**: To avoid address reuse you must persist the changes resulting from one or more
     /// calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
@@ -712,46 +1245,50 @@ impl Wallet {
         &mut self,
         keychain: KeychainKind,
         index: u32,
-    ) -> impl Iterator<Item = AddressInfo> + '_ {
+    ) -> Result<impl Iterator<Item = AddressInfo> + '_, KeychainNotFound> {
         let (spks, index_changeset) = self
             .indexed_graph
             .index
             .reveal_to_target(&keychain, index)
-            .expect("keychain must exist");
+            .ok_or(KeychainNotFound(keychain))?;
 
         self.stage.append(index_changeset.into());
 
-        spks.into_iter().map(move |(index, spk)| AddressInfo {
+        Ok(spks.into_iter().map(move |(index, spk)| AddressInfo {
             index,
             address: Address::from_script(&spk, self.network).expect("must have address form"),
             keychain,
-        })
+        }))
     }
 
     /// Get the next unused address for the given `keychain`, i.e. the address with the lowest
     /// derivation index that hasn't been used.
     ///
     /// This will attempt to derive and reveal a new address if no newly revealed addresses
-    /// are available. See also [`reveal_next_address`](Self::reveal_next_address).
+    /// are available. See also [`reveal_next_address`](Self::reveal_next_address). Returns
+    /// [`KeychainNotFound`] if `keychain` is not part of the wallet.
     ///
     /// **WARNING**: To avoid address reuse you must persist the changes resulting from one or more
     /// calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
-    pub fn next_unused_address(&mut self, keychain: KeychainKind) -> AddressInfo {
+    pub fn next_unused_address(
+        &mut self,
+        keychain: KeychainKind,
+    ) -> Result<AddressInfo, KeychainNotFound> {
         let index = &mut self.indexed_graph.index;
 
         let ((index, spk), index_changeset) = index
             .next_unused_spk(&keychain)
-            .expect("keychain must exist");
+            .ok_or(KeychainNotFound(keychain))?;
 
         self.stage
             .append(indexed_tx_graph::ChangeSet::from(index_changeset).into());
 
-        AddressInfo {
+        Ok(AddressInfo {
             index,
             address: Address::from_script(spk.as_script(), self.network)
                 .expect("must have address form"),
             keychain,
-        }
+        })
     }
 
     /// Marks an address used of the given `keychain` at `index`.
@@ -816,6 +1353,50 @@ impl Wallet {
             .map(|((k, i), full_txo)| new_local_utxo(k, i, full_txo))
     }
 
+    /// Return the unspent outputs of this wallet that are confirmed in the best chain.
+    ///
+    /// These are the outputs of [`Wallet::list_unspent`] whose transaction is anchored in a block
+    /// of the wallet's local chain.
+    pub fn confirmed_utxos(&self) -> impl Iterator<Item = LocalOutput> + '_ {
+        self.list_unspent()
+            .filter(|utxo| utxo.confirmation_time.is_confirmed())
+    }
+
+    /// Return the unspent outputs of this wallet that are not confirmed yet.
+    ///
+    /// These are the outputs of [`Wallet::list_unspent`] whose transaction is unconfirmed, i.e. in
+    /// the mempool or not anchored in the wallet's local chain. Like [`Wallet::list_unspent`], it
+    /// excludes outputs spent by another transaction the wallet knows about, confirmed or not.
+    pub fn unconfirmed_utxos(&self) -> impl Iterator<Item = LocalOutput> + '_ {
+        self.list_unspent()
+            .filter(|utxo| !utxo.confirmation_time.is_confirmed())
+    }
+
+    /// Compare the wallet's UTXO set with an `expected` one, e.g. obtained from an external source.
+    ///
+    /// The wallet's UTXO set is the one returned by [`Wallet::list_unspent`]. The returned
+    /// [`Reconciliation`] lists the UTXOs that only one of the sets contains and the ones whose
+    /// amounts differ. This is a read-only diagnostic, useful to catch sync or persistence issues.
+    pub fn reconcile_utxos(&self, expected: &[(OutPoint, Amount)]) -> Reconciliation {
+        let mut expected = expected.iter().copied().collect::<BTreeMap<_, _>>();
+        let mut reconciliation = Reconciliation::default();
+        for utxo in self.list_unspent() {
+            match expected.remove(&utxo.outpoint) {
+                None => reconciliation
+                    .unexpected
+                    .push((utxo.outpoint, utxo.txout.value)),
+                Some(amount) if amount != utxo.txout.value => {
+                    reconciliation
+                        .mismatched
+                        .push((utxo.outpoint, utxo.txout.value, amount))
+                }
+                Some(_) => {}
+            }
+        }
+        reconciliation.missing = expected.into_iter().collect();
+        reconciliation
+    }
+
     /// List all relevant outputs (includes both spent and unspent, confirmed and unconfirmed).
     ///
     /// To list only unspent outputs (UTXOs), use [`Wallet::list_unspent`] instead.
@@ -854,7 +1435,8 @@ impl Wallet {
         self.indexed_graph.index.all_unbounded_spk_iters()
     }
 
-    /// Get an unbounded script pubkey iterator for the given `keychain`.
+    /// Get an unbounded script pubkey iterator for the given `keychain`, or `None` if the keychain
+    /// is not part of the wallet.
     ///
     /// See [`all_unbounded_spk_iters`] for more documentation
     ///
@@ -862,11 +1444,8 @@ impl Wallet {
     pub fn unbounded_spk_iter(
         &self,
         keychain: KeychainKind,
-    ) -> impl Iterator<Item = Indexed<ScriptBuf>> + Clone {
-        self.indexed_graph
-            .index
-            .unbounded_spk_iter(&keychain)
-            .expect("keychain must exist")
+    ) -> Option<impl Iterator<Item = Indexed<ScriptBuf>> + Clone> {
+        self.indexed_graph.index.unbounded_spk_iter(&keychain)
     }
 
     /// Returns the utxo owned by this wallet corresponding to `outpoint` if it exists in the
@@ -884,6 +1463,26 @@ impl Wallet {
             .next()
     }
 
+    /// Returns the label of the output at `outpoint`, if it has one.
+    ///
+    /// Outputs are labeled with [`TxBuilder::add_recipient_with_label`] and
+    /// [`TxBuilder::change_label`] when creating a transaction.
+    #[cfg(feature = "labels")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "labels")))]
+    pub fn output_label(&self, outpoint: OutPoint) -> Option<&str> {
+        self.labels.get(&LabelKey::Output(outpoint))
+    }
+
+    /// Returns the number of confirmations of the unspent output at `outpoint` when the chain tip
+    /// is at height `tip`, i.e. `1` if it was created in the block at `tip`.
+    ///
+    /// Returns `None` if the output is not an unspent output of this wallet, is unconfirmed, or
+    /// was confirmed above `tip`. See [`LocalOutput::confirmation_height`].
+    pub fn utxo_age(&self, outpoint: OutPoint, tip: u32) -> Option<u32> {
+        let height = self.get_utxo(outpoint)?.confirmation_height()?;
+        tip.checked_sub(height).map(|age| age + 1)
+    }
+
     /// Inserts a [`TxOut`] at [`OutPoint`] into the wallet's transaction graph.
     ///
     /// This is used for providing a previous output's value so that we can use [`calculate_fee`]
@@ -904,6 +1503,7 @@ impl Wallet {
     pub fn insert_txout(&mut self, outpoint: OutPoint, txout: TxOut) {
         let additions = self.indexed_graph.insert_txout(outpoint, txout);
         self.stage.append(additions.into());
+        self.balance_cache.invalidate();
     }
 
     /// Calculates the fee of a given transaction. Returns [`Amount::ZERO`] if `tx` is a coinbase transaction.
@@ -965,7 +1565,7 @@ impl Wallet {
     /// ```
     /// [`insert_txout`]: Self::insert_txout
     pub fn calculate_fee_rate(&self, tx: &Transaction) -> Result<FeeRate, CalculateFeeError> {
-        self.calculate_fee(tx).map(|fee| fee / tx.weight())
+        self.indexed_graph.graph().calculate_fee_rate(tx)
     }
 
     /// Compute the `tx`'s sent and received [`Amount`]s.
@@ -997,6 +1597,154 @@ impl Wallet {
         self.indexed_graph.index.sent_and_received(tx, ..)
     }
 
+    /// Lists the outputs of the transaction of `txid` with the derivation of those owned by the
+    /// wallet.
+    ///
+    /// Each entry is the output's outpoint, script pubkey, amount, and the keychain and derivation
+    /// index of the script pubkey if it is one of the wallet's (see [`derivation_of_spk`]), in the
+    /// order of the transaction's outputs.
+    ///
+    /// Returns an empty `Vec` if the transaction is not in the wallet.
+    ///
+    /// [`derivation_of_spk`]: Self::derivation_of_spk
+    pub fn tx_output_ownership(
+        &self,
+        txid: Txid,
+    ) -> Vec<(OutPoint, ScriptBuf, Amount, Option<(KeychainKind, u32)>)> {
+        let tx = match self.indexed_graph.graph().get_tx(txid) {
+            Some(tx) => tx,
+            None => return Vec::new(),
+        };
+        tx.output
+            .iter()
+            .enumerate()
+            .map(|(vout, txout)| {
+                (
+                    OutPoint::new(txid, vout as u32),
+                    txout.script_pubkey.clone(),
+                    txout.value,
+                    self.derivation_of_spk(&txout.script_pubkey),
+                )
+            })
+            .collect()
+    }
+
+    /// Mark which inputs and outputs of `psbt` belong to this wallet.
+    ///
+    /// An input is considered ours if the output it spends is indexed by the wallet, or if its
+    /// `witness_utxo`/`non_witness_utxo` pays to a script pubkey of the wallet. An output is
+    /// considered change if its script pubkey was derived from the [`KeychainKind::Internal`]
+    /// keychain or the keychain set with [`Wallet::set_change_descriptor`].
+    ///
+    /// This is useful to show a PSBT for review before signing it. Only script pubkeys that the
+    /// wallet has revealed (plus the lookahead) are recognized.
+    pub fn annotate_psbt(&self, psbt: &Psbt) -> PsbtAnnotation {
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .enumerate()
+            .map(|(input_index, txin)| {
+                let is_mine = self
+                    .indexed_graph
+                    .index
+                    .txout(txin.previous_output)
+                    .is_some()
+                    || matches!(
+                        psbt.get_utxo_for(input_index),
+                        Some(txout) if self.is_mine(&txout.script_pubkey)
+                    );
+                InputAnnotation { is_mine }
+            })
+            .collect();
+        let outputs = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| {
+                let derivation = self.derivation_of_spk(&txout.script_pubkey);
+                OutputAnnotation {
+                    is_mine: derivation.is_some(),
+                    is_change: matches!(derivation, Some((k, _)) if self.is_change_keychain(k)),
+                    derivation,
+                }
+            })
+            .collect();
+        PsbtAnnotation { inputs, outputs }
+    }
+
+    /// Check that `psbt` makes the payment described by `expected` before signing it.
+    ///
+    /// Every recipient must be paid its exact amount by an output of its own, and every other
+    /// output must pay to a script pubkey of the wallet, i.e. be change. If
+    /// [`PaymentIntent::max_fee`] is set, the fee must not exceed it. The fee is computed from the
+    /// previous outputs stored in the wallet, not from the `witness_utxo` and `non_witness_utxo`
+    /// of the PSBT which could be tampered with, so the previous output of every input must be
+    /// known to the wallet (see [`Wallet::insert_txout`]). All the differences found are returned
+    /// in the [`MismatchError`].
+    ///
+    /// This guards against a tampered or buggy PSBT, e.g. in automated signing pipelines. Only
+    /// script pubkeys that the wallet has revealed (plus the lookahead) are recognized as change.
+    pub fn assert_psbt_matches(
+        &self,
+        psbt: &Psbt,
+        expected: &PaymentIntent,
+    ) -> Result<(), MismatchError> {
+        let outputs = &psbt.unsigned_tx.output;
+        let mut matched = vec![false; outputs.len()];
+        let mut mismatches = Vec::new();
+
+        for (script_pubkey, amount) in &expected.recipients {
+            let unmatched =
+                |vout: &usize| !matched[*vout] && outputs[*vout].script_pubkey == *script_pubkey;
+            let exact = (0..outputs.len())
+                .filter(unmatched)
+                .find(|&vout| outputs[vout].value == *amount);
+            match exact.or_else(|| (0..outputs.len()).find(unmatched)) {
+                Some(vout) => {
+                    matched[vout] = true;
+                    if outputs[vout].value != *amount {
+                        mismatches.push(PaymentMismatch::WrongAmount {
+                            vout,
+                            expected: *amount,
+                            actual: outputs[vout].value,
+                        });
+                    }
+                }
+                None => mismatches.push(PaymentMismatch::MissingRecipient {
+                    script_pubkey: script_pubkey.clone(),
+                    amount: *amount,
+                }),
+            }
+        }
+
+        for (vout, txout) in outputs.iter().enumerate() {
+            if !matched[vout] && !self.is_mine(&txout.script_pubkey) {
+                mismatches.push(PaymentMismatch::UnexpectedOutput {
+                    vout,
+                    script_pubkey: txout.script_pubkey.clone(),
+                    amount: txout.value,
+                });
+            }
+        }
+
+        if let Some(max) = expected.max_fee {
+            match self.calculate_fee(&psbt.unsigned_tx) {
+                Ok(actual) if actual > max => {
+                    mismatches.push(PaymentMismatch::FeeTooHigh { max, actual })
+                }
+                Ok(_) => {}
+                Err(_) => mismatches.push(PaymentMismatch::UnknownFee),
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(MismatchError { mismatches })
+        }
+    }
+
     /// Get a single transaction from the wallet as a [`CanonicalTx`] (if the transaction exists).
     ///
     /// `CanonicalTx` contains the full transaction alongside meta-data such as:
@@ -1056,6 +1804,55 @@ impl Wallet {
         })
     }
 
+    /// Returns the txids of the other transactions in the wallet's [`TxGraph`] that spend at least
+    /// one of the outputs spent by the transaction of `txid`, in ascending order.
+    ///
+    /// Only direct conflicts are returned, not their descendants. Conflicting transactions can't
+    /// be canonical at the same time: [`get_tx`] returns `None` for the ones that lost to the
+    /// canonical transaction, so this can be used to tell a replaced transaction from its
+    /// replacement. Nothing is returned if the transaction of `txid` is not in the graph.
+    ///
+    /// [`get_tx`]: Self::get_tx
+    pub fn get_tx_conflicts(&self, txid: Txid) -> impl Iterator<Item = Txid> {
+        let graph = self.indexed_graph.graph();
+        let conflicts = match graph.get_tx(txid) {
+            Some(tx) => graph
+                .direct_conflicts(&tx)
+                .map(|(_, conflict)| conflict)
+                .collect::<BTreeSet<Txid>>(),
+            None => BTreeSet::new(),
+        };
+        conflicts.into_iter()
+    }
+
+    /// Returns the txid of the confirmed transaction spending `outpoint`, other than `txid`, if
+    /// there is one in the wallet's best chain.
+    fn confirmed_spend_of(&self, outpoint: OutPoint, txid: Txid) -> Option<Txid> {
+        let graph = self.indexed_graph.graph();
+        let chain_tip = self.chain.tip().block_id();
+        graph
+            .outspends(outpoint)
+            .iter()
+            .filter(|&&spend_txid| spend_txid != txid)
+            .find(|&&spend_txid| {
+                matches!(
+                    graph.get_chain_position(&self.chain, chain_tip, spend_txid),
+                    Some(ChainPosition::Confirmed(_))
+                )
+            })
+            .copied()
+    }
+
+    /// Returns the first input of `tx` spending an output that a confirmed transaction already
+    /// spends, together with the txid of that transaction.
+    fn find_input_already_spent(&self, tx: &Transaction) -> Option<(OutPoint, Txid)> {
+        let txid = tx.compute_txid();
+        tx.input.iter().find_map(|txin| {
+            self.confirmed_spend_of(txin.previous_output, txid)
+                .map(|by_txid| (txin.previous_output, by_txid))
+        })
+    }
+
     /// Add a new checkpoint to the wallet's internal view of the chain.
     ///
     /// Returns whether anything changed with the insertion (e.g. `false` if checkpoint was already
@@ -1073,6 +1870,7 @@ impl Wallet {
         let changeset = self.chain.insert_block(block_id)?;
         let changed = !changeset.is_empty();
         self.stage.append(changeset.into());
+        self.balance_cache.invalidate();
         Ok(changed)
     }
 
@@ -1133,8 +1931,11 @@ impl Wallet {
             changeset.append(self.indexed_graph.insert_seen_at(txid, last_seen).into());
         }
 
+        #[cfg(feature = "labels")]
+        self.attach_pending_labels(&mut changeset);
         let changed = !changeset.is_empty();
         self.stage.append(changeset);
+        self.balance_cache.invalidate();
         Ok(changed)
     }
 
@@ -1150,13 +1951,22 @@ impl Wallet {
 
     /// Return the balance, separated into available, trusted-pending, untrusted-pending and immature
     /// values.
+    ///
+    /// Unconfirmed outputs to the wallet's change addresses (see [`Wallet::set_change_descriptor`])
+    /// are trusted since they can only be created by the wallet itself, while unconfirmed outputs to
+    /// its receive addresses are untrusted. See [`TxGraph::try_balance`] for how the other
+    /// categories are classified.
+    ///
+    /// [`TxGraph::try_balance`]: bdk_chain::tx_graph::TxGraph::try_balance
     pub fn balance(&self) -> Balance {
-        self.indexed_graph.graph().balance(
-            &self.chain,
-            self.chain.tip().block_id(),
-            self.indexed_graph.index.outpoints().iter().cloned(),
-            |&(k, _), _| k == KeychainKind::Internal,
-        )
+        self.balance_cache.get_or_compute(|| {
+            self.indexed_graph.graph().balance(
+                &self.chain,
+                self.chain.tip().block_id(),
+                self.indexed_graph.index.outpoints().iter().cloned(),
+                |&(k, _), _| self.is_change_keychain(k),
+            )
+        })
     }
 
     /// Add an external signer
@@ -1171,11 +1981,42 @@ impl Wallet {
         let signers = match keychain {
             KeychainKind::External => Arc::make_mut(&mut self.signers),
             KeychainKind::Internal => Arc::make_mut(&mut self.change_signers),
+            KeychainKind::Custom(id) => Arc::make_mut(self.custom_signers.entry(id).or_default()),
         };
 
         signers.add_external(signer.id(&self.secp), ordering, signer);
     }
 
+    /// Add signers for the private keys in `descriptor`, which must be one of the wallet's
+    /// descriptors with its private keys included
+    ///
+    /// This allows a wallet loaded from public descriptors, for example with
+    /// [`Wallet::load_from_changeset`], to sign transactions once the keys are available, without
+    /// building it again. The descriptor is matched against the wallet's public descriptors to
+    /// find its keychain, so that only keys deriving the wallet's scripts are added. A descriptor
+    /// without private keys adds no signer.
+    ///
+    /// Returns [`AddPrivateKeysError::DescriptorMismatch`] if `descriptor` doesn't match any of
+    /// the wallet's descriptors.
+    pub fn add_private_keys<E: IntoWalletDescriptor>(
+        &mut self,
+        descriptor: E,
+    ) -> Result<(), AddPrivateKeysError> {
+        let (descriptor, keymap) =
+            into_wallet_descriptor_checked(descriptor, &self.secp, self.network)?;
+        let keychain = self
+            .keychains()
+            .find(|(_, public_descriptor)| *public_descriptor == &descriptor)
+            .map(|(&keychain, _)| keychain)
+            .ok_or(AddPrivateKeysError::DescriptorMismatch)?;
+
+        let signer_container = SignersContainer::build(keymap, &descriptor, &self.secp);
+        for signer in signer_container.signers() {
+            self.add_signer(keychain, SignerOrdering::default(), signer.clone());
+        }
+        Ok(())
+    }
+
     /// Get the signers
     ///
     /// ## Example
@@ -1197,6 +2038,7 @@ impl Wallet {
         match keychain {
             KeychainKind::External => Arc::clone(&self.signers),
             KeychainKind::Internal => Arc::clone(&self.change_signers),
+            KeychainKind::Custom(id) => self.custom_signers.get(&id).cloned().unwrap_or_default(),
         }
     }
 
@@ -1236,56 +2078,168 @@ impl Wallet {
         }
     }
 
-    pub(crate) fn create_tx<Cs: coin_selection::CoinSelectionAlgorithm>(
+    /// Build a transaction whose only purpose is to commit to `data` with an `OP_RETURN` output.
+    ///
+    /// The wallet's utxos fund the fee at `fee_rate`, and any excess goes back to a change
+    /// output. This is a shorthand for calling [`TxBuilder::add_data`] on [`build_tx`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CreateTxError::OpReturnTooLarge`] if `data` is larger than the 80 bytes allowed by
+    /// the standardness rules, and a [`CreateTxError::CoinSelection`] error if the wallet has no
+    /// utxo to pay for the fee.
+    ///
+    /// [`build_tx`]: Self::build_tx
+    pub fn build_data_tx(&mut self, data: &[u8], fee_rate: FeeRate) -> Result<Psbt, CreateTxError> {
+        const MAX_OP_RETURN_DATA_SIZE: usize = 80;
+        if data.len() > MAX_OP_RETURN_DATA_SIZE {
+            return Err(CreateTxError::OpReturnTooLarge(data.len()));
+        }
+        let data = bitcoin::script::PushBytesBuf::try_from(data.to_vec())
+            .expect("data is at most 80 bytes long");
+        let mut builder = self.build_tx();
+        builder.add_data(&data).fee_rate(fee_rate);
+        builder.finish()
+    }
+
+    /// Build a transaction sending all the funds of the wallet to `to` at `fee_rate`.
+    ///
+    /// The transaction has a single output paying `to` the value of the spent utxos minus the fee.
+    /// Utxos that cost more to spend at `fee_rate` than they are worth are left out, as are the
+    /// locked ones (see [`lock_utxo_until`]). This is a shorthand for calling
+    /// [`TxBuilder::drain_wallet`] and [`TxBuilder::drain_to`] on [`build_tx`], marking the
+    /// uneconomical and locked utxos as [`unspendable`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CreateTxError::CoinSelection`] error if the wallet has no economical utxo, or
+    /// if the swept amount is below the dust limit of `to`.
+    ///
+    /// [`build_tx`]: Self::build_tx
+    /// [`lock_utxo_until`]: Self::lock_utxo_until
+    /// [`unspendable`]: TxBuilder::unspendable
+    pub fn build_sweep(&mut self, to: &Address, fee_rate: FeeRate) -> Result<Psbt, CreateTxError> {
+        let excluded = self
+            .list_unspent()
+            .filter(|utxo| {
+                !self.is_economical(utxo, fee_rate) || self.is_utxo_locked(utxo.outpoint)
+            })
+            .map(|utxo| utxo.outpoint)
+            .collect();
+        let mut builder = self.build_tx();
+        builder
+            .drain_wallet()
+            .drain_to(to.script_pubkey())
+            .unspendable(excluded)
+            .fee_rate(fee_rate);
+        builder.finish()
+    }
+
+    /// Build a transaction consolidating the utxos worth less than `threshold` into a single
+    /// output paying `to`, at `fee_rate`.
+    ///
+    /// Utxos worth `threshold` or more are left untouched, as are the locked utxos (see
+    /// [`lock_utxo_until`]) and the small utxos that cost more to spend at `fee_rate` than they
+    /// are worth. The number of utxos consolidated is the number
+    /// of inputs of the returned PSBT. This is a shorthand for calling [`TxBuilder::add_utxos`],
+    /// [`TxBuilder::manually_selected_only`] and [`TxBuilder::drain_to`] on [`build_tx`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CreateTxError::NoUtxosSelected`] if no unlocked utxo is both below `threshold`
+    /// and economical to spend, or a [`CreateTxError::CoinSelection`] error if the consolidated amount
+    /// is below the dust limit of `to`. [`CreateTxError::UnknownUtxo`] is returned if one of the
+    /// listed utxos can't be added to the transaction.
+    ///
+    /// [`build_tx`]: Self::build_tx
+    /// [`lock_utxo_until`]: Self::lock_utxo_until
+    pub fn build_consolidation_below(
         &mut self,
-        coin_selection: Cs,
-        params: TxParams,
+        threshold: Amount,
+        to: ScriptBuf,
+        fee_rate: FeeRate,
     ) -> Result<Psbt, CreateTxError> {
-        let keychains: BTreeMap<_, _> = self.indexed_graph.index.keychains().collect();
-        let external_descriptor = keychains.get(&KeychainKind::External).expect("must exist");
-        let internal_descriptor = keychains.get(&KeychainKind::Internal).expect("must exist");
+        let small = self
+            .list_unspent()
+            .filter(|utxo| {
+                utxo.txout.value < threshold
+                    && self.is_economical(utxo, fee_rate)
+                    && !self.is_utxo_locked(utxo.outpoint)
+            })
+            .map(|utxo| utxo.outpoint)
+            .collect::<Vec<_>>();
+        let mut builder = self.build_tx();
+        builder
+            .add_utxos(&small)
+            .map_err(|_| CreateTxError::UnknownUtxo)?
+            .manually_selected_only()
+            .drain_to(to)
+            .fee_rate(fee_rate);
+        builder.finish()
+    }
 
-        let external_policy = external_descriptor
-            .extract_policy(&self.signers, BuildSatisfaction::None, &self.secp)?
-            .unwrap();
-        let internal_policy = internal_descriptor
-            .extract_policy(&self.change_signers, BuildSatisfaction::None, &self.secp)?
-            .unwrap();
+    /// Whether `utxo` is worth more than what it costs to spend it at `fee_rate`.
+    fn is_economical(&self, utxo: &LocalOutput, fee_rate: FeeRate) -> bool {
+        let weight = bitcoin::TxIn::default().segwit_weight()
+            + self
+                .input_weight_estimate(utxo.keychain)
+                .expect("keychain must exist");
+        utxo.txout.value > fee_rate * weight
+    }
 
-        // The policy allows spending external outputs, but it requires a policy path that hasn't been
-        // provided
-        if params.change_policy != tx_builder::ChangeSpendPolicy::OnlyChange
-            && external_policy.requires_path()
-            && params.external_policy_path.is_none()
-        {
-            return Err(CreateTxError::SpendingPolicyRequired(
-                KeychainKind::External,
-            ));
-        };
-        // Same for the internal_policy path
-        if params.change_policy != tx_builder::ChangeSpendPolicy::ChangeForbidden
-            && internal_policy.requires_path()
-            && params.internal_policy_path.is_none()
-        {
-            return Err(CreateTxError::SpendingPolicyRequired(
-                KeychainKind::Internal,
-            ));
+    /// Build, sign and extract a transaction paying `recipients` at `fee_rate` in a single call.
+    ///
+    /// This is a shorthand for the common case of a simple payment from a wallet that holds all
+    /// the keys it needs. Coin selection and change are handled as with [`build_tx`]. Use
+    /// [`build_tx`] and [`sign`] directly for anything more advanced.
+    ///
+    /// If the transaction can't be fully signed the change address it revealed is released with
+    /// [`cancel_tx`] and [`CreateSignedTxError::NotFinalized`] is returned.
+    ///
+    /// [`build_tx`]: Self::build_tx
+    /// [`sign`]: Self::sign
+    /// [`cancel_tx`]: Self::cancel_tx
+    pub fn create_signed_tx(
+        &mut self,
+        recipients: Vec<(ScriptBuf, Amount)>,
+        fee_rate: FeeRate,
+        sign_options: SignOptions,
+    ) -> Result<Transaction, CreateSignedTxError> {
+        let mut builder = self.build_tx();
+        builder.set_recipients(recipients).fee_rate(fee_rate);
+        let mut psbt = builder.finish()?;
+
+        let finalized = match self.sign(&mut psbt, sign_options) {
+            Ok(finalized) => finalized,
+            Err(e) => {
+                self.cancel_psbt(&psbt);
+                return Err(e.into());
+            }
         };
+        if !finalized {
+            self.cancel_psbt(&psbt);
+            return Err(CreateSignedTxError::NotFinalized);
+        }
+        Ok(psbt.extract_tx()?)
+    }
 
-        let external_requirements = external_policy.get_condition(
-            params
-                .external_policy_path
-                .as_ref()
-                .unwrap_or(&BTreeMap::new()),
-        )?;
-        let internal_requirements = internal_policy.get_condition(
-            params
-                .internal_policy_path
-                .as_ref()
-                .unwrap_or(&BTreeMap::new()),
-        )?;
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub(crate) fn create_tx<Cs: coin_selection::CoinSelectionAlgorithm>(
+        &mut self,
+        coin_selection: Cs,
+        params: TxParams,
+    ) -> Result<(Psbt, TxDetails), CreateTxError> {
+        if let Some(preserved) = &params.preserved_recipients {
+            let mut preserved = preserved.clone();
+            let mut recipients = params.recipients.clone();
+            preserved.sort();
+            recipients.sort();
+            if preserved != recipients {
+                return Err(CreateTxError::PreservedRecipientsModified);
+            }
+        }
 
-        let requirements = external_requirements.merge(&internal_requirements)?;
+        let requirements = self.spending_requirements(&params)?;
 
         let version = match params.version {
             Some(tx_builder::Version(0)) => return Err(CreateTxError::Version0),
@@ -1347,8 +2301,16 @@ impl Wallet {
             }
         };
 
+        // A single input signaling RBF makes the whole tx replaceable (BIP125), so if a foreign
+        // utxo was added with such a sequence the wallet's own inputs signal it as well.
+        let foreign_signals_rbf = params.utxos.iter().any(|u| {
+            matches!(u.utxo, Utxo::Foreign { sequence: Some(sequence), .. } if sequence.is_rbf())
+        });
+
         // The nSequence to be by default for inputs unless an explicit sequence is specified.
         let n_sequence = match (params.rbf, requirements.csv) {
+            // No RBF requested, but a foreign input already signals it
+            (None, None) if foreign_signals_rbf => Sequence::ENABLE_RBF_NO_LOCKTIME,
             // No RBF or CSV but there's an nLockTime, so the nSequence cannot be final
             (None, None) if lock_time != absolute::LockTime::ZERO => {
                 Sequence::ENABLE_LOCKTIME_NO_RBF
@@ -1397,12 +2359,30 @@ impl Wallet {
                         previous_fee.rate.to_sat_per_kwu()
                             + FeeRate::BROADCAST_MIN.to_sat_per_kwu(), // +1 sat/vb
                     );
+                    match params.max_fee_rate {
+                        Some(max) if required_feerate > max => {
+                            return Err(CreateTxError::FeeRateTooHigh {
+                                max,
+                                required: required_feerate,
+                            });
+                        }
+                        _ => {}
+                    }
                     if rate < required_feerate {
                         return Err(CreateTxError::FeeRateTooLow {
                             required: required_feerate,
                         });
                     }
                 }
+                match params.max_fee_rate {
+                    Some(max) if rate > max => {
+                        return Err(CreateTxError::FeeRateTooHigh {
+                            max,
+                            required: rate,
+                        });
+                    }
+                    _ => {}
+                }
                 (rate, 0)
             }
         };
@@ -1424,7 +2404,10 @@ impl Wallet {
         let recipients = params.recipients.iter().map(|(r, v)| (r, *v));
 
         for (index, (script_pubkey, value)) in recipients.enumerate() {
-            if !params.allow_dust && value.is_dust(script_pubkey) && !script_pubkey.is_op_return() {
+            if !params.allow_dust
+                && value.is_dust_with_relay_fee(script_pubkey, self.dust_relay_fee)
+                && !script_pubkey.is_op_return()
+            {
                 return Err(CreateTxError::OutputBelowDustLimit(index));
             }
 
@@ -1448,33 +2431,193 @@ impl Wallet {
             self.preselect_utxos(&params, Some(current_height.to_consensus_u32()));
 
         // get drain script
-        let drain_script = match params.drain_to {
-            Some(ref drain_recipient) => drain_recipient.clone(),
-            None => {
-                let change_keychain = KeychainKind::Internal;
+        let drain_script = match (&params.drain_to, params.change_index) {
+            (Some(drain_recipient), _) => drain_recipient.clone(),
+            (None, Some((change_keychain, index))) => {
+                let unavailable = CreateTxError::ChangeIndexUnavailable {
+                    keychain: change_keychain,
+                    index,
+                };
+                let descriptor = self
+                    .get_descriptor_for_keychain(change_keychain)
+                    .ok_or(CreateTxError::KeychainNotFound(change_keychain))?;
+                if !descriptor.has_wildcard() && index > 0 {
+                    return Err(unavailable);
+                }
+                let spk = descriptor
+                    .at_derivation_index(index)
+                    .map_err(|_| unavailable)?
+                    .script_pubkey();
+                let txout_index = &mut self.indexed_graph.index;
+                if txout_index
+                    .inner()
+                    .outputs_in_range((change_keychain, index)..=(change_keychain, index))
+                    .next()
+                    .is_some()
+                {
+                    return Err(CreateTxError::ChangeIndexUsed {
+                        keychain: change_keychain,
+                        index,
+                    });
+                }
+                let (_, index_changeset) = txout_index
+                    .reveal_to_target(&change_keychain, index)
+                    .ok_or(CreateTxError::KeychainNotFound(change_keychain))?;
+                self.stage.append(index_changeset.into());
+                txout_index.mark_used(change_keychain, index);
+                spk
+            }
+            (None, None) => {
+                let change_keychain = self.change_keychain;
                 let ((index, spk), index_changeset) = self
                     .indexed_graph
                     .index
                     .next_unused_spk(&change_keychain)
-                    .expect("keychain must exist");
+                    .ok_or(CreateTxError::KeychainNotFound(change_keychain))?;
                 self.indexed_graph.index.mark_used(change_keychain, index);
                 self.stage.append(index_changeset.into());
                 spk
             }
         };
 
-        let (required_utxos, optional_utxos) =
+        // The reserved CPFP output is paid for upfront, and any change is later added to it.
+        let cpfp_reserve = match params.cpfp_reserve {
+            Some(reserve) => {
+                if !self.is_mine(&drain_script) {
+                    return Err(CreateTxError::CpfpReserveNotMine);
+                }
+                if reserve
+                    .to_sat()
+                    .is_dust_with_relay_fee(&drain_script, self.dust_relay_fee)
+                {
+                    return Err(CreateTxError::OutputBelowDustLimit(tx.output.len()));
+                }
+                let reserve_output = TxOut {
+                    value: reserve,
+                    script_pubkey: drain_script.clone(),
+                };
+                fee_amount += (fee_rate * reserve_output.weight()).to_sat();
+                Some(reserve)
+            }
+            None => None,
+        };
+
+        let (mut required_utxos, mut optional_utxos) =
             coin_selection::filter_duplicates(required_utxos, optional_utxos);
+        if params.deterministic {
+            required_utxos.sort_by_key(|wu| wu.utxo.outpoint());
+            optional_utxos.sort_by_key(|wu| wu.utxo.outpoint());
+        }
+        // the required utxos are always spent, so no selection fits in a lower limit
+        if let Some(max_inputs) = params.max_inputs {
+            if required_utxos.len() > max_inputs {
+                return Err(CreateTxError::TooManyInputs {
+                    required: required_utxos.len(),
+                    max_inputs,
+                });
+            }
+        }
 
-        let coin_selection = coin_selection.coin_select(
-            required_utxos,
-            optional_utxos,
-            fee_rate,
-            outgoing.to_sat() + fee_amount,
-            &drain_script,
-        )?;
+        let satisfaction_weights = required_utxos
+            .iter()
+            .chain(&optional_utxos)
+            .map(|wu| (wu.utxo.outpoint(), wu.satisfaction_weight as u64))
+            .collect::<HashMap<_, _>>();
+        let required_outpoints = required_utxos
+            .iter()
+            .map(|wu| wu.utxo.outpoint())
+            .collect::<Vec<_>>();
+        let considered_outpoints = required_utxos
+            .iter()
+            .chain(&optional_utxos)
+            .map(|wu| wu.utxo.outpoint())
+            .collect::<Vec<_>>();
+
+        log_event!(
+            debug,
+            algorithm = core::any::type_name::<Cs>(),
+            required = required_utxos.len(),
+            optional = optional_utxos.len(),
+            ?fee_rate,
+            "selecting coins"
+        );
+        let target_amount = outgoing.to_sat() + fee_amount + cpfp_reserve.map_or(0, |r| r.to_sat());
+        let select = |required_utxos, optional_utxos| {
+            if params.deterministic {
+                coin_selection.coin_select_deterministic(
+                    required_utxos,
+                    optional_utxos,
+                    fee_rate,
+                    target_amount,
+                    &drain_script,
+                )
+            } else {
+                coin_selection.coin_select(
+                    required_utxos,
+                    optional_utxos,
+                    fee_rate,
+                    target_amount,
+                    &drain_script,
+                )
+            }
+        };
+        let coin_selection = match params.max_inputs {
+            Some(max_inputs) => {
+                let result = select(required_utxos.clone(), optional_utxos.clone())?;
+                if result.selected.len() <= max_inputs {
+                    result
+                } else {
+                    // Retry with only the optional utxos contributing the most value, so that
+                    // any selection fits in the limit.
+                    optional_utxos.sort_by_key(|wu| {
+                        let weight = bitcoin::TxIn::default().segwit_weight()
+                            + Weight::from_wu(wu.satisfaction_weight as u64);
+                        core::cmp::Reverse(
+                            wu.utxo.txout().value.to_sat() as i64
+                                - (fee_rate * weight).to_sat() as i64,
+                        )
+                    });
+                    optional_utxos.truncate(max_inputs.saturating_sub(required_utxos.len()));
+                    if params.deterministic {
+                        optional_utxos.sort_by_key(|wu| wu.utxo.outpoint());
+                    }
+                    select(required_utxos, optional_utxos)?
+                }
+            }
+            None => select(required_utxos, optional_utxos)?,
+        };
+        log_event!(
+            debug,
+            selected = ?coin_selection
+                .selected
+                .iter()
+                .map(|utxo| utxo.outpoint())
+                .collect::<Vec<_>>(),
+            fee = coin_selection.fee_amount,
+            excess = ?coin_selection.excess,
+            "selected coins"
+        );
         fee_amount += coin_selection.fee_amount;
-        let excess = &coin_selection.excess;
+        let excess = &coin_selection
+            .excess
+            .with_dust_relay_fee(&drain_script, self.dust_relay_fee);
+
+        if let Some(csv) = requirements.csv {
+            for utxo in &coin_selection.selected {
+                if let Utxo::Local(local) = utxo {
+                    match params.sequences.get(&local.outpoint) {
+                        Some(&sequence) if !check_nsequence_rbf(sequence, csv) => {
+                            return Err(CreateTxError::SequenceCsv {
+                                outpoint: local.outpoint,
+                                sequence,
+                                csv,
+                            })
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
 
         tx.input = coin_selection
             .selected
@@ -1482,11 +2625,26 @@ impl Wallet {
             .map(|u| bitcoin::TxIn {
                 previous_output: u.outpoint(),
                 script_sig: ScriptBuf::default(),
-                sequence: u.sequence().unwrap_or(n_sequence),
+                sequence: params
+                    .sequences
+                    .get(&u.outpoint())
+                    .copied()
+                    .or_else(|| u.sequence())
+                    .unwrap_or(n_sequence),
                 witness: Witness::new(),
             })
             .collect();
 
+        if params.rbf.is_some() && !tx.input.iter().any(|txin| txin.sequence.is_rbf()) {
+            return Err(CreateTxError::RbfNotSignaled);
+        }
+
+        if !params.allow_spent_inputs {
+            if let Some((outpoint, by_txid)) = self.find_input_already_spent(&tx) {
+                return Err(CreateTxError::InputAlreadySpent { outpoint, by_txid });
+            }
+        }
+
         if tx.output.is_empty() {
             // Uh oh, our transaction has no outputs.
             // We allow this when:
@@ -1512,34 +2670,196 @@ impl Wallet {
             }
         }
 
-        match excess {
+        let drain_output = match excess {
             NoChange {
                 remaining_amount, ..
-            } => fee_amount += remaining_amount,
+            } => {
+                fee_amount += remaining_amount;
+                cpfp_reserve.map(|reserve| {
+                    received += reserve;
+                    TxOut {
+                        value: reserve,
+                        script_pubkey: drain_script,
+                    }
+                })
+            }
             Change { amount, fee } => {
+                // the change is merged into the reserved output, whose fee was already paid
+                let amount = match cpfp_reserve {
+                    Some(reserve) => reserve.to_sat() + amount + fee,
+                    None => {
+                        fee_amount += fee;
+                        *amount
+                    }
+                };
                 if self.is_mine(&drain_script) {
-                    received += Amount::from_sat(*amount);
+                    received += Amount::from_sat(amount);
                 }
-                fee_amount += fee;
 
                 // create drain output
-                let drain_output = TxOut {
-                    value: Amount::from_sat(*amount),
-                    script_pubkey: drain_script,
-                };
-
                 // TODO: We should pay attention when adding a new output: this might increase
                 // the length of the "number of vouts" parameter by 2 bytes, potentially making
                 // our feerate too low
-                tx.output.push(drain_output);
+                Some(TxOut {
+                    value: Amount::from_sat(amount),
+                    script_pubkey: drain_script,
+                })
             }
         };
+        let drain_vout = drain_output.as_ref().map(|drain_output| {
+            tx.output.push(drain_output.clone());
+            tx.output.len() - 1
+        });
 
-        // sort input/outputs according to the chosen algorithm
-        params.ordering.sort_tx(&mut tx);
+        match params.max_fee {
+            Some(max) if fee_amount > max.to_sat() => {
+                return Err(CreateTxError::FeeTooHigh {
+                    max,
+                    fee: Amount::from_sat(fee_amount),
+                });
+            }
+            _ => {}
+        }
+
+        // the witnesses are still empty, so add the weight needed to satisfy each input, and the
+        // segwit marker, flag and empty witness of every input if any of them is segwit
+        let is_segwit = coin_selection.selected.iter().any(|utxo| match utxo {
+            Utxo::Local(local) => {
+                let desc = self.known_descriptor(local.keychain);
+                desc.is_witness() || desc.is_taproot()
+            }
+            Utxo::Foreign { psbt_input, .. } => psbt_input.witness_utxo.is_some(),
+        });
+        let weight = tx.weight().to_wu()
+            + tx.input
+                .iter()
+                .filter_map(|txin| satisfaction_weights.get(&txin.previous_output))
+                .sum::<u64>()
+            + if is_segwit {
+                2 + tx.input.len() as u64
+            } else {
+                0
+            };
+        if weight > bitcoin::policy::MAX_STANDARD_TX_WEIGHT as u64 {
+            return Err(CreateTxError::TransactionTooLarge { weight });
+        }
 
+        // sort input/outputs according to the chosen algorithm, keeping track of where the drain
+        // output ends up
+        let drain_vout = match params.ordering {
+            TxOrdering::Shuffle if params.deterministic => {
+                TxOrdering::Bip69Lexicographic.sort_tx_tracking_output(&mut tx, drain_vout)
+            }
+            ordering => ordering.sort_tx_tracking_output(&mut tx, drain_vout),
+        };
+        let change = drain_output
+            .zip(drain_vout)
+            .map(|(drain_output, vout)| ChangeOutput {
+                vout,
+                script_pubkey: drain_output.script_pubkey,
+            });
+
+        // the labels are attached once the transaction is applied to the wallet, see
+        // `attach_pending_labels`
+        #[cfg(feature = "labels")]
+        let labels = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter_map(|(vout, txout)| {
+                let label = match &change {
+                    Some(change) if change.vout == vout => params.change_label.as_ref(),
+                    _ => None,
+                }
+                .or_else(|| params.output_labels.get(&txout.script_pubkey))?;
+                Some((vout as u32, label.clone()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let absolute_fee = Amount::from_sat(coin_selection.selected_amount())
+            - tx.output.iter().map(|txout| txout.value).sum::<Amount>();
+        let fee_details = FeeDetails {
+            absolute_fee,
+            fee_rate: absolute_fee / Weight::from_wu(weight),
+            vsize: ((weight + 3) / 4) as usize,
+            change_amount: change.as_ref().map(|change| tx.output[change.vout].value),
+        };
+        let (change_fee, excess_to_fee) = match excess {
+            Change { fee, .. } if cpfp_reserve.is_none() => (Some(Amount::from_sat(*fee)), 0),
+            Change { .. } => (None, 0),
+            NoChange {
+                remaining_amount, ..
+            } => (None, *remaining_amount),
+        };
+        // spending the change later needs the satisfaction weight of its descriptor, fall back to
+        // assuming a P2WPKH output if the drain script isn't ours
+        let change_spend_weight = change_fee.and(change.as_ref()).map(|change| {
+            self.indexed_graph
+                .index
+                .index_of_spk(&change.script_pubkey)
+                .map(|(keychain, _)| {
+                    bitcoin::TxIn::default().segwit_weight()
+                        + self
+                            .input_weight_estimate(*keychain)
+                            .expect("keychain must exist")
+                })
+                .unwrap_or_else(coin_selection::WasteMinimizingCoinSelection::change_spend_weight)
+        });
+        let coin_selection_details = CoinSelectionDetails {
+            considered: considered_outpoints,
+            required: required_outpoints,
+            selected: coin_selection
+                .selected
+                .iter()
+                .map(|utxo| utxo.outpoint())
+                .collect(),
+            selected_amount: Amount::from_sat(coin_selection.selected_amount()),
+            fee: absolute_fee,
+            fee_rate,
+            inputs_weight: coin_selection
+                .selected
+                .iter()
+                .map(|utxo| {
+                    bitcoin::TxIn::default().segwit_weight()
+                        + Weight::from_wu(satisfaction_weights[&utxo.outpoint()])
+                })
+                .sum(),
+            change_amount: fee_details.change_amount,
+            change_fee,
+            change_spend_weight,
+            excess_to_fee: Amount::from_sat(excess_to_fee),
+        };
+
+        #[cfg(feature = "std")]
+        let local_inputs = coin_selection
+            .selected
+            .iter()
+            .filter(|utxo| matches!(utxo, Utxo::Local(_)))
+            .map(|utxo| utxo.outpoint())
+            .collect::<Vec<_>>();
+
+        #[cfg(feature = "labels")]
+        let txid = tx.compute_txid();
         let psbt = self.complete_transaction(tx, coin_selection.selected, params)?;
-        Ok(psbt)
+        #[cfg(feature = "labels")]
+        if !labels.is_empty() {
+            self.pending_labels.insert(txid, labels);
+        }
+
+        #[cfg(feature = "std")]
+        if let Some(ttl) = self.utxo_lock_ttl {
+            for outpoint in local_inputs {
+                self.lock_utxo(outpoint, ttl);
+            }
+        }
+
+        Ok((
+            psbt,
+            TxDetails {
+                change,
+                fee: fee_details,
+                coin_selection: coin_selection_details,
+            },
+        ))
     }
 
     /// Bump the fee of a transaction previously created with this wallet.
@@ -1548,6 +2868,11 @@ impl Wallet {
     /// *replace by fee* (RBF). If the transaction can be fee bumped then it returns a [`TxBuilder`]
     /// pre-populated with the inputs and outputs of the original transaction.
     ///
+    /// The change output of the original transaction is removed and the change of the replacement
+    /// is sent back to the same address. If the original inputs can't pay for the higher fee,
+    /// confirmed UTXOs of the wallet are added, unless disabled with
+    /// [`TxBuilder::allow_additional_inputs`].
+    ///
     /// ## Example
     ///
     /// ```no_run
@@ -1641,7 +2966,7 @@ impl Wallet {
                 let weighted_utxo = match txout_index.index_of_spk(&txout.script_pubkey) {
                     Some(&(keychain, derivation_index)) => {
                         let satisfaction_weight = self
-                            .get_descriptor_for_keychain(keychain)
+                            .known_descriptor(keychain)
                             .max_weight_to_satisfy()
                             .unwrap()
                             .to_wu() as usize;
@@ -1679,23 +3004,39 @@ impl Wallet {
             })
             .collect::<Result<Vec<_>, _>>()?;
 
+        let mut change_index = None;
         if tx.output.len() > 1 {
-            let mut change_index = None;
             for (index, txout) in tx.output.iter().enumerate() {
-                let change_keychain = KeychainKind::Internal;
                 match txout_index.index_of_spk(&txout.script_pubkey) {
-                    Some((keychain, _)) if *keychain == change_keychain => {
+                    Some((keychain, _)) if self.is_change_keychain(*keychain) => {
                         change_index = Some(index)
                     }
                     _ => {}
                 }
             }
+        }
 
-            if let Some(change_index) = change_index {
-                tx.output.remove(change_index);
+        // carry the labels over to the outputs of the replacement
+        #[cfg(feature = "labels")]
+        let mut output_labels = BTreeMap::new();
+        #[cfg(feature = "labels")]
+        let mut change_label = None;
+        #[cfg(feature = "labels")]
+        for (index, txout) in tx.output.iter().enumerate() {
+            let outpoint = OutPoint::new(txid, index as u32);
+            if let Some(label) = self.labels.get(&LabelKey::Output(outpoint)) {
+                if change_index == Some(index) {
+                    change_label = Some(label.to_string());
+                } else {
+                    output_labels.insert(txout.script_pubkey.clone(), label.to_string());
+                }
             }
         }
 
+        // the change of the replacement goes back to the original change address, if any
+        let change_script =
+            change_index.map(|change_index| tx.output.remove(change_index).script_pubkey);
+
         let params = TxParams {
             // TODO: figure out what rbf option should be?
             version: Some(tx_builder::Version(tx.version.0)),
@@ -1704,19 +3045,161 @@ impl Wallet {
                 .into_iter()
                 .map(|txout| (txout.script_pubkey, txout.value.to_sat()))
                 .collect(),
+            drain_to: change_script,
             utxos: original_utxos,
             bumping_fee: Some(tx_builder::PreviousFee {
                 absolute: fee.to_sat(),
                 rate: fee_rate,
             }),
+            #[cfg(feature = "labels")]
+            output_labels,
+            #[cfg(feature = "labels")]
+            change_label,
             ..Default::default()
         };
 
-        Ok(TxBuilder {
-            wallet: alloc::rc::Rc::new(core::cell::RefCell::new(self)),
-            params,
-            coin_selection: DefaultCoinSelectionAlgorithm::default(),
-        })
+        Ok(TxBuilder {
+            wallet: alloc::rc::Rc::new(core::cell::RefCell::new(self)),
+            params,
+            coin_selection: DefaultCoinSelectionAlgorithm::default(),
+        })
+    }
+
+    /// Compute the minimum absolute fee a transaction replacing `txid` must pay according to the
+    /// BIP125 replacement rules.
+    ///
+    /// The replacement evicts `txid` and its unconfirmed descendants, so it must pay at least
+    /// their fees combined (rule 3), plus its own relay cost at [`FeeRate::BROADCAST_MIN`]
+    /// (rule 4). The size of the replacement is projected as the size of `txid` as stored in
+    /// the wallet, which holds for a fee bump keeping the same inputs and outputs. A replacement
+    /// adding inputs or outputs must pay for their size too.
+    ///
+    /// Returns an error if `txid` isn't known, is already confirmed or doesn't signal RBF, or if
+    /// the fee of `txid` or of one of its descendants can't be computed.
+    pub fn min_rbf_fee(&self, txid: Txid) -> Result<Amount, BuildFeeBumpError> {
+        let graph = self.indexed_graph.graph();
+        let chain_tip = self.chain.tip().block_id();
+
+        let tx = graph
+            .get_tx(txid)
+            .ok_or(BuildFeeBumpError::TransactionNotFound(txid))?;
+        let pos = graph
+            .get_chain_position(&self.chain, chain_tip, txid)
+            .ok_or(BuildFeeBumpError::TransactionNotFound(txid))?;
+        if let ChainPosition::Confirmed(_) = pos {
+            return Err(BuildFeeBumpError::TransactionConfirmed(txid));
+        }
+        if !tx.is_explicitly_rbf() {
+            return Err(BuildFeeBumpError::IrreplaceableTransaction(txid));
+        }
+
+        let evicted_txids = core::iter::once(txid).chain(
+            graph
+                .walk_descendants(txid, |_, txid| Some(txid))
+                .filter(|&txid| {
+                    graph
+                        .get_chain_position(&self.chain, chain_tip, txid)
+                        .is_some()
+                }),
+        );
+        let mut evicted_fees = Amount::ZERO;
+        for txid in evicted_txids {
+            let tx = graph
+                .get_tx(txid)
+                .ok_or(BuildFeeBumpError::TransactionNotFound(txid))?;
+            evicted_fees += self
+                .calculate_fee(&tx)
+                .map_err(|_| BuildFeeBumpError::FeeRateUnavailable)?;
+        }
+
+        Ok(evicted_fees
+            + FeeRate::BROADCAST_MIN
+                .fee_vb(tx.vsize() as u64)
+                .expect("valid fee"))
+    }
+
+    /// Same as [`build_fee_bump`], but guarantees that the recipients of the original transaction
+    /// are paid exactly the same amounts to exactly the same scripts by the replacement.
+    ///
+    /// Every output of the original transaction except for the change output is locked, so the
+    /// higher fee can only be paid for by reducing the change or by adding inputs. Creating the
+    /// transaction fails with:
+    ///
+    /// * [`CreateTxError::PreservedRecipientsModified`] if the recipients were changed on the
+    ///   returned [`TxBuilder`], e.g. with [`TxBuilder::set_recipients`] or
+    ///   [`TxBuilder::add_recipient`].
+    /// * [`CreateTxError::CoinSelection`] if the change and the available UTXOs can't cover the
+    ///   new fee, rather than reducing the amount paid to a recipient.
+    ///
+    /// [`build_fee_bump`]: Self::build_fee_bump
+    pub fn build_fee_bump_preserving_outputs(
+        &mut self,
+        txid: Txid,
+    ) -> Result<TxBuilder<'_, DefaultCoinSelectionAlgorithm>, BuildFeeBumpError> {
+        let mut builder = self.build_fee_bump(txid)?;
+        builder.params.preserved_recipients = Some(builder.params.recipients.clone());
+        Ok(builder)
+    }
+
+    /// Bump the fee of each of several transactions to `fee_rate`, building a separate replacement
+    /// for each of them.
+    ///
+    /// This doesn't merge the transactions into a single replacement: a replacement is built with
+    /// [`build_fee_bump`] for each of the `txids`, in order, and each returned PSBT replaces one
+    /// transaction. UTXOs added as inputs to one replacement are made unspendable for the
+    /// following ones, so the returned PSBTs don't conflict with each other and can all be
+    /// broadcast. A transaction spending an output of another transaction in `txids` is not
+    /// bumped, since replacing the parent evicts it.
+    ///
+    /// Transactions that can't be bumped are reported in [`FeeBumps::failed`] together with
+    /// the reason, the others are still returned in [`FeeBumps::psbts`].
+    ///
+    /// [`build_fee_bump`]: Self::build_fee_bump
+    pub fn build_fee_bumps(&mut self, txids: &[Txid], fee_rate: FeeRate) -> FeeBumps {
+        let mut bumps = FeeBumps::default();
+        let mut selected = Vec::<OutPoint>::new();
+        let mut visited = HashSet::<Txid>::new();
+
+        for &txid in txids {
+            if !visited.insert(txid) {
+                continue;
+            }
+
+            let replaced_parent = self.indexed_graph.graph().get_tx(txid).and_then(|tx| {
+                tx.input
+                    .iter()
+                    .map(|txin| txin.previous_output.txid)
+                    .find(|parent| txids.contains(parent))
+            });
+            if let Some(parent) = replaced_parent {
+                bumps
+                    .failed
+                    .push((txid, FeeBumpsError::SpendsReplacedTransaction(parent)));
+                continue;
+            }
+
+            let result = self
+                .build_fee_bump(txid)
+                .map_err(FeeBumpsError::from)
+                .and_then(|mut builder| {
+                    builder.fee_rate(fee_rate).unspendable(selected.clone());
+                    builder.finish().map_err(FeeBumpsError::from)
+                });
+            match result {
+                Ok(psbt) => {
+                    selected.extend(
+                        psbt.unsigned_tx
+                            .input
+                            .iter()
+                            .map(|txin| txin.previous_output),
+                    );
+                    bumps.psbts.push((txid, psbt));
+                }
+                Err(e) => bumps.failed.push((txid, e)),
+            }
+        }
+
+        bumps
     }
 
     /// Sign a transaction with all the wallet's signers, in the order specified by every signer's
@@ -1727,6 +3210,11 @@ impl Wallet {
     /// signers will follow the options, but the "software signers" (WIF keys and `xprv`) defined
     /// in this library will.
     ///
+    /// Signing fails with [`SignerError::InputAlreadySpent`] if an input of the PSBT spends an
+    /// output that a confirmed transaction of the wallet already spends, since such a stale PSBT
+    /// can never confirm, unless [`SignOptions::allow_spent_inputs`] is set. Inputs spent by
+    /// unconfirmed transactions are allowed, as the PSBT may be replacing them.
+    ///
     /// ## Example
     ///
     /// ```
@@ -1746,18 +3234,36 @@ impl Wallet {
     /// let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
     /// assert!(finalized, "we should have signed all the inputs");
     /// # Ok::<(),anyhow::Error>(())
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn sign(&self, psbt: &mut Psbt, sign_options: SignOptions) -> Result<bool, SignerError> {
         // This adds all the PSBT metadata for the inputs, which will help us later figure out how
         // to derive our keys
         self.update_psbt_with_descriptor(psbt)
             .map_err(SignerError::MiniscriptPsbt)?;
 
+        if let Some(indices) = &sign_options.sign_inputs {
+            if indices.iter().any(|&index| index >= psbt.inputs.len()) {
+                return Err(SignerError::InputIndexOutOfRange);
+            }
+        }
+
+        // A transaction spending an output already spent by a confirmed transaction can never
+        // confirm, don't waste a signature on it
+        if !sign_options.allow_spent_inputs {
+            if let Some((outpoint, by_txid)) = self.find_input_already_spent(&psbt.unsigned_tx) {
+                return Err(SignerError::InputAlreadySpent { outpoint, by_txid });
+            }
+        }
+
         // If we aren't allowed to use `witness_utxo`, ensure that every input (except p2tr and finalized ones)
         // has the `non_witness_utxo`
         if !sign_options.trust_witness_utxo
             && psbt
                 .inputs
                 .iter()
+                .enumerate()
+                .filter(|(n, _)| sign_options.signs_input(*n))
+                .map(|(_, i)| i)
                 .filter(|i| i.final_script_witness.is_none() && i.final_script_sig.is_none())
                 .filter(|i| i.tap_internal_key.is_none() && i.tap_merkle_root.is_none())
                 .any(|i| i.non_witness_utxo.is_none())
@@ -1765,60 +3271,129 @@ impl Wallet {
             return Err(SignerError::MissingNonWitnessUtxo);
         }
 
-        // If the user hasn't explicitly opted-in, refuse to sign the transaction unless every input
-        // is using `SIGHASH_ALL` or `SIGHASH_DEFAULT` for taproot
-        if !sign_options.allow_all_sighashes
-            && !psbt.inputs.iter().all(|i| {
-                i.sighash_type.is_none()
-                    || i.sighash_type == Some(EcdsaSighashType::All.into())
-                    || i.sighash_type == Some(TapSighashType::All.into())
-                    || i.sighash_type == Some(TapSighashType::Default.into())
-            })
-        {
-            return Err(SignerError::NonStandardSighash);
+        // Refuse to sign the transaction if an input uses a sighash that isn't valid for its type
+        // and, unless the user has explicitly opted-in, one that doesn't commit to the outputs
+        for (n, input) in psbt.inputs.iter().enumerate() {
+            if !sign_options.signs_input(n) {
+                continue;
+            }
+            let sighash_type = match input.sighash_type {
+                Some(sighash_type) => sighash_type,
+                None => continue,
+            };
+            let is_taproot = input.tap_internal_key.is_some()
+                || input.tap_merkle_root.is_some()
+                || psbt
+                    .get_utxo_for(n)
+                    .map(|txout| txout.script_pubkey.is_p2tr())
+                    .unwrap_or(false);
+            if !is_valid_sighash(sighash_type, is_taproot) {
+                return Err(SignerError::InvalidSighash);
+            }
+            if !sign_options.allow_all_sighashes
+                && !is_safe_sighash(sighash_type, sign_options.allow_anyone_can_pay)
+            {
+                return Err(SignerError::NonStandardSighash);
+            }
         }
 
         for signer in self
             .signers
             .signers()
-            .iter()
-            .chain(self.change_signers.signers().iter())
+            .into_iter()
+            .chain(self.change_signers.signers())
+            .chain(
+                self.custom_signers
+                    .values()
+                    .flat_map(|signers| signers.signers()),
+            )
         {
             signer.sign_transaction(psbt, &sign_options, &self.secp)?;
         }
+        log_event!(
+            debug,
+            inputs = psbt.inputs.len(),
+            signed_inputs = psbt
+                .inputs
+                .iter()
+                .filter(|input| !input.partial_sigs.is_empty()
+                    || input.tap_key_sig.is_some()
+                    || !input.tap_script_sigs.is_empty())
+                .count(),
+            "signed psbt"
+        );
 
         // attempt to finalize
-        if sign_options.try_finalize {
-            self.finalize_psbt(psbt, sign_options)
-        } else {
-            Ok(false)
+        if !sign_options.try_finalize {
+            return Ok(false);
         }
+        if sign_options.sign_inputs.is_none() {
+            return self.finalize_psbt(psbt, sign_options);
+        }
+        // when signing only some inputs, don't finalize any input unless all of them are complete
+        let mut finalized_psbt = psbt.clone();
+        let finished = self.finalize_psbt(&mut finalized_psbt, sign_options)?;
+        if finished {
+            *psbt = finalized_psbt;
+        }
+        Ok(finished)
     }
 
     /// Return the spending policies for the wallet's descriptor
+    ///
+    /// Returns `Ok(None)` if the keychain is not part of the wallet.
     pub fn policies(&self, keychain: KeychainKind) -> Result<Option<Policy>, DescriptorError> {
-        let signers = match keychain {
-            KeychainKind::External => &self.signers,
-            KeychainKind::Internal => &self.change_signers,
-        };
-
-        self.public_descriptor(keychain).extract_policy(
-            signers,
-            BuildSatisfaction::None,
-            &self.secp,
-        )
+        match self.public_descriptor(keychain) {
+            Some(descriptor) => descriptor.extract_policy(
+                &self.get_signers(keychain),
+                BuildSatisfaction::None,
+                &self.secp,
+            ),
+            None => Ok(None),
+        }
     }
 
     /// Return the "public" version of the wallet's descriptor, meaning a new descriptor that has
     /// the same structure but with every secret key removed
     ///
-    /// This can be used to build a watch-only version of a wallet
-    pub fn public_descriptor(&self, keychain: KeychainKind) -> &ExtendedDescriptor {
+    /// This can be used to build a watch-only version of a wallet, or shared with a coordinator.
+    /// Extended private keys are replaced by their extended public key, and the key origin of
+    /// every key is kept: a key derived with hardened steps from a master key gets the master
+    /// fingerprint and the hardened derivation path as its origin, since the public key can only
+    /// be derived from that point on.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use bdk_wallet::{KeychainKind, Wallet};
+    /// # use bdk_wallet::bitcoin::Network;
+    /// let descriptor = "wpkh(tprv8ZgxMBicQKsPe73PBRSmNbTfbcsZnwWhz5eVmhHpi31HW29Z7mc9B4cWGRQzopNUzZUT391DeDJxL2PefNunWyLgqCKRMDkU1s2s8bAfoSk/84'/1'/0'/0/*)";
+    /// let change_descriptor = "wpkh(tprv8ZgxMBicQKsPe73PBRSmNbTfbcsZnwWhz5eVmhHpi31HW29Z7mc9B4cWGRQzopNUzZUT391DeDJxL2PefNunWyLgqCKRMDkU1s2s8bAfoSk/84'/1'/0'/1/*)";
+    /// let wallet = Wallet::new(descriptor, change_descriptor, Network::Testnet)?;
+    ///
+    /// let watch_only = Wallet::new(
+    ///     &wallet.public_descriptor(KeychainKind::External).unwrap().to_string(),
+    ///     &wallet.public_descriptor(KeychainKind::Internal).unwrap().to_string(),
+    ///     Network::Testnet,
+    /// )?;
+    /// assert_eq!(
+    ///     watch_only.peek_address(KeychainKind::External, 0)?,
+    ///     wallet.peek_address(KeychainKind::External, 0)?,
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn public_descriptor(&self, keychain: KeychainKind) -> Option<&ExtendedDescriptor> {
         self.indexed_graph
             .index
             .keychains()
             .find(|(k, _)| *k == &keychain)
             .map(|(_, d)| d)
+    }
+
+    /// The descriptor of a keychain known to be part of the wallet, e.g. because it was returned
+    /// by the wallet's index.
+    fn known_descriptor(&self, keychain: KeychainKind) -> &ExtendedDescriptor {
+        self.public_descriptor(keychain)
             .expect("keychain must exist")
     }
 
@@ -1919,16 +3494,60 @@ impl Wallet {
         Ok(finished)
     }
 
+    /// Extract the signed transaction from a finalized PSBT.
+    ///
+    /// Unlike [`Psbt::extract_tx`] this doesn't consume the PSBT, and if some inputs aren't
+    /// finalized yet, e.g. because a multisig is still missing signatures, it returns
+    /// [`ExtractError::NotFinalized`] with their indexes instead of an invalid transaction. The
+    /// fee rate of the transaction is checked by [`Psbt::extract_tx`].
+    pub fn try_extract_tx(&self, psbt: &Psbt) -> Result<Transaction, ExtractError> {
+        let unfinalized = psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| {
+                input.final_script_sig.is_none() && input.final_script_witness.is_none()
+            })
+            .map(|(n, _)| n)
+            .collect::<Vec<_>>();
+        if !unfinalized.is_empty() {
+            return Err(ExtractError::NotFinalized(unfinalized));
+        }
+        Ok(psbt.clone().extract_tx()?)
+    }
+
     /// Return the secp256k1 context used for all signing operations
     pub fn secp_ctx(&self) -> &SecpCtx {
         &self.secp
     }
 
-    /// Returns the descriptor used to create addresses for a particular `keychain`.
-    pub fn get_descriptor_for_keychain(&self, keychain: KeychainKind) -> &ExtendedDescriptor {
+    /// Returns the descriptor used to create addresses for a particular `keychain`, or `None` if
+    /// the keychain is not part of the wallet.
+    pub fn get_descriptor_for_keychain(
+        &self,
+        keychain: KeychainKind,
+    ) -> Option<&ExtendedDescriptor> {
         self.public_descriptor(keychain)
     }
 
+    /// Returns the estimated weight of the scriptSig and witness needed to spend an output of
+    /// `keychain`.
+    ///
+    /// This is the maximum satisfaction weight of the keychain's descriptor, the same value used
+    /// for the wallet's own UTXOs during coin selection. Adding the weight of the outpoint, sequence
+    /// and scriptSig length of the input and multiplying by the expected number of inputs gives a
+    /// fee preview without building a transaction. A taproot key-path spend is much lighter than a
+    /// multisig one, for example.
+    ///
+    /// Returns `None` if the keychain is not part of the wallet.
+    pub fn input_weight_estimate(&self, keychain: KeychainKind) -> Option<Weight> {
+        self.public_descriptor(keychain).map(|descriptor| {
+            descriptor
+                .max_weight_to_satisfy()
+                .expect("wallet descriptors are always satisfiable")
+        })
+    }
+
     /// The derivation index of this wallet. It will return `None` if it has not derived any addresses.
     /// Otherwise, it will return the index of the highest address it has derived.
     pub fn derivation_index(&self, keychain: KeychainKind) -> Option<u32> {
@@ -1936,18 +3555,64 @@ impl Wallet {
     }
 
     /// The index of the next address that you would get if you were to ask the wallet for a new address
-    pub fn next_derivation_index(&self, keychain: KeychainKind) -> u32 {
+    ///
+    /// Returns `None` if the keychain is not part of the wallet.
+    pub fn next_derivation_index(&self, keychain: KeychainKind) -> Option<u32> {
         self.indexed_graph
             .index
             .next_index(&keychain)
-            .expect("keychain must exist")
-            .0
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the address usage statistics of `keychain`.
+    ///
+    /// This can be used to decide when to reveal more addresses or to warn when the number of
+    /// revealed but unused addresses approaches the lookahead. Returns `None` if the keychain is
+    /// not part of the wallet.
+    pub fn keychain_stats(&self, keychain: KeychainKind) -> Option<KeychainStats> {
+        let index = &self.indexed_graph.index;
+        let next_index = self.next_derivation_index(keychain)?;
+        let last_revealed = index.last_revealed_index(&keychain);
+        let last_used = index.last_used_index(&keychain);
+        let next_unused = index
+            .unused_keychain_spks(&keychain)
+            .next()
+            .map(|(i, _)| i)
+            .unwrap_or(next_index);
+        let unused_gap = match (last_revealed, last_used) {
+            (Some(revealed), Some(used)) => revealed.saturating_sub(used),
+            (Some(revealed), None) => revealed + 1,
+            (None, _) => 0,
+        };
+        Some(KeychainStats {
+            last_revealed,
+            last_used,
+            next_unused,
+            gap_to_lookahead: index.lookahead().saturating_sub(unused_gap),
+        })
     }
 
     /// Informs the wallet that you no longer intend to broadcast a tx that was built from it.
     ///
     /// This frees up the change address used when creating the tx for use in future transactions.
-    // TODO: Make this free up reserved utxos when that's implemented
+    ///
+    /// It only touches in-memory state: the "used" marking of the wallet's [`KeychainTxOutIndex`],
+    /// the UTXO locks and the pending output labels. Every output of `tx` paying to one of the
+    /// wallet's script pubkeys is unmarked, so that [`next_unused_address`] and the change
+    /// selection of the [`TxBuilder`] may hand it out again. In particular:
+    ///
+    /// * Nothing is staged, so there is nothing to persist and it is safe to call whether or not
+    ///   the tx (or the wallet changes produced while building it) was ever persisted.
+    /// * The last revealed derivation index is left untouched; revealing is monotonic.
+    /// * Script pubkeys that are used by a transaction already in the wallet's tx graph stay used,
+    ///   see [`KeychainTxOutIndex::unmark_used`].
+    /// * The locks held on the UTXOs spent by `tx` are released, see [`lock_utxo_until`].
+    /// * The output labels set when building `tx` are discarded, see
+    ///   [`TxBuilder::add_recipient_with_label`].
+    ///
+    /// [`next_unused_address`]: Self::next_unused_address
+    /// [`lock_utxo_until`]: Self::lock_utxo_until
+    /// [`KeychainTxOutIndex::unmark_used`]: bdk_chain::keychain::KeychainTxOutIndex::unmark_used
     pub fn cancel_tx(&mut self, tx: &Transaction) {
         let txout_index = &mut self.indexed_graph.index;
         for txout in &tx.output {
@@ -1957,6 +3622,112 @@ impl Wallet {
                 txout_index.unmark_used(*keychain, *index);
             }
         }
+        for txin in &tx.input {
+            self.locked_utxos.remove(&txin.previous_output);
+        }
+        #[cfg(feature = "labels")]
+        self.pending_labels.remove(&unsigned_txid(tx));
+    }
+
+    /// Locks the UTXO at `outpoint` until the unix timestamp `expires_at` (in seconds), replacing
+    /// any previous lock on it.
+    ///
+    /// Coin selection skips locked UTXOs, unless they are added explicitly with
+    /// [`TxBuilder::add_utxo`]. This prevents two transactions built before either is broadcast
+    /// from spending the same UTXO. A lock is released by [`unlock_utxo`], by [`cancel_tx`] for
+    /// the inputs of the cancelled transaction, and once the UTXO is spent by a transaction of the
+    /// wallet.
+    ///
+    /// Locks are only kept in memory: they are not part of the [`ChangeSet`] and are lost when the
+    /// wallet is dropped. They are also held per `Wallet` instance: another `Wallet` loaded from
+    /// the same database doesn't see them, so transactions built concurrently from separate
+    /// instances may still spend the same UTXO. Without the `std` feature the wallet has no
+    /// clock, so locks don't expire by themselves and must be released with
+    /// [`release_expired_utxo_locks`].
+    ///
+    /// [`unlock_utxo`]: Self::unlock_utxo
+    /// [`cancel_tx`]: Self::cancel_tx
+    /// [`release_expired_utxo_locks`]: Self::release_expired_utxo_locks
+    pub fn lock_utxo_until(&mut self, outpoint: OutPoint, expires_at: u64) {
+        let graph = self.indexed_graph.graph();
+        self.locked_utxos
+            .retain(|outpoint, _| graph.outspends(*outpoint).is_empty());
+        self.locked_utxos.insert(outpoint, expires_at);
+    }
+
+    /// Locks the UTXO at `outpoint` for `ttl`, see [`lock_utxo_until`].
+    ///
+    /// [`lock_utxo_until`]: Self::lock_utxo_until
+    #[cfg(feature = "std")]
+    pub fn lock_utxo(&mut self, outpoint: OutPoint, ttl: Duration) {
+        self.lock_utxo_until(outpoint, unix_now().saturating_add(ttl.as_secs()));
+    }
+
+    /// Releases the lock on the UTXO at `outpoint`. Returns whether it was locked.
+    pub fn unlock_utxo(&mut self, outpoint: OutPoint) -> bool {
+        self.locked_utxos.remove(&outpoint).is_some()
+    }
+
+    /// Releases the locks that expired at the unix timestamp `now` (in seconds).
+    pub fn release_expired_utxo_locks(&mut self, now: u64) {
+        self.locked_utxos.retain(|_, expires_at| *expires_at > now);
+    }
+
+    /// Lock the UTXOs selected as inputs of every transaction created from now on for `ttl`,
+    /// [`DEFAULT_UTXO_LOCK_TTL`] by default.
+    ///
+    /// A locked UTXO is skipped by coin selection until the transaction is broadcast or
+    /// cancelled, so building several candidate transactions from the same UTXOs, e.g. to compare
+    /// their fees, requires a [`cancel_tx`] between each of them. Passing `None` disables it. See
+    /// [`lock_utxo_until`].
+    ///
+    /// [`cancel_tx`]: Self::cancel_tx
+    /// [`lock_utxo_until`]: Self::lock_utxo_until
+    #[cfg(feature = "std")]
+    pub fn set_utxo_lock_ttl(&mut self, ttl: Option<Duration>) {
+        self.utxo_lock_ttl = ttl;
+    }
+
+    /// Get the time the inputs of a created transaction stay locked, see [`set_utxo_lock_ttl`].
+    ///
+    /// [`set_utxo_lock_ttl`]: Self::set_utxo_lock_ttl
+    #[cfg(feature = "std")]
+    pub fn utxo_lock_ttl(&self) -> Option<Duration> {
+        self.utxo_lock_ttl
+    }
+
+    /// Iterate over the locked UTXOs together with the unix timestamp (in seconds) their lock
+    /// expires at.
+    ///
+    /// Expired locks (with the `std` feature) and the locks of UTXOs that have since been spent
+    /// are not returned.
+    pub fn locked_utxos(&self) -> impl Iterator<Item = (OutPoint, u64)> + '_ {
+        self.locked_utxos
+            .keys()
+            .filter(move |&&outpoint| self.is_utxo_locked(outpoint))
+            .map(move |outpoint| (*outpoint, self.locked_utxos[outpoint]))
+    }
+
+    /// Whether the UTXO at `outpoint` holds a lock that hasn't expired and isn't spent yet.
+    ///
+    /// Without the `std` feature there is no clock, so a lock holds until it is released.
+    fn is_utxo_locked(&self, outpoint: OutPoint) -> bool {
+        match self.locked_utxos.get(&outpoint) {
+            #[cfg(feature = "std")]
+            Some(&expires_at) if expires_at <= unix_now() => false,
+            Some(_) => self.indexed_graph.graph().outspends(outpoint).is_empty(),
+            None => false,
+        }
+    }
+
+    /// Informs the wallet that you no longer intend to finalize and broadcast `psbt`.
+    ///
+    /// This is equivalent to calling [`cancel_tx`] with the PSBT's unsigned transaction and touches
+    /// exactly the same state, so it is safe to call for a PSBT that was never persisted.
+    ///
+    /// [`cancel_tx`]: Self::cancel_tx
+    pub fn cancel_psbt(&mut self, psbt: &Psbt) {
+        self.cancel_tx(&psbt.unsigned_tx)
     }
 
     fn get_descriptor_for_txout(&self, txout: &TxOut) -> Option<DerivedDescriptor> {
@@ -1964,7 +3735,7 @@ impl Wallet {
             .indexed_graph
             .index
             .index_of_spk(&txout.script_pubkey)?;
-        let descriptor = self.get_descriptor_for_keychain(keychain);
+        let descriptor = self.known_descriptor(keychain);
         descriptor.at_derivation_index(child).ok()
     }
 
@@ -1973,7 +3744,7 @@ impl Wallet {
             .map(|utxo| {
                 let keychain = utxo.keychain;
                 (utxo, {
-                    self.get_descriptor_for_keychain(keychain)
+                    self.known_descriptor(keychain)
                         .max_weight_to_satisfy()
                         .unwrap()
                         .to_wu() as usize
@@ -1982,6 +3753,104 @@ impl Wallet {
             .collect()
     }
 
+    /// Attach the output labels of the transactions created by this wallet that are added by
+    /// `changeset`, staging them in `changeset`.
+    ///
+    /// The labels are remembered by the txid of the unsigned transaction, as the txid of a
+    /// transaction spending non-segwit outputs changes once it's signed.
+    #[cfg(feature = "labels")]
+    fn attach_pending_labels(&mut self, changeset: &mut ChangeSet) {
+        if self.pending_labels.is_empty() {
+            return;
+        }
+        for tx in &changeset.indexed_tx_graph.graph.txs {
+            if let Some(labels) = self.pending_labels.remove(&unsigned_txid(tx)) {
+                let txid = tx.compute_txid();
+                for (vout, label) in labels {
+                    let outpoint = OutPoint::new(txid, vout);
+                    changeset.labels.append(self.labels.insert(outpoint, label));
+                }
+            }
+        }
+    }
+
+    /// The spending requirements of the keychains whose utxos may be selected with `params`.
+    fn spending_requirements(&self, params: &TxParams) -> Result<Condition, CreateTxError> {
+        let keychains: BTreeMap<_, _> = self.indexed_graph.index.keychains().collect();
+        let external_descriptor = keychains.get(&KeychainKind::External).expect("must exist");
+        let internal_descriptor = keychains.get(&KeychainKind::Internal).expect("must exist");
+
+        let external_policy = external_descriptor
+            .extract_policy(&self.signers, BuildSatisfaction::None, &self.secp)?
+            .unwrap();
+        let internal_policy = internal_descriptor
+            .extract_policy(&self.change_signers, BuildSatisfaction::None, &self.secp)?
+            .unwrap();
+
+        // The policy allows spending external outputs, but it requires a policy path that hasn't been
+        // provided
+        if params.may_spend_from(KeychainKind::External, false)
+            && external_policy.requires_path()
+            && params.external_policy_path.is_none()
+        {
+            return Err(CreateTxError::SpendingPolicyRequired(
+                KeychainKind::External,
+            ));
+        };
+        // Same for the internal_policy path
+        if params.may_spend_from(KeychainKind::Internal, true)
+            && internal_policy.requires_path()
+            && params.internal_policy_path.is_none()
+        {
+            return Err(CreateTxError::SpendingPolicyRequired(
+                KeychainKind::Internal,
+            ));
+        };
+
+        let external_requirements = external_policy.get_condition(
+            params
+                .external_policy_path
+                .as_ref()
+                .unwrap_or(&BTreeMap::new()),
+        )?;
+        let internal_requirements = internal_policy.get_condition(
+            params
+                .internal_policy_path
+                .as_ref()
+                .unwrap_or(&BTreeMap::new()),
+        )?;
+
+        let mut requirements = external_requirements.merge(&internal_requirements)?;
+
+        // Same for the custom keychains, but only the ones that may be spent
+        for (&keychain, descriptor) in self.indexed_graph.index.keychains() {
+            let id = match keychain {
+                KeychainKind::Custom(id)
+                    if params.may_spend_from(keychain, self.is_change_keychain(keychain)) =>
+                {
+                    id
+                }
+                _ => continue,
+            };
+            let policy = descriptor
+                .extract_policy(
+                    &self.get_signers(keychain),
+                    BuildSatisfaction::None,
+                    &self.secp,
+                )?
+                .unwrap();
+            let policy_path = params.custom_policy_paths.get(&id);
+            if policy.requires_path() && policy_path.is_none() {
+                return Err(CreateTxError::SpendingPolicyRequired(keychain));
+            }
+            let custom_requirements =
+                policy.get_condition(policy_path.unwrap_or(&BTreeMap::new()))?;
+            requirements = requirements.merge(&custom_requirements)?;
+        }
+
+        Ok(requirements)
+    }
+
     /// Given the options returns the list of utxos that must be used to form the
     /// transaction and any further that may be used if needed.
     fn preselect_utxos(
@@ -1990,12 +3859,12 @@ impl Wallet {
         current_height: Option<u32>,
     ) -> (Vec<WeightedUtxo>, Vec<WeightedUtxo>) {
         let TxParams {
-            change_policy,
             unspendable,
             utxos,
             drain_wallet,
             manually_selected_only,
             bumping_fee,
+            allow_respending_unconfirmed,
             ..
         } = params;
 
@@ -2064,10 +3933,25 @@ impl Wallet {
             })
             .collect::<Vec<_>>();
 
+        // An output that is unspent in the best chain can still be spent by an unconfirmed
+        // transaction that lost a conflict, which may nevertheless end up being mined. Spenders
+        // that conflict with a confirmed transaction or were evicted from the mempool can't be.
+        let spent_by_unconfirmed = |outpoint: OutPoint| -> bool {
+            let graph = self.indexed_graph.graph();
+            graph.outspends(outpoint).iter().any(|&txid| {
+                matches!(
+                    graph.canonicalization_reason(&self.chain, chain_tip, txid),
+                    None | Some(NonCanonicalReason::ConflictUnconfirmed { .. })
+                )
+            })
+        };
+
         let mut i = 0;
         may_spend.retain(|u| {
-            let retain = change_policy.is_satisfied_by(&u.0)
+            let retain = params.may_spend_from(u.0.keychain, self.is_change_keychain(u.0.keychain))
                 && !unspendable.contains(&u.0.outpoint)
+                && !self.is_utxo_locked(u.0.outpoint)
+                && (*allow_respending_unconfirmed || !spent_by_unconfirmed(u.0.outpoint))
                 && satisfies_confirmed[i];
             i += 1;
             retain
@@ -2129,17 +4013,29 @@ impl Wallet {
 
             match utxo {
                 Utxo::Local(utxo) => {
-                    *psbt_input =
-                        match self.get_psbt_input(utxo, params.sighash, params.only_witness_utxo) {
-                            Ok(psbt_input) => psbt_input,
-                            Err(e) => match e {
-                                CreateTxError::UnknownUtxo => psbt::Input {
-                                    sighash_type: params.sighash,
-                                    ..psbt::Input::default()
-                                },
-                                _ => return Err(e),
-                            },
+                    let sighash = match params.sighashes.get(&utxo.outpoint) {
+                        Some(&sighash) => {
+                            if !is_valid_sighash(sighash, utxo.txout.script_pubkey.is_p2tr()) {
+                                return Err(CreateTxError::InvalidSighash {
+                                    outpoint: utxo.outpoint,
+                                    sighash,
+                                });
+                            }
+                            Some(sighash)
                         }
+                        None => params.sighash,
+                    };
+                    *psbt_input = match self.get_psbt_input(utxo, sighash, params.only_witness_utxo)
+                    {
+                        Ok(psbt_input) => psbt_input,
+                        Err(e) => match e {
+                            CreateTxError::UnknownUtxo => psbt::Input {
+                                sighash_type: sighash,
+                                ..psbt::Input::default()
+                            },
+                            _ => return Err(e),
+                        },
+                    }
                 }
                 Utxo::Foreign {
                     outpoint,
@@ -2158,6 +4054,12 @@ impl Wallet {
                         return Err(CreateTxError::MissingNonWitnessUtxo(outpoint));
                     }
                     *psbt_input = *foreign_psbt_input;
+                    if let Some(&sighash) = params.sighashes.get(&outpoint) {
+                        if !is_valid_sighash(sighash, is_taproot) {
+                            return Err(CreateTxError::InvalidSighash { outpoint, sighash });
+                        }
+                        psbt_input.sighash_type = Some(sighash);
+                    }
                 }
             }
         }
@@ -2187,7 +4089,7 @@ impl Wallet {
             ..psbt::Input::default()
         };
 
-        let desc = self.get_descriptor_for_keychain(keychain);
+        let desc = self.known_descriptor(keychain);
         let derived_descriptor = desc
             .at_derivation_index(child)
             .expect("child can't be hardened");
@@ -2227,7 +4129,7 @@ impl Wallet {
             if let Some(&(keychain, child)) =
                 self.indexed_graph.index.index_of_spk(&out.script_pubkey)
             {
-                let desc = self.get_descriptor_for_keychain(keychain);
+                let desc = self.known_descriptor(keychain);
                 let desc = desc
                     .at_derivation_index(child)
                     .expect("child can't be hardened");
@@ -2245,16 +4147,13 @@ impl Wallet {
         Ok(())
     }
 
-    /// Return the checksum of the public descriptor associated to `keychain`
+    /// Return the checksum of the public descriptor associated to `keychain`, or `None` if the
+    /// keychain is not part of the wallet
     ///
     /// Internally calls [`Self::get_descriptor_for_keychain`] to fetch the right descriptor
-    pub fn descriptor_checksum(&self, keychain: KeychainKind) -> String {
-        self.get_descriptor_for_keychain(keychain)
-            .to_string()
-            .split_once('#')
-            .unwrap()
-            .1
-            .to_string()
+    pub fn descriptor_checksum(&self, keychain: KeychainKind) -> Option<String> {
+        let descriptor = self.get_descriptor_for_keychain(keychain)?.to_string();
+        Some(descriptor.split_once('#').unwrap().1.to_string())
     }
 
     /// Applies an update to the wallet and stages the changes (but does not persist them).
@@ -2266,8 +4165,16 @@ impl Wallet {
     /// to persist staged wallet changes see [`Wallet::reveal_next_address`]. `
     ///
     /// [`commit`]: Self::commit
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn apply_update(&mut self, update: impl Into<Update>) -> Result<(), CannotConnectError> {
         let update = update.into();
+        log_event!(
+            debug,
+            chain_update = update.chain.is_some(),
+            txs = update.graph.full_txs().count(),
+            anchors = update.graph.all_anchors().len(),
+            "applying update"
+        );
         let mut changeset = match update.chain {
             Some(chain_update) => ChangeSet::from(self.chain.apply_update(chain_update)?),
             None => ChangeSet::default(),
@@ -2279,7 +4186,11 @@ impl Wallet {
             .reveal_to_target_multi(&update.last_active_indices);
         changeset.append(index_changeset.into());
         changeset.append(self.indexed_graph.apply_update(update.graph).into());
+        #[cfg(feature = "labels")]
+        self.attach_pending_labels(&mut changeset);
+        log_event!(debug, changed = !changeset.is_empty(), "applied update");
         self.stage.append(changeset);
+        self.balance_cache.invalidate();
         Ok(())
     }
 
@@ -2290,14 +4201,56 @@ impl Wallet {
     /// # Error
     ///
     /// Returns a backend-defined error if this fails.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn commit_to<B>(&mut self, persist_backend: &mut B) -> Result<bool, B::WriteError>
     where
         B: PersistBackend<ChangeSet>,
     {
         let committed = StageExt::commit_to(&mut self.stage, persist_backend)?;
+        log_event!(
+            debug,
+            persisted = committed.is_some(),
+            "committed staged changes"
+        );
+        #[cfg(feature = "std")]
+        if committed.is_some() {
+            self.last_commit = Some(std::time::Instant::now());
+        }
         Ok(committed.is_some())
     }
 
+    /// Commits all currently [`staged`](Wallet::staged) changes to the `persist_backend`, unless
+    /// the previous commit happened less than `min_interval` ago.
+    ///
+    /// This is meant to be called after every update in a busy sync loop, to write changes in
+    /// fewer, larger batches. Changes that are not committed stay staged, so they are written by
+    /// the next commit. Until then they are lost if the process exits, so make sure to call
+    /// [`commit_to`] before shutting down, or whenever the changes must be durable.
+    ///
+    /// This returns whether anything was persisted.
+    ///
+    /// # Error
+    ///
+    /// Returns a backend-defined error if this fails.
+    ///
+    /// [`commit_to`]: Self::commit_to
+    #[cfg(feature = "std")]
+    pub fn commit_to_debounced<B>(
+        &mut self,
+        persist_backend: &mut B,
+        min_interval: core::time::Duration,
+    ) -> Result<bool, B::WriteError>
+    where
+        B: PersistBackend<ChangeSet>,
+    {
+        if let Some(last_commit) = self.last_commit {
+            if last_commit.elapsed() < min_interval {
+                return Ok(false);
+            }
+        }
+        self.commit_to(persist_backend)
+    }
+
     /// Commits all currently [`staged`](Wallet::staged) changes to the async `persist_backend`.
     ///
     /// This returns whether anything was persisted.
@@ -2315,6 +4268,10 @@ impl Wallet {
     {
         let committed =
             bdk_chain::persist::StageExtAsync::commit_to(&mut self.stage, persist_backend).await?;
+        #[cfg(feature = "std")]
+        if committed.is_some() {
+            self.last_commit = Some(std::time::Instant::now());
+        }
         Ok(committed.is_some())
     }
 
@@ -2372,6 +4329,11 @@ impl Wallet {
     /// [`LocalChain`]. Relevant transactions are filtered from the `block` and inserted into the
     /// internal [`TxGraph`].
     ///
+    /// If `block` replaces a block previously applied at the same height (a reorg), the replaced
+    /// block and all blocks above it are removed from the chain. The anchors pointing to them stay
+    /// in the [`TxGraph`] but no longer point to the best chain, so transactions that aren't
+    /// confirmed again by `block` (or later blocks) become unconfirmed.
+    ///
     /// **WARNING**: You must persist the changes resulting from one or more calls to this method
     /// if you need the inserted block data to be reloaded after closing the wallet.
     /// See [`Wallet::reveal_next_address`].
@@ -2392,7 +4354,10 @@ impl Wallet {
                 .apply_block_relevant(block, height)
                 .into(),
         );
+        #[cfg(feature = "labels")]
+        self.attach_pending_labels(&mut changeset);
         self.stage.append(changeset);
+        self.balance_cache.invalidate();
         Ok(())
     }
 
@@ -2412,10 +4377,75 @@ impl Wallet {
         &mut self,
         unconfirmed_txs: impl IntoIterator<Item = (&'t Transaction, u64)>,
     ) {
+        let mut changeset = ChangeSet::from(
+            self.indexed_graph
+                .batch_insert_relevant_unconfirmed(unconfirmed_txs),
+        );
+        #[cfg(feature = "labels")]
+        self.attach_pending_labels(&mut changeset);
+        self.stage.append(changeset);
+        self.balance_cache.invalidate();
+    }
+
+    /// Import transactions obtained outside of a chain source, e.g. exported from another tool,
+    /// as unconfirmed transactions.
+    ///
+    /// This can be used to bootstrap a wallet without syncing it. Raw transactions can be decoded
+    /// with [`bitcoin::consensus::encode::deserialize_hex`]. Like with [`apply_unconfirmed_txs`],
+    /// transactions that neither pay to nor spend from the wallet are filtered out. Script pubkeys
+    /// of the wallet found in the transactions are revealed, along with the ones below them, so
+    /// the next address handed out is above the highest used one. A later sync will confirm the
+    /// transactions or replace them.
+    ///
+    /// The returned [`ChangeSet`] is also staged. You must persist it if you need the imported
+    /// transactions to be reloaded after closing the wallet.
+    ///
+    /// [`apply_unconfirmed_txs`]: Self::apply_unconfirmed_txs
+    pub fn import_transactions(&mut self, txs: impl IntoIterator<Item = Transaction>) -> ChangeSet {
+        let txs = txs.into_iter().collect::<Vec<_>>();
+        // stage the import on its own so it can be returned, then merge it back
+        let staged = core::mem::take(&mut self.stage);
+        self.apply_unconfirmed_txs(txs.iter().map(|tx| (tx, 0)));
+        let changeset = core::mem::replace(&mut self.stage, staged);
+        self.stage.append(changeset.clone());
+        changeset
+    }
+
+    /// Apply relevant unconfirmed transactions to the wallet, like [`apply_unconfirmed_txs`], and
+    /// return the incoming payments among them.
+    ///
+    /// An incoming payment is a transaction that wasn't known to the wallet before, pays to at
+    /// least one of the wallet's script pubkeys and doesn't spend any of the wallet's outputs. It
+    /// is returned with the total amount paid to the wallet. This can be used to notify the user
+    /// that a payment was detected before it confirms.
+    ///
+    /// [`apply_unconfirmed_txs`]: Self::apply_unconfirmed_txs
+    pub fn apply_unconfirmed_txs_with_incoming<'t>(
+        &mut self,
+        unconfirmed_txs: impl IntoIterator<Item = (&'t Transaction, u64)>,
+    ) -> Vec<(Txid, Amount)> {
         let indexed_graph_changeset = self
             .indexed_graph
             .batch_insert_relevant_unconfirmed(unconfirmed_txs);
-        self.stage.append(indexed_graph_changeset.into());
+        let incoming = indexed_graph_changeset
+            .graph
+            .txs
+            .iter()
+            .filter_map(|tx| {
+                let (sent, received) = self.sent_and_received(tx);
+                if sent == Amount::ZERO && received > Amount::ZERO {
+                    Some((tx.compute_txid(), received))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let mut changeset = ChangeSet::from(indexed_graph_changeset);
+        #[cfg(feature = "labels")]
+        self.attach_pending_labels(&mut changeset);
+        self.stage.append(changeset);
+        self.balance_cache.invalidate();
+        incoming
     }
 }
 
@@ -2431,6 +4461,19 @@ impl Wallet {
             .populate_with_revealed_spks(&self.indexed_graph.index, ..)
     }
 
+    /// Create a partial [`SyncRequest`] for an explicit list of `spks`.
+    ///
+    /// This allows re-scanning the history of a few scripts without syncing all revealed spks,
+    /// for example to check a single address a payment was reportedly sent to. The `spks` don't
+    /// need to belong to this wallet's keychains. The resulting update is applied with
+    /// [`apply_update`] as usual, transactions that aren't relevant to the wallet won't affect its
+    /// balance or utxos.
+    ///
+    /// [`apply_update`]: Self::apply_update
+    pub fn start_sync_with_spks(&self, spks: Vec<ScriptBuf>) -> SyncRequest {
+        SyncRequest::from_chain_tip(self.chain.tip()).set_spks(spks)
+    }
+
     /// Create a [`FullScanRequest] for this wallet.
     ///
     /// This is the first step when performing a spk-based wallet full scan, the returned
@@ -2481,6 +4524,49 @@ where
     Ok(wallet_name)
 }
 
+/// Whether `sighash` can be used to sign a taproot input if `is_taproot`, or an ECDSA one otherwise.
+fn is_valid_sighash(sighash: psbt::PsbtSighashType, is_taproot: bool) -> bool {
+    if is_taproot {
+        sighash.taproot_hash_ty().is_ok()
+    } else {
+        sighash.ecdsa_hash_ty().is_ok()
+    }
+}
+
+/// Whether `sighash` commits to every input and output of the transaction, or only to every output
+/// with `allow_anyone_can_pay`.
+///
+/// `SIGHASH_NONE` commits to no output and `SIGHASH_SINGLE` only to the output at the index of the
+/// input, so another party could redirect the funds of the other outputs, e.g. the change.
+fn is_safe_sighash(sighash: psbt::PsbtSighashType, allow_anyone_can_pay: bool) -> bool {
+    match sighash.taproot_hash_ty() {
+        Ok(TapSighashType::Default) | Ok(TapSighashType::All) => true,
+        Ok(TapSighashType::AllPlusAnyoneCanPay) => allow_anyone_can_pay,
+        _ => false,
+    }
+}
+
+/// The txid of `tx` once its inputs are stripped of their signatures, i.e. the txid it had when
+/// it was created by the wallet.
+#[cfg(feature = "labels")]
+fn unsigned_txid(tx: &Transaction) -> Txid {
+    let mut unsigned_tx = tx.clone();
+    for txin in &mut unsigned_tx.input {
+        txin.script_sig = ScriptBuf::new();
+        txin.witness = Witness::new();
+    }
+    unsigned_tx.compute_txid()
+}
+
+/// The current unix timestamp in seconds.
+#[cfg(feature = "std")]
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
 fn new_local_utxo(
     keychain: KeychainKind,
     derivation_index: u32,
@@ -2558,7 +4644,7 @@ macro_rules! doctest_wallet {
             Network::Regtest,
         )
         .unwrap();
-        let address = wallet.peek_address(KeychainKind::External, 0).address;
+        let address = wallet.peek_address(KeychainKind::External, 0).unwrap().address;
         let tx = Transaction {
             version: transaction::Version::ONE,
             lock_time: absolute::LockTime::ZERO,
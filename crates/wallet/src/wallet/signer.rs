@@ -93,7 +93,7 @@ use bitcoin::secp256k1::Message;
 use bitcoin::sighash::{EcdsaSighashType, TapSighash, TapSighashType};
 use bitcoin::{ecdsa, psbt, sighash, taproot, transaction};
 use bitcoin::{key::TapTweak, key::XOnlyPublicKey, secp256k1};
-use bitcoin::{PrivateKey, Psbt, PublicKey};
+use bitcoin::{OutPoint, PrivateKey, Psbt, PublicKey, ScriptBuf, Txid};
 
 use miniscript::descriptor::{
     Descriptor, DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey, DescriptorXKey,
@@ -151,11 +151,12 @@ pub enum SignerError {
     MissingWitnessScript,
     /// The fingerprint and derivation path are missing from the psbt input
     MissingHdKeypath,
-    /// The psbt contains a non-`SIGHASH_ALL` sighash in one of its input and the user hasn't
-    /// explicitly allowed them
+    /// The psbt contains a sighash other than `SIGHASH_ALL` in one of its inputs and the user
+    /// hasn't explicitly allowed them
     ///
     /// To enable signing transactions with non-standard sighashes set
-    /// [`SignOptions::allow_all_sighashes`] to `true`.
+    /// [`SignOptions::allow_all_sighashes`] (or [`SignOptions::allow_anyone_can_pay`] for
+    /// `SIGHASH_ALL | SIGHASH_ANYONECANPAY`) to `true`.
     NonStandardSighash,
     /// Invalid SIGHASH for the signing context in use
     InvalidSighash,
@@ -167,6 +168,17 @@ pub enum SignerError {
     TxInputsIndexError(transaction::InputsIndexError),
     /// Miniscript PSBT error
     MiniscriptPsbt(MiniscriptPsbtError),
+    /// The taproot internal key tweaked with the input's merkle root doesn't match the output key
+    /// of the UTXO being spent
+    TaprootOutputKeyMismatch,
+    /// An input spends an output that is already spent by a confirmed transaction, so the
+    /// transaction can never confirm
+    InputAlreadySpent {
+        /// The output spent by the input
+        outpoint: OutPoint,
+        /// The confirmed transaction spending `outpoint`
+        by_txid: Txid,
+    },
     /// To be used only by external libraries implementing [`InputSigner`] or
     /// [`TransactionSigner`], so that they can return their own custom errors, without having to
     /// modify [`SignerError`] in BDK.
@@ -209,6 +221,8 @@ impl fmt::Display for SignerError {
             Self::SighashTaproot(err) => write!(f, "Error while computing the hash to sign a Taproot input: {}", err),
             Self::TxInputsIndexError(err) => write!(f, "Error while computing the hash, out of bounds access on the transaction inputs: {}", err),
             Self::MiniscriptPsbt(err) => write!(f, "Miniscript PSBT error: {}", err),
+            Self::TaprootOutputKeyMismatch => write!(f, "The tweaked taproot internal key doesn't match the output key being spent"),
+            Self::InputAlreadySpent { outpoint, by_txid } => write!(f, "Input {} is already spent by confirmed transaction {}", outpoint, by_txid),
             Self::External(err) => write!(f, "{}", err),
         }
     }
@@ -311,6 +325,9 @@ impl<T: InputSigner> TransactionSigner for T {
         secp: &SecpCtx,
     ) -> Result<(), SignerError> {
         for input_index in 0..psbt.inputs.len() {
+            if !sign_options.signs_input(input_index) {
+                continue;
+            }
             self.sign_input(psbt, input_index, sign_options, secp)?;
         }
 
@@ -481,6 +498,18 @@ impl InputSigner for SignerWrapper<PrivateKey> {
                     && sign_options.sign_with_tap_internal_key
                     && x_only_pubkey == psbt_internal_key
                 {
+                    // The key-path signature commits to the internal key tweaked with the merkle
+                    // root of the script tree (if any). Make sure that's the key actually locking
+                    // the UTXO, otherwise we'd produce a signature the network would reject.
+                    if let Some(utxo) = psbt.get_utxo_for(input_index) {
+                        let merkle_root = psbt.inputs[input_index].tap_merkle_root;
+                        let expected_spk =
+                            ScriptBuf::new_p2tr(secp, psbt_internal_key, merkle_root);
+                        if utxo.script_pubkey != expected_spk {
+                            return Err(SignerError::TaprootOutputKeyMismatch);
+                        }
+                    }
+
                     let (hash, hash_ty) = Tap::sighash(psbt, input_index, None)?;
                     sign_psbt_schnorr(
                         &self.inner,
@@ -798,9 +827,21 @@ pub struct SignOptions {
     /// Whether the signer should use the `sighash_type` set in the PSBT when signing, no matter
     /// what its value is
     ///
-    /// Defaults to `false` which will only allow signing using `SIGHASH_ALL`.
+    /// Defaults to `false` which will only allow signing using `SIGHASH_ALL` (or `SIGHASH_DEFAULT`
+    /// for taproot), see also [`allow_anyone_can_pay`]. A sighash that isn't valid for the type of
+    /// the input is never allowed.
+    ///
+    /// [`allow_anyone_can_pay`]: Self::allow_anyone_can_pay
     pub allow_all_sighashes: bool,
 
+    /// Whether to also allow signing with `SIGHASH_ALL | SIGHASH_ANYONECANPAY`, which commits to
+    /// every output but lets other parties add inputs, e.g. to crowdfund a transaction.
+    ///
+    /// Defaults to `false`. It has no effect if [`allow_all_sighashes`] is set.
+    ///
+    /// [`allow_all_sighashes`]: Self::allow_all_sighashes
+    pub allow_anyone_can_pay: bool,
+
     /// Whether to remove partial signatures from the PSBT inputs while finalizing PSBT.
     ///
     /// Defaults to `true` which will remove partial signatures during finalization.
@@ -837,6 +878,35 @@ pub struct SignOptions {
     /// or not.
     /// Defaults to `true`, i.e., we always grind ECDSA signature to sign with low r.
     pub allow_grinding: bool,
+
+    /// The indices of the inputs to sign, leaving the other inputs untouched.
+    ///
+    /// When set, the PSBT is only finalized if every input can be finalized, so that no input is
+    /// finalized while other parties may still have to sign theirs. The inputs that aren't
+    /// selected are also exempt from the `trust_witness_utxo` and `allow_all_sighashes` checks.
+    ///
+    /// Signers implementing [`TransactionSigner`] directly (rather than [`InputSigner`]) are
+    /// responsible for honoring this option themselves.
+    ///
+    /// Defaults to `None`, i.e., the wallet signs every input it can.
+    pub sign_inputs: Option<Vec<usize>>,
+
+    /// Whether to sign a PSBT spending an output that a confirmed transaction of the wallet
+    /// already spends.
+    ///
+    /// Defaults to `false`, i.e., signing fails with [`SignerError::InputAlreadySpent`], since
+    /// such a PSBT can never confirm unless the spending transaction is reorged out.
+    pub allow_spent_inputs: bool,
+}
+
+impl SignOptions {
+    /// Whether the input at `input_index` should be signed.
+    pub(crate) fn signs_input(&self, input_index: usize) -> bool {
+        match &self.sign_inputs {
+            Some(indices) => indices.contains(&input_index),
+            None => true,
+        }
+    }
 }
 
 /// Customize which taproot script-path leaves the signer should sign.
@@ -860,12 +930,15 @@ impl Default for SignOptions {
             trust_witness_utxo: false,
             assume_height: None,
             allow_all_sighashes: false,
+            allow_anyone_can_pay: false,
             remove_partial_sigs: true,
             remove_taproot_extras: true,
             try_finalize: true,
             tap_leaves_options: TapLeavesOptions::default(),
             sign_with_tap_internal_key: true,
             allow_grinding: true,
+            sign_inputs: None,
+            allow_spent_inputs: false,
         }
     }
 }
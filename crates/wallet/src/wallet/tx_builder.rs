@@ -42,14 +42,19 @@ use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
 use core::cell::RefCell;
 use core::fmt;
 
+use bdk_chain::FeeEstimator;
+use bitcoin::amount::ParseAmountError;
 use bitcoin::psbt::{self, Psbt};
 use bitcoin::script::PushBytes;
-use bitcoin::{absolute, Amount, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, Txid};
+use bitcoin::{
+    absolute, Address, Amount, Denomination, FeeRate, Network, OutPoint, ScriptBuf, Sequence,
+    Transaction, Txid, Weight,
+};
 
 use super::coin_selection::CoinSelectionAlgorithm;
 use super::{CreateTxError, Wallet};
-use crate::collections::{BTreeMap, HashSet};
-use crate::{KeychainKind, LocalOutput, Utxo, WeightedUtxo};
+use crate::collections::{BTreeMap, BTreeSet, HashSet};
+use crate::{KeychainKind, Utxo, WeightedUtxo};
 
 /// A transaction builder
 ///
@@ -69,6 +74,7 @@ use crate::{KeychainKind, LocalOutput, Utxo, WeightedUtxo};
 /// # use bdk_wallet::wallet::error::CreateTxError;
 /// # use anyhow::Error;
 /// # let mut wallet = doctest_wallet!();
+/// # wallet.set_utxo_lock_ttl(None);
 /// # let addr1 = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt").unwrap().assume_checked();
 /// # let addr2 = addr1.clone();
 /// // chaining
@@ -118,9 +124,11 @@ pub(crate) struct TxParams {
     pub(crate) recipients: Vec<(ScriptBuf, u64)>,
     pub(crate) drain_wallet: bool,
     pub(crate) drain_to: Option<ScriptBuf>,
+    pub(crate) change_index: Option<(KeychainKind, u32)>,
     pub(crate) fee_policy: Option<FeePolicy>,
     pub(crate) internal_policy_path: Option<BTreeMap<String, Vec<usize>>>,
     pub(crate) external_policy_path: Option<BTreeMap<String, Vec<usize>>>,
+    pub(crate) custom_policy_paths: BTreeMap<u32, BTreeMap<String, Vec<usize>>>,
     pub(crate) utxos: Vec<WeightedUtxo>,
     pub(crate) unspendable: HashSet<OutPoint>,
     pub(crate) manually_selected_only: bool,
@@ -130,12 +138,39 @@ pub(crate) struct TxParams {
     pub(crate) rbf: Option<RbfValue>,
     pub(crate) version: Option<Version>,
     pub(crate) change_policy: ChangeSpendPolicy,
+    pub(crate) spend_keychains: Option<BTreeSet<KeychainKind>>,
     pub(crate) only_witness_utxo: bool,
     pub(crate) add_global_xpubs: bool,
     pub(crate) include_output_redeem_witness_script: bool,
     pub(crate) bumping_fee: Option<PreviousFee>,
     pub(crate) current_height: Option<absolute::LockTime>,
     pub(crate) allow_dust: bool,
+    pub(crate) allow_respending_unconfirmed: bool,
+    pub(crate) allow_spent_inputs: bool,
+    pub(crate) cpfp_reserve: Option<Amount>,
+    pub(crate) deterministic: bool,
+    pub(crate) max_fee_rate: Option<FeeRate>,
+    pub(crate) max_fee: Option<Amount>,
+    pub(crate) max_inputs: Option<usize>,
+    #[cfg(feature = "labels")]
+    pub(crate) output_labels: BTreeMap<ScriptBuf, String>,
+    #[cfg(feature = "labels")]
+    pub(crate) change_label: Option<String>,
+    pub(crate) sequences: BTreeMap<OutPoint, Sequence>,
+    pub(crate) sighashes: BTreeMap<OutPoint, psbt::PsbtSighashType>,
+    pub(crate) preserved_recipients: Option<Vec<(ScriptBuf, u64)>>,
+}
+
+impl TxParams {
+    /// Whether the utxos of `keychain` may be selected, given the keychains to spend from and the
+    /// change spend policy. `is_change` tells whether `keychain` holds the wallet's change.
+    pub(crate) fn may_spend_from(&self, keychain: KeychainKind, is_change: bool) -> bool {
+        let selected = match &self.spend_keychains {
+            Some(keychains) => keychains.contains(&keychain),
+            None => true,
+        };
+        selected && self.change_policy.allows(is_change)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -183,6 +218,26 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Set the fee rate to the one `estimator` gives for confirming within `target` blocks.
+    ///
+    /// This is the same as calling [`fee_rate`] with the estimate, so the estimator can be any
+    /// source of fee rates, e.g. a chain source or the estimates it returned earlier.
+    ///
+    /// Returns [`FeeRateForTargetError::Unavailable`] and leaves the fee policy unchanged if
+    /// `estimator` has no estimate for `target`.
+    ///
+    /// [`fee_rate`]: Self::fee_rate
+    pub fn fee_rate_for_target(
+        &mut self,
+        estimator: &impl FeeEstimator,
+        target: u16,
+    ) -> Result<&mut Self, FeeRateForTargetError> {
+        let fee_rate = estimator
+            .estimate(target)
+            .ok_or(FeeRateForTargetError::Unavailable { target })?;
+        Ok(self.fee_rate(fee_rate))
+    }
+
     /// Set an absolute fee
     /// The fee_absolute method refers to the absolute transaction fee in [`Amount`].
     /// If anyone sets both the `fee_absolute` method and the `fee_rate` method,
@@ -197,6 +252,33 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Set the maximum fee rate of the transaction.
+    ///
+    /// Building the transaction fails with [`CreateTxError::FeeRateTooHigh`] if the rate set with
+    /// [`fee_rate`] is higher. When bumping a fee with [`Wallet::build_fee_bump`] it also fails if
+    /// the minimum rate required by BIP125 to replace the original transaction is higher, which
+    /// caps the fees paid when repeatedly outbidding another transaction.
+    ///
+    /// This applies to the requested rate, which can be overshot slightly (see [`fee_rate`]).
+    /// Combine it with [`max_fee`] to also bound the fee actually paid.
+    ///
+    /// [`fee_rate`]: Self::fee_rate
+    /// [`max_fee`]: Self::max_fee
+    /// [`Wallet::build_fee_bump`]: crate::Wallet::build_fee_bump
+    pub fn max_fee_rate(&mut self, max_fee_rate: FeeRate) -> &mut Self {
+        self.params.max_fee_rate = Some(max_fee_rate);
+        self
+    }
+
+    /// Set the maximum absolute fee of the transaction.
+    ///
+    /// Building the transaction fails with [`CreateTxError::FeeTooHigh`] if the fee it pays, once
+    /// the coins are selected and the change is added or dropped, is higher.
+    pub fn max_fee(&mut self, max_fee: Amount) -> &mut Self {
+        self.params.max_fee = Some(max_fee);
+        self
+    }
+
     /// Set the policy path to use while creating the transaction for a given keychain.
     ///
     /// This method accepts a map where the key is the policy node id (see
@@ -264,12 +346,13 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         policy_path: BTreeMap<String, Vec<usize>>,
         keychain: KeychainKind,
     ) -> &mut Self {
-        let to_update = match keychain {
-            KeychainKind::Internal => &mut self.params.internal_policy_path,
-            KeychainKind::External => &mut self.params.external_policy_path,
+        match keychain {
+            KeychainKind::Internal => self.params.internal_policy_path = Some(policy_path),
+            KeychainKind::External => self.params.external_policy_path = Some(policy_path),
+            KeychainKind::Custom(id) => {
+                self.params.custom_policy_paths.insert(id, policy_path);
+            }
         };
-
-        *to_update = Some(policy_path);
         self
     }
 
@@ -292,7 +375,7 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
                 .collect::<Result<Vec<_>, _>>()?;
 
             for utxo in utxos {
-                let descriptor = wallet.get_descriptor_for_keychain(utxo.keychain);
+                let descriptor = wallet.known_descriptor(utxo.keychain);
 
                 let satisfaction_weight =
                     descriptor.max_weight_to_satisfy().unwrap().to_wu() as usize;
@@ -314,6 +397,44 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self.add_utxos(&[outpoint])
     }
 
+    /// Add the unspent wallet output paying `amount` to `address` to the internal list of utxos
+    /// that **must** be spent.
+    ///
+    /// This is useful to spend a payment tracked by its address rather than its outpoint. If
+    /// several unspent outputs match, one that hasn't already been added is picked. Use
+    /// [`manually_selected_only`] to spend only that output.
+    ///
+    /// Returns [`AddUtxoError::NoMatchingUtxo`] if the wallet has no such output.
+    ///
+    /// [`manually_selected_only`]: Self::manually_selected_only
+    pub fn add_utxo_by_address(
+        &mut self,
+        address: &Address,
+        amount: Amount,
+    ) -> Result<&mut Self, AddUtxoError> {
+        let script_pubkey = address.script_pubkey();
+        let outpoint = self
+            .wallet
+            .borrow()
+            .list_unspent()
+            .filter(|utxo| utxo.txout.script_pubkey == script_pubkey && utxo.txout.value == amount)
+            .map(|utxo| utxo.outpoint)
+            .find(|outpoint| {
+                !self
+                    .params
+                    .utxos
+                    .iter()
+                    .any(|wutxo| wutxo.utxo.outpoint() == *outpoint)
+            });
+        match outpoint {
+            Some(outpoint) => self.add_utxo(outpoint),
+            None => Err(AddUtxoError::NoMatchingUtxo {
+                script_pubkey,
+                amount,
+            }),
+        }
+    }
+
     /// Add a foreign UTXO i.e. a UTXO not owned by this wallet.
     ///
     /// At a minimum to add a foreign UTXO we need:
@@ -375,6 +496,15 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
     }
 
     /// Same as [add_foreign_utxo](TxBuilder::add_foreign_utxo) but allows to set the nSequence value.
+    ///
+    /// The input keeps `sequence` in the created transaction, regardless of [`enable_rbf`] or any
+    /// timelock requirement of the wallet's descriptors.
+    ///
+    /// Since a single input signaling RBF makes the whole transaction replaceable, a `sequence`
+    /// that signals RBF (see [`Sequence::is_rbf`]) also makes the wallet's own inputs signal it
+    /// when RBF was not enabled on the builder and no CSV sequence is required.
+    ///
+    /// [`enable_rbf`]: Self::enable_rbf
     pub fn add_foreign_utxo_with_sequence(
         &mut self,
         outpoint: OutPoint,
@@ -413,6 +543,22 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         Ok(self)
     }
 
+    /// Spend at most `max_inputs` utxos in the transaction.
+    ///
+    /// The utxos added by [`add_utxo`] and the ones that must be spent by [`drain_wallet`] count
+    /// against the limit, and creating the transaction fails with
+    /// [`CreateTxError::TooManyInputs`] if they exceed it. If the coin selection algorithm picks
+    /// too many inputs, the selection is repeated with only the optional utxos of the highest
+    /// effective value, failing with [`InsufficientFunds`] if those can't meet the target.
+    ///
+    /// [`add_utxo`]: Self::add_utxo
+    /// [`drain_wallet`]: Self::drain_wallet
+    /// [`InsufficientFunds`]: crate::wallet::coin_selection::Error::InsufficientFunds
+    pub fn max_inputs(&mut self, max_inputs: usize) -> &mut Self {
+        self.params.max_inputs = Some(max_inputs);
+        self
+    }
+
     /// Only spend utxos added by [`add_utxo`].
     ///
     /// The wallet will **not** add additional utxos to the transaction even if they are needed to
@@ -424,6 +570,21 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Whether utxos other than the ones added by [`add_utxo`] may be spent.
+    ///
+    /// This is `true` by default, and setting it to `false` is the same as
+    /// [`manually_selected_only`]. When bumping the fee with [`build_fee_bump`], this decides
+    /// whether confirmed utxos of the wallet may be added to pay for the higher fee when the
+    /// original inputs are not enough.
+    ///
+    /// [`add_utxo`]: Self::add_utxo
+    /// [`manually_selected_only`]: Self::manually_selected_only
+    /// [`build_fee_bump`]: Wallet::build_fee_bump
+    pub fn allow_additional_inputs(&mut self, allow: bool) -> &mut Self {
+        self.params.manually_selected_only = !allow;
+        self
+    }
+
     /// Replace the internal list of unspendable utxos with a new list
     ///
     /// It's important to note that the "must-be-spent" utxos added with [`TxBuilder::add_utxo`]
@@ -450,12 +611,46 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Sign the input spending `outpoint` with a specific sig hash, overriding the one set with
+    /// [`sighash`] for this input
+    ///
+    /// The sig hash must be valid for the type of the spent output, i.e. a [`TapSighashType`] for
+    /// taproot outputs and an [`EcdsaSighashType`] for the others, otherwise
+    /// [`CreateTxError::InvalidSighash`] is returned. It has no effect if `outpoint` isn't spent by
+    /// the transaction.
+    ///
+    /// **Use this option very carefully**, see [`SignOptions::allow_all_sighashes`].
+    ///
+    /// [`sighash`]: Self::sighash
+    /// [`TapSighashType`]: bitcoin::sighash::TapSighashType
+    /// [`EcdsaSighashType`]: bitcoin::sighash::EcdsaSighashType
+    /// [`SignOptions::allow_all_sighashes`]: crate::SignOptions::allow_all_sighashes
+    pub fn sighash_for(&mut self, outpoint: OutPoint, sighash: psbt::PsbtSighashType) -> &mut Self {
+        self.params.sighashes.insert(outpoint, sighash);
+        self
+    }
+
     /// Choose the ordering for inputs and outputs of the transaction
     pub fn ordering(&mut self, ordering: TxOrdering) -> &mut Self {
         self.params.ordering = ordering;
         self
     }
 
+    /// Build the transaction deterministically.
+    ///
+    /// The coin selection candidates are sorted by outpoint and selected with
+    /// [`CoinSelectionAlgorithm::coin_select_deterministic`], so the same wallet state and
+    /// parameters always produce the same transaction. If the ordering is left to the default
+    /// [`TxOrdering::Shuffle`], inputs and outputs are sorted with
+    /// [`TxOrdering::Bip69Lexicographic`] instead.
+    ///
+    /// This is meant for reproducible tests, as it gives up the privacy of randomized selection
+    /// and ordering.
+    pub fn deterministic(&mut self) -> &mut Self {
+        self.params.deterministic = true;
+        self
+    }
+
     /// Use a specific nLockTime while creating the transaction
     ///
     /// This can cause conflicts if the wallet's descriptors contain an "after" (OP_CLTV) operator.
@@ -491,6 +686,31 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Only spend outputs of `keychain`
+    ///
+    /// Coin selection is restricted to the utxos of `keychain`, so building the transaction fails
+    /// with [`InsufficientFunds`] if they can't cover it. This is a shortcut for
+    /// [`TxBuilder::spend_from_keychains`] with a single keychain.
+    ///
+    /// [`InsufficientFunds`]: super::coin_selection::Error::InsufficientFunds
+    pub fn spend_from_keychain(&mut self, keychain: KeychainKind) -> &mut Self {
+        self.spend_from_keychains([keychain])
+    }
+
+    /// Only spend outputs of the given `keychains`
+    ///
+    /// By default the utxos of every keychain of the wallet, including the [`KeychainKind::Custom`]
+    /// ones, can be selected. This restricts coin selection to the utxos of `keychains`, on top of
+    /// the [`ChangeSpendPolicy`]. The policy path of each keychain that may be spent is set with
+    /// [`TxBuilder::policy_path`].
+    pub fn spend_from_keychains(
+        &mut self,
+        keychains: impl IntoIterator<Item = KeychainKind>,
+    ) -> &mut Self {
+        self.params.spend_keychains = Some(keychains.into_iter().collect());
+        self
+    }
+
     /// Set a specific [`ChangeSpendPolicy`]. See [`TxBuilder::do_not_spend_change`] and
     /// [`TxBuilder::only_spend_change`] for some shortcuts.
     pub fn change_policy(&mut self, change_policy: ChangeSpendPolicy) -> &mut Self {
@@ -566,6 +786,31 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Set the nSequence of the input spending `outpoint`.
+    ///
+    /// The inputs without an explicit nSequence use the one derived from [`enable_rbf`],
+    /// [`enable_rbf_with_sequence`], the wallet's OP_CSV requirement and the nLockTime, so this
+    /// allows e.g. making a single input signal RBF while the others are final, or giving an input
+    /// a longer relative timelock than the others. It also overrides the nSequence given to
+    /// [`add_foreign_utxo_with_sequence`]. It has no effect if `outpoint` isn't spent by the
+    /// transaction.
+    ///
+    /// When creating the transaction:
+    ///
+    /// 1. If the wallet's descriptors require an OP_CSV, the nSequence set for one of the wallet's
+    ///    own utxos must satisfy it, otherwise [`CreateTxError::SequenceCsv`] is returned.
+    /// 2. If RBF is enabled, at least one input must still signal it, otherwise
+    ///    [`CreateTxError::RbfNotSignaled`] is returned. If RBF is not enabled, an nSequence
+    ///    signaling it still makes the transaction replaceable.
+    ///
+    /// [`enable_rbf`]: Self::enable_rbf
+    /// [`enable_rbf_with_sequence`]: Self::enable_rbf_with_sequence
+    /// [`add_foreign_utxo_with_sequence`]: Self::add_foreign_utxo_with_sequence
+    pub fn set_sequence_for(&mut self, outpoint: OutPoint, sequence: Sequence) -> &mut Self {
+        self.params.sequences.insert(outpoint, sequence);
+        self
+    }
+
     /// Set the current blockchain height.
     ///
     /// This will be used to:
@@ -591,6 +836,50 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Allow coin selection to pick utxos that are already spent by an unconfirmed transaction
+    /// known to the wallet.
+    ///
+    /// By default such utxos are never selected automatically, even if the spending transaction
+    /// was replaced by an unconfirmed conflict, to avoid accidentally double-spending a
+    /// transaction that was broadcast earlier and may still be mined. Spending transactions that
+    /// conflict with a confirmed transaction or were evicted from the mempool are ignored. Use
+    /// this when the conflict is intended, i.e. to replace the unconfirmed transaction.
+    pub fn allow_respending_unconfirmed(&mut self) -> &mut Self {
+        self.params.allow_respending_unconfirmed = true;
+        self
+    }
+
+    /// Allow the transaction to spend utxos that a confirmed transaction known to the wallet
+    /// already spends.
+    ///
+    /// By default [`finish`](Self::finish) fails with [`CreateTxError::InputAlreadySpent`] in that
+    /// case, since such a transaction can never confirm unless the spending transaction is reorged
+    /// out. This only matters for utxos added with [`add_utxo`](Self::add_utxo) or
+    /// [`add_foreign_utxo`](Self::add_foreign_utxo), coin selection never picks spent utxos.
+    pub fn allow_spent_inputs(&mut self) -> &mut Self {
+        self.params.allow_spent_inputs = true;
+        self
+    }
+
+    /// Make sure the transaction has a change output worth at least `amount`, to be spent later
+    /// by a child transaction that bumps the fee with CPFP (child-pays-for-parent).
+    ///
+    /// The reserved amount is selected on top of the recipients and the fee, and any change is
+    /// added to the same output instead of creating a separate one. The output pays to the
+    /// wallet's change script pubkey (or to [`drain_to`], in which case the script pubkey must
+    /// belong to the wallet).
+    ///
+    /// [`finish`] fails with [`CreateTxError::OutputBelowDustLimit`] if `amount` is below the dust
+    /// limit, and with [`CreateTxError::CpfpReserveNotMine`] if the output would not belong to the
+    /// wallet.
+    ///
+    /// [`drain_to`]: Self::drain_to
+    /// [`finish`]: Self::finish
+    pub fn reserve_cpfp_output(&mut self, amount: Amount) -> &mut Self {
+        self.params.cpfp_reserve = Some(amount);
+        self
+    }
+
     /// Replace the recipients already added with a new list
     pub fn set_recipients(&mut self, recipients: Vec<(ScriptBuf, Amount)>) -> &mut Self {
         self.params.recipients = recipients
@@ -608,6 +897,92 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Add a recipient paying to `address` to the internal list
+    ///
+    /// Unlike [`add_recipient`], this checks that `address` is valid for the wallet's network and
+    /// returns [`AddRecipientError::WrongNetwork`] otherwise. An address of another network, for
+    /// example one marked as checked with [`Address::assume_checked`], would otherwise result in
+    /// an output paying to a script nobody controls on the wallet's network.
+    ///
+    /// [`add_recipient`]: Self::add_recipient
+    pub fn add_recipient_address(
+        &mut self,
+        address: &Address,
+        amount: Amount,
+    ) -> Result<&mut Self, AddRecipientError> {
+        let network = self.wallet.borrow().network();
+        if !address.as_unchecked().is_valid_for_network(network) {
+            return Err(AddRecipientError::WrongNetwork {
+                address: address.clone(),
+                network,
+            });
+        }
+        Ok(self.add_recipient(address.script_pubkey(), amount))
+    }
+
+    /// Add a recipient to the internal list, labeling the output paying to it
+    ///
+    /// The label is attached to the output of the transaction once it is applied to the wallet, for
+    /// example with [`Wallet::insert_tx`] or [`Wallet::apply_update`], and can then be read with
+    /// [`Wallet::output_label`]. If several outputs pay to `script_pubkey`, all of them get the
+    /// last label set for it.
+    ///
+    /// Only then is the label staged in the wallet's [`ChangeSet`] to be persisted. Until the
+    /// transaction is applied the label is only kept in memory: it is lost if the wallet is
+    /// dropped, and discarded by [`Wallet::cancel_tx`].
+    ///
+    /// [`ChangeSet`]: crate::wallet::ChangeSet
+    #[cfg(feature = "labels")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "labels")))]
+    pub fn add_recipient_with_label(
+        &mut self,
+        script_pubkey: ScriptBuf,
+        amount: Amount,
+        label: String,
+    ) -> &mut Self {
+        self.params
+            .output_labels
+            .insert(script_pubkey.clone(), label);
+        self.add_recipient(script_pubkey, amount)
+    }
+
+    /// Label the change output of the transaction, if there is one
+    ///
+    /// Like the labels of [`add_recipient_with_label`], it is attached once the transaction is
+    /// applied to the wallet. When bumping the fee of a transaction with a labeled change output
+    /// the label is carried over to the change output of the replacement.
+    ///
+    /// [`add_recipient_with_label`]: Self::add_recipient_with_label
+    #[cfg(feature = "labels")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "labels")))]
+    pub fn change_label(&mut self, label: String) -> &mut Self {
+        self.params.change_label = Some(label);
+        self
+    }
+
+    /// Add a recipient to the internal list, parsing `amount` from a denominated string
+    ///
+    /// The string must contain the value followed by its denomination, separated by whitespace,
+    /// e.g. `"0.001 BTC"` or `"1500 sat"`. Values with a missing or ambiguous denomination,
+    /// negative values and values with sub-satoshi precision are rejected.
+    pub fn add_recipient_str(
+        &mut self,
+        script_pubkey: ScriptBuf,
+        amount: &str,
+    ) -> Result<&mut Self, AddRecipientError> {
+        let mut parts = amount.split_whitespace();
+        let (value, denomination) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(value), Some(denomination), None) => denomination
+                .parse::<Denomination>()
+                .map(|denomination| (value, denomination))
+                .map_err(|_| AddRecipientError::InvalidDenomination(amount.into()))?,
+            _ => return Err(AddRecipientError::InvalidDenomination(amount.into())),
+        };
+        let amount =
+            Amount::from_str_in(value, denomination).map_err(AddRecipientError::InvalidAmount)?;
+        Ok(self.add_recipient(script_pubkey, amount))
+    }
+
     /// Add data as an output, using OP_RETURN
     pub fn add_data<T: AsRef<PushBytes>>(&mut self, data: &T) -> &mut Self {
         let script = ScriptBuf::new_op_return(data);
@@ -664,6 +1039,23 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self.params.drain_to = Some(script_pubkey);
         self
     }
+
+    /// Send the change output to the script pubkey derived at `index` of `keychain`, instead of
+    /// the next unused script pubkey of the wallet's [`change_keychain`].
+    ///
+    /// If `index` has not been revealed yet, [`finish`] will reveal all the script pubkeys up to
+    /// and including it. Building the transaction fails with [`CreateTxError::ChangeIndexUsed`] if
+    /// the script pubkey at `index` has already received funds.
+    ///
+    /// This has no effect if [`drain_to`] is also set.
+    ///
+    /// [`finish`]: Self::finish
+    /// [`drain_to`]: Self::drain_to
+    /// [`change_keychain`]: crate::Wallet::change_keychain
+    pub fn change_to_index(&mut self, keychain: KeychainKind, index: u32) -> &mut Self {
+        self.params.change_index = Some((keychain, index));
+        self
+    }
 }
 
 impl<'a, Cs: CoinSelectionAlgorithm> TxBuilder<'a, Cs> {
@@ -676,17 +1068,126 @@ impl<'a, Cs: CoinSelectionAlgorithm> TxBuilder<'a, Cs> {
     /// **WARNING**: To avoid change address reuse you must persist the changes resulting from one
     /// or more calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
     pub fn finish(self) -> Result<Psbt, CreateTxError> {
+        self.finish_with_details().map(|(psbt, _)| psbt)
+    }
+
+    /// Finish building the transaction, also returning the [`TxDetails`] of the transaction: its
+    /// change output, its fee and how its coins were selected.
+    ///
+    /// This allows previewing the fee and labeling the change before the transaction is signed,
+    /// or keeping an audit trail of the coin selection.
+    ///
+    /// **WARNING**: To avoid change address reuse you must persist the changes resulting from one
+    /// or more calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
+    pub fn finish_with_details(self) -> Result<(Psbt, TxDetails), CreateTxError> {
         self.wallet
             .borrow_mut()
             .create_tx(self.coin_selection, self.params)
     }
 }
 
+/// The details of a transaction, returned by [`TxBuilder::finish_with_details`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TxDetails {
+    /// The output that the excess coins were sent to, if any
+    pub change: Option<ChangeOutput>,
+    /// The fee of the transaction once signed
+    pub fee: FeeDetails,
+    /// Which UTXOs were spent and why
+    pub coin_selection: CoinSelectionDetails,
+}
+
+/// The output that the excess coins of a transaction were sent to
+///
+/// The index points to the output's final position, after the [`TxOrdering`] has been applied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChangeOutput {
+    /// Index of the output in the transaction
+    pub vout: usize,
+    /// The output's script pubkey
+    pub script_pubkey: ScriptBuf,
+}
+
+/// The fee of a transaction
+///
+/// The size of the transaction is estimated with the maximum weight needed to satisfy each input,
+/// so the actual fee rate of the signed transaction may be slightly higher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FeeDetails {
+    /// The absolute fee paid by the transaction
+    pub absolute_fee: Amount,
+    /// The fee rate of the signed transaction
+    pub fee_rate: FeeRate,
+    /// The estimated virtual size of the signed transaction
+    pub vsize: usize,
+    /// The amount of the change output, if any
+    pub change_amount: Option<Amount>,
+}
+
+/// The coin selection of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CoinSelectionDetails {
+    /// The UTXOs the transaction could spend
+    pub considered: Vec<OutPoint>,
+    /// The UTXOs the transaction had to spend, i.e. the ones added with [`TxBuilder::add_utxo`]
+    /// and the like, or spent by the transaction being fee bumped
+    pub required: Vec<OutPoint>,
+    /// The UTXOs spent by the transaction, i.e. the required ones and the ones picked by the coin
+    /// selection algorithm
+    pub selected: Vec<OutPoint>,
+    /// The total value of the selected UTXOs
+    pub selected_amount: Amount,
+    /// The absolute fee paid by the transaction
+    pub fee: Amount,
+    /// The fee rate the coins were selected at
+    pub fee_rate: FeeRate,
+    /// The estimated weight of the selected inputs once satisfied
+    pub inputs_weight: Weight,
+    /// The amount of the change output, if any
+    pub change_amount: Option<Amount>,
+    /// The fee paid for the change output, if one was created
+    pub change_fee: Option<Amount>,
+    /// The estimated weight of spending the change output later, if one was created
+    pub change_spend_weight: Option<Weight>,
+    /// The excess given to the fee because a change output would have been dust
+    pub excess_to_fee: Amount,
+}
+
+impl CoinSelectionDetails {
+    /// The waste metric of the selection, in satoshis.
+    ///
+    /// This is the cost of spending the selected inputs now rather than at `long_term_feerate`,
+    /// plus either the cost of creating the change output and spending it later at
+    /// `long_term_feerate`, or the excess given to the fee when there is no change. It's the
+    /// metric minimized by [`WasteMinimizingCoinSelection`].
+    ///
+    /// [`WasteMinimizingCoinSelection`]: super::coin_selection::WasteMinimizingCoinSelection
+    pub fn waste(&self, long_term_feerate: FeeRate) -> i64 {
+        let inputs_waste = (self.fee_rate * self.inputs_weight).to_sat() as i64
+            - (long_term_feerate * self.inputs_weight).to_sat() as i64;
+        let excess_waste = match (self.change_fee, self.change_spend_weight) {
+            (Some(change_fee), Some(change_spend_weight)) => {
+                change_fee + long_term_feerate * change_spend_weight
+            }
+            _ => self.excess_to_fee,
+        };
+        inputs_waste + excess_waste.to_sat() as i64
+    }
+}
+
 #[derive(Debug)]
-/// Error returned from [`TxBuilder::add_utxo`] and [`TxBuilder::add_utxos`]
+/// Error returned from [`TxBuilder::add_utxo`], [`TxBuilder::add_utxos`] and
+/// [`TxBuilder::add_utxo_by_address`]
 pub enum AddUtxoError {
     /// Happens when trying to spend an UTXO that is not in the internal database
     UnknownUtxo(OutPoint),
+    /// No unspent output of the wallet pays the amount to the script pubkey
+    NoMatchingUtxo {
+        /// Script pubkey of the requested address
+        script_pubkey: ScriptBuf,
+        /// Requested amount
+        amount: Amount,
+    },
 }
 
 impl fmt::Display for AddUtxoError {
@@ -697,6 +1198,14 @@ impl fmt::Display for AddUtxoError {
                 "UTXO not found in the internal database for txid: {} with vout: {}",
                 outpoint.txid, outpoint.vout
             ),
+            Self::NoMatchingUtxo {
+                script_pubkey,
+                amount,
+            } => write!(
+                f,
+                "No unspent output of {} to script pubkey {}",
+                amount, script_pubkey
+            ),
         }
     }
 }
@@ -704,6 +1213,64 @@ impl fmt::Display for AddUtxoError {
 #[cfg(feature = "std")]
 impl std::error::Error for AddUtxoError {}
 
+#[derive(Debug)]
+/// Error returned from [`TxBuilder::add_recipient_address`] and [`TxBuilder::add_recipient_str`]
+pub enum AddRecipientError {
+    /// The address is not valid for the wallet's network
+    WrongNetwork {
+        /// The recipient's address
+        address: Address,
+        /// The wallet's network
+        network: Network,
+    },
+    /// The amount string has a missing or unknown denomination
+    InvalidDenomination(String),
+    /// The amount could not be parsed in its denomination
+    InvalidAmount(ParseAmountError),
+}
+
+impl fmt::Display for AddRecipientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongNetwork { address, network } => write!(
+                f,
+                "Address {} is not valid for the wallet's network {}",
+                address, network
+            ),
+            Self::InvalidDenomination(amount) => {
+                write!(f, "Missing or unknown denomination in amount {}", amount)
+            }
+            Self::InvalidAmount(err) => write!(f, "Invalid amount: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AddRecipientError {}
+
+#[derive(Debug)]
+/// Error returned from [`TxBuilder::fee_rate_for_target`]
+pub enum FeeRateForTargetError {
+    /// The fee estimator has no estimate for the confirmation target
+    Unavailable {
+        /// The confirmation target, in blocks
+        target: u16,
+    },
+}
+
+impl fmt::Display for FeeRateForTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable { target } => {
+                write!(f, "No fee rate estimate for a target of {} blocks", target)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FeeRateForTargetError {}
+
 #[derive(Debug)]
 /// Error returned from [`TxBuilder::add_foreign_utxo`].
 pub enum AddForeignUtxoError {
@@ -759,22 +1326,35 @@ pub enum TxOrdering {
 impl TxOrdering {
     /// Sort transaction inputs and outputs by [`TxOrdering`] variant
     pub fn sort_tx(&self, tx: &mut Transaction) {
+        self.sort_tx_tracking_output(tx, None);
+    }
+
+    /// Like [`TxOrdering::sort_tx`], but also returns the index the output at `vout` was moved to
+    pub(crate) fn sort_tx_tracking_output(
+        &self,
+        tx: &mut Transaction,
+        vout: Option<usize>,
+    ) -> Option<usize> {
+        let mut outputs = tx.output.drain(..).enumerate().collect::<Vec<_>>();
         match self {
             TxOrdering::Untouched => {}
             TxOrdering::Shuffle => {
                 use rand::seq::SliceRandom;
                 let mut rng = rand::thread_rng();
                 tx.input.shuffle(&mut rng);
-                tx.output.shuffle(&mut rng);
+                outputs.shuffle(&mut rng);
             }
             TxOrdering::Bip69Lexicographic => {
                 tx.input.sort_unstable_by_key(|txin| {
                     (txin.previous_output.txid, txin.previous_output.vout)
                 });
-                tx.output
-                    .sort_unstable_by_key(|txout| (txout.value, txout.script_pubkey.clone()));
+                outputs
+                    .sort_unstable_by_key(|(_, txout)| (txout.value, txout.script_pubkey.clone()));
             }
         }
+        let new_vout = vout.and_then(|vout| outputs.iter().position(|(index, _)| *index == vout));
+        tx.output = outputs.into_iter().map(|(_, txout)| txout).collect();
+        new_vout
     }
 }
 
@@ -809,6 +1389,11 @@ impl RbfValue {
 }
 
 /// Policy regarding the use of change outputs when creating a transaction
+///
+/// The change outputs are the outputs of the [`KeychainKind::Internal`] keychain and of the
+/// keychain set with [`Wallet::set_change_descriptor`], if any.
+///
+/// [`Wallet::set_change_descriptor`]: crate::Wallet::set_change_descriptor
 #[derive(Default, Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Copy)]
 pub enum ChangeSpendPolicy {
     /// Use both change and non-change outputs (default)
@@ -821,11 +1406,16 @@ pub enum ChangeSpendPolicy {
 }
 
 impl ChangeSpendPolicy {
-    pub(crate) fn is_satisfied_by(&self, utxo: &LocalOutput) -> bool {
+    /// Whether outputs may be spent, given whether they are change. Change is the outputs of the
+    /// [`KeychainKind::Internal`] keychain and of the keychain set with
+    /// [`Wallet::set_change_descriptor`].
+    ///
+    /// [`Wallet::set_change_descriptor`]: crate::Wallet::set_change_descriptor
+    pub(crate) fn allows(&self, is_change: bool) -> bool {
         match self {
             ChangeSpendPolicy::ChangeAllowed => true,
-            ChangeSpendPolicy::OnlyChange => utxo.keychain == KeychainKind::Internal,
-            ChangeSpendPolicy::ChangeForbidden => utxo.keychain == KeychainKind::External,
+            ChangeSpendPolicy::OnlyChange => is_change,
+            ChangeSpendPolicy::ChangeForbidden => !is_change,
         }
     }
 }
@@ -851,6 +1441,7 @@ mod test {
     use bitcoin::TxOut;
 
     use super::*;
+    use crate::LocalOutput;
 
     #[test]
     fn test_output_ordering_default_shuffle() {
@@ -964,7 +1555,7 @@ mod test {
         let change_spend_policy = ChangeSpendPolicy::default();
         let filtered = get_test_utxos()
             .into_iter()
-            .filter(|u| change_spend_policy.is_satisfied_by(u))
+            .filter(|u| change_spend_policy.allows(u.keychain == KeychainKind::Internal))
             .count();
 
         assert_eq!(filtered, 2);
@@ -975,7 +1566,7 @@ mod test {
         let change_spend_policy = ChangeSpendPolicy::ChangeForbidden;
         let filtered = get_test_utxos()
             .into_iter()
-            .filter(|u| change_spend_policy.is_satisfied_by(u))
+            .filter(|u| change_spend_policy.allows(u.keychain == KeychainKind::Internal))
             .collect::<Vec<_>>();
 
         assert_eq!(filtered.len(), 1);
@@ -987,7 +1578,7 @@ mod test {
         let change_spend_policy = ChangeSpendPolicy::OnlyChange;
         let filtered = get_test_utxos()
             .into_iter()
-            .filter(|u| change_spend_policy.is_satisfied_by(u))
+            .filter(|u| change_spend_policy.allows(u.keychain == KeychainKind::Internal))
             .collect::<Vec<_>>();
 
         assert_eq!(filtered.len(), 1);
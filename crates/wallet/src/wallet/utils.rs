@@ -9,8 +9,9 @@
 // You may not use this file except in accordance with one or both of these
 // licenses.
 
+use bdk_chain::keychain::Balance;
 use bitcoin::secp256k1::{All, Secp256k1};
-use bitcoin::{absolute, relative, Script, Sequence};
+use bitcoin::{absolute, relative, FeeRate, Script, Sequence};
 
 use miniscript::{MiniscriptKey, Satisfier, ToPublicKey};
 
@@ -22,12 +23,25 @@ use miniscript::{MiniscriptKey, Satisfier, ToPublicKey};
 pub trait IsDust {
     /// Check whether or not a value is below dust limit
     fn is_dust(&self, script: &Script) -> bool;
+
+    /// Check whether or not a value is below the dust limit computed with a custom dust relay fee
+    ///
+    /// Defaults to [`IsDust::is_dust`], i.e. ignores `dust_relay_fee`, for implementations that
+    /// don't support custom dust relay fees.
+    fn is_dust_with_relay_fee(&self, script: &Script, dust_relay_fee: FeeRate) -> bool {
+        let _ = dust_relay_fee;
+        self.is_dust(script)
+    }
 }
 
 impl IsDust for u64 {
     fn is_dust(&self, script: &Script) -> bool {
         *self < script.minimal_non_dust().to_sat()
     }
+
+    fn is_dust_with_relay_fee(&self, script: &Script, dust_relay_fee: FeeRate) -> bool {
+        *self < script.minimal_non_dust_custom(dust_relay_fee).to_sat()
+    }
 }
 
 pub struct After {
@@ -112,6 +126,43 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for Older {
 
 pub(crate) type SecpCtx = Secp256k1<All>;
 
+/// Caches the wallet [`Balance`] between changes to the wallet's state.
+///
+/// Without the `std` feature there is no lock to guard the cache with, so the balance is computed
+/// on every call.
+#[derive(Debug, Default)]
+pub(crate) struct BalanceCache {
+    #[cfg(feature = "std")]
+    balance: std::sync::Mutex<Option<Balance>>,
+}
+
+impl BalanceCache {
+    /// Returns the cached balance, or computes and caches it with `compute`.
+    pub(crate) fn get_or_compute(&self, compute: impl FnOnce() -> Balance) -> Balance {
+        #[cfg(feature = "std")]
+        {
+            let mut balance = self
+                .balance
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            balance.get_or_insert_with(compute).clone()
+        }
+        #[cfg(not(feature = "std"))]
+        compute()
+    }
+
+    /// Drops the cached balance. Must be called whenever the chain or the graph changes.
+    pub(crate) fn invalidate(&mut self) {
+        #[cfg(feature = "std")]
+        {
+            *self
+                .balance
+                .get_mut()
+                .unwrap_or_else(std::sync::PoisonError::into_inner) = None;
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     // When nSequence is lower than this flag the timelock is interpreted as block-height-based,
@@ -119,7 +170,7 @@ mod test {
     pub(crate) const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
 
     use super::{check_nsequence_rbf, IsDust};
-    use crate::bitcoin::{Address, Network, Sequence};
+    use crate::bitcoin::{Address, FeeRate, Network, Script, Sequence};
     use core::str::FromStr;
 
     #[test]
@@ -143,6 +194,28 @@ mod test {
         assert!(!294.is_dust(&script_p2wpkh));
     }
 
+    #[test]
+    fn test_is_dust_with_relay_fee() {
+        let script_p2wpkh = Address::from_str("bc1qxlh2mnc0yqwas76gqq665qkggee5m98t8yskd8")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap()
+            .script_pubkey();
+        let dust_relay_fee = FeeRate::from_sat_per_vb_unchecked(6);
+        assert!(587.is_dust_with_relay_fee(&script_p2wpkh, dust_relay_fee));
+        assert!(!588.is_dust_with_relay_fee(&script_p2wpkh, dust_relay_fee));
+
+        // implementations without support for custom fees fall back to `is_dust`
+        struct Value(u64);
+        impl IsDust for Value {
+            fn is_dust(&self, script: &Script) -> bool {
+                self.0.is_dust(script)
+            }
+        }
+        assert!(!Value(294).is_dust_with_relay_fee(&script_p2wpkh, dust_relay_fee));
+        assert!(Value(293).is_dust_with_relay_fee(&script_p2wpkh, dust_relay_fee));
+    }
+
     #[test]
     fn test_check_nsequence_rbf_msb_set() {
         let result = check_nsequence_rbf(Sequence(0x80000000), Sequence(5000));
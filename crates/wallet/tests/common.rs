@@ -17,7 +17,12 @@ use std::str::FromStr;
 /// sats are the transaction fee.
 pub fn get_funded_wallet_with_change(descriptor: &str, change: &str) -> (Wallet, bitcoin::Txid) {
     let mut wallet = Wallet::new(descriptor, change, Network::Regtest).unwrap();
-    let receive_address = wallet.peek_address(KeychainKind::External, 0).address;
+    // tests build several transactions from the same utxos
+    wallet.set_utxo_lock_ttl(None);
+    let receive_address = wallet
+        .peek_address(KeychainKind::External, 0)
+        .unwrap()
+        .address;
     let sendto_address = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
         .expect("address")
         .require_network(Network::Regtest)
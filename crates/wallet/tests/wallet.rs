@@ -5,15 +5,24 @@ use std::str::FromStr;
 use assert_matches::assert_matches;
 use bdk_chain::collections::BTreeMap;
 use bdk_chain::COINBASE_MATURITY;
-use bdk_chain::{persist::PersistBackend, BlockId, ConfirmationTime};
+use bdk_chain::{persist::PersistBackend, tx_graph::TxGraph, BlockId, ConfirmationTime};
 use bdk_sqlite::rusqlite::Connection;
 use bdk_wallet::descriptor::{calc_checksum, DescriptorError, IntoWalletDescriptor};
 use bdk_wallet::psbt::PsbtUtils;
 use bdk_wallet::signer::{SignOptions, SignerError};
+use bdk_wallet::wallet::bip21::Bip21Error;
 use bdk_wallet::wallet::coin_selection::{self, LargestFirstCoinSelection};
-use bdk_wallet::wallet::error::CreateTxError;
-use bdk_wallet::wallet::tx_builder::AddForeignUtxoError;
-use bdk_wallet::wallet::{AddressInfo, Balance, NewError, Wallet};
+use bdk_wallet::wallet::error::{
+    AddKeychainError, AddPrivateKeysError, BuildFeeBumpError, CreateSignedTxError, CreateTxError,
+    ExtractError, FeeBumpsError, KeychainNotFound,
+};
+use bdk_wallet::wallet::tx_builder::{
+    AddForeignUtxoError, AddRecipientError, AddUtxoError, FeeRateForTargetError, TxDetails,
+};
+use bdk_wallet::wallet::{
+    AddressInfo, Balance, DescriptorMismatch, DescriptorMismatchPolicy, KeychainStats, NewError,
+    NewOrLoadError, Update, Wallet, DEFAULT_DUST_RELAY_FEE, DEFAULT_UTXO_LOCK_TTL,
+};
 use bdk_wallet::KeychainKind;
 use bitcoin::hashes::Hash;
 use bitcoin::key::Secp256k1;
@@ -30,7 +39,7 @@ mod common;
 use common::*;
 
 fn receive_output(wallet: &mut Wallet, value: u64, height: ConfirmationTime) -> OutPoint {
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let tx = Transaction {
         version: transaction::Version::ONE,
         lock_time: absolute::LockTime::ZERO,
@@ -87,7 +96,7 @@ fn load_recovers_wallet() -> anyhow::Result<()> {
             let mut wallet =
                 Wallet::new(desc, change_desc, Network::Testnet).expect("must init wallet");
 
-            wallet.reveal_next_address(KeychainKind::External);
+            wallet.reveal_next_address(KeychainKind::External).unwrap();
 
             // persist new wallet changes
             let mut db = create_new(&file_path).expect("must create db");
@@ -118,7 +127,9 @@ fn load_recovers_wallet() -> anyhow::Result<()> {
             );
             let secp = Secp256k1::new();
             assert_eq!(
-                *wallet.get_descriptor_for_keychain(KeychainKind::External),
+                *wallet
+                    .get_descriptor_for_keychain(KeychainKind::External)
+                    .unwrap(),
                 desc.into_wallet_descriptor(&secp, wallet.network())
                     .unwrap()
                     .0
@@ -292,6 +303,139 @@ fn new_or_load() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn new_or_load_descriptor_mismatch_policy() {
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    let (other_desc, _) = get_test_tr_single_sig_xprv_with_change_desc();
+    let genesis_hash = bitcoin::blockdata::constants::genesis_block(Network::Testnet).block_hash();
+    let wallet = Wallet::new(desc, change_desc, Network::Testnet).expect("must create wallet");
+    let changeset = wallet.staged().clone();
+
+    // the default policy rejects a different descriptor
+    let err = Wallet::load()
+        .genesis_hash(genesis_hash)
+        .on_descriptor_mismatch(DescriptorMismatchPolicy::default())
+        .new_or_load(
+            other_desc,
+            change_desc,
+            Some(changeset.clone()),
+            Network::Testnet,
+        )
+        .expect_err("descriptor does not match");
+    assert!(
+        matches!(
+            err,
+            NewOrLoadError::LoadedDescriptorDoesNotMatch { keychain, .. }
+            if keychain == KeychainKind::External
+        ),
+        "err: {}",
+        err,
+    );
+
+    // keeping the stored descriptor loads the original wallet without the provided signers
+    let (loaded, mismatches) = Wallet::load()
+        .genesis_hash(genesis_hash)
+        .on_descriptor_mismatch(DescriptorMismatchPolicy::KeepStored)
+        .new_or_load(
+            other_desc,
+            change_desc,
+            Some(changeset.clone()),
+            Network::Testnet,
+        )
+        .expect("must load wallet");
+    let other_descriptor = other_desc
+        .into_wallet_descriptor(&Secp256k1::new(), Network::Testnet)
+        .unwrap()
+        .0;
+    assert_eq!(
+        mismatches,
+        vec![DescriptorMismatch {
+            keychain: KeychainKind::External,
+            stored: wallet
+                .public_descriptor(KeychainKind::External)
+                .unwrap()
+                .clone(),
+            provided: other_descriptor.clone(),
+        }]
+    );
+    assert_eq!(
+        loaded.public_descriptor(KeychainKind::External).unwrap(),
+        wallet.public_descriptor(KeychainKind::External).unwrap()
+    );
+    assert_eq!(
+        loaded.public_descriptor(KeychainKind::Internal).unwrap(),
+        wallet.public_descriptor(KeychainKind::Internal).unwrap()
+    );
+    assert_eq!(loaded.id(), wallet.id());
+    assert!(loaded.get_signers(KeychainKind::External).ids().is_empty());
+    assert_eq!(
+        loaded.get_signers(KeychainKind::Internal).ids(),
+        wallet.get_signers(KeychainKind::Internal).ids()
+    );
+
+    // migrating replaces the stored descriptor and stages the whole migrated wallet
+    let (migrated, mismatches) = Wallet::load()
+        .genesis_hash(genesis_hash)
+        .on_descriptor_mismatch(DescriptorMismatchPolicy::Migrate)
+        .new_or_load(other_desc, change_desc, Some(changeset), Network::Testnet)
+        .expect("must migrate wallet");
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].provided, other_descriptor);
+    assert_eq!(
+        migrated.public_descriptor(KeychainKind::External).unwrap(),
+        &other_descriptor
+    );
+    assert_eq!(
+        migrated.public_descriptor(KeychainKind::Internal).unwrap(),
+        wallet.public_descriptor(KeychainKind::Internal).unwrap()
+    );
+    assert!(!migrated
+        .get_signers(KeychainKind::External)
+        .ids()
+        .is_empty());
+
+    // the staged changes load the migrated wallet without any mismatch
+    let (reloaded, mismatches) = Wallet::load()
+        .genesis_hash(genesis_hash)
+        .new_or_load(
+            other_desc,
+            change_desc,
+            Some(migrated.staged().clone()),
+            Network::Testnet,
+        )
+        .expect("must load migrated wallet");
+    assert!(mismatches.is_empty());
+    assert_eq!(reloaded.id(), migrated.id());
+}
+
+#[test]
+fn new_or_load_migrate_keeps_transactions_and_revealed_index() {
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    let (other_desc, _) = get_test_tr_single_sig_xprv_with_change_desc();
+    let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
+    let _ = wallet
+        .reveal_addresses_to(KeychainKind::External, 5)
+        .unwrap();
+    let changeset = wallet.staged().clone();
+
+    let (migrated, _) = Wallet::load()
+        .on_descriptor_mismatch(DescriptorMismatchPolicy::Migrate)
+        .new_or_load(other_desc, change_desc, Some(changeset), Network::Regtest)
+        .expect("must migrate wallet");
+    assert_eq!(
+        migrated.derivation_index(KeychainKind::External),
+        Some(5),
+        "revealed index must be carried over to the new descriptor"
+    );
+    assert_eq!(
+        migrated.transactions().count(),
+        wallet.transactions().count()
+    );
+    // the funds were received by the replaced descriptor so they are no longer tracked
+    assert_eq!(wallet.list_unspent().count(), 1);
+    assert_eq!(migrated.list_unspent().count(), 0);
+}
+
 #[test]
 fn test_error_external_and_internal_are_the_same() {
     // identical descriptors should fail to create wallet
@@ -327,7 +471,7 @@ fn test_error_external_and_internal_are_the_same() {
 #[test]
 fn test_descriptor_checksum() {
     let (wallet, _) = get_funded_wallet_wpkh();
-    let checksum = wallet.descriptor_checksum(KeychainKind::External);
+    let checksum = wallet.descriptor_checksum(KeychainKind::External).unwrap();
     assert_eq!(checksum.len(), 8);
 
     let raw_descriptor = wallet
@@ -343,6 +487,44 @@ fn test_descriptor_checksum() {
     assert_eq!(calc_checksum(&raw_descriptor).unwrap(), checksum);
 }
 
+#[test]
+fn test_commit_to_debounced() {
+    use bdk_chain::Append;
+    use std::time::Duration;
+
+    let insert_checkpoint = |wallet: &mut Wallet, height: u32| {
+        wallet
+            .insert_checkpoint(BlockId {
+                height,
+                hash: BlockHash::all_zeros(),
+            })
+            .unwrap();
+    };
+    let (external, internal) = get_test_wpkh_with_change_desc();
+    let mut wallet = Wallet::new(external, internal, Network::Testnet).unwrap();
+    let mut db = ();
+
+    // nothing was committed yet, so the first commit goes through
+    assert!(wallet
+        .commit_to_debounced(&mut db, Duration::from_secs(3600))
+        .unwrap());
+    assert!(wallet.staged().is_empty());
+
+    // changes are kept staged until the interval elapses
+    insert_checkpoint(&mut wallet, 1);
+    assert!(!wallet
+        .commit_to_debounced(&mut db, Duration::from_secs(3600))
+        .unwrap());
+    assert!(!wallet.staged().is_empty());
+    assert!(wallet.commit_to_debounced(&mut db, Duration::ZERO).unwrap());
+    assert!(wallet.staged().is_empty());
+
+    // `commit_to` always flushes the staged changes
+    insert_checkpoint(&mut wallet, 2);
+    assert!(wallet.commit_to(&mut db).unwrap());
+    assert!(wallet.staged().is_empty());
+}
+
 #[test]
 fn test_get_funded_wallet_balance() {
     let (wallet, _) = get_funded_wallet_wpkh();
@@ -353,6 +535,152 @@ fn test_get_funded_wallet_balance() {
     assert_eq!(wallet.balance().confirmed, Amount::from_sat(50_000));
 }
 
+#[test]
+fn test_balance_is_updated_after_mutations() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let balance = wallet.balance();
+    assert_eq!(wallet.balance(), balance);
+
+    // receiving an unconfirmed output must not return the cached balance
+    let outpoint = receive_output(
+        &mut wallet,
+        25_000,
+        ConfirmationTime::Unconfirmed { last_seen: 0 },
+    );
+    assert_eq!(wallet.balance().confirmed, Amount::from_sat(50_000));
+    assert_eq!(wallet.balance().untrusted_pending, Amount::from_sat(25_000));
+
+    // confirming it moves it into the confirmed balance
+    let tx = wallet
+        .get_tx(outpoint.txid)
+        .unwrap()
+        .tx_node
+        .tx
+        .as_ref()
+        .clone();
+    let height = wallet.latest_checkpoint().height();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Confirmed { height, time: 0 })
+        .unwrap();
+    assert_eq!(wallet.balance().confirmed, Amount::from_sat(75_000));
+    assert_eq!(wallet.balance().untrusted_pending, Amount::ZERO);
+}
+
+#[test]
+fn test_balance_cache_is_never_stale() {
+    // the balance computed from the wallet's graph and chain, bypassing the cache
+    fn uncached_balance(wallet: &Wallet) -> Balance {
+        wallet.tx_graph().balance(
+            wallet.local_chain(),
+            wallet.latest_checkpoint().block_id(),
+            wallet.spk_index().outpoints().iter().cloned(),
+            |&(k, _), _| k == KeychainKind::Internal,
+        )
+    }
+    // the balance cached before the last mutation was dropped, and the new one is cached
+    fn assert_updated(wallet: &Wallet, previous: &mut Balance) {
+        let balance = wallet.balance();
+        assert_ne!(&balance, previous);
+        assert_eq!(balance, uncached_balance(wallet));
+        assert_eq!(wallet.balance(), balance);
+        *previous = balance;
+    }
+    // a transaction paying `value` to the wallet
+    fn payment(wallet: &mut Wallet, value: u64, is_coinbase: bool) -> Transaction {
+        let previous_output = if is_coinbase {
+            OutPoint::null()
+        } else {
+            OutPoint::new(Txid::from_byte_array([value as u8; 32]), 0)
+        };
+        Transaction {
+            version: transaction::Version::ONE,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                script_pubkey: wallet
+                    .next_unused_address(KeychainKind::External)
+                    .unwrap()
+                    .script_pubkey(),
+                value: Amount::from_sat(value),
+            }],
+        }
+    }
+
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let mut balance = wallet.balance();
+
+    let tx = payment(&mut wallet, 1_000, false);
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    assert_updated(&wallet, &mut balance);
+
+    let tip = wallet.latest_checkpoint().height();
+    let coinbase = payment(&mut wallet, 2_000, true);
+    wallet
+        .insert_tx(
+            coinbase,
+            ConfirmationTime::Confirmed {
+                height: tip,
+                time: 0,
+            },
+        )
+        .unwrap();
+    assert_updated(&wallet, &mut balance);
+
+    // the coinbase matures
+    wallet
+        .insert_checkpoint(BlockId {
+            height: tip + COINBASE_MATURITY,
+            hash: BlockHash::from_byte_array([1; 32]),
+        })
+        .unwrap();
+    assert_updated(&wallet, &mut balance);
+
+    let tx = payment(&mut wallet, 3_000, false);
+    let txid = tx.compute_txid();
+    let mut graph = TxGraph::default();
+    let _ = graph.insert_tx(tx);
+    let _ = graph.insert_seen_at(txid, 1);
+    wallet
+        .apply_update(Update {
+            graph,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_updated(&wallet, &mut balance);
+
+    let tip = wallet.latest_checkpoint();
+    let block = bitcoin::Block {
+        header: bitcoin::block::Header {
+            version: bitcoin::block::Version::ONE,
+            prev_blockhash: tip.hash(),
+            merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: bitcoin::CompactTarget::from_consensus(0),
+            nonce: 0,
+        },
+        txdata: vec![payment(&mut wallet, 4_000, false)],
+    };
+    wallet.apply_block(&block, tip.height() + 1).unwrap();
+    assert_updated(&wallet, &mut balance);
+
+    let tx = payment(&mut wallet, 5_000, false);
+    wallet.apply_unconfirmed_txs([(&tx, 2)]);
+    assert_updated(&wallet, &mut balance);
+
+    let tx = payment(&mut wallet, 6_000, false);
+    wallet.apply_unconfirmed_txs_with_incoming([(&tx, 3)]);
+    assert_updated(&wallet, &mut balance);
+
+    let tx = payment(&mut wallet, 7_000, false);
+    wallet.import_transactions([tx]);
+    assert_updated(&wallet, &mut balance);
+}
+
 #[test]
 fn test_get_funded_wallet_sent_and_received() {
     let (wallet, txid) = get_funded_wallet_wpkh();
@@ -425,6 +753,49 @@ fn test_list_output() {
     }
 }
 
+#[test]
+fn test_confirmed_and_unconfirmed_utxos() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let unconfirmed_op = receive_output(
+        &mut wallet,
+        25_000,
+        ConfirmationTime::Unconfirmed { last_seen: 0 },
+    );
+
+    let confirmed = wallet
+        .confirmed_utxos()
+        .map(|utxo| utxo.outpoint)
+        .collect::<Vec<_>>();
+    assert_eq!(confirmed, vec![OutPoint { txid, vout: 0 }]);
+    let unconfirmed = wallet
+        .unconfirmed_utxos()
+        .map(|utxo| utxo.outpoint)
+        .collect::<Vec<_>>();
+    assert_eq!(unconfirmed, vec![unconfirmed_op]);
+
+    // spending the unconfirmed output with an unconfirmed tx removes it from the set
+    let addr = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .add_utxo(unconfirmed_op)
+        .unwrap()
+        .manually_selected_only();
+    let tx = builder.finish().unwrap().extract_tx().unwrap();
+    let spending_txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 1 })
+        .unwrap();
+
+    assert_eq!(wallet.confirmed_utxos().count(), 1);
+    let unconfirmed = wallet.unconfirmed_utxos().collect::<Vec<_>>();
+    assert_eq!(unconfirmed.len(), 1);
+    assert_eq!(unconfirmed[0].outpoint.txid, spending_txid);
+    assert_eq!(unconfirmed[0].keychain, KeychainKind::Internal);
+}
+
 macro_rules! assert_fee_rate {
     ($psbt:expr, $fees:expr, $fee_rate:expr $( ,@dust_change $( $dust_change:expr )* )* $( ,@add_signature $( $add_signature:expr )* )* ) => ({
         let psbt = $psbt.clone();
@@ -494,7 +865,7 @@ fn test_create_tx_empty_recipients() {
 #[should_panic(expected = "NoUtxosSelected")]
 fn test_create_tx_manually_selected_empty_utxos() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -505,7 +876,7 @@ fn test_create_tx_manually_selected_empty_utxos() {
 #[test]
 fn test_create_tx_version_0() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -516,7 +887,7 @@ fn test_create_tx_version_0() {
 #[test]
 fn test_create_tx_version_1_csv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_csv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -527,7 +898,7 @@ fn test_create_tx_version_1_csv() {
 #[test]
 fn test_create_tx_custom_version() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -541,7 +912,7 @@ fn test_create_tx_custom_version() {
 fn test_create_tx_default_locktime_is_last_sync_height() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
 
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
     let psbt = builder.finish().unwrap();
@@ -554,7 +925,7 @@ fn test_create_tx_default_locktime_is_last_sync_height() {
 #[test]
 fn test_create_tx_fee_sniping_locktime_last_sync() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
 
@@ -570,7 +941,7 @@ fn test_create_tx_fee_sniping_locktime_last_sync() {
 #[test]
 fn test_create_tx_default_locktime_cltv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_cltv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
     let psbt = builder.finish().unwrap();
@@ -581,7 +952,7 @@ fn test_create_tx_default_locktime_cltv() {
 #[test]
 fn test_create_tx_custom_locktime() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -598,7 +969,7 @@ fn test_create_tx_custom_locktime() {
 #[test]
 fn test_create_tx_custom_locktime_compatible_with_cltv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_cltv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -611,7 +982,7 @@ fn test_create_tx_custom_locktime_compatible_with_cltv() {
 #[test]
 fn test_create_tx_custom_locktime_incompatible_with_cltv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_cltv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -624,7 +995,7 @@ fn test_create_tx_custom_locktime_incompatible_with_cltv() {
 #[test]
 fn test_create_tx_no_rbf_csv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_csv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
     let psbt = builder.finish().unwrap();
@@ -635,7 +1006,7 @@ fn test_create_tx_no_rbf_csv() {
 #[test]
 fn test_create_tx_with_default_rbf_csv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_csv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -649,7 +1020,7 @@ fn test_create_tx_with_default_rbf_csv() {
 #[test]
 fn test_create_tx_with_custom_rbf_csv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_csv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -659,10 +1030,73 @@ fn test_create_tx_with_custom_rbf_csv() {
         if rbf.to_consensus_u32() == 3 && csv.to_consensus_u32() == 6));
 }
 
+#[test]
+fn test_create_tx_set_sequence_for() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let first = wallet.list_unspent().next().unwrap().outpoint;
+    let second = receive_output_in_latest_block(&mut wallet, 25_000);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    // only one input signals RBF, the other one uses the default nSequence
+    let mut builder = wallet.build_tx();
+    builder
+        .drain_to(addr.script_pubkey())
+        .drain_wallet()
+        .set_sequence_for(second, Sequence::ENABLE_RBF_NO_LOCKTIME);
+    let psbt = builder.finish().unwrap();
+    let sequence_of = |outpoint| {
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .find(|txin| txin.previous_output == outpoint)
+            .map(|txin| txin.sequence)
+    };
+    assert_eq!(sequence_of(first), Some(Sequence::ENABLE_LOCKTIME_NO_RBF));
+    assert_eq!(sequence_of(second), Some(Sequence::ENABLE_RBF_NO_LOCKTIME));
+
+    // RBF is enabled but every input is made final
+    let mut builder = wallet.build_tx();
+    builder
+        .drain_to(addr.script_pubkey())
+        .drain_wallet()
+        .enable_rbf()
+        .set_sequence_for(first, Sequence::MAX)
+        .set_sequence_for(second, Sequence::MAX);
+    assert!(matches!(
+        builder.finish(),
+        Err(CreateTxError::RbfNotSignaled)
+    ));
+}
+
+#[test]
+fn test_create_tx_set_sequence_for_csv() {
+    let (mut wallet, _) = get_funded_wallet(get_test_single_sig_csv());
+    let utxo = wallet.list_unspent().next().unwrap().outpoint;
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .set_sequence_for(utxo, Sequence(10));
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(10));
+
+    // the nSequence must satisfy the OP_CSV of 6
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .set_sequence_for(utxo, Sequence(3));
+    assert!(matches!(builder.finish(),
+        Err(CreateTxError::SequenceCsv { outpoint, sequence, csv })
+        if outpoint == utxo && sequence.to_consensus_u32() == 3 && csv.to_consensus_u32() == 6));
+}
+
 #[test]
 fn test_create_tx_no_rbf_cltv() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_cltv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
     let psbt = builder.finish().unwrap();
@@ -673,7 +1107,7 @@ fn test_create_tx_no_rbf_cltv() {
 #[test]
 fn test_create_tx_invalid_rbf_sequence() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -684,7 +1118,7 @@ fn test_create_tx_invalid_rbf_sequence() {
 #[test]
 fn test_create_tx_custom_rbf_sequence() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -697,7 +1131,7 @@ fn test_create_tx_custom_rbf_sequence() {
 #[test]
 fn test_create_tx_change_policy() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -719,88 +1153,369 @@ fn test_create_tx_change_policy() {
 }
 
 #[test]
-fn test_create_tx_default_sequence() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+fn test_change_descriptor_script_type() {
+    let (mut wallet, _) = get_funded_wallet_with_change(get_test_tr_single_sig(), get_test_wpkh());
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
-    let psbt = builder.finish().unwrap();
-
-    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFE));
-}
+    let tx = builder.finish().unwrap().unsigned_tx;
+    let (vout, change) = tx
+        .output
+        .iter()
+        .enumerate()
+        .find(|(_, txout)| wallet.is_mine(&txout.script_pubkey))
+        .unwrap();
+    assert!(change.script_pubkey.is_p2wpkh());
+    assert_eq!(
+        wallet.derivation_of_spk(&change.script_pubkey),
+        Some((KeychainKind::Internal, 0))
+    );
 
-macro_rules! check_fee {
-    ($wallet:expr, $psbt: expr) => {{
-        let tx = $psbt.clone().extract_tx().expect("failed to extract tx");
-        let tx_fee = $wallet.calculate_fee(&tx).ok();
-        assert_eq!(tx_fee, $psbt.fee_amount());
-        tx_fee
-    }};
-}
+    let change_outpoint = OutPoint::new(tx.compute_txid(), vout as u32);
+    let height = wallet.latest_checkpoint().height();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Confirmed { height, time: 0 })
+        .unwrap();
+    assert!(wallet.get_utxo(change_outpoint).is_some());
 
-#[test]
-fn test_create_tx_drain_wallet_and_drain_to() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    // the change is selected by the change policy
     let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    builder
+        .drain_to(addr.script_pubkey())
+        .drain_wallet()
+        .only_spend_change();
     let psbt = builder.finish().unwrap();
-    let fee = check_fee!(wallet, psbt);
-
-    assert_eq!(psbt.unsigned_tx.output.len(), 1);
-    assert_eq!(
-        psbt.unsigned_tx.output[0].value,
-        Amount::from_sat(50_000) - fee.unwrap_or(Amount::ZERO)
-    );
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, change_outpoint);
 }
 
 #[test]
-fn test_create_tx_drain_wallet_and_drain_to_and_with_recipient() {
+fn test_set_change_descriptor() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+    assert_eq!(wallet.change_keychain(), KeychainKind::Internal);
+    wallet
+        .set_change_descriptor(0, get_test_tr_single_sig())
+        .unwrap();
+    assert_eq!(wallet.change_keychain(), KeychainKind::Custom(0));
+    // setting the same descriptor again is a no-op
+    wallet
+        .set_change_descriptor(0, get_test_tr_single_sig())
+        .unwrap();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
-    let drain_addr = wallet.next_unused_address(KeychainKind::External);
+
     let mut builder = wallet.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
-        .drain_to(drain_addr.script_pubkey())
-        .drain_wallet();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
     let psbt = builder.finish().unwrap();
-    let fee = check_fee!(wallet, psbt);
-    let outputs = psbt.unsigned_tx.output;
-
-    assert_eq!(outputs.len(), 2);
-    let main_output = outputs
-        .iter()
-        .find(|x| x.script_pubkey == addr.script_pubkey())
-        .unwrap();
-    let drain_output = outputs
+    let annotation = wallet.annotate_psbt(&psbt);
+    let tx = psbt.unsigned_tx;
+    let vout = annotation
+        .outputs
         .iter()
-        .find(|x| x.script_pubkey == drain_addr.script_pubkey())
+        .position(|output| output.is_change)
         .unwrap();
-    assert_eq!(main_output.value, Amount::from_sat(20_000));
+    assert!(tx.output[vout].script_pubkey.is_p2tr());
     assert_eq!(
-        drain_output.value,
-        Amount::from_sat(30_000) - fee.unwrap_or(Amount::ZERO)
+        annotation.outputs[vout].derivation,
+        Some((KeychainKind::Custom(0), 0))
     );
-}
 
-#[test]
-fn test_create_tx_drain_to_and_utxos() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
-    let utxos: Vec<_> = wallet.list_unspent().map(|u| u.outpoint).collect();
+    // unconfirmed change is trusted
+    let change_outpoint = OutPoint::new(tx.compute_txid(), vout as u32);
+    let change_value = tx.output[vout].value;
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    assert_eq!(wallet.balance().trusted_pending, change_value);
+
+    // the change is selected by the change policy
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
-        .add_utxos(&utxos)
-        .unwrap();
+        .drain_wallet()
+        .only_spend_change();
     let psbt = builder.finish().unwrap();
-    let fee = check_fee!(wallet, psbt);
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, change_outpoint);
 
-    assert_eq!(psbt.unsigned_tx.output.len(), 1);
-    assert_eq!(
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(1_000))
+        .do_not_spend_change();
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+}
+
+#[test]
+fn test_create_tx_spend_from_keychain() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let change_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::Internal)
+                .unwrap()
+                .script_pubkey(),
+            value: Amount::from_sat(30_000),
+        }],
+    };
+    let change_txid = change_tx.compute_txid();
+    wallet
+        .insert_tx(
+            change_tx,
+            ConfirmationTime::Confirmed {
+                height: 2_000,
+                time: 200,
+            },
+        )
+        .unwrap();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .spend_from_keychain(KeychainKind::Internal);
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output.txid, change_txid);
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
+        .spend_from_keychain(KeychainKind::External);
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_ne!(psbt.unsigned_tx.input[0].previous_output.txid, change_txid);
+
+    // the change keychain alone can't cover the amount
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
+        .spend_from_keychain(KeychainKind::Internal);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+}
+
+#[test]
+fn test_create_tx_default_sequence() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFE));
+}
+
+macro_rules! check_fee {
+    ($wallet:expr, $psbt: expr) => {{
+        let tx = $psbt.clone().extract_tx().expect("failed to extract tx");
+        let tx_fee = $wallet.calculate_fee(&tx).ok();
+        assert_eq!(tx_fee, $psbt.fee_amount());
+        tx_fee
+    }};
+}
+
+#[test]
+fn test_add_recipient_address() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet
+        .parse_address("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap();
+    let mainnet_addr = Address::from_str("bc1qxlh2mnc0yqwas76gqq665qkggee5m98t8yskd8")
+        .unwrap()
+        .assume_checked();
+
+    let mut builder = wallet.build_tx();
+    assert_matches!(
+        builder.add_recipient_address(&mainnet_addr, Amount::from_sat(25_000)),
+        Err(AddRecipientError::WrongNetwork { address, network: Network::Regtest })
+        if address == mainnet_addr
+    );
+    builder
+        .add_recipient_address(&addr, Amount::from_sat(25_000))
+        .unwrap();
+    let psbt = builder.finish().unwrap();
+
+    assert!(psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == addr.script_pubkey()
+            && txout.value == Amount::from_sat(25_000)));
+    assert!(!psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == mainnet_addr.script_pubkey()));
+}
+
+#[test]
+fn test_create_tx_drain_wallet_and_drain_to() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt);
+
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert_eq!(
+        psbt.unsigned_tx.output[0].value,
+        Amount::from_sat(50_000) - fee.unwrap_or(Amount::ZERO)
+    );
+}
+
+#[test]
+fn test_build_sweep() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    // spending this output at 5 sat/vb costs more than it is worth
+    let dust = receive_output_in_latest_block(&mut wallet, 300);
+    let addr = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked();
+    let psbt = wallet
+        .build_sweep(&addr, FeeRate::from_sat_per_vb_unchecked(5))
+        .unwrap();
+    let fee = check_fee!(wallet, psbt);
+
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_ne!(psbt.unsigned_tx.input[0].previous_output, dust);
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert_eq!(
+        psbt.unsigned_tx.output[0].script_pubkey,
+        addr.script_pubkey()
+    );
+    assert_eq!(
+        psbt.unsigned_tx.output[0].value,
+        Amount::from_sat(50_000) - fee.unwrap_or(Amount::ZERO)
+    );
+    assert_fee_rate!(psbt, fee.unwrap_or(Amount::ZERO), FeeRate::from_sat_per_vb_unchecked(5), @add_signature);
+
+    // at a lower fee rate the small output is worth spending
+    let psbt = wallet.build_sweep(&addr, FeeRate::BROADCAST_MIN).unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+}
+
+#[test]
+fn test_build_sweep_no_economical_utxo() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked();
+    assert_matches!(
+        wallet.build_sweep(&addr, FeeRate::from_sat_per_vb_unchecked(1_000)),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+}
+
+#[test]
+fn test_build_consolidation_below() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    // spending this output at 5 sat/vb costs more than it is worth
+    let dust = receive_output_in_latest_block(&mut wallet, 300);
+    let small_1 = receive_output_in_latest_block(&mut wallet, 2_000);
+    let small_2 = receive_output_in_latest_block(&mut wallet, 3_000);
+    let to = wallet
+        .next_unused_address(KeychainKind::External)
+        .unwrap()
+        .script_pubkey();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(5);
+
+    let psbt = wallet
+        .build_consolidation_below(Amount::from_sat(10_000), to.clone(), fee_rate)
+        .unwrap();
+    let fee = check_fee!(wallet, psbt);
+
+    let spent = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|txin| txin.previous_output)
+        .collect::<Vec<_>>();
+    assert_eq!(spent.len(), 2);
+    assert!(spent.contains(&small_1) && spent.contains(&small_2));
+    assert!(!spent.contains(&dust));
+    assert!(!spent.contains(&OutPoint { txid, vout: 0 }));
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert_eq!(psbt.unsigned_tx.output[0].script_pubkey, to);
+    assert_eq!(
+        psbt.unsigned_tx.output[0].value,
+        Amount::from_sat(5_000) - fee.unwrap()
+    );
+
+    // the only utxo below the threshold is uneconomical
+    assert_matches!(
+        wallet.build_consolidation_below(Amount::from_sat(1_000), to, fee_rate),
+        Err(CreateTxError::NoUtxosSelected)
+    );
+}
+
+#[test]
+fn test_create_tx_drain_wallet_and_drain_to_and_with_recipient() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+        .unwrap()
+        .assume_checked();
+    let drain_addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .drain_to(drain_addr.script_pubkey())
+        .drain_wallet();
+    let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt);
+    let outputs = psbt.unsigned_tx.output;
+
+    assert_eq!(outputs.len(), 2);
+    let main_output = outputs
+        .iter()
+        .find(|x| x.script_pubkey == addr.script_pubkey())
+        .unwrap();
+    let drain_output = outputs
+        .iter()
+        .find(|x| x.script_pubkey == drain_addr.script_pubkey())
+        .unwrap();
+    assert_eq!(main_output.value, Amount::from_sat(20_000));
+    assert_eq!(
+        drain_output.value,
+        Amount::from_sat(30_000) - fee.unwrap_or(Amount::ZERO)
+    );
+}
+
+#[test]
+fn test_create_tx_drain_to_and_utxos() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let utxos: Vec<_> = wallet.list_unspent().map(|u| u.outpoint).collect();
+    let mut builder = wallet.build_tx();
+    builder
+        .drain_to(addr.script_pubkey())
+        .add_utxos(&utxos)
+        .unwrap();
+    let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt);
+
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert_eq!(
         psbt.unsigned_tx.output[0].value,
         Amount::from_sat(50_000) - fee.unwrap_or(Amount::ZERO)
     );
@@ -810,7 +1525,7 @@ fn test_create_tx_drain_to_and_utxos() {
 #[should_panic(expected = "NoRecipients")]
 fn test_create_tx_drain_to_no_drain_wallet_no_utxos() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let drain_addr = wallet.next_unused_address(KeychainKind::External);
+    let drain_addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(drain_addr.script_pubkey());
     builder.finish().unwrap();
@@ -819,7 +1534,7 @@ fn test_create_tx_drain_to_no_drain_wallet_no_utxos() {
 #[test]
 fn test_create_tx_default_fee_rate() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
     let psbt = builder.finish().unwrap();
@@ -831,7 +1546,7 @@ fn test_create_tx_default_fee_rate() {
 #[test]
 fn test_create_tx_custom_fee_rate() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -845,7 +1560,7 @@ fn test_create_tx_custom_fee_rate() {
 #[test]
 fn test_create_tx_absolute_fee() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
@@ -865,7 +1580,7 @@ fn test_create_tx_absolute_fee() {
 #[test]
 fn test_create_tx_absolute_zero_fee() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
@@ -886,7 +1601,7 @@ fn test_create_tx_absolute_zero_fee() {
 #[should_panic(expected = "InsufficientFunds")]
 fn test_create_tx_absolute_high_fee() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
@@ -900,7 +1615,7 @@ fn test_create_tx_add_change() {
     use bdk_wallet::wallet::tx_builder::TxOrdering;
 
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -917,488 +1632,1379 @@ fn test_create_tx_add_change() {
 }
 
 #[test]
-fn test_create_tx_skip_change_dust() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+fn test_create_tx_change_to_index() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let change_spk = wallet
+        .peek_address(KeychainKind::Internal, 5)
+        .unwrap()
+        .script_pubkey();
+    assert_eq!(wallet.derivation_index(KeychainKind::Internal), None);
+
     let mut builder = wallet.build_tx();
-    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(49_800));
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .change_to_index(KeychainKind::Internal, 5);
     let psbt = builder.finish().unwrap();
-    let fee = check_fee!(wallet, psbt);
 
-    assert_eq!(psbt.unsigned_tx.output.len(), 1);
-    assert_eq!(psbt.unsigned_tx.output[0].value.to_sat(), 49_800);
-    assert_eq!(fee.unwrap_or(Amount::ZERO), Amount::from_sat(200));
-}
+    assert_eq!(psbt.unsigned_tx.output.len(), 2);
+    assert!(psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == change_spk));
+    assert_eq!(wallet.derivation_index(KeychainKind::Internal), Some(5));
 
-#[test]
-#[should_panic(expected = "InsufficientFunds")]
-fn test_create_tx_drain_to_dust_amount() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
-    // very high fee rate, so that the only output would be below dust
+    // the external address at index 0 has already received funds
     let mut builder = wallet.build_tx();
     builder
-        .drain_to(addr.script_pubkey())
-        .drain_wallet()
-        .fee_rate(FeeRate::from_sat_per_vb_unchecked(454));
-    builder.finish().unwrap();
-}
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .change_to_index(KeychainKind::External, 0);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::ChangeIndexUsed {
+            keychain: KeychainKind::External,
+            index: 0
+        })
+    );
 
-#[test]
-fn test_create_tx_ordering_respected() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    // a hardened index cannot be derived, and nothing must be revealed on error
     let mut builder = wallet.build_tx();
     builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
-        .ordering(bdk_wallet::wallet::tx_builder::TxOrdering::Bip69Lexicographic);
-    let psbt = builder.finish().unwrap();
-    let fee = check_fee!(wallet, psbt);
-
-    assert_eq!(psbt.unsigned_tx.output.len(), 3);
-    assert_eq!(
-        psbt.unsigned_tx.output[0].value,
-        Amount::from_sat(10_000) - fee.unwrap_or(Amount::ZERO)
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .change_to_index(KeychainKind::Internal, 1 << 31);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::ChangeIndexUnavailable {
+            keychain: KeychainKind::Internal,
+            ..
+        })
     );
-    assert_eq!(psbt.unsigned_tx.output[1].value, Amount::from_sat(10_000));
-    assert_eq!(psbt.unsigned_tx.output[2].value, Amount::from_sat(30_000));
+    assert_eq!(wallet.derivation_index(KeychainKind::Internal), Some(5));
 }
 
 #[test]
-fn test_create_tx_default_sighash() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
-    let mut builder = wallet.build_tx();
-    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(30_000));
-    let psbt = builder.finish().unwrap();
+fn test_create_tx_does_not_respend_unconfirmed_spent_utxos() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let funded_op = OutPoint { txid, vout: 0 };
+    let extra_op = receive_output_in_latest_block(&mut wallet, 25_000);
+    let foreign_spk = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+    let spending_tx = |outpoints: &[OutPoint], value: u64| Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: outpoints
+            .iter()
+            .map(|&previous_output| TxIn {
+                previous_output,
+                ..Default::default()
+            })
+            .collect(),
+        output: vec![TxOut {
+            script_pubkey: foreign_spk.clone(),
+            value: Amount::from_sat(value),
+        }],
+    };
 
-    assert_eq!(psbt.inputs[0].sighash_type, None);
-}
+    // `tx1` spends both utxos but is replaced by `tx2`, which only spends `funded_op`
+    let tx1 = spending_tx(&[funded_op, extra_op], 74_000);
+    let tx2 = spending_tx(&[funded_op], 49_000);
+    wallet
+        .insert_tx(tx1, ConfirmationTime::Unconfirmed { last_seen: 1 })
+        .unwrap();
+    wallet
+        .insert_tx(tx2, ConfirmationTime::Unconfirmed { last_seen: 2 })
+        .unwrap();
+    let unspent = wallet
+        .list_unspent()
+        .map(|u| u.outpoint)
+        .collect::<Vec<_>>();
+    assert_eq!(unspent, vec![extra_op]);
+
+    // `extra_op` is still spent by the unconfirmed `tx1`, so it is not selected by default
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(foreign_spk.clone(), Amount::from_sat(10_000));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
 
-#[test]
-fn test_create_tx_custom_sighash() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
     let mut builder = wallet.build_tx();
     builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .sighash(EcdsaSighashType::Single.into());
+        .add_recipient(foreign_spk, Amount::from_sat(10_000))
+        .allow_respending_unconfirmed();
     let psbt = builder.finish().unwrap();
-
-    assert_eq!(
-        psbt.inputs[0].sighash_type,
-        Some(EcdsaSighashType::Single.into())
-    );
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, extra_op);
 }
 
 #[test]
-fn test_create_tx_input_hd_keypaths() {
-    use bitcoin::bip32::{DerivationPath, Fingerprint};
-    use core::str::FromStr;
+fn test_create_tx_respends_utxos_of_conflicted_out_spenders() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let op_a = OutPoint { txid, vout: 0 };
+    let op_b = receive_output_in_latest_block(&mut wallet, 25_000);
+    let op_c = receive_output_in_latest_block(&mut wallet, 30_000);
+    let foreign_spk = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+    let spending_tx = |outpoints: &[OutPoint], value: u64| Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: outpoints
+            .iter()
+            .map(|&previous_output| TxIn {
+                previous_output,
+                ..Default::default()
+            })
+            .collect(),
+        output: vec![TxOut {
+            script_pubkey: foreign_spk.clone(),
+            value: Amount::from_sat(value),
+        }],
+    };
 
-    let (mut wallet, _) = get_funded_wallet("wpkh([d34db33f/44'/0'/0']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    // `tx1` spends `op_a` and `op_b`, its replacement `tx2` spends `op_a` and `op_c` and confirms
+    let tx1 = spending_tx(&[op_a, op_b], 74_000);
+    let tx2 = spending_tx(&[op_a, op_c], 79_000);
+    wallet
+        .insert_tx(tx1, ConfirmationTime::Unconfirmed { last_seen: 1 })
+        .unwrap();
+    let height = wallet.latest_checkpoint().height();
+    wallet
+        .insert_tx(tx2, ConfirmationTime::Confirmed { height, time: 0 })
+        .unwrap();
+    let unspent = wallet
+        .list_unspent()
+        .map(|u| u.outpoint)
+        .collect::<Vec<_>>();
+    assert_eq!(unspent, vec![op_b]);
+
+    // `tx1` can never be mined, so `op_b` is selected without `allow_respending_unconfirmed`
     let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    builder.add_recipient(foreign_spk, Amount::from_sat(10_000));
     let psbt = builder.finish().unwrap();
-
-    assert_eq!(psbt.inputs[0].bip32_derivation.len(), 1);
-    assert_eq!(
-        psbt.inputs[0].bip32_derivation.values().next().unwrap(),
-        &(
-            Fingerprint::from_str("d34db33f").unwrap(),
-            DerivationPath::from_str("m/44'/0'/0'/0/0").unwrap()
-        )
-    );
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, op_b);
 }
 
 #[test]
-fn test_create_tx_output_hd_keypaths() {
-    use bitcoin::bip32::{DerivationPath, Fingerprint};
-    use core::str::FromStr;
-
-    let (mut wallet, _) = get_funded_wallet("wpkh([d34db33f/44'/0'/0']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*)");
+fn test_annotate_psbt() {
+    use bdk_wallet::wallet::{InputAnnotation, OutputAnnotation};
 
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let foreign_spk = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
     let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    builder
+        .add_recipient(foreign_spk.clone(), Amount::from_sat(25_000))
+        .ordering(bdk_wallet::wallet::tx_builder::TxOrdering::Untouched);
     let psbt = builder.finish().unwrap();
 
-    assert_eq!(psbt.outputs[0].bip32_derivation.len(), 1);
-    let expected_derivation_path = format!("m/44'/0'/0'/0/{}", addr.index);
+    let annotation = wallet.annotate_psbt(&psbt);
+    assert_eq!(annotation.inputs, vec![InputAnnotation { is_mine: true }]);
     assert_eq!(
-        psbt.outputs[0].bip32_derivation.values().next().unwrap(),
-        &(
-            Fingerprint::from_str("d34db33f").unwrap(),
-            DerivationPath::from_str(&expected_derivation_path).unwrap()
-        )
+        annotation.outputs,
+        vec![
+            OutputAnnotation {
+                is_mine: false,
+                is_change: false,
+                derivation: None,
+            },
+            OutputAnnotation {
+                is_mine: true,
+                is_change: true,
+                derivation: Some((KeychainKind::Internal, 0)),
+            },
+        ]
     );
 }
 
 #[test]
-fn test_create_tx_set_redeem_script_p2sh() {
-    use bitcoin::hex::FromHex;
+fn test_assert_psbt_matches() {
+    use bdk_wallet::wallet::error::{MismatchError, PaymentMismatch};
+    use bdk_wallet::wallet::PaymentIntent;
 
-    let (mut wallet, _) =
-        get_funded_wallet("sh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let foreign_spk = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+    let other_spk = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
     let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
-    let psbt = builder.finish().unwrap();
+    builder
+        .add_recipient(foreign_spk.clone(), Amount::from_sat(25_000))
+        .ordering(bdk_wallet::wallet::tx_builder::TxOrdering::Untouched);
+    let mut psbt = builder.finish().unwrap();
+    let fee = psbt.fee_amount().unwrap();
 
+    // the change output is the wallet's
+    let intent = PaymentIntent {
+        recipients: vec![(foreign_spk.clone(), Amount::from_sat(25_000))],
+        max_fee: Some(fee),
+    };
+    assert_eq!(wallet.assert_psbt_matches(&psbt, &intent), Ok(()));
+
+    let intent = PaymentIntent {
+        recipients: vec![
+            (foreign_spk.clone(), Amount::from_sat(20_000)),
+            (other_spk.clone(), Amount::from_sat(1_000)),
+        ],
+        max_fee: Some(fee - Amount::from_sat(1)),
+    };
     assert_eq!(
-        psbt.inputs[0].redeem_script,
-        Some(ScriptBuf::from(
-            Vec::<u8>::from_hex(
-                "21032b0558078bec38694a84933d659303e2575dae7e91685911454115bfd64487e3ac"
-            )
-            .unwrap()
-        ))
+        wallet.assert_psbt_matches(&psbt, &intent),
+        Err(MismatchError {
+            mismatches: vec![
+                PaymentMismatch::WrongAmount {
+                    vout: 0,
+                    expected: Amount::from_sat(20_000),
+                    actual: Amount::from_sat(25_000),
+                },
+                PaymentMismatch::MissingRecipient {
+                    script_pubkey: other_spk.clone(),
+                    amount: Amount::from_sat(1_000),
+                },
+                PaymentMismatch::FeeTooHigh {
+                    max: fee - Amount::from_sat(1),
+                    actual: fee,
+                },
+            ]
+        })
     );
-    assert_eq!(psbt.inputs[0].witness_script, None);
-}
-
-#[test]
-fn test_create_tx_set_witness_script_p2wsh() {
-    use bitcoin::hex::FromHex;
 
-    let (mut wallet, _) =
-        get_funded_wallet("wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
-    let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
-    let psbt = builder.finish().unwrap();
+    // inflating the value of the previous output in the PSBT doesn't hide the fee
+    let mut tampered = psbt.clone();
+    let witness_utxo = tampered.inputs[0].witness_utxo.as_mut().unwrap();
+    witness_utxo.value += Amount::from_sat(1_000);
+    tampered.inputs[0].non_witness_utxo = None;
+    let intent = PaymentIntent {
+        recipients: vec![(foreign_spk.clone(), Amount::from_sat(25_000))],
+        max_fee: Some(fee - Amount::from_sat(1)),
+    };
+    assert_eq!(
+        wallet.assert_psbt_matches(&tampered, &intent),
+        Err(MismatchError {
+            mismatches: vec![PaymentMismatch::FeeTooHigh {
+                max: fee - Amount::from_sat(1),
+                actual: fee,
+            }]
+        })
+    );
 
-    assert_eq!(psbt.inputs[0].redeem_script, None);
+    // tamper with the change output, and spend an output unknown to the wallet
+    psbt.unsigned_tx.output[1].script_pubkey = other_spk.clone();
+    let change = psbt.unsigned_tx.output[1].value;
+    psbt.unsigned_tx.input[0].previous_output = OutPoint::new(Txid::all_zeros(), 0);
+    let intent = PaymentIntent {
+        recipients: vec![(foreign_spk, Amount::from_sat(25_000))],
+        max_fee: Some(fee),
+    };
     assert_eq!(
-        psbt.inputs[0].witness_script,
-        Some(ScriptBuf::from(
-            Vec::<u8>::from_hex(
-                "21032b0558078bec38694a84933d659303e2575dae7e91685911454115bfd64487e3ac"
-            )
-            .unwrap()
-        ))
+        wallet.assert_psbt_matches(&psbt, &intent),
+        Err(MismatchError {
+            mismatches: vec![
+                PaymentMismatch::UnexpectedOutput {
+                    vout: 1,
+                    script_pubkey: other_spk,
+                    amount: change,
+                },
+                PaymentMismatch::UnknownFee,
+            ]
+        })
     );
 }
 
 #[test]
-fn test_create_tx_set_redeem_witness_script_p2wsh_p2sh() {
-    let (mut wallet, _) =
-        get_funded_wallet("sh(wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+fn test_create_tx_reserve_cpfp_output() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let foreign_spk = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+
+    // the change is added to the reserved output
     let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    builder
+        .add_recipient(foreign_spk.clone(), Amount::from_sat(10_000))
+        .reserve_cpfp_output(Amount::from_sat(20_000));
     let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt).unwrap();
+    let reserved = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .find(|txout| wallet.is_mine(&txout.script_pubkey))
+        .unwrap();
+    assert_eq!(psbt.unsigned_tx.output.len(), 2);
+    assert_eq!(reserved.value, Amount::from_sat(50_000 - 10_000) - fee);
 
-    let script = ScriptBuf::from_hex(
-        "21032b0558078bec38694a84933d659303e2575dae7e91685911454115bfd64487e3ac",
-    )
-    .unwrap();
-
-    assert_eq!(psbt.inputs[0].redeem_script, Some(script.to_p2wsh()));
-    assert_eq!(psbt.inputs[0].witness_script, Some(script));
-}
-
-#[test]
-fn test_create_tx_non_witness_utxo() {
-    let (mut wallet, _) =
-        get_funded_wallet("sh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    // the reserved output is kept even if there isn't enough left for a change output
     let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    builder
+        .add_recipient(foreign_spk.clone(), Amount::from_sat(29_700))
+        .reserve_cpfp_output(Amount::from_sat(20_000));
     let psbt = builder.finish().unwrap();
+    check_fee!(wallet, psbt);
+    assert_eq!(psbt.unsigned_tx.output.len(), 2);
+    assert!(psbt.unsigned_tx.output.iter().any(
+        |txout| wallet.is_mine(&txout.script_pubkey) && txout.value == Amount::from_sat(20_000)
+    ));
 
-    assert!(psbt.inputs[0].non_witness_utxo.is_some());
-    assert!(psbt.inputs[0].witness_utxo.is_none());
-}
-
-#[test]
-fn test_create_tx_only_witness_utxo() {
-    let (mut wallet, _) =
-        get_funded_wallet("wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
     let mut builder = wallet.build_tx();
     builder
-        .drain_to(addr.script_pubkey())
-        .only_witness_utxo()
-        .drain_wallet();
-    let psbt = builder.finish().unwrap();
+        .add_recipient(foreign_spk.clone(), Amount::from_sat(10_000))
+        .reserve_cpfp_output(Amount::from_sat(100));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::OutputBelowDustLimit(1))
+    );
 
-    assert!(psbt.inputs[0].non_witness_utxo.is_none());
-    assert!(psbt.inputs[0].witness_utxo.is_some());
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(foreign_spk.clone(), Amount::from_sat(10_000))
+        .drain_to(foreign_spk)
+        .reserve_cpfp_output(Amount::from_sat(20_000));
+    assert_matches!(builder.finish(), Err(CreateTxError::CpfpReserveNotMine));
 }
 
 #[test]
-fn test_create_tx_shwpkh_has_witness_utxo() {
-    let (mut wallet, _) =
-        get_funded_wallet("sh(wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
-    let mut builder = wallet.build_tx();
-    builder.drain_to(addr.script_pubkey()).drain_wallet();
-    let psbt = builder.finish().unwrap();
+fn test_build_data_tx() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let data = [0x42; 80];
+    let psbt = wallet
+        .build_data_tx(&data, FeeRate::from_sat_per_vb_unchecked(2))
+        .unwrap();
+    check_fee!(wallet, psbt);
+    assert_eq!(psbt.unsigned_tx.output.len(), 2);
+    let op_return = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .find(|txout| txout.script_pubkey.is_op_return())
+        .unwrap();
+    assert_eq!(op_return.value, Amount::ZERO);
+    assert!(op_return.script_pubkey.as_bytes().ends_with(&data));
+    assert!(psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| wallet.is_mine(&txout.script_pubkey)));
 
-    assert!(psbt.inputs[0].witness_utxo.is_some());
+    assert_matches!(
+        wallet.build_data_tx(&[0x42; 81], FeeRate::from_sat_per_vb_unchecked(2)),
+        Err(CreateTxError::OpReturnTooLarge(81))
+    );
+
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Testnet).expect("wallet");
+    assert_matches!(
+        wallet.build_data_tx(&data, FeeRate::from_sat_per_vb_unchecked(2)),
+        Err(CreateTxError::CoinSelection(_))
+    );
 }
 
 #[test]
-fn test_create_tx_both_non_witness_utxo_and_witness_utxo_default() {
-    let (mut wallet, _) =
-        get_funded_wallet("wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+fn test_try_extract_tx() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
-    let psbt = builder.finish().unwrap();
+    let mut psbt = builder.finish().unwrap();
 
-    assert!(psbt.inputs[0].non_witness_utxo.is_some());
-    assert!(psbt.inputs[0].witness_utxo.is_some());
+    assert_matches!(
+        wallet.try_extract_tx(&psbt),
+        Err(ExtractError::NotFinalized(inputs)) if inputs == vec![0]
+    );
+
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                try_finalize: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!finalized);
+    assert_matches!(
+        wallet.try_extract_tx(&psbt),
+        Err(ExtractError::NotFinalized(inputs)) if inputs == vec![0]
+    );
+
+    assert!(wallet.finalize_psbt(&mut psbt, Default::default()).unwrap());
+    let tx = wallet.try_extract_tx(&psbt).unwrap();
+    assert_eq!(tx, psbt.extract_tx().unwrap());
 }
 
 #[test]
-fn test_create_tx_add_utxo() {
+fn test_create_signed_tx() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let small_output_tx = Transaction {
-        input: vec![],
-        output: vec![TxOut {
-            script_pubkey: wallet
-                .next_unused_address(KeychainKind::External)
-                .script_pubkey(),
-            value: Amount::from_sat(25_000),
-        }],
-        version: transaction::Version::non_standard(0),
-        lock_time: absolute::LockTime::ZERO,
-    };
-    wallet
-        .insert_tx(
-            small_output_tx.clone(),
-            ConfirmationTime::Unconfirmed { last_seen: 0 },
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .expect("address")
+        .require_network(Network::Testnet)
+        .unwrap();
+    let tx = wallet
+        .create_signed_tx(
+            vec![(addr.script_pubkey(), Amount::from_sat(25_000))],
+            FeeRate::from_sat_per_vb_unchecked(2),
+            SignOptions::default(),
         )
         .unwrap();
+    assert_eq!(tx.input.len(), 1);
+    assert!(!tx.input[0].witness.is_empty());
+    assert!(tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == addr.script_pubkey()
+            && txout.value == Amount::from_sat(25_000)));
+    let fee = wallet.calculate_fee(&tx).unwrap();
+    assert!(
+        fee >= FeeRate::from_sat_per_vb_unchecked(2)
+            .fee_wu(tx.weight())
+            .unwrap()
+    );
 
-    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
-        .unwrap()
-        .assume_checked();
-    let mut builder = wallet.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .add_utxo(OutPoint {
-            txid: small_output_tx.compute_txid(),
-            vout: 0,
-        })
-        .unwrap();
-    let psbt = builder.finish().unwrap();
-    let sent_received =
-        wallet.sent_and_received(&psbt.clone().extract_tx().expect("failed to extract tx"));
-
-    assert_eq!(
-        psbt.unsigned_tx.input.len(),
-        2,
-        "should add an additional input since 25_000 < 30_000"
-    );
-    assert_eq!(
-        sent_received.0,
-        Amount::from_sat(75_000),
-        "total should be sum of both inputs"
+    // a watch-only wallet can't sign the transaction
+    let (mut wallet, _) = get_funded_wallet("wpkh(tpubD6NzVbkrYhZ4Y55A58Gv9RSNF5hy84b5AJqYy7sCcjFrkcLpPre8kmgfit6kY1Zs3BLgeypTDBZJM222guPpdz7Cup5yzaMu62u7mYGbwFL/0/*)");
+    assert_matches!(
+        wallet.create_signed_tx(
+            vec![(addr.script_pubkey(), Amount::from_sat(25_000))],
+            FeeRate::from_sat_per_vb_unchecked(2),
+            SignOptions::default(),
+        ),
+        Err(CreateSignedTxError::NotFinalized)
     );
 }
 
 #[test]
-#[should_panic(expected = "InsufficientFunds")]
-fn test_create_tx_manually_selected_insufficient() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
-    let small_output_tx = Transaction {
-        input: vec![],
-        output: vec![TxOut {
-            script_pubkey: wallet
-                .next_unused_address(KeychainKind::External)
-                .script_pubkey(),
-            value: Amount::from_sat(25_000),
-        }],
-        version: transaction::Version::non_standard(0),
+fn test_create_tx_too_large() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
+    let tx = Transaction {
+        version: transaction::Version::ONE,
         lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: wallet
+            .reveal_addresses_to(KeychainKind::External, 2_000)
+            .unwrap()
+            .map(|addr| TxOut {
+                script_pubkey: addr.script_pubkey(),
+                value: Amount::from_sat(10_000),
+            })
+            .collect(),
     };
-
+    let height = wallet.latest_checkpoint().height();
     wallet
-        .insert_tx(
-            small_output_tx.clone(),
-            ConfirmationTime::Unconfirmed { last_seen: 0 },
-        )
+        .insert_tx(tx, ConfirmationTime::Confirmed { height, time: 0 })
         .unwrap();
 
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
     let mut builder = wallet.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .add_utxo(OutPoint {
-            txid: small_output_tx.compute_txid(),
-            vout: 0,
-        })
-        .unwrap()
-        .manually_selected_only();
-    builder.finish().unwrap();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::TransactionTooLarge { weight }) if weight > 400_000
+    );
+
+    // spending only some of the utxos is fine
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(1_000_000));
+    assert!(builder.finish().is_ok());
 }
 
 #[test]
-#[should_panic(expected = "SpendingPolicyRequired(External)")]
-fn test_create_tx_policy_path_required() {
-    let (mut wallet, _) = get_funded_wallet(get_test_a_or_b_plus_csv());
-
+fn test_create_tx_details_change() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
-    let mut builder = wallet.build_tx();
-    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
-    builder.finish().unwrap();
-}
 
-#[test]
-fn test_create_tx_policy_path_no_csv() {
-    let (desc, change_desc) = get_test_wpkh_with_change_desc();
-    let mut wallet = Wallet::new(desc, change_desc, Network::Regtest).expect("wallet");
+    for _ in 0..10 {
+        let mut builder = wallet.build_tx();
+        builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+        let (psbt, TxDetails { change, .. }) = builder.finish_with_details().unwrap();
+        let change = change.expect("must have change");
+        let txout = &psbt.unsigned_tx.output[change.vout];
+        assert_eq!(txout.script_pubkey, change.script_pubkey);
+        assert_eq!(
+            wallet
+                .derivation_of_spk(&change.script_pubkey)
+                .map(|(k, _)| k),
+            Some(KeychainKind::Internal)
+        );
+    }
 
-    let tx = Transaction {
-        version: transaction::Version::non_standard(0),
-        lock_time: absolute::LockTime::ZERO,
-        input: vec![],
-        output: vec![TxOut {
-            script_pubkey: wallet
-                .next_unused_address(KeychainKind::External)
-                .script_pubkey(),
-            value: Amount::from_sat(50_000),
-        }],
-    };
-    wallet
-        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
-        .unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let (_, TxDetails { change, .. }) = builder.finish_with_details().unwrap();
+    assert_eq!(change.map(|c| c.vout), Some(0));
 
-    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
-    let root_id = external_policy.id;
-    // child #0 is just the key "A"
-    let path = vec![(root_id, vec![0])].into_iter().collect();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(49_800));
+    let (_, TxDetails { change, .. }) = builder.finish_with_details().unwrap();
+    assert_eq!(change, None);
+}
 
+#[test]
+fn test_create_tx_details_fee() {
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
-    let mut builder = wallet.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .policy_path(path, KeychainKind::External);
-    let psbt = builder.finish().unwrap();
 
-    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFF));
-}
+    for descriptor in [get_test_wpkh(), get_test_tr_single_sig_xprv()] {
+        let (mut wallet, _) = get_funded_wallet(descriptor);
+        let mut builder = wallet.build_tx();
+        builder
+            .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+            .fee_rate(FeeRate::from_sat_per_vb_unchecked(5));
+        let (
+            mut psbt,
+            TxDetails {
+                fee: fee_details, ..
+            },
+        ) = builder.finish_with_details().unwrap();
 
-#[test]
-fn test_create_tx_policy_path_use_csv() {
-    let (mut wallet, _) = get_funded_wallet(get_test_a_or_b_plus_csv());
+        assert_eq!(fee_details.absolute_fee, psbt.fee().unwrap());
+        assert_eq!(
+            fee_details.change_amount,
+            Some(Amount::from_sat(50_000 - 25_000) - fee_details.absolute_fee)
+        );
 
-    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
-    let root_id = external_policy.id;
-    // child #1 is or(pk(B),older(144))
-    let path = vec![(root_id, vec![1])].into_iter().collect();
+        let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+        assert!(finalized);
+        let tx = psbt.extract_tx().expect("failed to extract tx");
+        // signatures can be shorter than their estimated size
+        assert!(fee_details.vsize >= tx.vsize() && fee_details.vsize <= tx.vsize() + 1);
+        assert!(fee_details.fee_rate <= fee_details.absolute_fee / tx.weight());
+    }
+}
 
+#[test]
+fn test_create_tx_fee_rate_for_target() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
+    let estimates: BTreeMap<u16, FeeRate> = [
+        (1, FeeRate::from_sat_per_vb_unchecked(20)),
+        (6, FeeRate::from_sat_per_vb_unchecked(5)),
+    ]
+    .into();
+
     let mut builder = wallet.build_tx();
     builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .policy_path(path, KeychainKind::External);
-    let psbt = builder.finish().unwrap();
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate_for_target(&estimates, 3)
+        .unwrap();
+    let (
+        psbt,
+        TxDetails {
+            fee: fee_details, ..
+        },
+    ) = builder.finish_with_details().unwrap();
+    // the estimate for 1 block is the best one for a target of 3 blocks
+    assert_fee_rate!(psbt, fee_details.absolute_fee, FeeRate::from_sat_per_vb_unchecked(20), @add_signature);
 
-    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(144));
+    let mut builder = wallet.build_tx();
+    assert_matches!(
+        builder.fee_rate_for_target(&estimates, 0),
+        Err(FeeRateForTargetError::Unavailable { target: 0 })
+    );
 }
 
 #[test]
-fn test_create_tx_policy_path_ignored_subtree_with_csv() {
-    let (mut wallet, _) = get_funded_wallet("wsh(or_d(pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu),or_i(and_v(v:pkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW),older(30)),and_v(v:pkh(cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8),older(90)))))");
-
-    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
-    let root_id = external_policy.id;
-    // child #0 is pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu)
-    let path = vec![(root_id, vec![0])].into_iter().collect();
-
+fn test_create_tx_details_coin_selection() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let required = receive_output_in_latest_block(&mut wallet, 30_000);
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(5);
+
     let mut builder = wallet.build_tx();
     builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
-        .policy_path(path, KeychainKind::External);
-    let psbt = builder.finish().unwrap();
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .add_utxo(required)
+        .unwrap()
+        .fee_rate(fee_rate);
+    let (
+        psbt,
+        TxDetails {
+            coin_selection: details,
+            ..
+        },
+    ) = builder.finish_with_details().unwrap();
+
+    assert_eq!(details.considered.len(), 2);
+    assert!(details.considered.contains(&OutPoint { txid, vout: 0 }));
+    assert_eq!(details.required, vec![required]);
+    // the required utxo covers the payment on its own
+    assert_eq!(details.selected, vec![required]);
+    assert_eq!(details.selected_amount, Amount::from_sat(30_000));
+    assert_eq!(details.fee, psbt.fee().unwrap());
+    assert_eq!(details.fee_rate, fee_rate);
+    assert_eq!(
+        details.change_amount,
+        Some(Amount::from_sat(30_000 - 25_000) - details.fee)
+    );
+    assert!(details.change_fee.is_some());
+    // the change is spent with the wallet's wpkh descriptor
+    assert_eq!(
+        details.change_spend_weight,
+        Some(
+            TxIn::default().segwit_weight()
+                + wallet
+                    .input_weight_estimate(KeychainKind::Internal)
+                    .unwrap()
+        )
+    );
+    assert_eq!(details.excess_to_fee, Amount::ZERO);
+    // at a long term fee rate equal to the current one, only the change costs anything
+    assert!(details.waste(fee_rate) > details.change_fee.unwrap().to_sat() as i64);
+}
 
-    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFE));
+#[test]
+fn test_wallet_id() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Testnet).unwrap();
+    let id = wallet.id();
+
+    // revealing addresses doesn't change the id
+    let _ = wallet
+        .reveal_addresses_to(KeychainKind::External, 10)
+        .unwrap();
+    assert_eq!(wallet.id(), id);
+
+    let other = Wallet::new(desc, change_desc, Network::Testnet).unwrap();
+    assert_eq!(other.id(), id);
+    let other = Wallet::new(desc, change_desc, Network::Signet).unwrap();
+    assert_ne!(other.id(), id);
+    let other = Wallet::new(change_desc, desc, Network::Testnet).unwrap();
+    assert_ne!(other.id(), id);
 }
 
 #[test]
-fn test_create_tx_global_xpubs_with_origin() {
-    use bitcoin::bip32;
-    let (mut wallet, _) = get_funded_wallet("wpkh([73756c7f/48'/0'/0'/2']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+fn test_create_tx_add_recipient_str() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+
     let mut builder = wallet.build_tx();
     builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
-        .add_global_xpubs();
+        .add_recipient_str(addr.script_pubkey(), "0.0001 BTC")
+        .unwrap()
+        .add_recipient_str(addr.script_pubkey(), "2500 sat")
+        .unwrap()
+        // any whitespace separates the value from the denomination
+        .add_recipient_str(addr.script_pubkey(), " 3000\tsat  ")
+        .unwrap();
     let psbt = builder.finish().unwrap();
+    let values = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|txout| txout.value)
+        .collect::<Vec<_>>();
+    assert!(values.contains(&Amount::from_sat(10_000)));
+    assert!(values.contains(&Amount::from_sat(2_500)));
+    assert!(values.contains(&Amount::from_sat(3_000)));
 
-    let key = bip32::Xpub::from_str("tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3").unwrap();
-    let fingerprint = bip32::Fingerprint::from_hex("73756c7f").unwrap();
-    let path = bip32::DerivationPath::from_str("m/48'/0'/0'/2'").unwrap();
+    let mut builder = wallet.build_tx();
+    for amount in [
+        "0.000000001 BTC",
+        "0.001",
+        "-1 BTC",
+        "1 MBTC",
+        "one BTC",
+        "1 BTC extra",
+    ] {
+        assert!(
+            builder
+                .add_recipient_str(addr.script_pubkey(), amount)
+                .is_err(),
+            "{} must be rejected",
+            amount
+        );
+    }
+}
 
-    assert_eq!(psbt.xpub.len(), 2);
-    assert_eq!(psbt.xpub.get(&key), Some(&(fingerprint, path)));
+#[test]
+fn test_reconcile_utxos() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let utxo = wallet.list_unspent().next().expect("must have a utxo");
+    assert_eq!(utxo.outpoint.txid, txid);
+    let expected = [(utxo.outpoint, utxo.txout.value)];
+    assert!(wallet.reconcile_utxos(&expected).is_consistent());
+
+    let other = receive_output_in_latest_block(&mut wallet, 25_000);
+    let foreign = OutPoint::new(Txid::all_zeros(), 0);
+    let reconciliation = wallet.reconcile_utxos(&[
+        (utxo.outpoint, Amount::from_sat(1)),
+        (foreign, Amount::from_sat(10_000)),
+    ]);
+    assert!(!reconciliation.is_consistent());
+    assert_eq!(
+        reconciliation.unexpected,
+        vec![(other, Amount::from_sat(25_000))]
+    );
+    assert_eq!(
+        reconciliation.missing,
+        vec![(foreign, Amount::from_sat(10_000))]
+    );
+    assert_eq!(
+        reconciliation.mismatched,
+        vec![(utxo.outpoint, utxo.txout.value, Amount::from_sat(1))]
+    );
 }
 
 #[test]
-fn test_add_foreign_utxo() {
-    let (mut wallet1, _) = get_funded_wallet_wpkh();
-    let (wallet2, _) =
-        get_funded_wallet("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+fn test_create_tx_deterministic() {
+    use bdk_wallet::wallet::tx_builder::TxOrdering;
 
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    for value in [12_345, 23_456, 34_567, 7_777, 45_678] {
+        receive_output_in_latest_block(&mut wallet, value);
+    }
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
-    let utxo = wallet2.list_unspent().next().expect("must take!");
-    let foreign_utxo_satisfaction = wallet2
-        .get_descriptor_for_keychain(KeychainKind::External)
-        .max_weight_to_satisfy()
-        .unwrap();
 
-    let psbt_input = psbt::Input {
-        witness_utxo: Some(utxo.txout.clone()),
-        ..Default::default()
+    let build = |wallet: &mut Wallet| {
+        let mut builder = wallet.build_tx();
+        builder
+            .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
+            .fee_rate(FeeRate::from_sat_per_vb_unchecked(5))
+            .deterministic();
+        let psbt = builder.finish().unwrap();
+        // release the change address so the next build can use it again
+        wallet.cancel_tx(&psbt.unsigned_tx);
+        psbt.unsigned_tx
     };
+    let tx = build(&mut wallet);
+    for _ in 0..10 {
+        assert_eq!(build(&mut wallet), tx);
+    }
 
-    let mut builder = wallet1.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
-        .only_witness_utxo()
-        .add_foreign_utxo(
-            utxo.outpoint,
-            psbt_input,
-            foreign_utxo_satisfaction.to_wu() as usize,
-        )
-        .unwrap();
-    let mut psbt = builder.finish().unwrap();
-    wallet1.insert_txout(utxo.outpoint, utxo.txout);
-    let fee = check_fee!(wallet1, psbt);
-    let sent_received =
-        wallet1.sent_and_received(&psbt.clone().extract_tx().expect("failed to extract tx"));
+    // the default shuffled ordering is replaced by BIP69
+    let mut sorted_tx = tx.clone();
+    TxOrdering::Bip69Lexicographic.sort_tx(&mut sorted_tx);
+    assert_eq!(sorted_tx, tx);
+}
 
-    assert_eq!(
-        (sent_received.0 - sent_received.1),
-        Amount::from_sat(10_000) + fee.unwrap_or(Amount::ZERO),
-        "we should have only net spent ~10_000"
-    );
+#[test]
+fn test_create_tx_skip_change_dust() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(49_800));
+    let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt);
 
-    assert!(
-        psbt.unsigned_tx
-            .input
-            .iter()
-            .any(|input| input.previous_output == utxo.outpoint),
-        "foreign_utxo should be in there"
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert_eq!(psbt.unsigned_tx.output[0].value.to_sat(), 49_800);
+    assert_eq!(fee.unwrap_or(Amount::ZERO), Amount::from_sat(200));
+}
+
+#[test]
+#[should_panic(expected = "InsufficientFunds")]
+fn test_create_tx_drain_to_dust_amount() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    // very high fee rate, so that the only output would be below dust
+    let mut builder = wallet.build_tx();
+    builder
+        .drain_to(addr.script_pubkey())
+        .drain_wallet()
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(454));
+    builder.finish().unwrap();
+}
+
+#[test]
+fn test_create_tx_ordering_respected() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .ordering(bdk_wallet::wallet::tx_builder::TxOrdering::Bip69Lexicographic);
+    let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt);
+
+    assert_eq!(psbt.unsigned_tx.output.len(), 3);
+    assert_eq!(
+        psbt.unsigned_tx.output[0].value,
+        Amount::from_sat(10_000) - fee.unwrap_or(Amount::ZERO)
+    );
+    assert_eq!(psbt.unsigned_tx.output[1].value, Amount::from_sat(10_000));
+    assert_eq!(psbt.unsigned_tx.output[2].value, Amount::from_sat(30_000));
+}
+
+#[test]
+fn test_create_tx_default_sighash() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(30_000));
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.inputs[0].sighash_type, None);
+}
+
+#[test]
+fn test_create_tx_custom_sighash() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .sighash(EcdsaSighashType::Single.into());
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(
+        psbt.inputs[0].sighash_type,
+        Some(EcdsaSighashType::Single.into())
+    );
+}
+
+#[test]
+fn test_create_tx_input_hd_keypaths() {
+    use bitcoin::bip32::{DerivationPath, Fingerprint};
+    use core::str::FromStr;
+
+    let (mut wallet, _) = get_funded_wallet("wpkh([d34db33f/44'/0'/0']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*)");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.inputs[0].bip32_derivation.len(), 1);
+    assert_eq!(
+        psbt.inputs[0].bip32_derivation.values().next().unwrap(),
+        &(
+            Fingerprint::from_str("d34db33f").unwrap(),
+            DerivationPath::from_str("m/44'/0'/0'/0/0").unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_create_tx_output_hd_keypaths() {
+    use bitcoin::bip32::{DerivationPath, Fingerprint};
+    use core::str::FromStr;
+
+    let (mut wallet, _) = get_funded_wallet("wpkh([d34db33f/44'/0'/0']tpubDEnoLuPdBep9bzw5LoGYpsxUQYheRQ9gcgrJhJEcdKFB9cWQRyYmkCyRoTqeD4tJYiVVgt6A3rN6rWn9RYhR9sBsGxji29LYWHuKKbdb1ev/0/*)");
+
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.outputs[0].bip32_derivation.len(), 1);
+    let expected_derivation_path = format!("m/44'/0'/0'/0/{}", addr.index);
+    assert_eq!(
+        psbt.outputs[0].bip32_derivation.values().next().unwrap(),
+        &(
+            Fingerprint::from_str("d34db33f").unwrap(),
+            DerivationPath::from_str(&expected_derivation_path).unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_create_tx_set_redeem_script_p2sh() {
+    use bitcoin::hex::FromHex;
+
+    let (mut wallet, _) =
+        get_funded_wallet("sh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(
+        psbt.inputs[0].redeem_script,
+        Some(ScriptBuf::from(
+            Vec::<u8>::from_hex(
+                "21032b0558078bec38694a84933d659303e2575dae7e91685911454115bfd64487e3ac"
+            )
+            .unwrap()
+        ))
+    );
+    assert_eq!(psbt.inputs[0].witness_script, None);
+}
+
+#[test]
+fn test_create_tx_set_witness_script_p2wsh() {
+    use bitcoin::hex::FromHex;
+
+    let (mut wallet, _) =
+        get_funded_wallet("wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.inputs[0].redeem_script, None);
+    assert_eq!(
+        psbt.inputs[0].witness_script,
+        Some(ScriptBuf::from(
+            Vec::<u8>::from_hex(
+                "21032b0558078bec38694a84933d659303e2575dae7e91685911454115bfd64487e3ac"
+            )
+            .unwrap()
+        ))
+    );
+}
+
+#[test]
+fn test_create_tx_set_redeem_witness_script_p2wsh_p2sh() {
+    let (mut wallet, _) =
+        get_funded_wallet("sh(wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    let script = ScriptBuf::from_hex(
+        "21032b0558078bec38694a84933d659303e2575dae7e91685911454115bfd64487e3ac",
+    )
+    .unwrap();
+
+    assert_eq!(psbt.inputs[0].redeem_script, Some(script.to_p2wsh()));
+    assert_eq!(psbt.inputs[0].witness_script, Some(script));
+}
+
+#[test]
+fn test_create_tx_non_witness_utxo() {
+    let (mut wallet, _) =
+        get_funded_wallet("sh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert!(psbt.inputs[0].non_witness_utxo.is_some());
+    assert!(psbt.inputs[0].witness_utxo.is_none());
+}
+
+#[test]
+fn test_create_tx_only_witness_utxo() {
+    let (mut wallet, _) =
+        get_funded_wallet("wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .drain_to(addr.script_pubkey())
+        .only_witness_utxo()
+        .drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert!(psbt.inputs[0].non_witness_utxo.is_none());
+    assert!(psbt.inputs[0].witness_utxo.is_some());
+}
+
+#[test]
+fn test_create_tx_shwpkh_has_witness_utxo() {
+    let (mut wallet, _) =
+        get_funded_wallet("sh(wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert!(psbt.inputs[0].witness_utxo.is_some());
+}
+
+#[test]
+fn test_create_tx_both_non_witness_utxo_and_witness_utxo_default() {
+    let (mut wallet, _) =
+        get_funded_wallet("wsh(pk(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    assert!(psbt.inputs[0].non_witness_utxo.is_some());
+    assert!(psbt.inputs[0].witness_utxo.is_some());
+}
+
+#[test]
+fn test_create_tx_add_utxo() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let small_output_tx = Transaction {
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .unwrap()
+                .script_pubkey(),
+            value: Amount::from_sat(25_000),
+        }],
+        version: transaction::Version::non_standard(0),
+        lock_time: absolute::LockTime::ZERO,
+    };
+    wallet
+        .insert_tx(
+            small_output_tx.clone(),
+            ConfirmationTime::Unconfirmed { last_seen: 0 },
+        )
+        .unwrap();
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .add_utxo(OutPoint {
+            txid: small_output_tx.compute_txid(),
+            vout: 0,
+        })
+        .unwrap();
+    let psbt = builder.finish().unwrap();
+    let sent_received =
+        wallet.sent_and_received(&psbt.clone().extract_tx().expect("failed to extract tx"));
+
+    assert_eq!(
+        psbt.unsigned_tx.input.len(),
+        2,
+        "should add an additional input since 25_000 < 30_000"
+    );
+    assert_eq!(
+        sent_received.0,
+        Amount::from_sat(75_000),
+        "total should be sum of both inputs"
+    );
+}
+
+#[test]
+fn test_create_tx_add_utxo_by_address() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let address = wallet
+        .next_unused_address(KeychainKind::External)
+        .unwrap()
+        .address;
+    let mut outpoints = vec![];
+    for (lock_time, value) in [(0, 25_000), (1, 25_000), (2, 10_000)] {
+        let tx = Transaction {
+            input: vec![],
+            output: vec![TxOut {
+                script_pubkey: address.script_pubkey(),
+                value: Amount::from_sat(value),
+            }],
+            version: transaction::Version::non_standard(0),
+            lock_time: absolute::LockTime::from_consensus(lock_time),
+        };
+        outpoints.push(OutPoint::new(tx.compute_txid(), 0));
+        wallet
+            .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+            .unwrap();
+    }
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
+        .manually_selected_only();
+    // each output paying the amount can only be added once
+    for _ in 0..2 {
+        builder
+            .add_utxo_by_address(&address, Amount::from_sat(25_000))
+            .unwrap();
+    }
+    assert_matches!(
+        builder.add_utxo_by_address(&address, Amount::from_sat(25_000)),
+        Err(AddUtxoError::NoMatchingUtxo { .. })
+    );
+    assert_matches!(
+        builder.add_utxo_by_address(&addr, Amount::from_sat(10_000)),
+        Err(AddUtxoError::NoMatchingUtxo { .. })
+    );
+    let psbt = builder.finish().unwrap();
+
+    let mut inputs = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|txin| txin.previous_output)
+        .collect::<Vec<_>>();
+    inputs.sort();
+    let mut expected = outpoints[..2].to_vec();
+    expected.sort();
+    assert_eq!(inputs, expected);
+}
+
+#[test]
+fn test_create_tx_max_inputs() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let small_outpoints = (0..3)
+        .map(|i| receive_output_in_latest_block(&mut wallet, 10_000 + i))
+        .collect::<Vec<_>>();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    // a single input can pay for it
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .max_inputs(1);
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(
+        psbt.inputs[0].witness_utxo.as_ref().unwrap().value.to_sat(),
+        50_000
+    );
+
+    // two inputs are needed
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(55_000))
+        .max_inputs(1);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(55_000))
+        .max_inputs(2);
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+
+    // manually selected utxos count against the limit
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .add_utxos(&small_outpoints)
+        .unwrap()
+        .max_inputs(2);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::TooManyInputs {
+            required: 3,
+            max_inputs: 2
+        })
+    );
+}
+
+#[test]
+#[should_panic(expected = "InsufficientFunds")]
+fn test_create_tx_manually_selected_insufficient() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let small_output_tx = Transaction {
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .unwrap()
+                .script_pubkey(),
+            value: Amount::from_sat(25_000),
+        }],
+        version: transaction::Version::non_standard(0),
+        lock_time: absolute::LockTime::ZERO,
+    };
+
+    wallet
+        .insert_tx(
+            small_output_tx.clone(),
+            ConfirmationTime::Unconfirmed { last_seen: 0 },
+        )
+        .unwrap();
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .add_utxo(OutPoint {
+            txid: small_output_tx.compute_txid(),
+            vout: 0,
+        })
+        .unwrap()
+        .manually_selected_only();
+    builder.finish().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "SpendingPolicyRequired(External)")]
+fn test_create_tx_policy_path_required() {
+    let (mut wallet, _) = get_funded_wallet(get_test_a_or_b_plus_csv());
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    builder.finish().unwrap();
+}
+
+#[test]
+fn test_create_tx_policy_path_no_csv() {
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Regtest).expect("wallet");
+
+    let tx = Transaction {
+        version: transaction::Version::non_standard(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .unwrap()
+                .script_pubkey(),
+            value: Amount::from_sat(50_000),
+        }],
+    };
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+    let root_id = external_policy.id;
+    // child #0 is just the key "A"
+    let path = vec![(root_id, vec![0])].into_iter().collect();
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .policy_path(path, KeychainKind::External);
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFF));
+}
+
+#[test]
+fn test_create_tx_policy_path_use_csv() {
+    let (mut wallet, _) = get_funded_wallet(get_test_a_or_b_plus_csv());
+
+    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+    let root_id = external_policy.id;
+    // child #1 is or(pk(B),older(144))
+    let path = vec![(root_id, vec![1])].into_iter().collect();
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .policy_path(path, KeychainKind::External);
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(144));
+}
+
+#[test]
+fn test_create_tx_policy_path_ignored_subtree_with_csv() {
+    let (mut wallet, _) = get_funded_wallet("wsh(or_d(pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu),or_i(and_v(v:pkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW),older(30)),and_v(v:pkh(cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8),older(90)))))");
+
+    let external_policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+    let root_id = external_policy.id;
+    // child #0 is pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu)
+    let path = vec![(root_id, vec![0])].into_iter().collect();
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .policy_path(path, KeychainKind::External);
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFE));
+}
+
+#[test]
+fn test_create_tx_global_xpubs_with_origin() {
+    use bitcoin::bip32;
+    let (mut wallet, _) = get_funded_wallet("wpkh([73756c7f/48'/0'/0'/2']tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .add_global_xpubs();
+    let psbt = builder.finish().unwrap();
+
+    let key = bip32::Xpub::from_str("tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3").unwrap();
+    let fingerprint = bip32::Fingerprint::from_hex("73756c7f").unwrap();
+    let path = bip32::DerivationPath::from_str("m/48'/0'/0'/2'").unwrap();
+
+    assert_eq!(psbt.xpub.len(), 2);
+    assert_eq!(psbt.xpub.get(&key), Some(&(fingerprint, path)));
+}
+
+#[test]
+fn test_add_foreign_utxo() {
+    let (mut wallet1, _) = get_funded_wallet_wpkh();
+    let (wallet2, _) =
+        get_funded_wallet("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let utxo = wallet2.list_unspent().next().expect("must take!");
+    let foreign_utxo_satisfaction = wallet2
+        .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
+        .max_weight_to_satisfy()
+        .unwrap();
+
+    let psbt_input = psbt::Input {
+        witness_utxo: Some(utxo.txout.clone()),
+        ..Default::default()
+    };
+
+    let mut builder = wallet1.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
+        .only_witness_utxo()
+        .add_foreign_utxo(
+            utxo.outpoint,
+            psbt_input,
+            foreign_utxo_satisfaction.to_wu() as usize,
+        )
+        .unwrap();
+    let mut psbt = builder.finish().unwrap();
+    wallet1.insert_txout(utxo.outpoint, utxo.txout);
+    let fee = check_fee!(wallet1, psbt);
+    let sent_received =
+        wallet1.sent_and_received(&psbt.clone().extract_tx().expect("failed to extract tx"));
+
+    assert_eq!(
+        (sent_received.0 - sent_received.1),
+        Amount::from_sat(10_000) + fee.unwrap_or(Amount::ZERO),
+        "we should have only net spent ~10_000"
+    );
+
+    assert!(
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .any(|input| input.previous_output == utxo.outpoint),
+        "foreign_utxo should be in there"
     );
 
     let finished = wallet1
@@ -1443,6 +3049,7 @@ fn test_calculate_fee_with_missing_foreign_utxo() {
     let utxo = wallet2.list_unspent().next().expect("must take!");
     let foreign_utxo_satisfaction = wallet2
         .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
         .max_weight_to_satisfy()
         .unwrap();
 
@@ -1466,12 +3073,64 @@ fn test_calculate_fee_with_missing_foreign_utxo() {
     wallet1.calculate_fee(&tx).unwrap();
 }
 
+#[test]
+fn test_add_foreign_utxo_with_rbf_sequence() {
+    let (mut wallet1, _) = get_funded_wallet_wpkh();
+    let (wallet2, _) =
+        get_funded_wallet("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let utxo = wallet2.list_unspent().next().expect("must take!");
+    let foreign_utxo_satisfaction = wallet2
+        .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
+        .max_weight_to_satisfy()
+        .unwrap();
+
+    let psbt_input = psbt::Input {
+        witness_utxo: Some(utxo.txout.clone()),
+        ..Default::default()
+    };
+
+    let mut builder = wallet1.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
+        .only_witness_utxo()
+        .add_foreign_utxo_with_sequence(
+            utxo.outpoint,
+            psbt_input,
+            foreign_utxo_satisfaction.to_wu() as usize,
+            Sequence(0xFFFFFFFD),
+        )
+        .unwrap();
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+    let foreign_txin = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .find(|txin| txin.previous_output == utxo.outpoint)
+        .expect("foreign_utxo should be in there");
+    assert_eq!(foreign_txin.sequence, Sequence(0xFFFFFFFD));
+
+    // the wallet's own input signals RBF as well
+    assert!(psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .all(|txin| txin.sequence == Sequence::ENABLE_RBF_NO_LOCKTIME));
+}
+
 #[test]
 fn test_add_foreign_utxo_invalid_psbt_input() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
     let outpoint = wallet.list_unspent().next().expect("must exist").outpoint;
     let foreign_utxo_satisfaction = wallet
         .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
         .max_weight_to_satisfy()
         .unwrap();
 
@@ -1496,6 +3155,7 @@ fn test_add_foreign_utxo_where_outpoint_doesnt_match_psbt_input() {
 
     let satisfaction_weight = wallet2
         .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
         .max_weight_to_satisfy()
         .unwrap();
 
@@ -1540,6 +3200,7 @@ fn test_add_foreign_utxo_only_witness_utxo() {
 
     let satisfaction_weight = wallet2
         .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
         .max_weight_to_satisfy()
         .unwrap();
 
@@ -1622,7 +3283,7 @@ fn test_get_psbt_input() {
 )]
 fn test_create_tx_global_xpubs_origin_missing() {
     let (mut wallet, _) = get_funded_wallet("wpkh(tpubDCKxNyM3bLgbEX13Mcd8mYxbVg9ajDkWXMh29hMWBurKfVmBfWAM96QVP3zaUcN51HvkZ3ar4VwP82kC8JZhhux8vFQoJintSpVBwpFvyU3/0/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -1630,107 +3291,349 @@ fn test_create_tx_global_xpubs_origin_missing() {
     builder.finish().unwrap();
 }
 
-#[test]
-fn test_create_tx_global_xpubs_master_without_origin() {
-    use bitcoin::bip32;
-    let (mut wallet, _) = get_funded_wallet("wpkh(tpubD6NzVbkrYhZ4Y55A58Gv9RSNF5hy84b5AJqYy7sCcjFrkcLpPre8kmgfit6kY1Zs3BLgeypTDBZJM222guPpdz7Cup5yzaMu62u7mYGbwFL/0/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+#[test]
+fn test_create_tx_global_xpubs_master_without_origin() {
+    use bitcoin::bip32;
+    let (mut wallet, _) = get_funded_wallet("wpkh(tpubD6NzVbkrYhZ4Y55A58Gv9RSNF5hy84b5AJqYy7sCcjFrkcLpPre8kmgfit6kY1Zs3BLgeypTDBZJM222guPpdz7Cup5yzaMu62u7mYGbwFL/0/*)");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .add_global_xpubs();
+    let psbt = builder.finish().unwrap();
+
+    let key = bip32::Xpub::from_str("tpubD6NzVbkrYhZ4Y55A58Gv9RSNF5hy84b5AJqYy7sCcjFrkcLpPre8kmgfit6kY1Zs3BLgeypTDBZJM222guPpdz7Cup5yzaMu62u7mYGbwFL").unwrap();
+    let fingerprint = bip32::Fingerprint::from_hex("997a323b").unwrap();
+
+    assert_eq!(psbt.xpub.len(), 2);
+    assert_eq!(
+        psbt.xpub.get(&key),
+        Some(&(fingerprint, bip32::DerivationPath::default()))
+    );
+}
+
+#[test]
+#[should_panic(expected = "IrreplaceableTransaction")]
+fn test_bump_fee_irreplaceable_tx() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    wallet.build_fee_bump(txid).unwrap().finish().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "TransactionConfirmed")]
+fn test_bump_fee_confirmed_tx() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+
+    wallet
+        .insert_tx(
+            tx,
+            ConfirmationTime::Confirmed {
+                height: 42,
+                time: 42_000,
+            },
+        )
+        .unwrap();
+
+    wallet.build_fee_bump(txid).unwrap().finish().unwrap();
+}
+
+#[test]
+fn test_bump_fee_low_fee_rate() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .enable_rbf();
+    let psbt = builder.finish().unwrap();
+    let feerate = psbt.fee_rate().unwrap();
+
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder.fee_rate(FeeRate::BROADCAST_MIN);
+    let res = builder.finish();
+    assert_matches!(
+        res,
+        Err(CreateTxError::FeeRateTooLow { .. }),
+        "expected FeeRateTooLow error"
+    );
+
+    let required = feerate.to_sat_per_kwu() + 250; // +1 sat/vb
+    let sat_vb = required as f64 / 250.0;
+    let expect = format!("Fee rate too low: required {} sat/vb", sat_vb);
+    assert_eq!(res.unwrap_err().to_string(), expect);
+}
+
+#[test]
+fn test_min_rbf_fee() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .enable_rbf();
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee_amount().unwrap();
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    let vsize = tx.vsize() as u64;
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    assert_eq!(
+        wallet.min_rbf_fee(txid).unwrap(),
+        fee + Amount::from_sat(vsize)
+    );
+
+    // a child spending the change is evicted too, so its fee must be paid as well
     let mut builder = wallet.build_tx();
     builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
-        .add_global_xpubs();
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .enable_rbf();
     let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output.txid, txid);
+    let child_fee = psbt.fee_amount().unwrap();
+    let child_tx = psbt.extract_tx().expect("failed to extract tx");
+    wallet
+        .insert_tx(child_tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    assert_eq!(
+        wallet.min_rbf_fee(txid).unwrap(),
+        fee + child_fee + Amount::from_sat(vsize)
+    );
 
-    let key = bip32::Xpub::from_str("tpubD6NzVbkrYhZ4Y55A58Gv9RSNF5hy84b5AJqYy7sCcjFrkcLpPre8kmgfit6kY1Zs3BLgeypTDBZJM222guPpdz7Cup5yzaMu62u7mYGbwFL").unwrap();
-    let fingerprint = bip32::Fingerprint::from_hex("997a323b").unwrap();
+    let unknown_txid = Txid::all_zeros();
+    assert_matches!(
+        wallet.min_rbf_fee(unknown_txid),
+        Err(BuildFeeBumpError::TransactionNotFound(t)) if t == unknown_txid
+    );
 
-    assert_eq!(psbt.xpub.len(), 2);
-    assert_eq!(
-        psbt.xpub.get(&key),
-        Some(&(fingerprint, bip32::DerivationPath::default()))
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let tx = builder.finish().unwrap().extract_tx().unwrap();
+    let txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    assert_matches!(
+        wallet.min_rbf_fee(txid),
+        Err(BuildFeeBumpError::IrreplaceableTransaction(t)) if t == txid
     );
 }
 
 #[test]
-#[should_panic(expected = "IrreplaceableTransaction")]
-fn test_bump_fee_irreplaceable_tx() {
+#[cfg(feature = "labels")]
+fn test_add_recipient_with_label() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
     let mut builder = wallet.build_tx();
-    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
-    let psbt = builder.finish().unwrap();
-
+    builder
+        .add_recipient_with_label(
+            addr.script_pubkey(),
+            Amount::from_sat(25_000),
+            "rent".into(),
+        )
+        .change_label("change".into())
+        .enable_rbf();
+    let (psbt, TxDetails { change, .. }) = builder.finish_with_details().unwrap();
+    let change = change.expect("must have change");
+    let recipient_vout = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .position(|txout| txout.script_pubkey == addr.script_pubkey())
+        .unwrap();
     let tx = psbt.extract_tx().expect("failed to extract tx");
     let txid = tx.compute_txid();
+
+    // labels are only attached once the transaction is applied
+    assert_eq!(
+        wallet.output_label(OutPoint::new(txid, recipient_vout as u32)),
+        None
+    );
     wallet
         .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
         .unwrap();
-    wallet.build_fee_bump(txid).unwrap().finish().unwrap();
+    assert_eq!(
+        wallet.output_label(OutPoint::new(txid, recipient_vout as u32)),
+        Some("rent")
+    );
+    assert_eq!(
+        wallet.output_label(OutPoint::new(txid, change.vout as u32)),
+        Some("change")
+    );
+    assert_eq!(wallet.staged().labels.outputs.len(), 2);
+
+    // the labels carry over to the replacement
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder.fee_rate(FeeRate::from_sat_per_vb_unchecked(5));
+    let (psbt, TxDetails { change, .. }) = builder.finish_with_details().unwrap();
+    let change = change.expect("must have change");
+    let recipient_vout = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .position(|txout| txout.script_pubkey == addr.script_pubkey())
+        .unwrap();
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let replacement_txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 1 })
+        .unwrap();
+    assert_eq!(
+        wallet.output_label(OutPoint::new(replacement_txid, recipient_vout as u32)),
+        Some("rent")
+    );
+    assert_eq!(
+        wallet.output_label(OutPoint::new(replacement_txid, change.vout as u32)),
+        Some("change")
+    );
+
+    // and they are restored from the changeset
+    let wallet = Wallet::load_from_changeset(wallet.staged().clone()).unwrap();
+    assert_eq!(
+        wallet.output_label(OutPoint::new(replacement_txid, recipient_vout as u32)),
+        Some("rent")
+    );
 }
 
 #[test]
-#[should_panic(expected = "TransactionConfirmed")]
-fn test_bump_fee_confirmed_tx() {
+#[cfg(feature = "labels")]
+fn test_cancel_tx_discards_labels() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
     let mut builder = wallet.build_tx();
-    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    builder.add_recipient_with_label(
+        addr.script_pubkey(),
+        Amount::from_sat(25_000),
+        "rent".into(),
+    );
     let psbt = builder.finish().unwrap();
+    wallet.cancel_psbt(&psbt);
 
+    // a cancelled transaction applied later on doesn't get the labels
     let tx = psbt.extract_tx().expect("failed to extract tx");
     let txid = tx.compute_txid();
-
     wallet
-        .insert_tx(
-            tx,
-            ConfirmationTime::Confirmed {
-                height: 42,
-                time: 42_000,
-            },
-        )
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
         .unwrap();
-
-    wallet.build_fee_bump(txid).unwrap().finish().unwrap();
+    assert!((0..2).all(|vout| wallet.output_label(OutPoint::new(txid, vout)).is_none()));
+    assert!(wallet.staged().labels.outputs.is_empty());
 }
 
 #[test]
-fn test_bump_fee_low_fee_rate() {
+fn test_bump_fee_max_fee_rate() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
         .enable_rbf();
     let psbt = builder.finish().unwrap();
     let feerate = psbt.fee_rate().unwrap();
 
     let tx = psbt.extract_tx().expect("failed to extract tx");
     let txid = tx.compute_txid();
-
     wallet
         .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
         .unwrap();
 
+    // replacing the transaction requires more than the maximum
+    let max = FeeRate::from_sat_per_vb_unchecked(10);
     let mut builder = wallet.build_fee_bump(txid).unwrap();
-    builder.fee_rate(FeeRate::BROADCAST_MIN);
-    let res = builder.finish();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(15))
+        .max_fee_rate(max);
     assert_matches!(
-        res,
-        Err(CreateTxError::FeeRateTooLow { .. }),
-        "expected FeeRateTooLow error"
+        builder.finish(),
+        Err(CreateTxError::FeeRateTooHigh { max: m, required })
+        if m == max && required.to_sat_per_kwu() == feerate.to_sat_per_kwu() + 250
     );
 
-    let required = feerate.to_sat_per_kwu() + 250; // +1 sat/vb
-    let sat_vb = required as f64 / 250.0;
-    let expect = format!("Fee rate too low: required {} sat/vb", sat_vb);
-    assert_eq!(res.unwrap_err().to_string(), expect);
+    let max = FeeRate::from_sat_per_vb_unchecked(20);
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(25))
+        .max_fee_rate(max);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::FeeRateTooHigh { max: m, required })
+        if m == max && required == FeeRate::from_sat_per_vb_unchecked(25)
+    );
+
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(15))
+        .max_fee_rate(max);
+    assert!(builder.finish().is_ok());
+}
+
+#[test]
+fn test_create_tx_max_fee() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10));
+    let fee = builder.finish().unwrap().fee_amount().unwrap();
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
+        .max_fee(fee - Amount::from_sat(1));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::FeeTooHigh { max, fee: f })
+        if max == fee - Amount::from_sat(1) && f == fee
+    );
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
+        .max_fee(fee);
+    assert!(builder.finish().is_ok());
 }
 
 #[test]
 #[should_panic(expected = "FeeTooLow")]
 fn test_bump_fee_low_abs() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -1753,7 +3656,7 @@ fn test_bump_fee_low_abs() {
 #[should_panic(expected = "FeeTooLow")]
 fn test_bump_fee_zero_abs() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
@@ -1975,6 +3878,7 @@ fn test_bump_fee_drain_wallet() {
         output: vec![TxOut {
             script_pubkey: wallet
                 .next_unused_address(KeychainKind::External)
+                .unwrap()
                 .script_pubkey(),
             value: Amount::from_sat(25_000),
         }],
@@ -2040,6 +3944,7 @@ fn test_bump_fee_remove_output_manually_selected_only() {
         output: vec![TxOut {
             script_pubkey: wallet
                 .next_unused_address(KeychainKind::External)
+                .unwrap()
                 .script_pubkey(),
             value: Amount::from_sat(25_000),
         }],
@@ -2096,6 +4001,7 @@ fn test_bump_fee_add_input() {
         output: vec![TxOut {
             script_pubkey: wallet
                 .next_unused_address(KeychainKind::External)
+                .unwrap()
                 .script_pubkey(),
             value: Amount::from_sat(25_000),
         }],
@@ -2289,6 +4195,141 @@ fn test_bump_fee_no_change_add_input_and_change() {
     assert_fee_rate!(psbt, fee.unwrap_or(Amount::ZERO), FeeRate::from_sat_per_vb_unchecked(50), @add_signature);
 }
 
+#[test]
+fn test_bump_fee_allow_additional_inputs() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let _ = receive_output_in_latest_block(&mut wallet, 25_000);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    // the 50_000 sat input pays for the recipient, leaving no change
+    let mut builder = wallet.build_tx().coin_selection(LargestFirstCoinSelection);
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(49_800))
+        .enable_rbf();
+    let psbt = builder.finish().unwrap();
+    let original_fee = check_fee!(wallet, psbt).unwrap_or(Amount::ZERO);
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(tx.input.len(), 1);
+    assert_eq!(tx.output.len(), 1);
+    let txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
+        .allow_additional_inputs(false);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
+        .enable_rbf();
+    let psbt = builder.finish().unwrap();
+    let fee = check_fee!(wallet, psbt).unwrap_or(Amount::ZERO);
+    assert!(fee > original_fee);
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(tx.input.len(), 2);
+    assert_eq!(tx.output.len(), 2);
+    assert_eq!(
+        tx.output
+            .iter()
+            .find(|txout| txout.script_pubkey == addr.script_pubkey())
+            .unwrap()
+            .value,
+        Amount::from_sat(49_800)
+    );
+
+    // the change of a replacement goes back to the original change address
+    let change_spk = tx
+        .output
+        .iter()
+        .find(|txout| txout.script_pubkey != addr.script_pubkey())
+        .unwrap()
+        .script_pubkey
+        .clone();
+    let txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 1 })
+        .unwrap();
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder.fee_rate(FeeRate::from_sat_per_vb_unchecked(20));
+    let psbt = builder.finish().unwrap();
+    let tx = &psbt.unsigned_tx;
+    assert_eq!(tx.input.len(), 2);
+    assert!(tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == change_spk));
+}
+
+#[test]
+fn test_bump_fee_preserving_outputs() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    // the only input pays for the recipient, leaving no change
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(49_800))
+        .enable_rbf();
+    let psbt = builder.finish().unwrap();
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(tx.output.len(), 1);
+    let txid = tx.compute_txid();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    // the recipients can't be modified
+    let mut builder = wallet.build_fee_bump_preserving_outputs(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
+        .set_recipients(vec![(addr.script_pubkey(), Amount::from_sat(49_000))]);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::PreservedRecipientsModified)
+    );
+
+    // without change or additional inputs the fee can't be bumped
+    let mut builder = wallet.build_fee_bump_preserving_outputs(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(10))
+        .allow_additional_inputs(false);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+
+    // with a new input the recipient is paid the same amount
+    let _ = receive_output_in_latest_block(&mut wallet, 25_000);
+    let mut builder = wallet.build_fee_bump_preserving_outputs(txid).unwrap();
+    builder.fee_rate(FeeRate::from_sat_per_vb_unchecked(10));
+    let psbt = builder.finish().unwrap();
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(tx.input.len(), 2);
+    assert_eq!(
+        tx.output
+            .iter()
+            .filter(|txout| txout.script_pubkey == addr.script_pubkey())
+            .map(|txout| txout.value)
+            .collect::<Vec<_>>(),
+        [Amount::from_sat(49_800)]
+    );
+}
+
 #[test]
 fn test_bump_fee_add_input_change_dust() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
@@ -2567,16 +4608,75 @@ fn test_bump_fee_unconfirmed_input() {
         .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
         .unwrap();
 
-    let mut builder = wallet.build_fee_bump(txid).unwrap();
-    builder
-        .fee_rate(FeeRate::from_sat_per_vb_unchecked(15))
-        // remove original tx drain_to address and amount
-        .set_recipients(Vec::new())
-        // set back original drain_to address
-        .drain_to(addr.script_pubkey())
-        // drain wallet output amount will be re-calculated with new fee rate
-        .drain_wallet();
-    builder.finish().unwrap();
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(15))
+        // remove original tx drain_to address and amount
+        .set_recipients(Vec::new())
+        // set back original drain_to address
+        .drain_to(addr.script_pubkey())
+        // drain wallet output amount will be re-calculated with new fee rate
+        .drain_wallet();
+    builder.finish().unwrap();
+}
+
+#[test]
+fn test_build_fee_bumps() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let spare_utxo = wallet.list_unspent().next().unwrap().outpoint;
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    let mut txids = vec![];
+    for value in [25_000, 25_100] {
+        let utxo = receive_output_in_latest_block(&mut wallet, value);
+        let mut builder = wallet.build_tx();
+        builder
+            .add_utxo(utxo)
+            .unwrap()
+            .manually_selected_only()
+            .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+            .enable_rbf();
+        let psbt = builder.finish().unwrap();
+        let tx = psbt.extract_tx().expect("failed to extract tx");
+        txids.push(tx.compute_txid());
+        wallet
+            .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+            .unwrap();
+    }
+    let unknown_txid = Txid::all_zeros();
+    txids.push(unknown_txid);
+
+    // both transactions need the spare UTXO to pay the new fee rate, only the first one gets it
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(50);
+    let bumps = wallet.build_fee_bumps(&txids, fee_rate);
+
+    assert_eq!(bumps.psbts.len(), 1);
+    let (txid, psbt) = &bumps.psbts[0];
+    assert_eq!(*txid, txids[0]);
+    assert!(psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .any(|txin| txin.previous_output == spare_utxo));
+    let fee = check_fee!(wallet, psbt);
+    assert_fee_rate!(psbt, fee.unwrap_or(Amount::ZERO), fee_rate, @add_signature);
+
+    assert_eq!(bumps.failed.len(), 2);
+    assert_eq!(bumps.failed[0].0, txids[1]);
+    assert_matches!(
+        bumps.failed[0].1,
+        FeeBumpsError::CreateTx(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
+    );
+    assert_eq!(bumps.failed[1].0, unknown_txid);
+    assert_matches!(
+        bumps.failed[1].1,
+        FeeBumpsError::BuildFeeBump(BuildFeeBumpError::TransactionNotFound(txid))
+            if txid == unknown_txid
+    );
 }
 
 #[test]
@@ -2613,7 +4713,7 @@ fn test_fee_amount_negative_drain_val() {
 #[test]
 fn test_sign_single_xprv() {
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -2628,7 +4728,7 @@ fn test_sign_single_xprv() {
 #[test]
 fn test_sign_single_xprv_with_master_fingerprint_and_path() {
     let (mut wallet, _) = get_funded_wallet("wpkh([d34db33f/84h/1h/0h]tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -2643,7 +4743,7 @@ fn test_sign_single_xprv_with_master_fingerprint_and_path() {
 #[test]
 fn test_sign_single_xprv_bip44_path() {
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/44'/0'/0'/0/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -2658,7 +4758,7 @@ fn test_sign_single_xprv_bip44_path() {
 #[test]
 fn test_sign_single_xprv_sh_wpkh() {
     let (mut wallet, _) = get_funded_wallet("sh(wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*))");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -2674,11 +4774,29 @@ fn test_sign_single_xprv_sh_wpkh() {
 fn test_sign_single_wif() {
     let (mut wallet, _) =
         get_funded_wallet("wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+
+    let finalized = wallet.sign(&mut psbt, Default::default()).unwrap();
+    assert!(finalized);
+
+    let extracted = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(extracted.input[0].witness.len(), 2);
+}
+
+#[test]
+fn test_sign_single_xprv_no_hd_keypaths() {
+    let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
 
+    psbt.inputs[0].bip32_derivation.clear();
+    assert_eq!(psbt.inputs[0].bip32_derivation.len(), 0);
+
     let finalized = wallet.sign(&mut psbt, Default::default()).unwrap();
     assert!(finalized);
 
@@ -2687,88 +4805,522 @@ fn test_sign_single_wif() {
 }
 
 #[test]
-fn test_sign_single_xprv_no_hd_keypaths() {
-    let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+fn test_include_output_redeem_witness_script() {
+    let desc = get_test_wpkh();
+    let change_desc = "sh(wsh(multi(1,cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW,cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu)))";
+    let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
+        .include_output_redeem_witness_script();
+    let psbt = builder.finish().unwrap();
+
+    // p2sh-p2wsh transaction should contain both witness and redeem scripts
+    assert!(psbt
+        .outputs
+        .iter()
+        .any(|output| output.redeem_script.is_some() && output.witness_script.is_some()));
+}
+
+#[test]
+fn test_public_descriptor_keeps_key_origin() {
+    use bdk_wallet::miniscript::descriptor::DescriptorPublicKey;
+    use bdk_wallet::miniscript::ForEachKey;
+    use bitcoin::bip32::{DerivationPath, Xpriv};
+
+    let tprv = "tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L";
+    let desc = format!("wpkh({}/84'/1'/0'/0/*)", tprv);
+    let change_desc = format!("wpkh({}/84'/1'/0'/1/*)", tprv);
+    let wallet = Wallet::new(&desc, &change_desc, Network::Regtest).unwrap();
+
+    let public_desc = wallet.public_descriptor(KeychainKind::External).unwrap();
+    assert!(!public_desc.to_string().contains("tprv"));
+    let fingerprint = Xpriv::from_str(tprv)
+        .unwrap()
+        .fingerprint(&Secp256k1::new());
+    let origin_path = DerivationPath::from_str("m/84'/1'/0'").unwrap();
+    assert!(public_desc.for_each_key(|key| match key {
+        DescriptorPublicKey::XPub(xpub) => {
+            xpub.origin == Some((fingerprint, origin_path.clone()))
+                && xpub.derivation_path == DerivationPath::from_str("m/0").unwrap()
+        }
+        _ => false,
+    }));
+
+    // a watch-only wallet made from the public descriptors derives the same addresses
+    let watch_only = Wallet::new(
+        &public_desc.to_string(),
+        &wallet
+            .public_descriptor(KeychainKind::Internal)
+            .unwrap()
+            .to_string(),
+        Network::Regtest,
+    )
+    .unwrap();
+    for index in 0..3 {
+        assert_eq!(
+            watch_only
+                .peek_address(KeychainKind::External, index)
+                .unwrap(),
+            wallet.peek_address(KeychainKind::External, index).unwrap()
+        );
+        assert_eq!(
+            watch_only
+                .peek_address(KeychainKind::Internal, index)
+                .unwrap(),
+            wallet.peek_address(KeychainKind::Internal, index).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_add_private_keys() {
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    let (wallet, _) = get_funded_wallet_with_change(desc, change_desc);
+    let public_desc = wallet
+        .public_descriptor(KeychainKind::External)
+        .unwrap()
+        .to_string();
+    let public_change_desc = wallet
+        .public_descriptor(KeychainKind::Internal)
+        .unwrap()
+        .to_string();
+
+    let (mut wallet, _) = get_funded_wallet_with_change(&public_desc, &public_change_desc);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+
+    // the watch-only wallet can't sign
+    let finalized = wallet
+        .sign(&mut psbt.clone(), SignOptions::default())
+        .unwrap();
+    assert!(!finalized);
+
+    // keys that don't derive the wallet's scripts are rejected
+    assert_matches!(
+        wallet.add_private_keys(get_test_tr_single_sig()),
+        Err(AddPrivateKeysError::DescriptorMismatch)
+    );
+
+    wallet.add_private_keys(desc).unwrap();
+    wallet.add_private_keys(change_desc).unwrap();
+    assert_eq!(
+        wallet.get_signers(KeychainKind::External).signers().len(),
+        1
+    );
+    assert_eq!(
+        wallet.get_signers(KeychainKind::Internal).signers().len(),
+        1
+    );
+
+    let finalized = wallet
+        .sign(&mut psbt.clone(), SignOptions::default())
+        .unwrap();
+    assert!(finalized);
+}
+
+#[test]
+fn test_keychain_kind_to_bytes() {
+    assert_eq!(KeychainKind::External.to_bytes(), b"e");
+    assert_eq!(KeychainKind::Internal.to_bytes(), b"i");
+    assert_eq!(KeychainKind::Custom(1).to_bytes(), [b'c', 0, 0, 0, 1]);
+    assert_ne!(
+        KeychainKind::Custom(1).to_bytes(),
+        KeychainKind::Custom(2).to_bytes()
+    );
+    assert_eq!(KeychainKind::External.as_byte(), b'e');
+    assert_eq!(KeychainKind::Custom(1).as_byte(), b'c');
+    assert_eq!(KeychainKind::Internal.as_ref(), b"i");
+    assert_eq!(KeychainKind::Custom(1).as_ref(), b"c");
+}
+
+#[test]
+fn test_unknown_keychain_is_not_found() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let keychain = KeychainKind::Custom(3);
+
+    assert_eq!(
+        wallet.peek_address(keychain, 0),
+        Err(KeychainNotFound(keychain))
+    );
+    assert_eq!(
+        wallet.reveal_next_address(keychain),
+        Err(KeychainNotFound(keychain))
+    );
+    assert_eq!(
+        wallet.next_unused_address(keychain),
+        Err(KeychainNotFound(keychain))
+    );
+    assert!(wallet.reveal_addresses_to(keychain, 2).is_err());
+    assert!(wallet.unbounded_spk_iter(keychain).is_none());
+    assert!(wallet.get_descriptor_for_keychain(keychain).is_none());
+    assert_eq!(wallet.input_weight_estimate(keychain), None);
+    assert_eq!(wallet.next_derivation_index(keychain), None);
+    assert_eq!(wallet.keychain_stats(keychain), None);
+    assert_eq!(wallet.descriptor_checksum(keychain), None);
+    assert!(wallet.policies(keychain).unwrap().is_none());
+
+    let addr = wallet.peek_address(KeychainKind::External, 0).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .change_to_index(keychain, 0);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::KeychainNotFound(k)) if k == keychain
+    );
+}
+
+#[test]
+fn test_add_keychain() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let keychain = KeychainKind::Custom(7);
+    let descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/1'/0/*)";
+    wallet.add_keychain(7, descriptor).unwrap();
+
+    // adding the same keychain again does nothing, reassigning it fails
+    wallet.add_keychain(7, descriptor).unwrap();
+    assert_matches!(
+        wallet.add_keychain(8, descriptor),
+        Err(AddKeychainError::DescriptorAlreadyAssigned(k)) if k == keychain
+    );
+    assert_matches!(
+        wallet.add_keychain(7, get_test_tr_single_sig()),
+        Err(AddKeychainError::KeychainAlreadyAssigned(k)) if k == keychain
+    );
+
+    let addr = wallet.reveal_next_address(keychain).unwrap();
+    let tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: addr.script_pubkey(),
+            value: Amount::from_sat(25_000),
+        }],
+    };
+    let outpoint = OutPoint::new(tx.compute_txid(), 0);
+    let height = wallet.latest_checkpoint().height();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Confirmed { height, time: 0 })
+        .unwrap();
+
+    // the keychain is part of the balance, the utxos and the sync requests
+    assert_eq!(wallet.balance().confirmed, Amount::from_sat(75_000));
+    assert_eq!(
+        wallet.get_utxo(outpoint).map(|utxo| utxo.keychain),
+        Some(keychain)
+    );
+    assert!(wallet
+        .start_sync_with_revealed_spks()
+        .spks
+        .any(|spk| spk == addr.script_pubkey()));
+    assert!(wallet
+        .start_full_scan()
+        .spks_by_keychain
+        .contains_key(&keychain));
+
+    // its utxos are selected by default, unless spending from other keychains only
+    let to = wallet
+        .next_unused_address(KeychainKind::External)
+        .unwrap()
+        .script_pubkey();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(to.clone()).drain_wallet();
+    let psbt = builder.finish().unwrap();
+    assert!(psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .any(|txin| txin.previous_output == outpoint));
+    let mut builder = wallet.build_tx();
+    builder
+        .drain_to(to.clone())
+        .drain_wallet()
+        .spend_from_keychains([KeychainKind::External, KeychainKind::Internal]);
+    let psbt = builder.finish().unwrap();
+    assert!(psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .all(|txin| txin.previous_output != outpoint));
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(to.clone(), Amount::from_sat(10_000))
+        .spend_from_keychain(keychain);
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, outpoint);
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+
+    // and it's restored from the changeset, without its private keys
+    let mut loaded = Wallet::load_from_changeset(wallet.staged().clone()).unwrap();
+    assert_eq!(
+        loaded.public_descriptor(keychain).unwrap(),
+        wallet.public_descriptor(keychain).unwrap()
+    );
+    assert_eq!(loaded.derivation_index(keychain), Some(0));
+    assert_eq!(loaded.balance(), wallet.balance());
+    assert!(loaded.get_signers(keychain).signers().is_empty());
+    loaded.add_private_keys(descriptor).unwrap();
+    assert!(!loaded.get_signers(keychain).signers().is_empty());
+}
+
+#[test]
+fn test_signing_only_one_of_multiple_inputs() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
+        .include_output_redeem_witness_script();
+    let mut psbt = builder.finish().unwrap();
+
+    // add another input to the psbt that is at least passable.
+    let dud_input = bitcoin::psbt::Input {
+        witness_utxo: Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: miniscript::Descriptor::<bitcoin::PublicKey>::from_str(
+                "wpkh(025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357)",
+            )
+            .unwrap()
+            .script_pubkey(),
+        }),
+        ..Default::default()
+    };
+
+    psbt.inputs.push(dud_input);
+    psbt.unsigned_tx.input.push(bitcoin::TxIn::default());
+    let is_final = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                trust_witness_utxo: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(
+        !is_final,
+        "shouldn't be final since we can't sign one of the inputs"
+    );
+    assert!(
+        psbt.inputs[0].final_script_witness.is_some(),
+        "should finalized input it signed"
+    )
+}
+
+#[test]
+fn test_sign_inputs_sign_option() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    receive_output_in_latest_block(&mut wallet, 25_000);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(psbt.inputs.len(), 2);
+
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                sign_inputs: Some(vec![1]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!finalized);
+    assert!(psbt.inputs[0].partial_sigs.is_empty());
+    assert_eq!(psbt.inputs[1].partial_sigs.len(), 1);
+    // nothing is finalized until every input is complete
+    assert!(psbt
+        .inputs
+        .iter()
+        .all(|input| input.final_script_witness.is_none()));
+
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                sign_inputs: Some(vec![0]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(finalized);
+    assert!(psbt.extract_tx().is_ok());
+}
+
+#[test]
+fn test_sign_inputs_sign_option_out_of_range() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+
+    let result = wallet.sign(
+        &mut psbt,
+        SignOptions {
+            sign_inputs: Some(vec![1]),
+            ..Default::default()
+        },
+    );
+    assert_matches!(result, Err(SignerError::InputIndexOutOfRange));
+}
+
+#[test]
+fn test_raw_public_key_descriptor() {
+    use bdk_wallet::signer::{SignerContext, SignerOrdering, SignerWrapper};
+    use std::sync::Arc;
+
+    let secp = Secp256k1::new();
+    let private_key =
+        bitcoin::PrivateKey::from_wif("cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW")
+            .unwrap();
+    let public_key = private_key.public_key(&secp);
+    let (mut wallet, _) = get_funded_wallet_with_change(
+        &format!("wpkh({})", public_key),
+        &format!("sh(wpkh({}))", public_key),
+    );
+
+    // a descriptor without wildcard always derives the same address
+    let addr = wallet.peek_address(KeychainKind::External, 0).unwrap();
+    assert_eq!(
+        wallet.peek_address(KeychainKind::External, 42).unwrap(),
+        addr
+    );
+    assert_eq!(
+        wallet
+            .reveal_next_address(KeychainKind::External)
+            .unwrap()
+            .address,
+        addr.address
+    );
+    assert_eq!(
+        addr.script_pubkey(),
+        ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap())
+    );
+    assert_eq!(wallet.balance().confirmed, Amount::from_sat(50_000));
+
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
 
-    psbt.inputs[0].bip32_derivation.clear();
-    assert_eq!(psbt.inputs[0].bip32_derivation.len(), 0);
+    // the descriptor has no private key, so the wallet can't sign
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(!finalized);
 
-    let finalized = wallet.sign(&mut psbt, Default::default()).unwrap();
+    wallet.add_signer(
+        KeychainKind::External,
+        SignerOrdering::default(),
+        Arc::new(SignerWrapper::new(private_key, SignerContext::Segwitv0)),
+    );
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
     assert!(finalized);
-
-    let extracted = psbt.extract_tx().expect("failed to extract tx");
-    assert_eq!(extracted.input[0].witness.len(), 2);
+    assert!(psbt.extract_tx().is_ok());
 }
 
 #[test]
-fn test_include_output_redeem_witness_script() {
-    let desc = get_test_wpkh();
-    let change_desc = "sh(wsh(multi(1,cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW,cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu)))";
-    let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
-    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+fn test_sign_multisig_with_separate_wallets() {
+    let secp = Secp256k1::new();
+    let wifs = [
+        "cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW",
+        "cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu",
+        "cTc4vURSzdx6QE6KVynWGomDbLaA75dNALMNyfjh3p8DRRar84Um",
+    ];
+    let pubkeys = wifs.map(|wif| {
+        bitcoin::PrivateKey::from_wif(wif)
+            .unwrap()
+            .public_key(&secp)
+            .to_string()
+    });
+    // each wallet holds a different key of the same `wsh(multi(2,A,B,C))` descriptor
+    let new_wallet = |keys: [&str; 3]| {
+        Wallet::new(
+            &format!("wsh(multi(2,{},{},{}))", keys[0], keys[1], keys[2]),
+            &format!("wsh(sortedmulti(2,{},{},{}))", keys[0], keys[1], keys[2]),
+            Network::Regtest,
+        )
         .unwrap()
-        .assume_checked();
-    let mut builder = wallet.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
-        .include_output_redeem_witness_script();
-    let psbt = builder.finish().unwrap();
+    };
+    let mut wallet_a = new_wallet([wifs[0], &pubkeys[1], &pubkeys[2]]);
+    let mut wallet_b = new_wallet([&pubkeys[0], wifs[1], &pubkeys[2]]);
+    assert_eq!(
+        wallet_a.public_descriptor(KeychainKind::External).unwrap(),
+        wallet_b.public_descriptor(KeychainKind::External).unwrap()
+    );
 
-    // p2sh-p2wsh transaction should contain both witness and redeem scripts
-    assert!(psbt
-        .outputs
-        .iter()
-        .any(|output| output.redeem_script.is_some() && output.witness_script.is_some()));
-}
+    let funding_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: wallet_a
+                .next_unused_address(KeychainKind::External)
+                .unwrap()
+                .script_pubkey(),
+            value: Amount::from_sat(50_000),
+        }],
+    };
+    for wallet in [&mut wallet_a, &mut wallet_b] {
+        let block = BlockId {
+            height: 100,
+            hash: BlockHash::all_zeros(),
+        };
+        wallet.insert_checkpoint(block).unwrap();
+        wallet
+            .insert_tx(
+                funding_tx.clone(),
+                ConfirmationTime::Confirmed {
+                    height: 100,
+                    time: 0,
+                },
+            )
+            .unwrap();
+    }
 
-#[test]
-fn test_signing_only_one_of_multiple_inputs() {
-    let (mut wallet, _) = get_funded_wallet_wpkh();
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
-    let mut builder = wallet.build_tx();
-    builder
-        .add_recipient(addr.script_pubkey(), Amount::from_sat(45_000))
-        .include_output_redeem_witness_script();
+    let mut builder = wallet_a.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
 
-    // add another input to the psbt that is at least passable.
-    let dud_input = bitcoin::psbt::Input {
-        witness_utxo: Some(TxOut {
-            value: Amount::from_sat(100_000),
-            script_pubkey: miniscript::Descriptor::<bitcoin::PublicKey>::from_str(
-                "wpkh(025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357)",
-            )
-            .unwrap()
-            .script_pubkey(),
-        }),
+    // one signature isn't enough to finalize
+    let finalized = wallet_a.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(!finalized);
+    assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+
+    // the second wallet adds its signature without removing the first one
+    let sign_options = SignOptions {
+        try_finalize: false,
         ..Default::default()
     };
+    let finalized = wallet_b.sign(&mut psbt, sign_options).unwrap();
+    assert!(!finalized);
+    assert_eq!(psbt.inputs[0].partial_sigs.len(), 2);
 
-    psbt.inputs.push(dud_input);
-    psbt.unsigned_tx.input.push(bitcoin::TxIn::default());
-    let is_final = wallet
-        .sign(
-            &mut psbt,
-            SignOptions {
-                trust_witness_utxo: true,
-                ..Default::default()
-            },
-        )
+    let finalized = wallet_b
+        .finalize_psbt(&mut psbt, SignOptions::default())
         .unwrap();
-    assert!(
-        !is_final,
-        "shouldn't be final since we can't sign one of the inputs"
+    assert!(finalized);
+    // empty push for `CHECKMULTISIG`, the two signatures and the witness script
+    assert_eq!(
+        psbt.inputs[0].final_script_witness.as_ref().unwrap().len(),
+        4
     );
-    assert!(
-        psbt.inputs[0].final_script_witness.is_some(),
-        "should finalized input it signed"
-    )
 }
 
 #[test]
@@ -2776,7 +5328,7 @@ fn test_remove_partial_sigs_after_finalize_sign_option() {
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
 
     for remove_partial_sigs in &[true, false] {
-        let addr = wallet.next_unused_address(KeychainKind::External);
+        let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
         let mut builder = wallet.build_tx();
         builder.drain_to(addr.script_pubkey()).drain_wallet();
         let mut psbt = builder.finish().unwrap();
@@ -2806,7 +5358,7 @@ fn test_try_finalize_sign_option() {
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
 
     for try_finalize in &[true, false] {
-        let addr = wallet.next_unused_address(KeychainKind::External);
+        let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
         let mut builder = wallet.build_tx();
         builder.drain_to(addr.script_pubkey()).drain_wallet();
         let mut psbt = builder.finish().unwrap();
@@ -2840,7 +5392,7 @@ fn test_sign_nonstandard_sighash() {
     let sighash = EcdsaSighashType::NonePlusAnyoneCanPay;
 
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
@@ -2873,262 +5425,1105 @@ fn test_sign_nonstandard_sighash() {
         "Should finalize the input since we can produce signatures"
     );
 
-    let extracted = psbt.extract_tx().expect("failed to extract tx");
+    let extracted = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(
+        *extracted.input[0].witness.to_vec()[0].last().unwrap(),
+        sighash.to_u32() as u8,
+        "The signature should have been made with the right sighash"
+    );
+}
+
+#[test]
+fn test_unused_address() {
+    let desc = "wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)";
+    let change_desc = get_test_wpkh();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Testnet).expect("wallet");
+
+    // `list_unused_addresses` should be empty if we haven't revealed any
+    assert!(wallet
+        .list_unused_addresses(KeychainKind::External)
+        .next()
+        .is_none());
+
+    assert_eq!(
+        wallet
+            .next_unused_address(KeychainKind::External)
+            .unwrap()
+            .to_string(),
+        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+    );
+    assert_eq!(
+        wallet
+            .list_unused_addresses(KeychainKind::External)
+            .next()
+            .unwrap()
+            .to_string(),
+        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+    );
+}
+
+#[test]
+fn test_next_unused_address() {
+    let descriptor = "wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)";
+    let change = get_test_wpkh();
+    let mut wallet = Wallet::new(descriptor, change, Network::Testnet).expect("wallet");
+    assert_eq!(wallet.derivation_index(KeychainKind::External), None);
+
+    assert_eq!(
+        wallet
+            .next_unused_address(KeychainKind::External)
+            .unwrap()
+            .to_string(),
+        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+    );
+    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(0));
+    // calling next_unused again gives same address
+    assert_eq!(
+        wallet
+            .next_unused_address(KeychainKind::External)
+            .unwrap()
+            .to_string(),
+        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+    );
+    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(0));
+
+    // test mark used / unused
+    assert!(wallet.mark_used(KeychainKind::External, 0));
+    let next_unused_addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    assert_eq!(next_unused_addr.index, 1);
+
+    assert!(wallet.unmark_used(KeychainKind::External, 0));
+    let next_unused_addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+    assert_eq!(next_unused_addr.index, 0);
+
+    // use the above address
+    receive_output_in_latest_block(&mut wallet, 25_000);
+
+    assert_eq!(
+        wallet
+            .next_unused_address(KeychainKind::External)
+            .unwrap()
+            .to_string(),
+        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+    );
+    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(1));
+
+    // trying to mark index 0 unused should return false
+    assert!(!wallet.unmark_used(KeychainKind::External, 0));
+}
+
+#[test]
+fn test_wallet_from_wallet_policy() {
+    use bdk_wallet::descriptor::WalletPolicy;
+
+    let policy = WalletPolicy::new(
+        "wsh(sortedmulti(2,@0/**,@1/**))",
+        vec![
+            "[c55b303f/84'/1'/0']tpubDDDzQ31JkZB7VxUr9bjvBivDdqoFLrDPyLWtLapArAi51ftfmCb2DPxwLQzX65iNcXz1DGaVvyvo6JQ6rTU73r2gqdEo8uov9QKRb7nKCSU".to_string(),
+            "[c55b303f/49'/1'/0']tpubDC49r947KGK52X5rBWS4BLs5m9SRY3pYHnvRrm7HcybZ3BfdEsGFyzCMzayi1u58eT82ZeyFZwH7DD6Q83E3fM9CpfMtmnTygnLfP59jL9L".to_string(),
+        ],
+    )
+    .unwrap();
+    let policy_wallet = Wallet::new(
+        (&policy, KeychainKind::External),
+        (&policy, KeychainKind::Internal),
+        Network::Testnet,
+    )
+    .unwrap();
+    let external = policy.descriptor(KeychainKind::External).unwrap();
+    let internal = policy.descriptor(KeychainKind::Internal).unwrap();
+    let descriptor_wallet = Wallet::new(&external, &internal, Network::Testnet).unwrap();
+
+    for keychain in [KeychainKind::External, KeychainKind::Internal] {
+        for index in 0..5 {
+            assert_eq!(
+                policy_wallet.peek_address(keychain, index).unwrap(),
+                descriptor_wallet.peek_address(keychain, index).unwrap(),
+            );
+        }
+    }
+
+    // the policy can be recovered from the wallet's descriptors
+    assert_eq!(
+        WalletPolicy::from_descriptors(
+            policy_wallet
+                .public_descriptor(KeychainKind::External)
+                .unwrap(),
+            policy_wallet
+                .public_descriptor(KeychainKind::Internal)
+                .unwrap(),
+        )
+        .unwrap(),
+        policy
+    );
+}
+
+#[test]
+fn test_parse_address_network() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    // a testnet4 taproot address, which uses the same `tb` HRP as testnet and signet
+    let testnet4_address = "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c";
+    let mainnet_address = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+
+    for network in [Network::Testnet, Network::Signet] {
+        let wallet = Wallet::new(desc, change_desc, network).unwrap();
+        let address = wallet.parse_address(testnet4_address).unwrap();
+        assert_eq!(address.to_string(), testnet4_address);
+        assert!(wallet.parse_address(mainnet_address).is_err());
+    }
+
+    let wallet = Wallet::new(desc, change_desc, Network::Regtest).unwrap();
+    assert!(wallet.parse_address(testnet4_address).is_err());
+    assert!(wallet.parse_address(mainnet_address).is_err());
+}
+
+#[test]
+fn test_peek_address_at_index() {
+    let desc = "wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)";
+    let change_desc = get_test_wpkh();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Testnet).unwrap();
+
+    assert_eq!(
+        wallet
+            .peek_address(KeychainKind::External, 1)
+            .unwrap()
+            .to_string(),
+        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+    );
+
+    assert_eq!(
+        wallet
+            .peek_address(KeychainKind::External, 0)
+            .unwrap()
+            .to_string(),
+        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+    );
+
+    assert_eq!(
+        wallet
+            .peek_address(KeychainKind::External, 2)
+            .unwrap()
+            .to_string(),
+        "tb1qzntf2mqex4ehwkjlfdyy3ewdlk08qkvkvrz7x2"
+    );
+
+    // current new address is not affected
+    assert_eq!(
+        wallet
+            .reveal_next_address(KeychainKind::External)
+            .unwrap()
+            .to_string(),
+        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+    );
+
     assert_eq!(
-        *extracted.input[0].witness.to_vec()[0].last().unwrap(),
-        sighash.to_u32() as u8,
-        "The signature should have been made with the right sighash"
+        wallet
+            .reveal_next_address(KeychainKind::External)
+            .unwrap()
+            .to_string(),
+        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
     );
 }
 
 #[test]
-fn test_unused_address() {
-    let desc = "wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)";
-    let change_desc = get_test_wpkh();
-    let mut wallet = Wallet::new(desc, change_desc, Network::Testnet).expect("wallet");
+fn test_peek_address_at_index_not_derivable() {
+    let wallet = Wallet::new("wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/1)",
+                                 get_test_wpkh(), Network::Testnet).unwrap();
 
-    // `list_unused_addresses` should be empty if we haven't revealed any
-    assert!(wallet
-        .list_unused_addresses(KeychainKind::External)
-        .next()
-        .is_none());
+    assert_eq!(
+        wallet
+            .peek_address(KeychainKind::External, 1)
+            .unwrap()
+            .to_string(),
+        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+    );
 
     assert_eq!(
         wallet
-            .next_unused_address(KeychainKind::External)
+            .peek_address(KeychainKind::External, 0)
+            .unwrap()
             .to_string(),
-        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
     );
+
     assert_eq!(
         wallet
-            .list_unused_addresses(KeychainKind::External)
-            .next()
+            .peek_address(KeychainKind::External, 2)
             .unwrap()
             .to_string(),
-        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
     );
 }
 
 #[test]
-fn test_next_unused_address() {
-    let descriptor = "wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)";
-    let change = get_test_wpkh();
-    let mut wallet = Wallet::new(descriptor, change, Network::Testnet).expect("wallet");
-    assert_eq!(wallet.derivation_index(KeychainKind::External), None);
+fn test_returns_index_and_address() {
+    let mut wallet = Wallet::new("wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)",
+                                 get_test_wpkh(), Network::Testnet).unwrap();
 
+    // new index 0
     assert_eq!(
-        wallet
-            .next_unused_address(KeychainKind::External)
-            .to_string(),
-        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+        wallet.reveal_next_address(KeychainKind::External).unwrap(),
+        AddressInfo {
+            index: 0,
+            address: Address::from_str("tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a")
+                .unwrap()
+                .assume_checked(),
+            keychain: KeychainKind::External,
+        }
     );
-    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(0));
-    // calling next_unused again gives same address
+
+    // new index 1
     assert_eq!(
-        wallet
-            .next_unused_address(KeychainKind::External)
-            .to_string(),
-        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+        wallet.reveal_next_address(KeychainKind::External).unwrap(),
+        AddressInfo {
+            index: 1,
+            address: Address::from_str("tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7")
+                .unwrap()
+                .assume_checked(),
+            keychain: KeychainKind::External,
+        }
     );
-    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(0));
 
-    // test mark used / unused
-    assert!(wallet.mark_used(KeychainKind::External, 0));
-    let next_unused_addr = wallet.next_unused_address(KeychainKind::External);
-    assert_eq!(next_unused_addr.index, 1);
+    // peek index 25
+    assert_eq!(
+        wallet.peek_address(KeychainKind::External, 25).unwrap(),
+        AddressInfo {
+            index: 25,
+            address: Address::from_str("tb1qsp7qu0knx3sl6536dzs0703u2w2ag6ppl9d0c2")
+                .unwrap()
+                .assume_checked(),
+            keychain: KeychainKind::External,
+        }
+    );
 
-    assert!(wallet.unmark_used(KeychainKind::External, 0));
-    let next_unused_addr = wallet.next_unused_address(KeychainKind::External);
-    assert_eq!(next_unused_addr.index, 0);
+    // new index 2
+    assert_eq!(
+        wallet.reveal_next_address(KeychainKind::External).unwrap(),
+        AddressInfo {
+            index: 2,
+            address: Address::from_str("tb1qzntf2mqex4ehwkjlfdyy3ewdlk08qkvkvrz7x2")
+                .unwrap()
+                .assume_checked(),
+            keychain: KeychainKind::External,
+        }
+    );
+}
 
-    // use the above address
-    receive_output_in_latest_block(&mut wallet, 25_000);
+#[test]
+fn test_sending_to_bip350_bech32m_address() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(45_000));
+    builder.finish().unwrap();
+}
+
+#[test]
+fn test_input_already_spent() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let utxo = wallet.list_unspent().next().unwrap();
+
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    let mut stale_psbt = builder.finish().unwrap();
+
+    // a conflicting transaction spending the same utxo confirms
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(20_000));
+    let tx = builder.finish().unwrap().unsigned_tx;
+    let txid = tx.compute_txid();
+    let height = wallet.latest_checkpoint().height();
+    wallet
+        .insert_tx(tx, ConfirmationTime::Confirmed { height, time: 0 })
+        .unwrap();
+
+    assert_matches!(
+        wallet.sign(&mut stale_psbt, SignOptions::default()),
+        Err(SignerError::InputAlreadySpent { outpoint, by_txid })
+        if outpoint == utxo.outpoint && by_txid == txid
+    );
+    // unless explicitly allowed
+    let sign_options = SignOptions {
+        allow_spent_inputs: true,
+        ..Default::default()
+    };
+    assert!(wallet.sign(&mut stale_psbt, sign_options).unwrap());
+
+    let satisfaction_weight = wallet
+        .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
+        .max_weight_to_satisfy()
+        .unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .only_witness_utxo()
+        .add_foreign_utxo(
+            utxo.outpoint,
+            psbt::Input {
+                witness_utxo: Some(utxo.txout.clone()),
+                ..Default::default()
+            },
+            satisfaction_weight.to_wu() as usize,
+        )
+        .unwrap();
+    let mut allowing_builder = builder.clone();
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::InputAlreadySpent { outpoint, by_txid })
+        if outpoint == utxo.outpoint && by_txid == txid
+    );
+    allowing_builder.allow_spent_inputs();
+    let psbt = allowing_builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, utxo.outpoint);
+}
+
+#[test]
+fn test_lock_utxo() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
 
+    wallet.lock_utxo(outpoint, std::time::Duration::from_secs(60));
     assert_eq!(
-        wallet
-            .next_unused_address(KeychainKind::External)
-            .to_string(),
-        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+        wallet.locked_utxos().map(|(op, _)| op).collect::<Vec<_>>(),
+        vec![outpoint]
+    );
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { .. }
+        ))
     );
-    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(1));
 
-    // trying to mark index 0 unused should return false
-    assert!(!wallet.unmark_used(KeychainKind::External, 0));
+    // manually selected utxos are spent even when locked
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .add_utxo(outpoint)
+        .unwrap();
+    assert!(builder.finish().is_ok());
+
+    assert!(wallet.unlock_utxo(outpoint));
+    assert!(!wallet.unlock_utxo(outpoint));
+    assert_eq!(wallet.locked_utxos().count(), 0);
+
+    // expired locks are ignored
+    wallet.lock_utxo_until(outpoint, 1);
+    assert_eq!(wallet.locked_utxos().count(), 0);
+    wallet.release_expired_utxo_locks(1);
+    assert!(!wallet.unlock_utxo(outpoint));
 }
 
 #[test]
-fn test_peek_address_at_index() {
-    let desc = "wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)";
-    let change_desc = get_test_wpkh();
-    let mut wallet = Wallet::new(desc, change_desc, Network::Testnet).unwrap();
+fn test_lock_utxo_sweep_and_consolidation() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let small = receive_output_in_latest_block(&mut wallet, 2_000);
+    let addr = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked();
+    let fee_rate = FeeRate::from_sat_per_vb_unchecked(5);
+    wallet.lock_utxo(small, std::time::Duration::from_secs(60));
 
+    assert_matches!(
+        wallet.build_consolidation_below(Amount::from_sat(10_000), addr.script_pubkey(), fee_rate),
+        Err(CreateTxError::NoUtxosSelected)
+    );
+    let psbt = wallet.build_sweep(&addr, fee_rate).unwrap();
     assert_eq!(
-        wallet.peek_address(KeychainKind::External, 1).to_string(),
-        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>(),
+        vec![OutPoint { txid, vout: 0 }]
+    );
+}
+
+#[test]
+fn test_lock_utxo_ttl() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
+    // the funded test wallets disable it, a new wallet locks inputs by default
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    assert_eq!(
+        Wallet::new(desc, change_desc, Network::Regtest)
+            .unwrap()
+            .utxo_lock_ttl(),
+        Some(DEFAULT_UTXO_LOCK_TTL)
     );
+    wallet.set_utxo_lock_ttl(Some(DEFAULT_UTXO_LOCK_TTL));
 
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    let tx = builder.finish().unwrap().unsigned_tx;
     assert_eq!(
-        wallet.peek_address(KeychainKind::External, 0).to_string(),
-        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+        wallet.locked_utxos().map(|(op, _)| op).collect::<Vec<_>>(),
+        vec![outpoint]
     );
 
+    // cancelling the transaction releases its inputs
+    wallet.cancel_tx(&tx);
+    assert_eq!(wallet.locked_utxos().count(), 0);
+
+    // and so does the transaction being broadcast
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    let tx = builder.finish().unwrap().unsigned_tx;
+    assert_eq!(wallet.locked_utxos().count(), 1);
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    assert_eq!(wallet.locked_utxos().count(), 0);
+}
+
+#[test]
+fn test_bip21() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+
+    let uri = addr.to_bip21(
+        Some(Amount::from_sat(150_000_000)),
+        Some("Alice's shop"),
+        Some("Order #42"),
+    );
     assert_eq!(
-        wallet.peek_address(KeychainKind::External, 2).to_string(),
-        "tb1qzntf2mqex4ehwkjlfdyy3ewdlk08qkvkvrz7x2"
+        uri,
+        format!(
+            "bitcoin:{}?amount=1.5&label=Alice%27s%20shop&message=Order%20%2342",
+            addr.address
+        )
+    );
+    assert_eq!(
+        addr.to_bip21(None, None, None),
+        format!("bitcoin:{}", addr.address)
+    );
+
+    let bip21 = wallet.parse_bip21(&uri).unwrap();
+    assert_eq!(bip21.address, addr.address);
+    assert_eq!(bip21.amount, Some(Amount::from_sat(150_000_000)));
+    assert_eq!(bip21.label.as_deref(), Some("Alice's shop"));
+    assert_eq!(bip21.message.as_deref(), Some("Order #42"));
+
+    // unknown parameters are ignored, unless they are required
+    let bip21 = wallet
+        .parse_bip21(&format!("BITCOIN:{}?foo=bar", addr.address))
+        .unwrap();
+    assert_eq!(bip21.address, addr.address);
+    assert_eq!(bip21.amount, None);
+    assert_matches!(
+        wallet.parse_bip21(&format!("bitcoin:{}?req-foo=bar", addr.address)),
+        Err(Bip21Error::UnknownRequiredParameter(key)) if key == "req-foo"
+    );
+    assert_matches!(
+        wallet.parse_bip21(&format!("bitcoin:{}?amount=1&amount=2", addr.address)),
+        Err(Bip21Error::DuplicateParameter(key)) if key == "amount"
+    );
+    assert_matches!(
+        wallet.parse_bip21(&addr.address.to_string()),
+        Err(Bip21Error::InvalidScheme)
+    );
+    assert_matches!(
+        wallet.parse_bip21("bitcoin:1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"),
+        Err(Bip21Error::WrongNetwork(Network::Regtest))
     );
+}
+
+#[test]
+fn test_get_tx_conflicts() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    let funding_txid = wallet.list_unspent().next().unwrap().outpoint.txid;
+
+    // two transactions double spending the only utxo of the wallet
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    let tx1 = builder.finish().unwrap().unsigned_tx;
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(20_000));
+    let tx2 = builder.finish().unwrap().unsigned_tx;
+    assert_eq!(tx1.input[0].previous_output, tx2.input[0].previous_output);
+    let (txid1, txid2) = (tx1.compute_txid(), tx2.compute_txid());
+
+    wallet
+        .insert_tx(tx1, ConfirmationTime::Unconfirmed { last_seen: 1 })
+        .unwrap();
+    wallet
+        .insert_tx(tx2, ConfirmationTime::Unconfirmed { last_seen: 2 })
+        .unwrap();
+
+    assert_eq!(wallet.get_tx_conflicts(txid2).collect::<Vec<_>>(), [txid1]);
+    assert_eq!(wallet.get_tx_conflicts(txid1).collect::<Vec<_>>(), [txid2]);
+    // tx2 was seen last so it is the canonical one
+    assert!(wallet.get_tx(txid1).is_none());
+    assert!(wallet.get_tx(txid2).is_some());
+
+    // the funding tx doesn't conflict with anything
+    assert_eq!(wallet.get_tx_conflicts(funding_txid).count(), 0);
+}
+
+#[test]
+fn test_tx_output_ownership() {
+    use bdk_wallet::wallet::tx_builder::TxOrdering;
+
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .ordering(TxOrdering::Untouched);
+    let tx = builder.finish().unwrap().unsigned_tx;
+    let txid = tx.compute_txid();
+    assert!(wallet.tx_output_ownership(txid).is_empty());
 
-    // current new address is not affected
+    wallet
+        .insert_tx(tx.clone(), ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    let ownership = wallet.tx_output_ownership(txid);
+    assert_eq!(ownership.len(), 2);
     assert_eq!(
-        wallet
-            .reveal_next_address(KeychainKind::External)
-            .to_string(),
-        "tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a"
+        ownership[0],
+        (
+            OutPoint::new(txid, 0),
+            addr.script_pubkey(),
+            Amount::from_sat(25_000),
+            None
+        )
     );
-
     assert_eq!(
-        wallet
-            .reveal_next_address(KeychainKind::External)
-            .to_string(),
-        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+        ownership[1],
+        (
+            OutPoint::new(txid, 1),
+            tx.output[1].script_pubkey.clone(),
+            tx.output[1].value,
+            Some((KeychainKind::Internal, 0))
+        )
     );
 }
 
 #[test]
-fn test_peek_address_at_index_not_derivable() {
-    let wallet = Wallet::new("wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/1)",
-                                 get_test_wpkh(), Network::Testnet).unwrap();
+fn test_utxo_age() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let tip = wallet.latest_checkpoint().height();
 
-    assert_eq!(
-        wallet.peek_address(KeychainKind::External, 1).to_string(),
-        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
-    );
+    let confirmed = receive_output_in_latest_block(&mut wallet, 25_000);
+    let utxo = wallet.get_utxo(confirmed).unwrap();
+    assert_eq!(utxo.confirmation_height(), Some(tip));
+    assert_eq!(wallet.utxo_age(confirmed, tip), Some(1));
+    assert_eq!(wallet.utxo_age(confirmed, tip + 9), Some(10));
+    assert_eq!(wallet.utxo_age(confirmed, tip - 1), None);
 
-    assert_eq!(
-        wallet.peek_address(KeychainKind::External, 0).to_string(),
-        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+    let unconfirmed = receive_output(
+        &mut wallet,
+        30_000,
+        ConfirmationTime::Unconfirmed { last_seen: 0 },
     );
-
     assert_eq!(
-        wallet.peek_address(KeychainKind::External, 2).to_string(),
-        "tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7"
+        wallet.get_utxo(unconfirmed).unwrap().confirmation_height(),
+        None
     );
+    assert_eq!(wallet.utxo_age(unconfirmed, tip), None);
+
+    let unknown = OutPoint::new(Txid::all_zeros(), 0);
+    assert_eq!(wallet.utxo_age(unknown, tip), None);
 }
 
 #[test]
-fn test_returns_index_and_address() {
-    let mut wallet = Wallet::new("wpkh(tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9/*)",
-                                 get_test_wpkh(), Network::Testnet).unwrap();
+fn test_get_address() {
+    use bdk_wallet::descriptor::template::Bip84;
+    let key = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPcx5nBGsR63Pe8KnRUqmbJNENAfGftF3yuXoMMoVJJcYeUw5eVkm9WBPjWYt6HMWYJNesB5HaNVBaFc1M6dRjWSYnmewUMYy").unwrap();
+    let wallet = Wallet::new(
+        Bip84(key, KeychainKind::External),
+        Bip84(key, KeychainKind::Internal),
+        Network::Regtest,
+    )
+    .unwrap();
 
-    // new index 0
     assert_eq!(
-        wallet.reveal_next_address(KeychainKind::External),
+        wallet.peek_address(KeychainKind::External, 0).unwrap(),
         AddressInfo {
             index: 0,
-            address: Address::from_str("tb1q6yn66vajcctph75pvylgkksgpp6nq04ppwct9a")
+            address: Address::from_str("bcrt1qrhgaqu0zvf5q2d0gwwz04w0dh0cuehhqvzpp4w")
                 .unwrap()
                 .assume_checked(),
             keychain: KeychainKind::External,
         }
     );
 
-    // new index 1
     assert_eq!(
-        wallet.reveal_next_address(KeychainKind::External),
+        wallet.peek_address(KeychainKind::Internal, 0).unwrap(),
         AddressInfo {
-            index: 1,
-            address: Address::from_str("tb1q4er7kxx6sssz3q7qp7zsqsdx4erceahhax77d7")
+            index: 0,
+            address: Address::from_str("bcrt1q0ue3s5y935tw7v3gmnh36c5zzsaw4n9c9smq79")
                 .unwrap()
                 .assume_checked(),
-            keychain: KeychainKind::External,
+            keychain: KeychainKind::Internal,
         }
     );
+}
 
-    // peek index 25
+#[test]
+fn test_reveal_addresses() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Signet).unwrap();
+    let keychain = KeychainKind::External;
+
+    let last_revealed_addr = wallet
+        .reveal_addresses_to(keychain, 9)
+        .unwrap()
+        .last()
+        .unwrap();
+    assert_eq!(wallet.derivation_index(keychain), Some(9));
+
+    let unused_addrs = wallet.list_unused_addresses(keychain).collect::<Vec<_>>();
+    assert_eq!(unused_addrs.len(), 10);
+    assert_eq!(unused_addrs.last().unwrap(), &last_revealed_addr);
+
+    // revealing to an already revealed index returns nothing
+    let mut already_revealed = wallet.reveal_addresses_to(keychain, 9).unwrap();
+    assert!(already_revealed.next().is_none());
+}
+
+#[test]
+fn test_keychain_stats() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Signet).unwrap();
+    let keychain = KeychainKind::External;
+    let lookahead = wallet.spk_index().lookahead();
+
+    let stats = wallet.keychain_stats(keychain).unwrap();
     assert_eq!(
-        wallet.peek_address(KeychainKind::External, 25),
-        AddressInfo {
-            index: 25,
-            address: Address::from_str("tb1qsp7qu0knx3sl6536dzs0703u2w2ag6ppl9d0c2")
-                .unwrap()
-                .assume_checked(),
-            keychain: KeychainKind::External,
+        stats,
+        KeychainStats {
+            last_revealed: None,
+            last_used: None,
+            next_unused: 0,
+            gap_to_lookahead: lookahead,
         }
     );
 
-    // new index 2
+    let _ = wallet.reveal_addresses_to(keychain, 2).unwrap().last();
+    let stats = wallet.keychain_stats(keychain).unwrap();
+    assert_eq!(stats.last_revealed, Some(2));
+    assert_eq!(stats.next_unused, 0);
+    assert_eq!(stats.gap_to_lookahead, lookahead - 3);
+
+    // receive to the address at index 1
+    let tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![TxOut {
+            script_pubkey: wallet.peek_address(keychain, 1).unwrap().script_pubkey(),
+            value: Amount::from_sat(10_000),
+        }],
+    };
+    wallet
+        .insert_tx(tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    let stats = wallet.keychain_stats(keychain).unwrap();
     assert_eq!(
-        wallet.reveal_next_address(KeychainKind::External),
-        AddressInfo {
-            index: 2,
-            address: Address::from_str("tb1qzntf2mqex4ehwkjlfdyy3ewdlk08qkvkvrz7x2")
-                .unwrap()
-                .assume_checked(),
-            keychain: KeychainKind::External,
+        stats,
+        KeychainStats {
+            last_revealed: Some(2),
+            last_used: Some(1),
+            next_unused: 0,
+            gap_to_lookahead: lookahead - 1,
         }
     );
+
+    // the other keychain is unaffected
+    assert_eq!(
+        wallet
+            .keychain_stats(KeychainKind::Internal)
+            .unwrap()
+            .last_revealed,
+        None
+    );
 }
 
 #[test]
-fn test_sending_to_bip350_bech32m_address() {
+fn test_input_weight_estimate() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let tr_wallet = Wallet::new(desc, change_desc, Network::Regtest).unwrap();
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    let wpkh_wallet = Wallet::new(desc, change_desc, Network::Regtest).unwrap();
+    let secp = Secp256k1::new();
+    let pubkeys = [
+        "cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW",
+        "cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu",
+        "cTc4vURSzdx6QE6KVynWGomDbLaA75dNALMNyfjh3p8DRRar84Um",
+    ]
+    .map(|wif| {
+        bitcoin::PrivateKey::from_wif(wif)
+            .unwrap()
+            .public_key(&secp)
+            .to_string()
+    });
+    let multisig_wallet = Wallet::new(
+        &format!("wsh(multi(2,{},{},{}))", pubkeys[0], pubkeys[1], pubkeys[2]),
+        &format!(
+            "wsh(sortedmulti(2,{},{},{}))",
+            pubkeys[0], pubkeys[1], pubkeys[2]
+        ),
+        Network::Regtest,
+    )
+    .unwrap();
+
+    for wallet in [&tr_wallet, &wpkh_wallet, &multisig_wallet] {
+        for keychain in [KeychainKind::External, KeychainKind::Internal] {
+            assert_eq!(
+                wallet.input_weight_estimate(keychain).unwrap(),
+                wallet
+                    .public_descriptor(keychain)
+                    .unwrap()
+                    .max_weight_to_satisfy()
+                    .unwrap()
+            );
+        }
+    }
+
+    let tr_weight = tr_wallet
+        .input_weight_estimate(KeychainKind::External)
+        .unwrap();
+    let wpkh_weight = wpkh_wallet
+        .input_weight_estimate(KeychainKind::External)
+        .unwrap();
+    let multisig_weight = multisig_wallet
+        .input_weight_estimate(KeychainKind::External)
+        .unwrap();
+    assert!(tr_weight < wpkh_weight);
+    assert!(wpkh_weight < multisig_weight);
+}
+
+#[test]
+fn test_apply_block() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
-    let addr = Address::from_str("tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c")
+    let tip = wallet.latest_checkpoint();
+    let receive_spk = wallet
+        .next_unused_address(KeychainKind::External)
         .unwrap()
-        .assume_checked();
-    let mut builder = wallet.build_tx();
-    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(45_000));
-    builder.finish().unwrap();
+        .script_pubkey();
+    let foreign_spk = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+
+    let coinbase = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            script_pubkey: receive_spk.clone(),
+            value: Amount::from_sat(5_000_000),
+        }],
+    };
+    let payment = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            script_pubkey: receive_spk,
+            value: Amount::from_sat(20_000),
+        }],
+    };
+    let unrelated = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            script_pubkey: foreign_spk,
+            value: Amount::from_sat(20_000),
+        }],
+    };
+    let block = bitcoin::Block {
+        header: bitcoin::block::Header {
+            version: bitcoin::block::Version::ONE,
+            prev_blockhash: tip.hash(),
+            merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: bitcoin::CompactTarget::from_consensus(0),
+            nonce: 0,
+        },
+        txdata: vec![coinbase.clone(), payment.clone(), unrelated.clone()],
+    };
+
+    let height = tip.height() + 1;
+    wallet.apply_block(&block, height).unwrap();
+
+    // the chain tip is updated
+    assert_eq!(
+        wallet.latest_checkpoint().block_id(),
+        BlockId {
+            height,
+            hash: block.block_hash(),
+        }
+    );
+    // relevant transactions, including the coinbase, are anchored to the block
+    for tx in [&coinbase, &payment] {
+        let canonical_tx = wallet.get_tx(tx.compute_txid()).expect("tx must exist");
+        assert_eq!(
+            ConfirmationTime::from(canonical_tx.chain_position.cloned()),
+            ConfirmationTime::Confirmed { height, time: 0 }
+        );
+    }
+    assert!(wallet.get_tx(unrelated.compute_txid()).is_none());
+    assert_eq!(
+        wallet.balance(),
+        Balance {
+            immature: Amount::from_sat(5_000_000),
+            confirmed: Amount::from_sat(70_000),
+            ..Default::default()
+        }
+    );
 }
 
 #[test]
-fn test_get_address() {
-    use bdk_wallet::descriptor::template::Bip84;
-    let key = bitcoin::bip32::Xpriv::from_str("tprv8ZgxMBicQKsPcx5nBGsR63Pe8KnRUqmbJNENAfGftF3yuXoMMoVJJcYeUw5eVkm9WBPjWYt6HMWYJNesB5HaNVBaFc1M6dRjWSYnmewUMYy").unwrap();
-    let wallet = Wallet::new(
-        Bip84(key, KeychainKind::External),
-        Bip84(key, KeychainKind::Internal),
-        Network::Regtest,
-    )
-    .unwrap();
+fn test_apply_block_connected_to_reorg() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let tip = wallet.latest_checkpoint();
+    let height = tip.height() + 1;
+    let receive_spk = wallet
+        .next_unused_address(KeychainKind::External)
+        .unwrap()
+        .script_pubkey();
+    let payment = |value: u64| Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([value as u8; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            script_pubkey: receive_spk.clone(),
+            value: Amount::from_sat(value),
+        }],
+    };
+    let block = |nonce: u32, txdata: Vec<Transaction>| bitcoin::Block {
+        header: bitcoin::block::Header {
+            version: bitcoin::block::Version::ONE,
+            prev_blockhash: tip.hash(),
+            merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: bitcoin::CompactTarget::from_consensus(0),
+            nonce,
+        },
+        txdata,
+    };
+
+    let tx_a = payment(10_000);
+    let tx_b = payment(20_000);
+    let block_a = block(0, vec![tx_a.clone()]);
+    let block_b = block(1, vec![tx_b.clone()]);
+
+    wallet
+        .apply_block_connected_to(&block_a, height, tip.block_id())
+        .unwrap();
+    assert_eq!(wallet.latest_checkpoint().hash(), block_a.block_hash());
+    assert_eq!(wallet.balance().confirmed, Amount::from_sat(60_000));
 
+    // block b replaces block a at the same height
+    wallet
+        .apply_block_connected_to(&block_b, height, tip.block_id())
+        .unwrap();
     assert_eq!(
-        wallet.peek_address(KeychainKind::External, 0),
-        AddressInfo {
-            index: 0,
-            address: Address::from_str("bcrt1qrhgaqu0zvf5q2d0gwwz04w0dh0cuehhqvzpp4w")
-                .unwrap()
-                .assume_checked(),
-            keychain: KeychainKind::External,
+        wallet.latest_checkpoint().block_id(),
+        BlockId {
+            height,
+            hash: block_b.block_hash(),
+        }
+    );
+    assert!(!wallet
+        .get_tx(tx_a.compute_txid())
+        .unwrap()
+        .chain_position
+        .is_confirmed());
+    assert!(wallet
+        .get_tx(tx_b.compute_txid())
+        .unwrap()
+        .chain_position
+        .is_confirmed());
+    assert_eq!(
+        wallet.balance(),
+        Balance {
+            confirmed: Amount::from_sat(70_000),
+            untrusted_pending: Amount::from_sat(10_000),
+            ..Default::default()
         }
     );
+    // the reorg is staged for persistence
+    assert_eq!(
+        wallet.staged().chain.get(&height),
+        Some(&Some(block_b.block_hash()))
+    );
+}
+
+#[test]
+fn test_import_transactions() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
+    let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
+    let revealed = wallet.derivation_index(KeychainKind::External).unwrap();
+    // pays to a script pubkey the wallet hasn't revealed yet
+    let spk = wallet
+        .peek_address(KeychainKind::External, revealed + 5)
+        .unwrap()
+        .script_pubkey();
+    let foreign_spk = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+    let tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(25_000),
+            script_pubkey: spk,
+        }],
+    };
+    let unrelated_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: foreign_spk,
+        }],
+    };
+    let hex = [
+        bitcoin::consensus::encode::serialize_hex(&tx),
+        bitcoin::consensus::encode::serialize_hex(&unrelated_tx),
+    ];
+
+    let changeset = wallet.import_transactions(
+        hex.iter()
+            .map(|hex| bitcoin::consensus::encode::deserialize_hex(hex).unwrap()),
+    );
 
+    let txid = tx.compute_txid();
+    assert!(changeset
+        .indexed_tx_graph
+        .graph
+        .txs
+        .iter()
+        .any(|tx| tx.compute_txid() == txid));
+    assert!(wallet.get_tx(unrelated_tx.compute_txid()).is_none());
     assert_eq!(
-        wallet.peek_address(KeychainKind::Internal, 0),
-        AddressInfo {
-            index: 0,
-            address: Address::from_str("bcrt1q0ue3s5y935tw7v3gmnh36c5zzsaw4n9c9smq79")
-                .unwrap()
-                .assume_checked(),
-            keychain: KeychainKind::Internal,
-        }
+        wallet.derivation_index(KeychainKind::External),
+        Some(revealed + 5)
     );
+    assert!(wallet
+        .unconfirmed_utxos()
+        .any(|utxo| utxo.outpoint == OutPoint::new(txid, 0)));
 }
 
 #[test]
-fn test_reveal_addresses() {
-    let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
-    let mut wallet = Wallet::new(desc, change_desc, Network::Signet).unwrap();
-    let keychain = KeychainKind::External;
+fn test_apply_unconfirmed_txs_with_incoming() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let receive_spk = wallet
+        .next_unused_address(KeychainKind::External)
+        .unwrap()
+        .script_pubkey();
+    let foreign_spk = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
 
-    let last_revealed_addr = wallet.reveal_addresses_to(keychain, 9).last().unwrap();
-    assert_eq!(wallet.derivation_index(keychain), Some(9));
+    let incoming_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(25_000),
+                script_pubkey: receive_spk,
+            },
+            TxOut {
+                value: Amount::from_sat(5_000),
+                script_pubkey: foreign_spk.clone(),
+            },
+        ],
+    };
+    let unrelated_tx = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: foreign_spk.clone(),
+        }],
+    };
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(foreign_spk, Amount::from_sat(10_000));
+    let outgoing_tx = builder.finish().unwrap().unsigned_tx;
+
+    let incoming = wallet.apply_unconfirmed_txs_with_incoming([
+        (&incoming_tx, 100),
+        (&unrelated_tx, 100),
+        (&outgoing_tx, 100),
+    ]);
+    assert_eq!(
+        incoming,
+        vec![(incoming_tx.compute_txid(), Amount::from_sat(25_000))]
+    );
+    assert!(wallet.get_tx(outgoing_tx.compute_txid()).is_some());
+    assert!(wallet.get_tx(unrelated_tx.compute_txid()).is_none());
 
-    let unused_addrs = wallet.list_unused_addresses(keychain).collect::<Vec<_>>();
-    assert_eq!(unused_addrs.len(), 10);
-    assert_eq!(unused_addrs.last().unwrap(), &last_revealed_addr);
+    // transactions already known to the wallet are not reported again
+    let incoming = wallet.apply_unconfirmed_txs_with_incoming([(&incoming_tx, 200)]);
+    assert!(incoming.is_empty());
+}
 
-    // revealing to an already revealed index returns nothing
-    let mut already_revealed = wallet.reveal_addresses_to(keychain, 9);
-    assert!(already_revealed.next().is_none());
+#[test]
+fn test_start_sync_with_spks() {
+    let (wallet, _) = get_funded_wallet_wpkh();
+    let spk = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+
+    let request = wallet.start_sync_with_spks(vec![spk.clone()]);
+    assert_eq!(request.chain_tip, wallet.latest_checkpoint());
+    assert_eq!(request.estimated_request_count(), 1);
+    assert_eq!(request.spks.collect::<Vec<_>>(), vec![spk]);
 }
 
 #[test]
@@ -3147,10 +6542,16 @@ fn test_get_address_no_reuse() {
     let mut used_set = HashSet::new();
 
     (0..3).for_each(|_| {
-        let external_addr = wallet.reveal_next_address(KeychainKind::External).address;
+        let external_addr = wallet
+            .reveal_next_address(KeychainKind::External)
+            .unwrap()
+            .address;
         assert!(used_set.insert(external_addr));
 
-        let internal_addr = wallet.reveal_next_address(KeychainKind::Internal).address;
+        let internal_addr = wallet
+            .reveal_next_address(KeychainKind::Internal)
+            .unwrap()
+            .address;
         assert!(used_set.insert(internal_addr));
     });
 }
@@ -3159,7 +6560,7 @@ fn test_get_address_no_reuse() {
 fn test_taproot_remove_tapfields_after_finalize_sign_option() {
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree());
 
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -3185,7 +6586,7 @@ fn test_taproot_remove_tapfields_after_finalize_sign_option() {
 fn test_taproot_psbt_populate_tap_key_origins() {
     let (desc, change_desc) = get_test_tr_single_sig_xprv_with_change_desc();
     let (mut wallet, _) = get_funded_wallet_with_change(desc, change_desc);
-    let addr = wallet.reveal_next_address(KeychainKind::External);
+    let addr = wallet.reveal_next_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
@@ -3221,7 +6622,7 @@ fn test_taproot_psbt_populate_tap_key_origins() {
 fn test_taproot_psbt_populate_tap_key_origins_repeated_key() {
     let (mut wallet, _) =
         get_funded_wallet_with_change(get_test_tr_repeated_key(), get_test_tr_single_sig());
-    let addr = wallet.reveal_next_address(KeychainKind::External);
+    let addr = wallet.reveal_next_address(KeychainKind::External).unwrap();
 
     let path = vec![("rn4nre9c".to_string(), vec![0])]
         .into_iter()
@@ -3288,7 +6689,7 @@ fn test_taproot_psbt_input_tap_tree() {
     use bitcoin::taproot;
 
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
@@ -3331,7 +6732,7 @@ fn test_taproot_psbt_input_tap_tree() {
 #[test]
 fn test_taproot_sign_missing_witness_utxo() {
     let (mut wallet, _) = get_funded_wallet(get_test_tr_single_sig());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -3371,7 +6772,7 @@ fn test_taproot_sign_missing_witness_utxo() {
 #[test]
 fn test_taproot_sign_using_non_witness_utxo() {
     let (mut wallet, prev_txid) = get_funded_wallet(get_test_tr_single_sig());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder.drain_to(addr.script_pubkey()).drain_wallet();
     let mut psbt = builder.finish().unwrap();
@@ -3404,6 +6805,7 @@ fn test_taproot_foreign_utxo() {
     let psbt_input = wallet2.get_psbt_input(utxo.clone(), None, false).unwrap();
     let foreign_utxo_satisfaction = wallet2
         .get_descriptor_for_keychain(KeychainKind::External)
+        .unwrap()
         .max_weight_to_satisfy()
         .unwrap();
 
@@ -3443,7 +6845,7 @@ fn test_taproot_foreign_utxo() {
 }
 
 fn test_spend_from_wallet(mut wallet: Wallet) {
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3467,7 +6869,7 @@ fn test_spend_from_wallet(mut wallet: Wallet) {
 #[test]
 fn test_taproot_no_key_spend() {
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_both_priv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3489,6 +6891,64 @@ fn test_taproot_no_key_spend() {
     assert!(psbt.inputs.iter().all(|i| i.tap_key_sig.is_none()));
 }
 
+#[test]
+fn test_taproot_key_spend_with_taptree() {
+    use bdk_wallet::signer::TapLeavesOptions;
+    use bitcoin::key::XOnlyPublicKey;
+    use bitcoin::secp256k1::Message;
+    use bitcoin::sighash::{Prevouts, SighashCache};
+
+    let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_xprv());
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
+
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let mut psbt = builder.finish().unwrap();
+    assert!(psbt.inputs[0].tap_merkle_root.is_some());
+
+    wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                tap_leaves_options: TapLeavesOptions::None,
+                try_finalize: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    let input = &psbt.inputs[0];
+    assert!(input.tap_script_sigs.is_empty());
+    let signature = input.tap_key_sig.expect("must have a key-path signature");
+
+    // the signature must verify against the (tweaked) output key of the spent UTXO
+    let prevouts = psbt
+        .inputs
+        .iter()
+        .map(|i| i.witness_utxo.clone().unwrap())
+        .collect::<Vec<_>>();
+    let sighash = SighashCache::new(&psbt.unsigned_tx)
+        .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+        .unwrap();
+    let output_key =
+        XOnlyPublicKey::from_slice(&prevouts[0].script_pubkey.as_bytes()[2..]).unwrap();
+    Secp256k1::verification_only()
+        .verify_schnorr(&signature.signature, &Message::from(sighash), &output_key)
+        .expect("signature must commit to the tweaked key");
+
+    // a PSBT with a wrong merkle root must not be signed
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let mut psbt = builder.finish().unwrap();
+    psbt.inputs[0].tap_merkle_root = None;
+    let signers = wallet.get_signers(KeychainKind::External);
+    let result = signers.signers().iter().try_for_each(|signer| {
+        signer.sign_transaction(&mut psbt, &SignOptions::default(), wallet.secp_ctx())
+    });
+    assert_matches!(result, Err(SignerError::TaprootOutputKeyMismatch));
+    assert!(psbt.inputs[0].tap_key_sig.is_none());
+}
+
 #[test]
 fn test_taproot_script_spend() {
     let (wallet, _) = get_funded_wallet(get_test_tr_with_taptree());
@@ -3502,7 +6962,7 @@ fn test_taproot_script_spend() {
 fn test_taproot_script_spend_sign_all_leaves() {
     use bdk_wallet::signer::TapLeavesOptions;
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_both_priv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3533,7 +6993,7 @@ fn test_taproot_script_spend_sign_include_some_leaves() {
     use bitcoin::taproot::TapLeafHash;
 
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_both_priv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3573,7 +7033,7 @@ fn test_taproot_script_spend_sign_exclude_some_leaves() {
     use bitcoin::taproot::TapLeafHash;
 
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_both_priv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3611,7 +7071,7 @@ fn test_taproot_script_spend_sign_exclude_some_leaves() {
 fn test_taproot_script_spend_sign_no_leaves() {
     use bdk_wallet::signer::TapLeavesOptions;
     let (mut wallet, _) = get_funded_wallet(get_test_tr_with_taptree_both_priv());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3634,7 +7094,7 @@ fn test_taproot_script_spend_sign_no_leaves() {
 fn test_taproot_sign_derive_index_from_psbt() {
     let (mut wallet, _) = get_funded_wallet(get_test_tr_single_sig_xprv());
 
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
     builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
@@ -3659,7 +7119,7 @@ fn test_taproot_sign_derive_index_from_psbt() {
 #[test]
 fn test_taproot_sign_explicit_sighash_all() {
     let (mut wallet, _) = get_funded_wallet(get_test_tr_single_sig());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
@@ -3674,12 +7134,150 @@ fn test_taproot_sign_explicit_sighash_all() {
     )
 }
 
+#[test]
+fn test_taproot_sighash_for_all_plus_anyonecanpay() {
+    let (mut wallet, _) = get_funded_wallet(get_test_tr_single_sig());
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .sighash_for(outpoint, TapSighashType::AllPlusAnyoneCanPay.into());
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(
+        psbt.inputs[0].sighash_type,
+        Some(TapSighashType::AllPlusAnyoneCanPay.into())
+    );
+
+    // it commits to every output, but other inputs can be added so it needs opting-in
+    assert_matches!(
+        wallet.sign(&mut psbt, SignOptions::default()),
+        Err(SignerError::NonStandardSighash)
+    );
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                allow_anyone_can_pay: true,
+                try_finalize: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!finalized);
+    assert_eq!(
+        psbt.inputs[0].tap_key_sig.unwrap().sighash_type,
+        TapSighashType::AllPlusAnyoneCanPay
+    );
+    assert!(wallet.finalize_psbt(&mut psbt, Default::default()).unwrap());
+}
+
+#[test]
+fn test_sighash_for_single() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .sighash_for(outpoint, EcdsaSighashType::SinglePlusAnyoneCanPay.into());
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.output.len(), 2);
+
+    // the input has a matching output, but the change could still be redirected
+    assert_matches!(
+        wallet.sign(&mut psbt, SignOptions::default()),
+        Err(SignerError::NonStandardSighash)
+    );
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                allow_all_sighashes: true,
+                try_finalize: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!finalized);
+    let sig = psbt.inputs[0].partial_sigs.values().next().unwrap();
+    assert_eq!(sig.sighash_type, EcdsaSighashType::SinglePlusAnyoneCanPay);
+}
+
+#[test]
+fn test_sighash_for_none() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
+
+    // `SIGHASH_DEFAULT` is not valid for an ECDSA input
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .sighash_for(outpoint, TapSighashType::Default.into());
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::InvalidSighash { outpoint: op, sighash })
+        if op == outpoint && sighash == TapSighashType::Default.into()
+    );
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .sighash_for(outpoint, EcdsaSighashType::None.into());
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(
+        psbt.inputs[0].sighash_type,
+        Some(EcdsaSighashType::None.into())
+    );
+
+    // the outputs could be replaced by anyone, so the signer must opt-in
+    assert_matches!(
+        wallet.sign(&mut psbt, SignOptions::default()),
+        Err(SignerError::NonStandardSighash)
+    );
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                allow_all_sighashes: true,
+                try_finalize: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!finalized);
+    let sig = psbt.inputs[0].partial_sigs.values().next().unwrap();
+    assert_eq!(sig.sighash_type, EcdsaSighashType::None);
+
+    // a sighash invalid for the input is refused even after opting-in
+    psbt.inputs[0].sighash_type = Some(TapSighashType::Default.into());
+    assert_matches!(
+        wallet.sign(
+            &mut psbt,
+            SignOptions {
+                allow_all_sighashes: true,
+                ..Default::default()
+            },
+        ),
+        Err(SignerError::InvalidSighash)
+    );
+}
+
 #[test]
 fn test_taproot_sign_non_default_sighash() {
     let sighash = TapSighashType::NonePlusAnyoneCanPay;
 
     let (mut wallet, _) = get_funded_wallet(get_test_tr_single_sig());
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let mut builder = wallet.build_tx();
     builder
         .drain_to(addr.script_pubkey())
@@ -3765,6 +7363,7 @@ fn test_spend_coinbase() {
         output: vec![TxOut {
             script_pubkey: wallet
                 .next_unused_address(KeychainKind::External)
+                .unwrap()
                 .script_pubkey(),
             value: Amount::from_sat(25_000),
         }],
@@ -3850,11 +7449,83 @@ fn test_spend_coinbase() {
     builder.finish().unwrap();
 }
 
+#[test]
+fn test_sweep_matured_coinbase() {
+    let (desc, change_desc) = get_test_wpkh_with_change_desc();
+    let mut wallet = Wallet::new(desc, change_desc, Network::Regtest).unwrap();
+    let spk = wallet
+        .next_unused_address(KeychainKind::External)
+        .unwrap()
+        .script_pubkey();
+
+    // mine 101 blocks to the wallet, like `TestEnv::mine_blocks` does
+    let mut coinbase_txids = vec![];
+    for height in 1..=COINBASE_MATURITY + 1 {
+        wallet
+            .insert_checkpoint(BlockId {
+                height,
+                hash: BlockHash::hash(&height.to_le_bytes()),
+            })
+            .unwrap();
+        let coinbase_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::builder().push_int(height as i64).into_script(),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                script_pubkey: spk.clone(),
+                value: Amount::from_sat(5_000_000_000),
+            }],
+        };
+        coinbase_txids.push(coinbase_tx.compute_txid());
+        wallet
+            .insert_tx(coinbase_tx, ConfirmationTime::Confirmed { height, time: 0 })
+            .unwrap();
+    }
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    // one block earlier, none of the coinbase outputs are mature yet
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(10_000))
+        .current_height(COINBASE_MATURITY);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::Error::InsufficientFunds { available: 0, .. }
+        ))
+    );
+
+    // at the tip, only the first coinbase output has matured
+    let mut builder = wallet.build_tx();
+    builder.drain_to(addr.script_pubkey()).drain_wallet();
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(
+        psbt.unsigned_tx.input[0].previous_output,
+        OutPoint::new(coinbase_txids[0], 0)
+    );
+    let fee = check_fee!(wallet, psbt);
+    assert_eq!(
+        psbt.unsigned_tx.output[0].value,
+        Amount::from_sat(5_000_000_000) - fee.unwrap()
+    );
+
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+}
+
 #[test]
 fn test_allow_dust_limit() {
     let (mut wallet, _) = get_funded_wallet(get_test_single_sig_cltv());
 
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
 
     let mut builder = wallet.build_tx();
 
@@ -3874,13 +7545,42 @@ fn test_allow_dust_limit() {
     assert!(builder.finish().is_ok());
 }
 
+#[test]
+fn test_set_dust_relay_fee() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    assert_eq!(wallet.dust_relay_fee(), DEFAULT_DUST_RELAY_FEE);
+    let spk = Address::from_str("bcrt1q3qtze4ys45tgdvguj66zrk4fu6hq3a3v9pfly5")
+        .unwrap()
+        .assume_checked()
+        .script_pubkey();
+
+    // 200 sats is below the 294 sats dust limit of p2wpkh at 3 sat/vB
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(spk.clone(), Amount::from_sat(200));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::OutputBelowDustLimit(0))
+    );
+
+    // but above the 98 sats dust limit at 1 sat/vB
+    wallet.set_dust_relay_fee(FeeRate::from_sat_per_vb_unchecked(1));
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(spk.clone(), Amount::from_sat(200));
+    let psbt = builder.finish().unwrap();
+    assert!(psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == spk && txout.value == Amount::from_sat(200)));
+}
+
 #[test]
 fn test_fee_rate_sign_no_grinding_high_r() {
     // Our goal is to obtain a transaction with a signature with high-R (71 bytes
     // instead of 70). We then check that our fee rate and fee calculation is
     // alright.
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
     let mut builder = wallet.build_tx();
     let mut data = PushBytesBuf::try_from(vec![0]).unwrap();
@@ -3949,7 +7649,7 @@ fn test_fee_rate_sign_grinding_low_r() {
     // We then check that our fee rate and fee calculation is alright and that our
     // signature is 70 bytes.
     let (mut wallet, _) = get_funded_wallet("wpkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/*)");
-    let addr = wallet.next_unused_address(KeychainKind::External);
+    let addr = wallet.next_unused_address(KeychainKind::External).unwrap();
     let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
     let mut builder = wallet.build_tx();
     builder
@@ -3986,7 +7686,7 @@ fn test_taproot_load_descriptor_duplicated_keys() {
     // Having the same key in multiple taproot leaves is safe and should be accepted by BDK
 
     let (wallet, _) = get_funded_wallet(get_test_tr_dup_keys());
-    let addr = wallet.peek_address(KeychainKind::External, 0);
+    let addr = wallet.peek_address(KeychainKind::External, 0).unwrap();
 
     assert_eq!(
         addr.to_string(),
@@ -4065,6 +7765,48 @@ fn test_tx_cancellation() {
     assert_eq!(change_derivation_4, (KeychainKind::Internal, 2));
 }
 
+#[test]
+fn test_cancel_psbt() {
+    let (mut wallet, _) =
+        get_funded_wallet_with_change(get_test_wpkh(), get_test_tr_single_sig_xprv());
+    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+        .unwrap()
+        .assume_checked();
+
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    let psbt1 = builder.finish().unwrap();
+    let change_spk = psbt1
+        .unsigned_tx
+        .output
+        .iter()
+        .find(|txout| wallet.is_mine(&txout.script_pubkey))
+        .map(|txout| txout.script_pubkey.clone())
+        .unwrap();
+    assert_eq!(
+        wallet.derivation_of_spk(&change_spk),
+        Some((KeychainKind::Internal, 0))
+    );
+    // cancelling must not stage anything
+    let staged = wallet.staged().clone();
+    wallet.cancel_psbt(&psbt1);
+    assert_eq!(wallet.staged(), &staged);
+
+    // the change address is handed out again, and the same utxo can be spent
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+    let psbt2 = builder.finish().unwrap();
+    assert!(psbt2
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|txout| txout.script_pubkey == change_spk));
+    assert_eq!(
+        psbt1.unsigned_tx.input[0].previous_output,
+        psbt2.unsigned_tx.input[0].previous_output
+    );
+}
+
 #[test]
 fn test_thread_safety() {
     fn thread_safe<T: Send + Sync>() {}
@@ -24,7 +24,9 @@ use example_cli::{
     Keychain,
 };
 
-const DB_MAGIC: &[u8] = b"bdk_example_rpc";
+// bumped with the layout of the changesets, the stores written before must be migrated with
+// `bdk_file_store::Store::migrate`
+const DB_MAGIC: &[u8] = b"bdk_example_rpc_v1";
 const DB_PATH: &str = ".bdk_example_rpc.db";
 
 /// The mpsc channel bound for emissions from [`Emitter`].
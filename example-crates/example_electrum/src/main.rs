@@ -23,7 +23,9 @@ use example_cli::{
     Keychain,
 };
 
-const DB_MAGIC: &[u8] = b"bdk_example_electrum";
+// bumped with the layout of the changesets, the stores written before must be migrated with
+// `bdk_file_store::Store::migrate`
+const DB_MAGIC: &[u8] = b"bdk_example_electrum_v1";
 const DB_PATH: &str = ".bdk_example_electrum.db";
 
 #[derive(Subcommand, Debug, Clone)]
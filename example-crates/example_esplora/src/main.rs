@@ -22,7 +22,9 @@ use example_cli::{
     Keychain,
 };
 
-const DB_MAGIC: &[u8] = b"bdk_example_esplora";
+// bumped with the layout of the changesets, the stores written before must be migrated with
+// `bdk_file_store::Store::migrate`
+const DB_MAGIC: &[u8] = b"bdk_example_esplora_v1";
 const DB_PATH: &str = ".bdk_esplora_example.db";
 
 type ChangeSet = (
@@ -1,4 +1,6 @@
-const DB_MAGIC: &str = "bdk_wallet_electrum_example";
+const DB_MAGIC: &str = "bdk_wallet_electrum_example_v1";
+// the magic of the stores written before the last layout change of the changesets
+const DB_MAGIC_V0: &str = "bdk_wallet_electrum_example";
 const SEND_AMOUNT: Amount = Amount::from_sat(5000);
 const STOP_GAP: usize = 50;
 const BATCH_SIZE: usize = 5;
@@ -9,7 +11,7 @@ use std::str::FromStr;
 
 use bdk_electrum::electrum_client;
 use bdk_electrum::BdkElectrumClient;
-use bdk_file_store::Store;
+use bdk_file_store::{FileError, Store};
 use bdk_wallet::bitcoin::{Address, Amount};
 use bdk_wallet::chain::collections::HashSet;
 use bdk_wallet::chain::persist::PersistBackend;
@@ -18,8 +20,19 @@ use bdk_wallet::{KeychainKind, SignOptions};
 
 fn main() -> Result<(), anyhow::Error> {
     let db_path = std::env::temp_dir().join("bdk-electrum-example");
-    let mut db =
-        Store::<bdk_wallet::wallet::ChangeSet>::open_or_create_new(DB_MAGIC.as_bytes(), db_path)?;
+    let mut db = match Store::<bdk_wallet::wallet::ChangeSet>::open_or_create_new(
+        DB_MAGIC.as_bytes(),
+        &db_path,
+    ) {
+        Err(FileError::InvalidMagicBytes { .. }) => {
+            Store::<bdk_wallet::wallet::ChangeSet>::migrate::<bdk_wallet::wallet::ChangeSetV0, _>(
+                DB_MAGIC_V0.as_bytes(),
+                DB_MAGIC.as_bytes(),
+                &db_path,
+            )?
+        }
+        db => db?,
+    };
     let external_descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/0'/0/*)";
     let internal_descriptor = "wpkh(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/84'/1'/0'/1/*)";
     let changeset = db
@@ -32,7 +45,7 @@ fn main() -> Result<(), anyhow::Error> {
         Network::Testnet,
     )?;
 
-    let address = wallet.next_unused_address(KeychainKind::External);
+    let address = wallet.next_unused_address(KeychainKind::External)?;
     wallet.commit_to(&mut db)?;
     println!("Generated Address: {}", address);
 
@@ -94,10 +107,9 @@ fn main() -> Result<(), anyhow::Error> {
         .enable_rbf();
 
     let mut psbt = tx_builder.finish()?;
-    let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-    assert!(finalized);
+    wallet.sign(&mut psbt, SignOptions::default())?;
 
-    let tx = psbt.extract_tx()?;
+    let tx = wallet.try_extract_tx(&psbt)?;
     client.transaction_broadcast(&tx)?;
     println!("Tx broadcasted! Txid: {}", tx.compute_txid());
 
@@ -29,7 +29,7 @@ async fn main() -> Result<(), anyhow::Error> {
         Network::Signet,
     )?;
 
-    let address = wallet.next_unused_address(KeychainKind::External);
+    let address = wallet.next_unused_address(KeychainKind::External)?;
     wallet.commit_to(&mut db)?;
     println!("Generated Address: {}", address);
 
@@ -101,10 +101,9 @@ async fn main() -> Result<(), anyhow::Error> {
         .enable_rbf();
 
     let mut psbt = tx_builder.finish()?;
-    let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-    assert!(finalized);
+    wallet.sign(&mut psbt, SignOptions::default())?;
 
-    let tx = psbt.extract_tx()?;
+    let tx = wallet.try_extract_tx(&psbt)?;
     client.broadcast(&tx).await?;
     println!("Tx broadcasted! Txid: {}", tx.compute_txid());
 
@@ -2,7 +2,7 @@ use bdk_bitcoind_rpc::{
     bitcoincore_rpc::{Auth, Client, RpcApi},
     Emitter,
 };
-use bdk_file_store::Store;
+use bdk_file_store::{FileError, Store};
 use bdk_wallet::chain::persist::PersistBackend;
 use bdk_wallet::{
     bitcoin::{Block, Network, Transaction},
@@ -11,7 +11,9 @@ use bdk_wallet::{
 use clap::{self, Parser};
 use std::{path::PathBuf, sync::mpsc::sync_channel, thread::spawn, time::Instant};
 
-const DB_MAGIC: &str = "bdk-rpc-wallet-example";
+const DB_MAGIC: &str = "bdk-rpc-wallet-example-v1";
+// the magic of the stores written before the last layout change of the changesets
+const DB_MAGIC_V0: &str = "bdk-rpc-wallet-example";
 
 /// Bitcoind RPC example using `bdk_wallet::Wallet`.
 ///
@@ -87,10 +89,19 @@ fn main() -> anyhow::Result<()> {
     );
 
     let start_load_wallet = Instant::now();
-    let mut db = Store::<bdk_wallet::wallet::ChangeSet>::open_or_create_new(
+    let mut db = match Store::<bdk_wallet::wallet::ChangeSet>::open_or_create_new(
         DB_MAGIC.as_bytes(),
-        args.db_path,
-    )?;
+        &args.db_path,
+    ) {
+        Err(FileError::InvalidMagicBytes { .. }) => {
+            Store::<bdk_wallet::wallet::ChangeSet>::migrate::<bdk_wallet::wallet::ChangeSetV0, _>(
+                DB_MAGIC_V0.as_bytes(),
+                DB_MAGIC.as_bytes(),
+                &args.db_path,
+            )?
+        }
+        db => db?,
+    };
     let changeset = db.load_changes()?;
 
     let mut wallet = Wallet::new_or_load(